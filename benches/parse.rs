@@ -0,0 +1,69 @@
+//! A simple parser benchmark.
+//!
+//! Run with `cargo bench --bench parse`.  This doesn't use the unstable
+//! `test` crate, so it works on stable Rust; it just reports the average
+//! time taken to parse a large schema.
+
+use cddl_cat::parse_cddl;
+use std::time::Instant;
+
+// A schema fragment in the style of RFC 8610, using a variety of syntax.
+// Each copy gets a unique suffix so that the rule names don't collide.
+const TEMPLATE: &str = r#"
+reputation-object-SUFFIX = {
+    application: tstr,
+    reputons: [* reputon-SUFFIX],
+}
+
+reputon-SUFFIX = {
+    rating: float16,
+    ? confidence: float16,
+    ? "rater" => tstr / nil,
+    ? sample-size: uint .size 4,
+    * tstr => any,
+}
+
+address-SUFFIX = { delivery-SUFFIX }
+
+delivery-SUFFIX = (
+    street: tstr, ? "number": uint, city-SUFFIX //
+    po_box: uint, city-SUFFIX //
+    per_pickup: true
+)
+
+city-SUFFIX = (
+    name: tstr,
+    zip_code: 10000..99999,
+)
+
+tcpflagbytes-SUFFIX = [1*2 bstr .size 1, h'0102', b64'AQI=', 'raw']
+
+message-SUFFIX<t, v> = [type: t, value: v]
+bigmap-SUFFIX = { 1: message-SUFFIX<uint, tstr>, 2: &colors-SUFFIX, ~tail-SUFFIX }
+colors-SUFFIX = ( red: 1, green: 2, blue: 3 )
+tail-SUFFIX = { ? -1: [[[ int ]]], 0x10: 0b1010 / 1.5e3 }
+"#;
+
+fn main() {
+    let copies = 200;
+    let schema: String = (0..copies)
+        .map(|n| TEMPLATE.replace("SUFFIX", &n.to_string()))
+        .collect();
+
+    // Make sure the schema is valid before timing anything.
+    let rules = parse_cddl(&schema).unwrap().rules.len();
+
+    let iterations = 20;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        parse_cddl(&schema).unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "parsed {} bytes ({} rules) in {:?} per iteration",
+        schema.len(),
+        rules,
+        elapsed / iterations
+    );
+}
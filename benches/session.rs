@@ -0,0 +1,113 @@
+//! Compare validating many small documents one at a time with validating
+//! them through a `Session`.
+//!
+//! Run with `cargo bench --bench session`.  A session always validates in
+//! lean mode, so one-shot validation is measured both with and without
+//! lean mode; the difference between lean one-shot validation and a
+//! session is what reusing the scratch buffers saves.  The first document
+//! is a small map, like a message in a log, and the second is a map with
+//! more than 64 entries, which needs a buffer to keep track of them.
+
+use cddl_cat::cbor::validate_cbor_slice;
+use cddl_cat::context::{LookupContext, ValidationOptions};
+use cddl_cat::schema::Schema;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Count allocations, as tests/alloc.rs does.
+struct Counter;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counter = Counter;
+
+const MESSAGE_SCHEMA: &str = r#"
+    message = { type: "ping", ? seq: uint }
+            / { type: "reading", sensor: tstr, value: int / float }
+"#;
+
+// {"type": "reading", "sensor": "t1", "value": 20}
+const MESSAGE: &[u8] = b"\xa3\x64type\x67reading\x66sensor\x62t1\x65value\x14";
+
+const TABLE_SCHEMA: &str = "table = { * tstr => uint }";
+
+// {"k0": 0, "k1": 1, ... "k99": 99}
+fn table() -> Vec<u8> {
+    let map: BTreeMap<String, u32> = (0..100).map(|n| (format!("k{}", n), n)).collect();
+    serde_cbor::to_vec(&map).unwrap()
+}
+
+// The time taken, and the allocations made, by `f`.
+fn measure<F: FnOnce()>(f: F) -> (Duration, usize) {
+    let count = COUNT.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    (start.elapsed(), COUNT.load(Ordering::Relaxed) - count)
+}
+
+fn compare(cddl: &str, name: &str, document: &[u8], documents: usize) {
+    let schema = Schema::from_str(cddl).unwrap();
+    let rule_def = schema.lookup_rule(name).unwrap();
+    let mut options = ValidationOptions::default();
+    options.lean = true;
+    let lean = schema.with_options(options);
+
+    let (single, single_allocs) = measure(|| {
+        for _ in 0..documents {
+            validate_cbor_slice(rule_def, document, &schema).unwrap();
+        }
+    });
+    let (single_lean, single_lean_allocs) = measure(|| {
+        for _ in 0..documents {
+            validate_cbor_slice(rule_def, document, &lean).unwrap();
+        }
+    });
+    let (session, session_allocs) = measure(|| {
+        let session = schema.session();
+        for _ in 0..documents {
+            session.validate_cbor_slice(rule_def, document).unwrap();
+        }
+    });
+
+    let per_doc = |allocs: usize| allocs as f64 / documents as f64;
+    println!("{}:", name);
+    println!(
+        "  validate_cbor_slice:        {:?} total, {:.1} allocations per document",
+        single,
+        per_doc(single_allocs)
+    );
+    println!(
+        "  validate_cbor_slice (lean): {:?} total, {:.1} allocations per document",
+        single_lean,
+        per_doc(single_lean_allocs)
+    );
+    println!(
+        "  Session:                    {:?} total, {:.1} allocations per document",
+        session,
+        per_doc(session_allocs)
+    );
+}
+
+fn main() {
+    compare(MESSAGE_SCHEMA, "message", MESSAGE, 100_000);
+    compare(TABLE_SCHEMA, "table", &table(), 10_000);
+}
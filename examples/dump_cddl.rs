@@ -0,0 +1,45 @@
+//! Print the syntax tree of a CDDL schema, for debugging.
+//!
+//! Usage: `cargo run --example dump_cddl -- schema.cddl`
+//!
+//! If no file is given, the schema is read from stdin.
+
+use cddl_cat::ast::dump_rule;
+use cddl_cat::parser::slice_parse_cddl;
+use std::io::Read;
+use std::process::exit;
+
+fn main() {
+    let mut cddl_input = String::new();
+    let result = match std::env::args().nth(1) {
+        Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut cddl_input)),
+        None => std::io::stdin().read_to_string(&mut cddl_input),
+    };
+    if let Err(e) = result {
+        eprintln!("failed to read schema: {}", e);
+        exit(1);
+    }
+
+    let cddl = match slice_parse_cddl(&cddl_input) {
+        Ok(cddl) => cddl,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    // The syntax tree doesn't record positions, but each rule's source text
+    // is a slice of the input, so we can search for it.
+    let mut offset = 0;
+    for (rule, source) in &cddl.rules {
+        let line = match cddl_input[offset..].find(source.as_str()) {
+            Some(pos) => {
+                offset += pos + source.len();
+                cddl_input[..offset - source.len()].matches('\n').count() + 1
+            }
+            None => 0,
+        };
+        println!("# line {}: {}", line, source.lines().next().unwrap_or(""));
+        println!("{}", dump_rule(rule));
+    }
+}
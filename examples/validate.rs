@@ -0,0 +1,108 @@
+//! Validate a CBOR or JSON file against a rule in a CDDL schema.
+//!
+//! Usage: `cargo run --example validate -- schema.cddl rule data.cbor`
+//!
+//! Files ending in `.json` are read as JSON; anything else is read as CBOR.
+//! On success this prints "valid".  On failure it prints the error, the
+//! place in the schema where it happened (e.g. `thing.items[].id`) and the
+//! schema rule, and if the error happened inside the data, the path to it
+//! and the data found there.  For CBOR, it also prints the byte range of
+//! the top-level element or map entry containing the error.
+//!
+//! The exit status is 0 if the data is valid, 1 if it isn't, and 2 if
+//! something else went wrong (e.g. the schema couldn't be parsed).
+
+use cddl_cat::context::LookupContext;
+use cddl_cat::schema::Schema;
+use cddl_cat::util::{render_value, PathElement};
+use cddl_cat::value::Value;
+use cddl_cat::{cbor, ValidateError};
+use std::convert::TryFrom;
+use std::process::exit;
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() != 3 {
+        fail("usage: validate <schema.cddl> <rule> <data.cbor|data.json>".into());
+    }
+    let (schema_path, rule, data_path) = (&args[0], &args[1], &args[2]);
+
+    let cddl_input = std::fs::read_to_string(schema_path)
+        .unwrap_or_else(|e| fail(format!("failed to read {}: {}", schema_path, e)));
+    let schema =
+        Schema::from_str(&cddl_input).unwrap_or_else(|e| fail(format!("{}: {}", schema_path, e)));
+    let rule_def = schema
+        .lookup_rule(rule)
+        .unwrap_or_else(|e| fail(format!("{}: {}", schema_path, e)));
+
+    let data = std::fs::read(data_path)
+        .unwrap_or_else(|e| fail(format!("failed to read {}: {}", data_path, e)));
+    let is_json = data_path.ends_with(".json");
+    let value = if is_json {
+        let json_value: serde_json::Value =
+            serde_json::from_slice(&data).unwrap_or_else(|e| fail(format!("{}: {}", data_path, e)));
+        Value::try_from(json_value)
+    } else {
+        let cbor_value: serde_cbor::Value =
+            serde_cbor::from_slice(&data).unwrap_or_else(|e| fail(format!("{}: {}", data_path, e)));
+        Value::try_from(cbor_value)
+    };
+    let value = value.unwrap_or_else(|e| fail(format!("{}: {}", data_path, e)));
+    let result = if is_json {
+        schema.session().validate(rule_def, &value)
+    } else {
+        cbor::validate_cbor_value(rule_def, &value, &data, &schema)
+    };
+
+    let err = match result {
+        Ok(()) => {
+            println!("valid");
+            return;
+        }
+        Err(e) => e,
+    };
+    println!("invalid: {:#}", err);
+    let path = match &err {
+        ValidateError::Mismatch(m) => m.path(),
+        ValidateError::MissingMapKey(m) => m.path(),
+        _ => &[],
+    };
+    if !path.is_empty() {
+        let max_len = schema.options().max_value_len;
+        let mut breadcrumbs = String::from("<top>");
+        let mut found = Some(&value);
+        for elem in path {
+            breadcrumbs.push_str(&elem.to_string());
+            found = found.and_then(|v| step(v, elem, max_len));
+        }
+        println!("  at: {}", breadcrumbs);
+        if let Some(v) = found {
+            println!("  found: {}", render_value(v, max_len));
+        }
+    }
+    exit(1);
+}
+
+// Follow one element of an error path into the data.
+//
+// Map keys in the path are rendered (and maybe shortened) by
+// `render_value`, so find the key that renders the same and look it up.
+// If more than one does, there's no telling which one the path means.
+fn step<'a>(value: &'a Value, elem: &PathElement, max_len: usize) -> Option<&'a Value> {
+    match (value, elem) {
+        (Value::Array(a), PathElement::Index(i)) => a.get(*i),
+        (Value::Map(m), PathElement::Key(k)) => {
+            let mut keys = m.keys().filter(|key| render_value(key, max_len) == *k);
+            match (keys.next(), keys.next()) {
+                (Some(key), None) => m.get(key),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
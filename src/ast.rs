@@ -4,6 +4,8 @@
 //! syntax used in the original CDDL text.
 //!
 
+use std::fmt;
+
 /// A literal value, i.e. `"foo"`, `1.0`, or `h'FFF7'`
 ///
 /// CDDL ABNF grammar:
@@ -17,7 +19,10 @@ pub enum Value {
     /// An unsigned integer literal.
     Uint(u64),
     /// A negative integer literal.
-    Nint(i64),
+    ///
+    /// This covers the full CBOR negative integer range, down to -2<sup>64</sup>,
+    /// which is why it doesn't fit in an `i64`.
+    Nint(i128),
     /// A floating-point literal.
     Float(f64),
     /// A byte-tring literal.
@@ -66,7 +71,46 @@ pub enum Occur {
     Optional,
     ZeroOrMore,
     OneOrMore,
-    Numbered(usize, usize),
+    Numbered(u64, u64),
+}
+
+impl Occur {
+    /// Return the lower and upper limits on this occurrence.
+    ///
+    /// An unlimited upper bound is represented by `u64::MAX`.
+    pub fn limits(&self) -> (u64, u64) {
+        match *self {
+            Occur::Optional => (0, 1),
+            Occur::ZeroOrMore => (0, u64::MAX),
+            Occur::OneOrMore => (1, u64::MAX),
+            Occur::Numbered(n, m) => (n, m),
+        }
+    }
+
+    /// Describe how many matches are allowed, e.g. `at most 1`,
+    /// `at least 2`, `2 to 5`, or `exactly 3`.
+    pub fn describe(&self) -> String {
+        match self.limits() {
+            (0, u64::MAX) => "any number".into(),
+            (0, m) => format!("at most {}", m),
+            (n, u64::MAX) => format!("at least {}", n),
+            (n, m) if n == m => format!("exactly {}", n),
+            (n, m) => format!("{} to {}", n, m),
+        }
+    }
+}
+
+/// Displays the CDDL symbol for this occurrence: `?`, `*`, `+`, or `n*m`
+impl fmt::Display for Occur {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Occur::Optional => write!(f, "?"),
+            Occur::ZeroOrMore => write!(f, "*"),
+            Occur::OneOrMore => write!(f, "+"),
+            Occur::Numbered(n, u64::MAX) => write!(f, "{}*", n),
+            Occur::Numbered(n, m) => write!(f, "{}*{}", n, m),
+        }
+    }
 }
 
 /// The part of a "group entry" after the occurrence.
@@ -172,6 +216,27 @@ pub enum Type2 {
     Unwrap(NameGeneric),
     ChoiceifyInline(Group),
     Choiceify(NameGeneric),
+    Tagged(TypeTagged),
+    Major(TypeMajor),
+    Any,
+}
+
+/// A CDDL tagged data item, e.g. `#6.32(tstr)`.
+#[derive(Debug, PartialEq)]
+pub struct TypeTagged {
+    /// The tag number, or `None` if any tag is allowed (e.g. `#6(tstr)`).
+    pub tag: Option<u64>,
+    /// The type of the tagged content.
+    pub ty: Type,
+}
+
+/// A CDDL data item specified by its CBOR major type, e.g. `#0` or `#7.25`.
+#[derive(Debug, PartialEq)]
+pub struct TypeMajor {
+    /// The major type.  CBOR only defines 0 to 7, but any digit parses.
+    pub major: u8,
+    /// The additional information, if given.
+    pub info: Option<u64>,
 }
 
 /// A CDDL type, with an additional range or control operator.
@@ -212,7 +277,152 @@ pub struct TypeRange {
 pub struct TypeControl {
     pub target: Type2,
     pub arg: Type2,
-    pub op: String,
+    pub op: ControlOp,
+}
+
+/// A control operator, e.g. `.size` or `.regexp`.
+///
+/// This includes the operators defined in [RFC8610] and [RFC9165].  Any
+/// other operator name is preserved as `Other`.
+///
+/// [RFC8610]: https://tools.ietf.org/html/rfc8610
+/// [RFC9165]: https://tools.ietf.org/html/rfc9165
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlOp {
+    /// `.size` (RFC8610 3.8.1)
+    Size,
+    /// `.bits` (RFC8610 3.8.2)
+    Bits,
+    /// `.regexp` (RFC8610 3.8.3)
+    Regexp,
+    /// `.cbor` (RFC8610 3.8.4)
+    Cbor,
+    /// `.cborseq` (RFC8610 3.8.4)
+    Cborseq,
+    /// `.within` (RFC8610 3.8.5)
+    Within,
+    /// `.and` (RFC8610 3.8.5)
+    And,
+    /// `.lt` (RFC8610 3.8.6)
+    Lt,
+    /// `.le` (RFC8610 3.8.6)
+    Le,
+    /// `.gt` (RFC8610 3.8.6)
+    Gt,
+    /// `.ge` (RFC8610 3.8.6)
+    Ge,
+    /// `.eq` (RFC8610 3.8.6)
+    Eq,
+    /// `.ne` (RFC8610 3.8.6)
+    Ne,
+    /// `.default` (RFC8610 3.8.6)
+    Default,
+    /// `.plus` (RFC9165 2.1)
+    Plus,
+    /// `.cat` (RFC9165 2.2)
+    Cat,
+    /// `.det` (RFC9165 2.2)
+    Det,
+    /// `.abnf` (RFC9165 3)
+    Abnf,
+    /// `.abnfb` (RFC9165 3)
+    Abnfb,
+    /// `.feature` (RFC9165 4)
+    Feature,
+    /// Any other control operator.
+    Other(String),
+}
+
+impl ControlOp {
+    /// Every operator that has its own variant, i.e. all but `Other`.
+    pub const ALL: &'static [ControlOp] = &[
+        ControlOp::Size,
+        ControlOp::Bits,
+        ControlOp::Regexp,
+        ControlOp::Cbor,
+        ControlOp::Cborseq,
+        ControlOp::Within,
+        ControlOp::And,
+        ControlOp::Lt,
+        ControlOp::Le,
+        ControlOp::Gt,
+        ControlOp::Ge,
+        ControlOp::Eq,
+        ControlOp::Ne,
+        ControlOp::Default,
+        ControlOp::Plus,
+        ControlOp::Cat,
+        ControlOp::Det,
+        ControlOp::Abnf,
+        ControlOp::Abnfb,
+        ControlOp::Feature,
+    ];
+
+    /// The name of the operator, without the leading `.`
+    pub fn name(&self) -> &str {
+        match self {
+            ControlOp::Size => "size",
+            ControlOp::Bits => "bits",
+            ControlOp::Regexp => "regexp",
+            ControlOp::Cbor => "cbor",
+            ControlOp::Cborseq => "cborseq",
+            ControlOp::Within => "within",
+            ControlOp::And => "and",
+            ControlOp::Lt => "lt",
+            ControlOp::Le => "le",
+            ControlOp::Gt => "gt",
+            ControlOp::Ge => "ge",
+            ControlOp::Eq => "eq",
+            ControlOp::Ne => "ne",
+            ControlOp::Default => "default",
+            ControlOp::Plus => "plus",
+            ControlOp::Cat => "cat",
+            ControlOp::Det => "det",
+            ControlOp::Abnf => "abnf",
+            ControlOp::Abnfb => "abnfb",
+            ControlOp::Feature => "feature",
+            ControlOp::Other(name) => name,
+        }
+    }
+
+    /// The cargo feature that this build lacks to support the operator, if
+    /// any.
+    pub(crate) fn missing_feature(&self) -> Option<&'static str> {
+        match self {
+            ControlOp::Regexp if cfg!(not(feature = "regex")) => Some("regex"),
+            ControlOp::Cbor if cfg!(not(feature = "serde_cbor")) => Some("serde_cbor"),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for ControlOp {
+    fn from(name: &str) -> ControlOp {
+        match name {
+            "size" => ControlOp::Size,
+            "bits" => ControlOp::Bits,
+            "regexp" => ControlOp::Regexp,
+            "cbor" => ControlOp::Cbor,
+            "cborseq" => ControlOp::Cborseq,
+            "within" => ControlOp::Within,
+            "and" => ControlOp::And,
+            "lt" => ControlOp::Lt,
+            "le" => ControlOp::Le,
+            "gt" => ControlOp::Gt,
+            "ge" => ControlOp::Ge,
+            "eq" => ControlOp::Eq,
+            "ne" => ControlOp::Ne,
+            "default" => ControlOp::Default,
+            "plus" => ControlOp::Plus,
+            "cat" => ControlOp::Cat,
+            "det" => ControlOp::Det,
+            "abnf" => ControlOp::Abnf,
+            "abnfb" => ControlOp::Abnfb,
+            "feature" => ControlOp::Feature,
+            _ => ControlOp::Other(name.to_string()),
+        }
+    }
 }
 
 /// A CDDL type, with choices.
@@ -229,9 +439,6 @@ pub struct Type(pub Vec<Type1>);
 /// Each CDDL rule has a name and a syntax tree.  Rules can be
 /// referenced by name by other rules, or even within the same rule.
 ///
-/// Note: `genericparm` is not yet supported.
-/// Note: "extend" assignment operators (`/=`,`//=`) are not yet supported.
-///
 /// CDDL ABNF grammar:
 /// ```text
 /// rule = typename [genericparm] S assignt S type
@@ -249,21 +456,25 @@ pub struct Rule {
 
 /// A rule's syntax tree, in either [`Type`] or [`GrpEnt`] form.
 ///
-/// Note: `genericparm` is not yet supported.
-/// Note: "extend" assignment operators (`/=`,`//=`) are not yet supported.
-///
 /// CDDL ABNF grammar:
 /// ```text
 /// rule = typename [genericparm] S assignt S type
 ///      / groupname [genericparm] S assigng S grpent
+/// assignt = "=" / "/="
+/// assigng = "=" / "//="
 /// ```
 #[derive(Debug, PartialEq)]
+#[allow(clippy::large_enum_variant)]
 pub enum RuleVal {
     /// A type assignment rule.
     AssignType(Type),
     /// A group assignment rule.
     AssignGroup(GrpEnt),
-    // TODO: /= and //= operators --> ExtendType(Type), ExtendGroup(GrpEnt)
+    /// A type choice added to a rule with `/=`, e.g. `$socket /= int`.
+    ExtendType(Type),
+    /// A group choice added to a rule with `//=`, e.g.
+    /// `$$socket //= (a: int)`.
+    ExtendGroup(GrpEnt),
 }
 
 /// A CDDL specification, containing multiple rule syntax trees.
@@ -287,3 +498,423 @@ pub struct CddlSlice {
     /// Rules and their syntax trees.
     pub rules: Vec<(Rule, String)>,
 }
+
+/// Render a CDDL syntax tree as indented text, for debugging.
+///
+/// Each rule is rendered in its own section, separated by blank lines.
+/// Simple types (like `tstr` or `1..10`) are written on a single line;
+/// everything else gets one line per node, indented below its parent.
+///
+/// The syntax tree doesn't record source positions.  The `dump_cddl`
+/// example shows how to find each rule's location using
+/// [`slice_parse_cddl`](crate::parser::slice_parse_cddl).
+///
+/// # Examples
+/// ```
+/// use cddl_cat::{ast::dump, parser::parse_cddl};
+///
+/// let cddl = parse_cddl("person = { name: tstr, ? age: uint .le 150 }").unwrap();
+/// assert_eq!(
+///     dump(&cddl),
+///     "person = type\n  map\n    name: tstr\n    ? age: uint .le 150\n"
+/// );
+/// ```
+pub fn dump(cddl: &Cddl) -> String {
+    let rules: Vec<String> = cddl.rules.iter().map(dump_rule).collect();
+    rules.join("\n")
+}
+
+/// Render a single rule's syntax tree as indented text.
+///
+/// See [`dump`] for details.
+pub fn dump_rule(rule: &Rule) -> String {
+    let mut dumper = Dumper::default();
+    let name = if rule.generic_parms.is_empty() {
+        rule.name.clone()
+    } else {
+        format!("{}<{}>", rule.name, rule.generic_parms.join(", "))
+    };
+    match &rule.val {
+        RuleVal::AssignType(ty) | RuleVal::ExtendType(ty) => {
+            let op = match &rule.val {
+                RuleVal::ExtendType(_) => "/=",
+                _ => "=",
+            };
+            match inline_type(ty) {
+                Some(s) => dumper.line(format!("{} {} type {}", name, op, s)),
+                None => {
+                    dumper.line(format!("{} {} type", name, op));
+                    dumper.nested(|d| d.ty(ty));
+                }
+            }
+        }
+        RuleVal::AssignGroup(grpent) | RuleVal::ExtendGroup(grpent) => {
+            let op = match &rule.val {
+                RuleVal::ExtendGroup(_) => "//=",
+                _ => "=",
+            };
+            dumper.line(format!("{} {} group", name, op));
+            dumper.nested(|d| d.grpent(grpent));
+        }
+    }
+    dumper.out
+}
+
+#[derive(Default)]
+struct Dumper {
+    out: String,
+    depth: usize,
+}
+
+impl Dumper {
+    fn line(&mut self, text: String) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(&text);
+        self.out.push('\n');
+    }
+
+    fn nested<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    // Write `prefix` followed by the type, either on the same line or
+    // nested below it.
+    fn labeled_ty(&mut self, prefix: String, ty: &Type) {
+        match inline_type(ty) {
+            Some(s) if prefix.is_empty() => self.line(s),
+            Some(s) => self.line(format!("{} {}", prefix, s)),
+            None if prefix.is_empty() => self.ty(ty),
+            None => {
+                self.line(prefix);
+                self.nested(|d| d.ty(ty));
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        if ty.0.len() == 1 {
+            self.ty1(&ty.0[0]);
+        } else {
+            self.line("type choice".to_string());
+            self.nested(|d| {
+                for ty1 in &ty.0 {
+                    d.ty1(ty1);
+                }
+            });
+        }
+    }
+
+    fn ty1(&mut self, ty1: &Type1) {
+        if let Some(s) = inline_type1(ty1) {
+            self.line(s);
+            return;
+        }
+        match ty1 {
+            Type1::Simple(ty2) => self.ty2(ty2),
+            Type1::Range(r) => {
+                let op = if r.inclusive { ".." } else { "..." };
+                self.line(format!("range {}", op));
+                self.nested(|d| {
+                    d.ty2(&r.start);
+                    d.ty2(&r.end);
+                });
+            }
+            Type1::Control(ctl) => {
+                self.line(format!("control .{}", ctl.op.name()));
+                self.nested(|d| {
+                    d.ty2(&ctl.target);
+                    d.ty2(&ctl.arg);
+                });
+            }
+        }
+    }
+
+    fn ty2(&mut self, ty2: &Type2) {
+        if let Some(s) = inline_type2(ty2) {
+            self.line(s);
+            return;
+        }
+        match ty2 {
+            Type2::Parethesized(ty) => {
+                self.line("parenthesized".to_string());
+                self.nested(|d| d.ty(ty));
+            }
+            Type2::Map(g) => {
+                self.line("map".to_string());
+                self.nested(|d| d.group(g));
+            }
+            Type2::Array(g) => {
+                self.line("array".to_string());
+                self.nested(|d| d.group(g));
+            }
+            Type2::ChoiceifyInline(g) => {
+                self.line("choiceify".to_string());
+                self.nested(|d| d.group(g));
+            }
+            Type2::Typename(ng) | Type2::Unwrap(ng) | Type2::Choiceify(ng) => {
+                let sigil = match ty2 {
+                    Type2::Unwrap(_) => "~",
+                    Type2::Choiceify(_) => "&",
+                    _ => "",
+                };
+                // Names without generic arguments are always inline, so
+                // there's at least one argument here.
+                self.line(format!("generic {}{}", sigil, ng.name));
+                self.nested(|d| {
+                    for arg in &ng.generic_args {
+                        d.ty1(arg);
+                    }
+                });
+            }
+            Type2::Tagged(t) => {
+                match t.tag {
+                    Some(tag) => self.line(format!("#6.{}", tag)),
+                    None => self.line("#6".to_string()),
+                }
+                self.nested(|d| d.ty(&t.ty));
+            }
+            // These are always inline; this is only a placeholder.
+            Type2::Value(_) | Type2::Major(_) | Type2::Any => self.line(format!("{:?}", ty2)),
+        }
+    }
+
+    fn group(&mut self, group: &Group) {
+        if group.0.len() == 1 {
+            self.grpchoice(&group.0[0]);
+        } else {
+            for grpchoice in &group.0 {
+                self.line("group choice".to_string());
+                self.nested(|d| d.grpchoice(grpchoice));
+            }
+        }
+    }
+
+    fn grpchoice(&mut self, grpchoice: &GrpChoice) {
+        for grpent in &grpchoice.0 {
+            self.grpent(grpent);
+        }
+    }
+
+    fn grpent(&mut self, grpent: &GrpEnt) {
+        let occur = match &grpent.occur {
+            Some(o) => format!("{} ", o),
+            None => String::new(),
+        };
+        match &grpent.val {
+            GrpEntVal::Member(member) => {
+                let key = match &member.key {
+                    None => None,
+                    Some(key) => match &key.val {
+                        MemberKeyVal::Bareword(s) => Some(format!("{}:", s)),
+                        MemberKeyVal::Value(v) => Some(format!("{}:", value_string(v))),
+                        MemberKeyVal::Type1(ty1) => {
+                            let sep = if key.cut { "^ =>" } else { "=>" };
+                            match inline_type1(ty1) {
+                                Some(s) => Some(format!("{} {}", s, sep)),
+                                None => {
+                                    // The key is too complex to fit on one line.
+                                    self.line(format!("{}member", occur));
+                                    self.nested(|d| {
+                                        d.line(format!("key {}", sep));
+                                        d.nested(|d| d.ty1(ty1));
+                                        d.labeled_ty("value".to_string(), &member.value);
+                                    });
+                                    return;
+                                }
+                            }
+                        }
+                    },
+                };
+                let prefix = match key {
+                    Some(key) => format!("{}{}", occur, key),
+                    None => occur.trim_end().to_string(),
+                };
+                self.labeled_ty(prefix, &member.value);
+            }
+            GrpEntVal::Groupname(name) => self.line(format!("{}group {}", occur, name)),
+            GrpEntVal::Parenthesized(g) => {
+                self.line(format!("{}group", occur));
+                self.nested(|d| d.group(g));
+            }
+        }
+    }
+}
+
+// Render a type on a single line, if it's simple enough.
+fn inline_type(ty: &Type) -> Option<String> {
+    let options: Option<Vec<String>> = ty.0.iter().map(inline_type1).collect();
+    options.map(|o| o.join(" / "))
+}
+
+fn inline_type1(ty1: &Type1) -> Option<String> {
+    match ty1 {
+        Type1::Simple(ty2) => inline_type2(ty2),
+        Type1::Range(r) => {
+            let op = if r.inclusive { ".." } else { "..." };
+            Some(format!(
+                "{}{}{}",
+                inline_type2(&r.start)?,
+                op,
+                inline_type2(&r.end)?
+            ))
+        }
+        Type1::Control(ctl) => Some(format!(
+            "{} .{} {}",
+            inline_type2(&ctl.target)?,
+            ctl.op.name(),
+            inline_type2(&ctl.arg)?
+        )),
+    }
+}
+
+fn inline_type2(ty2: &Type2) -> Option<String> {
+    match ty2 {
+        Type2::Value(v) => Some(value_string(v)),
+        Type2::Typename(ng) => inline_name(ng, ""),
+        Type2::Unwrap(ng) => inline_name(ng, "~"),
+        Type2::Choiceify(ng) => inline_name(ng, "&"),
+        Type2::Major(m) => match m.info {
+            Some(info) => Some(format!("#{}.{}", m.major, info)),
+            None => Some(format!("#{}", m.major)),
+        },
+        Type2::Any => Some("#".to_string()),
+        Type2::Map(g) if g.0.iter().all(|gc| gc.0.is_empty()) => Some("{}".to_string()),
+        Type2::Array(g) if g.0.iter().all(|gc| gc.0.is_empty()) => Some("[]".to_string()),
+        _ => None,
+    }
+}
+
+fn inline_name(ng: &NameGeneric, sigil: &str) -> Option<String> {
+    if ng.generic_args.is_empty() {
+        return Some(format!("{}{}", sigil, ng.name));
+    }
+    let args: Option<Vec<String>> = ng.generic_args.iter().map(inline_type1).collect();
+    Some(format!("{}{}<{}>", sigil, ng.name, args?.join(", ")))
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::Text(s) => format!("{:?}", s),
+        Value::Uint(n) => n.to_string(),
+        Value::Nint(n) => n.to_string(),
+        Value::Float(n) => format!("{:?}", n),
+        Value::Bytes(b) => format!("h'{}'", hex::encode(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_cddl;
+
+    fn dump_str(cddl_input: &str) -> String {
+        dump(&parse_cddl(cddl_input).unwrap())
+    }
+
+    #[test]
+    fn test_occur_describe() {
+        assert_eq!(Occur::Optional.describe(), "at most 1");
+        assert_eq!(Occur::ZeroOrMore.describe(), "any number");
+        assert_eq!(Occur::OneOrMore.describe(), "at least 1");
+        assert_eq!(Occur::Numbered(3, 3).describe(), "exactly 3");
+        assert_eq!(Occur::Numbered(2, 5).describe(), "2 to 5");
+        assert_eq!(Occur::Numbered(0, 4).describe(), "at most 4");
+        assert_eq!(Occur::Numbered(2, u64::MAX).describe(), "at least 2");
+    }
+
+    #[test]
+    fn test_control_op_all() {
+        // This match has no catch-all, so a new operator can't be added
+        // without listing it here, and then ALL must have one entry for
+        // each operator listed.
+        let listed = |op: &ControlOp| match op {
+            ControlOp::Size
+            | ControlOp::Bits
+            | ControlOp::Regexp
+            | ControlOp::Cbor
+            | ControlOp::Cborseq
+            | ControlOp::Within
+            | ControlOp::And
+            | ControlOp::Lt
+            | ControlOp::Le
+            | ControlOp::Gt
+            | ControlOp::Ge
+            | ControlOp::Eq
+            | ControlOp::Ne
+            | ControlOp::Default
+            | ControlOp::Plus
+            | ControlOp::Cat
+            | ControlOp::Det
+            | ControlOp::Abnf
+            | ControlOp::Abnfb
+            | ControlOp::Feature => true,
+            ControlOp::Other(_) => false,
+        };
+        assert_eq!(ControlOp::ALL.len(), 20);
+        let mut names = std::collections::BTreeSet::new();
+        for op in ControlOp::ALL {
+            assert!(listed(op), "{:?}", op);
+            assert_eq!(&ControlOp::from(op.name()), op);
+            assert!(names.insert(op.name()), "{:?}", op);
+        }
+        assert!(!listed(&ControlOp::Other("foo".into())));
+    }
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(dump_str("a = int"), "a = type int\n");
+        assert_eq!(
+            dump_str("a = 1..10 / 1.5 / h'00ff' / -3  b<T> = [* T]"),
+            "a = type 1..10 / 1.5 / h'00ff' / -3\n\nb<T> = type\n  array\n    * T\n"
+        );
+
+        let cddl_input = r#"
+            msg = {
+                "type" ^ => "ping" / "pong",
+                ? body: [* pair<tstr, { id: uint }>],
+                (x: int // y: tstr),
+                1*3 extra,
+                [int] => any,
+            }
+            extra = ( ~headers, &colors )
+        "#;
+        let expected = "\
+msg = type
+  map
+    \"type\" ^ => \"ping\" / \"pong\"
+    ? body:
+      array
+        *
+          generic pair
+            tstr
+            map
+              id: uint
+    group
+      group choice
+        x: int
+      group choice
+        y: tstr
+    1*3 extra
+    member
+      key =>
+        array
+          int
+      value any
+
+extra = group
+  group
+    ~headers
+    &colors
+";
+        assert_eq!(dump_str(cddl_input), expected);
+
+        assert_eq!(
+            dump_str("a = ~b<{ x: int }>"),
+            "a = type\n  generic ~b\n    map\n      x: int\n"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! This module reports which CDDL constructs this build supports.
+//!
+//! Support isn't recorded by hand: [`capabilities`] parses and flattens a
+//! small example schema for each construct, so the answer comes from the
+//! same code that handles real schemas, including any cargo features
+//! (e.g. `regex`) that were left out of this build.
+//!
+//! # Examples
+//! ```
+//! use cddl_cat::capabilities::{capabilities, Kind};
+//!
+//! let size = capabilities()
+//!     .into_iter()
+//!     .find(|c| c.kind == Kind::Control && c.name == "size")
+//!     .unwrap();
+//! assert!(size.supported);
+//! ```
+
+use crate::ast::ControlOp;
+use crate::flatten::flatten_from_str;
+
+/// The kind of CDDL construct described by a [`Capability`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    /// A control operator, e.g. `.size`.
+    Control,
+    /// A type from the standard prelude (RFC 8610 appendix D), e.g. `tdate`.
+    Prelude,
+    /// Any other syntax, e.g. generics or tags.
+    Syntax,
+}
+
+/// One CDDL construct, and whether this build supports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capability {
+    /// The kind of construct.
+    pub kind: Kind,
+    /// The name of the construct: an operator name without the leading
+    /// `.`, a prelude type name, or a short name for the syntax
+    /// (e.g. `"generics"`).
+    pub name: &'static str,
+    /// The schema used to check for support.  Its first rule uses the
+    /// construct.
+    pub example: &'static str,
+    /// Whether the example schema can be parsed and flattened.
+    pub supported: bool,
+}
+
+// A schema using each control operator on a reasonable target.  There's
+// no catch-all, so every operator in `ControlOp::ALL` needs one.
+fn control_example(op: &ControlOp) -> Option<&'static str> {
+    let example = match op {
+        ControlOp::Size => "a = bstr .size 4",
+        ControlOp::Bits => "a = uint .bits 1",
+        ControlOp::Regexp => "a = tstr .regexp \"[a-z]+\"",
+        ControlOp::Cbor => "a = bstr .cbor int",
+        ControlOp::Cborseq => "a = bstr .cborseq int",
+        ControlOp::Within => "a = int .within uint",
+        ControlOp::And => "a = int .and uint",
+        ControlOp::Lt => "a = uint .lt 10",
+        ControlOp::Le => "a = uint .le 10",
+        ControlOp::Gt => "a = uint .gt 10",
+        ControlOp::Ge => "a = uint .ge 10",
+        ControlOp::Eq => "a = uint .eq 10",
+        ControlOp::Ne => "a = uint .ne 10",
+        ControlOp::Default => "a = uint .default 10",
+        ControlOp::Plus => "a = 1 .plus 2",
+        ControlOp::Cat => "a = \"a\" .cat \"b\"",
+        ControlOp::Det => "a = \"a\" .det \"b\"",
+        ControlOp::Abnf => "a = tstr .abnf \"x\"",
+        ControlOp::Abnfb => "a = bstr .abnfb \"x\"",
+        ControlOp::Feature => "a = uint .feature \"x\"",
+        ControlOp::Other(_) => return None,
+    };
+    Some(example)
+}
+
+// The standard prelude, from RFC 8610 appendix D.  This has the same names
+// as the flattener's list.
+const PRELUDE: &[(&str, &str)] = &[
+    ("any", "a = any"),
+    ("uint", "a = uint"),
+    ("nint", "a = nint"),
+    ("int", "a = int"),
+    ("bstr", "a = bstr"),
+    ("bytes", "a = bytes"),
+    ("tstr", "a = tstr"),
+    ("text", "a = text"),
+    ("tdate", "a = tdate"),
+    ("time", "a = time"),
+    ("number", "a = number"),
+    ("biguint", "a = biguint"),
+    ("bignint", "a = bignint"),
+    ("bigint", "a = bigint"),
+    ("integer", "a = integer"),
+    ("unsigned", "a = unsigned"),
+    ("decfrac", "a = decfrac"),
+    ("bigfloat", "a = bigfloat"),
+    ("eb64url", "a = eb64url"),
+    ("eb64legacy", "a = eb64legacy"),
+    ("eb16", "a = eb16"),
+    ("encoded-cbor", "a = encoded-cbor"),
+    ("uri", "a = uri"),
+    ("b64url", "a = b64url"),
+    ("b64legacy", "a = b64legacy"),
+    ("regexp", "a = regexp"),
+    ("mime-message", "a = mime-message"),
+    ("cbor-any", "a = cbor-any"),
+    ("float16", "a = float16"),
+    ("float32", "a = float32"),
+    ("float64", "a = float64"),
+    ("float16-32", "a = float16-32"),
+    ("float32-64", "a = float32-64"),
+    ("float", "a = float"),
+    ("false", "a = false"),
+    ("true", "a = true"),
+    ("bool", "a = bool"),
+    ("nil", "a = nil"),
+    ("null", "a = null"),
+    ("undefined", "a = undefined"),
+];
+
+// Everything else.  Each syntax tree node that needs support of its own
+// has an entry here.
+const SYNTAX: &[(&str, &str)] = &[
+    ("groups", "a = [b, c]  b = (x: int)  c = (y: tstr)"),
+    ("choices", "a = int / tstr  b = [(x: int // y: tstr)]"),
+    ("occurrences", "a = [? int, * tstr, + bool, 2*3 nil]"),
+    ("ranges", "a = 1..7 / 1...8 / 1.5..2.5"),
+    ("unwrap", "a = [~b]  b = [int]"),
+    ("choiceify", "a = &b / &(c: 1)  b = (d: 2)"),
+    ("cuts", "a = { \"x\" ^ => int, x: int }"),
+    ("generics", "a = b<int>  b<T> = [T]"),
+    ("type-sockets", "a = $b  $b /= int"),
+    ("group-sockets", "a = { $$b }  $$b //= (x: int)"),
+    ("tags", "a = #6.32(tstr)"),
+    ("major-types", "a = #0"),
+    ("hexfloat", "a = 0x1.8p1"),
+];
+
+/// List the CDDL constructs that this library knows about, and whether
+/// this build supports each of them.
+///
+/// Constructs are listed by [`Kind`], in a fixed order.
+pub fn capabilities() -> Vec<Capability> {
+    let controls = ControlOp::ALL
+        .iter()
+        .filter_map(|op| Some((Kind::Control, op.name(), control_example(op)?)));
+    let prelude = PRELUDE
+        .iter()
+        .map(|(name, example)| (Kind::Prelude, *name, *example));
+    let syntax = SYNTAX
+        .iter()
+        .map(|(name, example)| (Kind::Syntax, *name, *example));
+    controls
+        .chain(prelude)
+        .chain(syntax)
+        .map(|(kind, name, example)| Capability {
+            kind,
+            name,
+            example,
+            supported: flatten_from_str(example).is_ok(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+    use crate::flatten::PRELUDE_NAMES;
+    use crate::parser::parse_cddl;
+    use crate::util::ValidateError;
+    use std::collections::BTreeSet;
+
+    fn find(kind: Kind, name: &str) -> Capability {
+        capabilities()
+            .into_iter()
+            .find(|c| c.kind == kind && c.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_capabilities() {
+        for cap in capabilities() {
+            let result = flatten_from_str(cap.example);
+            if cap.supported {
+                // The first rule flattens into something real; that's the
+                // one that uses the construct.
+                let rules = result.unwrap();
+                let cddl = parse_cddl(cap.example).unwrap();
+                assert!(rules.contains_key(&cddl.rules[0].name), "{:?}", cap);
+            } else {
+                // An unsupported example should only fail because of the
+                // construct itself, not because it's otherwise broken.
+                match result {
+                    Err(ValidateError::Unsupported(_)) | Err(ValidateError::ParseError(_)) => {}
+                    _ => panic!("{:?}: {:?}", cap, result),
+                }
+            }
+            if cap.kind == Kind::Control {
+                let op = ControlOp::from(cap.name);
+                assert_ne!(op, ControlOp::Other(cap.name.into()), "{:?}", cap);
+                // The example's control operator is the one named.
+                match &parse_cddl(cap.example).unwrap().rules[0].val {
+                    crate::ast::RuleVal::AssignType(t) => match &t.0[0] {
+                        crate::ast::Type1::Control(ctl) => assert_eq!(ctl.op, op),
+                        _ => panic!("{:?}", cap),
+                    },
+                    _ => panic!("{:?}", cap),
+                }
+            }
+        }
+
+        assert!(find(Kind::Control, "size").supported);
+        assert!(find(Kind::Control, "bits").supported);
+        assert!(find(Kind::Control, "default").supported);
+        assert!(!find(Kind::Control, "lt").supported);
+        assert_eq!(
+            find(Kind::Control, "regexp").supported,
+            cfg!(feature = "regex")
+        );
+        assert_eq!(
+            find(Kind::Control, "cbor").supported,
+            cfg!(feature = "serde_cbor")
+        );
+        assert!(find(Kind::Prelude, "tstr").supported);
+        assert!(!find(Kind::Prelude, "undefined").supported);
+        assert!(find(Kind::Syntax, "generics").supported);
+        assert!(find(Kind::Syntax, "tags").supported);
+        assert!(find(Kind::Syntax, "hexfloat").supported);
+    }
+
+    #[test]
+    fn test_capabilities_complete() {
+        let controls: Vec<_> = capabilities()
+            .into_iter()
+            .filter(|c| c.kind == Kind::Control)
+            .map(|c| c.name)
+            .collect();
+        let all: Vec<_> = ControlOp::ALL.iter().map(ControlOp::name).collect();
+        assert_eq!(controls, all);
+
+        let prelude: Vec<_> = PRELUDE.iter().map(|(name, _)| *name).collect();
+        assert_eq!(prelude, PRELUDE_NAMES);
+        for (name, example) in PRELUDE {
+            assert_eq!(*example, format!("a = {}", name));
+        }
+
+        // Each syntax example uses the syntax it's named for, and any
+        // syntax found in the examples is listed.
+        let listed: BTreeSet<_> = SYNTAX.iter().map(|(name, _)| *name).collect();
+        for (name, example) in SYNTAX {
+            let mut found = BTreeSet::new();
+            for rule in parse_cddl(example).unwrap().rules {
+                rule_syntax(&rule, &mut found);
+            }
+            // Hex floats are only a way of writing a literal, so they
+            // don't show up in the syntax tree.
+            if *name != "hexfloat" {
+                assert!(found.contains(name), "{}: {:?}", name, found);
+            }
+            assert!(found.is_subset(&listed), "{}: {:?}", name, found);
+        }
+    }
+
+    // Collect the names of the SYNTAX entries used by a rule.  The matches
+    // have no catch-alls, so new kinds of syntax tree node can't be left
+    // out.
+    fn rule_syntax(rule: &Rule, found: &mut BTreeSet<&'static str>) {
+        if !rule.generic_parms.is_empty() {
+            found.insert("generics");
+        }
+        name_syntax(&rule.name, found);
+        match &rule.val {
+            RuleVal::AssignType(t) | RuleVal::ExtendType(t) => type_syntax(t, found),
+            RuleVal::AssignGroup(g) | RuleVal::ExtendGroup(g) => {
+                found.insert("groups");
+                grpent_syntax(g, found);
+            }
+        }
+    }
+
+    fn name_syntax(name: &str, found: &mut BTreeSet<&'static str>) {
+        if name.starts_with("$$") {
+            found.insert("group-sockets");
+        } else if name.starts_with('$') {
+            found.insert("type-sockets");
+        }
+    }
+
+    fn type_syntax(ty: &Type, found: &mut BTreeSet<&'static str>) {
+        if ty.0.len() > 1 {
+            found.insert("choices");
+        }
+        for t1 in &ty.0 {
+            type1_syntax(t1, found);
+        }
+    }
+
+    fn type1_syntax(t1: &Type1, found: &mut BTreeSet<&'static str>) {
+        match t1 {
+            Type1::Simple(t2) => type2_syntax(t2, found),
+            Type1::Range(r) => {
+                found.insert("ranges");
+                type2_syntax(&r.start, found);
+                type2_syntax(&r.end, found);
+            }
+            // Control operators are listed separately.
+            Type1::Control(ctl) => {
+                type2_syntax(&ctl.target, found);
+                type2_syntax(&ctl.arg, found);
+            }
+        }
+    }
+
+    fn type2_syntax(t2: &Type2, found: &mut BTreeSet<&'static str>) {
+        match t2 {
+            Type2::Value(_) => {}
+            Type2::Typename(ng) => name_generic_syntax(ng, found),
+            Type2::Parethesized(t) => type_syntax(t, found),
+            Type2::Map(g) | Type2::Array(g) => group_syntax(g, found),
+            Type2::Unwrap(ng) => {
+                found.insert("unwrap");
+                name_generic_syntax(ng, found);
+            }
+            Type2::ChoiceifyInline(g) => {
+                found.insert("choiceify");
+                group_syntax(g, found);
+            }
+            Type2::Choiceify(ng) => {
+                found.insert("choiceify");
+                name_generic_syntax(ng, found);
+            }
+            Type2::Tagged(tagged) => {
+                found.insert("tags");
+                type_syntax(&tagged.ty, found);
+            }
+            Type2::Major(_) | Type2::Any => {
+                found.insert("major-types");
+            }
+        }
+    }
+
+    fn name_generic_syntax(ng: &NameGeneric, found: &mut BTreeSet<&'static str>) {
+        if !ng.generic_args.is_empty() {
+            found.insert("generics");
+        }
+        name_syntax(&ng.name, found);
+        for arg in &ng.generic_args {
+            type1_syntax(arg, found);
+        }
+    }
+
+    fn group_syntax(group: &Group, found: &mut BTreeSet<&'static str>) {
+        if group.0.len() > 1 {
+            found.insert("choices");
+        }
+        for grpchoice in &group.0 {
+            for grpent in &grpchoice.0 {
+                grpent_syntax(grpent, found);
+            }
+        }
+    }
+
+    fn grpent_syntax(grpent: &GrpEnt, found: &mut BTreeSet<&'static str>) {
+        if grpent.occur.is_some() {
+            found.insert("occurrences");
+        }
+        match &grpent.val {
+            GrpEntVal::Member(member) => {
+                if let Some(key) = &member.key {
+                    match &key.val {
+                        MemberKeyVal::Type1(t1) => {
+                            // Keys written with `:` always have cut
+                            // semantics; only `^ =>` asks for them.
+                            if key.cut {
+                                found.insert("cuts");
+                            }
+                            type1_syntax(t1, found);
+                        }
+                        MemberKeyVal::Bareword(_) | MemberKeyVal::Value(_) => {}
+                    }
+                }
+                type_syntax(&member.value, found);
+            }
+            GrpEntVal::Groupname(name) => {
+                found.insert("groups");
+                name_syntax(name, found);
+            }
+            GrpEntVal::Parenthesized(g) => {
+                found.insert("groups");
+                group_syntax(g, found);
+            }
+        }
+    }
+}
@@ -22,6 +22,10 @@
 //! validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap();
 //! ```
 //!
+//! The functions that take a rule name look up that rule before decoding
+//! any data, so a misspelled name fails quickly (with a `MissingRule` error
+//! suggesting similar names) no matter how large the data is.
+//!
 //! If the caller wants to reuse the parsed CDDL IVT, replace
 //! `validate_cbor_bytes(...)` with:
 //! ```
@@ -53,18 +57,127 @@
 //! // Perform the validation.
 //! validate_cbor(&rule_def, &cbor_value, &ctx).unwrap();
 //! ```
+//!
+//! # Enums
+//!
+//! `serde_cbor` serializes a unit enum variant as a text string containing
+//! the variant name.  Other variants are serialized as a map with a single
+//! key (the variant name), whose value is the variant's contents.  A schema
+//! for an enum can use a choice with one option per variant:
+//! ```
+//! use cddl_cat::validate_cbor_bytes;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! enum Shape {
+//!     Circle(u32),
+//!     Rect { width: u32, height: u32 },
+//!     Empty,
+//! }
+//!
+//! let cddl_input = r#"
+//!     shape = { "Circle": uint } / { "Rect": rect } / "Empty"
+//!     rect = { width: uint, height: uint }
+//! "#;
+//!
+//! let shapes = [
+//!     Shape::Circle(5),
+//!     Shape::Rect { width: 3, height: 4 },
+//!     Shape::Empty,
+//! ];
+//! for shape in &shapes {
+//!     let cbor_bytes = serde_cbor::to_vec(shape).unwrap();
+//!     validate_cbor_bytes("shape", cddl_input, &cbor_bytes).unwrap();
+//! }
+//! ```
+//!
+//! # Packed structs
+//!
+//! `serde_cbor`'s packed format ([`serde_cbor::ser::to_vec_packed`]) writes
+//! struct fields as map keys numbered from 0, in declaration order, instead
+//! of using the field names.  Enum variants are numbered the same way.  A
+//! schema for packed data can use integer member keys:
+//! ```
+//! use cddl_cat::validate_cbor_bytes;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Person {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let person = Person { name: "Bob".to_string(), age: 43 };
+//! // {0: "Bob", 1: 43}
+//! let cbor_bytes = serde_cbor::ser::to_vec_packed(&person).unwrap();
+//! validate_cbor_bytes("person", "person = { 0: tstr, 1: uint }", &cbor_bytes).unwrap();
+//! ```
+//! Giving each field number a name (e.g. `name = 0`) makes the schema
+//! easier to read; a rule that is a literal value can be used as a key,
+//! as in `person = { name => tstr, age => uint }`.
+//!
+//! # Tags
+//!
+//! Tags in the data are kept, as [`Value::Tag`], and by default a tagged
+//! value only matches tagged data in the schema (e.g. `#6.1(uint)`) or
+//! `any`.  To ignore some or all tags, set [`ValidationOptions::tags`] to a
+//! different [`TagPolicy`].
+//!
+//! [`Value::Tag`]: crate::value::Value::Tag
+//!
+//! [`ValidationOptions::tags`]: crate::context::ValidationOptions::tags
+//! [`TagPolicy`]: crate::context::TagPolicy
+//!
+//! # Untrusted data
+//!
+//! Validating CBOR data never panics because of the data: any bytes, valid
+//! CBOR or not, give either `Ok` or an error.  Data nested too deeply is
+//! rejected by the decoder, and recursion through the schema is limited by
+//! [`ValidationOptions::max_depth`].  `tests/no_panic.rs` checks this with
+//! generated data; please add a schema there when adding a feature that
+//! looks inside the data.
+//!
+//! [`ValidationOptions::max_depth`]: crate::context::ValidationOptions::max_depth
+//!
+//! # Null values
+//!
+//! `serde_cbor` serializes `()`, unit structs, and `Option::None` as CBOR
+//! `null`, which matches the CDDL type `nil`.  A CBOR `undefined` value is
+//! decoded by `serde_cbor` as `null` too, so it will also match `nil`.
+//!
+//! Note that a struct field containing `None` is still present in the
+//! serialized map (with a `null` value).  So a struct like this:
+//! ```
+//! # use serde::Serialize;
+//! #[derive(Serialize)]
+//! struct Person {
+//!     name: String,
+//!     nickname: Option<String>,
+//! }
+//! ```
+//! won't match `person = { name: tstr, ? nickname: tstr }` when `nickname`
+//! is `None`.  Either use `nickname: tstr / nil` in the schema, or add
+//! `#[serde(skip_serializing_if = "Option::is_none")]` to the field so that
+//! it's omitted from the map.
 
 #![cfg(feature = "serde_cbor")]
 
-use crate::context::{BasicContext, LookupContext};
-use crate::flatten::flatten_from_str;
+use crate::context::{BasicContext, LookupContext, TagPolicy, ValidationReport};
+use crate::flatten::{flatten_partial_from_str, flatten_type_expr};
 use crate::ivt::RuleDef;
-use crate::util::{ValidateError, ValidateResult};
-use crate::validate::do_validate;
+use crate::util::{
+    DecodeError, PathElement, Truncated, ValidateError, ValidateErrors, ValidateResult,
+};
+use crate::validate::{
+    do_validate, do_validate_collect, do_validate_report, do_validate_seq, do_validate_session,
+    remove_tags, Scratch,
+};
 use crate::value::Value;
 use serde_cbor::Value as CBOR_Value;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::ops::Range;
 
 // These conversions seem obvious and pointless, but over time they may
 // diverge.  However, CDDL and CBOR were designed to work with one another, so
@@ -75,6 +188,8 @@ impl TryFrom<&CBOR_Value> for Value {
 
     fn try_from(value: &CBOR_Value) -> Result<Self, Self::Error> {
         let result = match value {
+            // serde_cbor uses Null for (), unit structs, None, and CBOR
+            // undefined.
             CBOR_Value::Null => Value::Null,
             CBOR_Value::Bool(b) => Value::Bool(*b),
             CBOR_Value::Integer(i) => Value::Integer(*i),
@@ -97,6 +212,7 @@ impl TryFrom<&CBOR_Value> for Value {
                     .collect();
                 Value::Map(map?)
             }
+            CBOR_Value::Tag(tag, v) => Value::Tag(*tag, Box::new(Value::try_from(v.as_ref())?)),
             _ => {
                 // cbor::Value has a few hidden internal variants.  We should
                 // never see them, but return an error if we do.
@@ -110,11 +226,38 @@ impl TryFrom<&CBOR_Value> for Value {
 }
 
 // A variant that consumes the CBOR Value.
+// Strings, byte strings and containers are moved rather than copied.
 impl TryFrom<CBOR_Value> for Value {
     type Error = ValidateError;
 
     fn try_from(value: CBOR_Value) -> Result<Self, Self::Error> {
-        Value::try_from(&value)
+        let result = match value {
+            CBOR_Value::Null => Value::Null,
+            CBOR_Value::Bool(b) => Value::Bool(b),
+            CBOR_Value::Integer(i) => Value::Integer(i),
+            CBOR_Value::Float(f) => Value::from_float(f),
+            CBOR_Value::Bytes(b) => Value::Bytes(b),
+            CBOR_Value::Text(t) => Value::Text(t),
+            CBOR_Value::Array(a) => {
+                let array: Result<_, _> = a.into_iter().map(Value::try_from).collect();
+                Value::Array(array?)
+            }
+            CBOR_Value::Map(m) => {
+                type MapTree = BTreeMap<Value, Value>;
+                let map: Result<MapTree, ValidateError> = m
+                    .into_iter()
+                    .map(|(k, v)| Ok((Value::try_from(k)?, Value::try_from(v)?)))
+                    .collect();
+                Value::Map(map?)
+            }
+            CBOR_Value::Tag(tag, v) => Value::Tag(tag, Box::new(Value::try_from(*v)?)),
+            _ => {
+                return Err(ValidateError::ValueError(
+                    "can't handle hidden cbor Value".into(),
+                ));
+            }
+        };
+        Ok(result)
     }
 }
 
@@ -128,23 +271,584 @@ pub fn validate_cbor(
     do_validate(&value, rule_def, ctx)
 }
 
+/// Validate already-parsed CBOR data, and report what was found.
+///
+/// This is like [`validate_cbor`], but on success it also returns a
+/// [`ValidationReport`], e.g. listing the tags that were accepted by `any`,
+/// or where `any` matched.
+///
+/// ```
+/// use cddl_cat::{cbor::validate_cbor_report, context::BasicContext, flatten::flatten_from_str};
+/// use cddl_cat::context::Permissive;
+/// use cddl_cat::util::PathElement;
+///
+/// let ctx = BasicContext::new(flatten_from_str("thing = [uint, any]").unwrap());
+/// let rule_def = &ctx.rules["thing"];
+/// // [1, 1(0)]
+/// let cbor_value = serde_cbor::from_slice(b"\x82\x01\xc1\x00").unwrap();
+/// let report = validate_cbor_report(rule_def, &cbor_value, &ctx).unwrap();
+/// assert_eq!(report.any_tags.get(&1), Some(&1));
+/// assert_eq!(report.permissive[0].path, vec![PathElement::Index(1)]);
+/// assert_eq!(report.permissive[0].construct, Permissive::Any);
+/// ```
+pub fn validate_cbor_report(
+    rule_def: &RuleDef,
+    value: &CBOR_Value,
+    ctx: &dyn LookupContext,
+) -> Result<ValidationReport, ValidateError> {
+    let value = Value::try_from(value)?;
+    do_validate_report(&value, rule_def, ctx)
+}
+
+/// Validate an already-parsed CBOR value against a specified rule in a
+/// UTF-8 CDDL schema, consuming the value.
+///
+/// Unlike [`validate_cbor`], this doesn't need to copy the value's text
+/// strings, byte strings and containers, which matters for large values
+/// that the caller doesn't need afterwards.
+///
+/// ```
+/// use cddl_cat::cbor::validate_cbor_owned;
+/// use serde_cbor::Value;
+///
+/// let value = Value::Bytes(vec![0; 1000]);
+/// validate_cbor_owned("blob", "blob = bstr .size 1000", value).unwrap();
+/// ```
+pub fn validate_cbor_owned(name: &str, cddl: &str, value: CBOR_Value) -> ValidateResult {
+    let ctx = BasicContext::from_partial(flatten_partial_from_str(cddl)?);
+    let rule_def: &RuleDef = ctx.lookup_rule(name)?;
+    validate_cbor_owned_with(rule_def, value, &ctx)
+}
+
+/// Validate an already-parsed CBOR value against an already-parsed CDDL
+/// schema, consuming the value.
+///
+/// This is [`validate_cbor_owned`] for a schema that's used more than once,
+/// in the same way as [`validate_cbor`].
+///
+/// ```
+/// use cddl_cat::cbor::validate_cbor_owned_with;
+/// use cddl_cat::context::LookupContext;
+/// use cddl_cat::schema::Schema;
+/// use serde_cbor::Value;
+///
+/// let schema = Schema::from_str("blob = bstr .size 1000").unwrap();
+/// let rule_def = schema.lookup_rule("blob").unwrap();
+/// let value = Value::Bytes(vec![0; 1000]);
+/// validate_cbor_owned_with(rule_def, value, &schema).unwrap();
+/// ```
+pub fn validate_cbor_owned_with(
+    rule_def: &RuleDef,
+    value: CBOR_Value,
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
+    let value = Value::try_from(value)?;
+    do_validate(&value, rule_def, ctx)
+}
+
 /// Validate CBOR-encoded data against a specified rule in a UTF-8 CDDL schema.
+///
+/// Empty data fails with [`ValidateError::EmptyInput`], and data that ends
+/// in the middle of an item fails with [`ValidateError::Truncated`], which
+/// says how many bytes were needed.  The same goes for the other functions
+/// that take encoded CBOR.
+///
+/// ```
+/// use cddl_cat::validate_cbor_bytes;
+///
+/// let cddl_input = "person = { name: tstr }";
+/// let err = validate_cbor_bytes("person", cddl_input, b"").unwrap_err();
+/// assert_eq!(err.to_string(), "EmptyInput");
+/// // {"name": "Bob"}, missing its last two bytes
+/// let err = validate_cbor_bytes("person", cddl_input, b"\xa1\x64name\x63B").unwrap_err();
+/// assert_eq!(err.to_string(), "Truncated(need at least 10 bytes, found 8)");
+/// ```
 pub fn validate_cbor_bytes(name: &str, cddl: &str, cbor: &[u8]) -> ValidateResult {
+    validate_cbor_encoded(name, cddl, || Ok(cbor.into()))
+}
+
+/// Validate CBOR-encoded data, and report all the errors found rather than
+/// just the first.
+///
+/// Validation carries on past a wrong map value or array element, so each
+/// one is reported, and if no option of a choice matches, so is the reason
+/// each option doesn't.  The errors come in the order of the data they point
+/// at, and at most [`ValidationOptions::max_errors`] are kept.  A value
+/// that has the wrong type altogether is a single error; nothing inside it
+/// is checked.
+///
+/// ```
+/// use cddl_cat::validate_cbor_bytes_collect;
+///
+/// let cddl_input = "person = { name: tstr, age: uint }";
+/// // {"age": -1, "name": 7}
+/// let cbor_bytes = b"\xa2\x63age\x20\x64name\x07";
+/// let errs = validate_cbor_bytes_collect("person", cddl_input, cbor_bytes).unwrap_err();
+/// assert_eq!(
+///     errs.to_string(),
+///     "Mismatch(expected uint at [\"age\"])\nMismatch(expected tstr at [\"name\"])"
+/// );
+/// ```
+///
+/// [`ValidationOptions::max_errors`]: crate::context::ValidationOptions::max_errors
+pub fn validate_cbor_bytes_collect(
+    name: &str,
+    cddl: &str,
+    cbor: &[u8],
+) -> Result<(), ValidateErrors> {
+    let ctx = BasicContext::from_partial(flatten_partial_from_str(cddl).map_err(single_error)?);
+    let rule_def: &RuleDef = ctx.lookup_rule(name).map_err(single_error)?;
+    let value = decode_item(cbor)
+        .and_then(Value::try_from)
+        .map_err(single_error)?;
+
+    let mut found: Vec<ValidateError> = do_validate_collect(&value, rule_def, &ctx)
+        .into_iter()
+        .map(|e| locate_error(e, cbor, &value, &ctx))
+        .collect();
+    if found.is_empty() {
+        return Ok(());
+    }
+    // Entries of a map may be encoded in any order, so put the errors in
+    // the order of the bytes where that's known.
+    found.sort_by_key(|e| e.data_range().map_or(0, |range| range.start));
+    let mut errors = ValidateErrors::new(ctx.options().max_errors);
+    for e in found {
+        errors.push(e);
+    }
+    Err(errors)
+}
+
+fn single_error(e: ValidateError) -> ValidateErrors {
+    let mut errors = ValidateErrors::new(1);
+    errors.push(e);
+    errors
+}
+
+// Parse the CDDL text and find the requested rule, and only then decode
+// the data and validate it.
+fn validate_cbor_encoded<'a, F>(name: &str, cddl: &str, decode: F) -> ValidateResult
+where
+    F: FnOnce() -> Result<Cow<'a, [u8]>, ValidateError>,
+{
     // Parse the CDDL text and flatten it into IVT form.
-    let flat_cddl = flatten_from_str(cddl)?;
-    let ctx = BasicContext::new(flat_cddl);
+    let ctx = BasicContext::from_partial(flatten_partial_from_str(cddl)?);
 
     // Find the rule name that was requested
-    let rule_def: &RuleDef = ctx
-        .rules
-        .get(name)
-        .ok_or_else(|| ValidateError::MissingRule(name.into()))?;
+    let rule_def: &RuleDef = ctx.lookup_rule(name)?;
 
+    let cbor = decode()?;
+    validate_cbor_slice(rule_def, &cbor, &ctx)
+}
+
+/// Validate CBOR-encoded data against an already-parsed CDDL schema.
+///
+/// This is like [`validate_cbor`], but because it has the encoded data,
+/// errors inside a top-level array or map record where the element or
+/// entry is (see [`ValidateError::data_range`]).
+///
+/// ```
+/// use cddl_cat::{cbor::validate_cbor_slice, context::LookupContext, schema::Schema};
+///
+/// let schema = Schema::from_str("log = [* entry]  entry = uint").unwrap();
+/// let rule_def = schema.lookup_rule("log").unwrap();
+/// // [1, 1000, "x"]
+/// let cbor_bytes = b"\x83\x01\x19\x03\xe8\x61x";
+/// let err = validate_cbor_slice(rule_def, cbor_bytes, &schema).unwrap_err();
+/// assert_eq!(err.to_string(), "Mismatch(expected uint at [2])");
+/// assert_eq!(err.data_range(), Some(5..7));
+/// ```
+pub fn validate_cbor_slice(
+    rule_def: &RuleDef,
+    cbor: &[u8],
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
     // Deserialize the CBOR bytes
-    let cbor_value: CBOR_Value =
-        serde_cbor::from_slice(cbor).map_err(|e| ValidateError::ValueError(format!("{}", e)))?;
+    let cbor_value = decode_item(cbor)?;
 
     // Convert the CBOR tree into a Value tree for validation
     let value = Value::try_from(cbor_value)?;
-    do_validate(&value, rule_def, &ctx)
+    validate_cbor_value(rule_def, &value, cbor, ctx)
+}
+
+/// Validate CBOR data that has already been decoded into a [`Value`].
+///
+/// This is [`validate_cbor_slice`] for callers that need the decoded data
+/// as well, e.g. to show what an error points to.  `cbor` is the data
+/// `value` was decoded from, and errors record where they are in it in the
+/// same way.
+///
+/// ```
+/// use cddl_cat::{cbor::validate_cbor_value, context::LookupContext, schema::Schema};
+/// use cddl_cat::value::Value;
+/// use std::convert::TryFrom;
+///
+/// let schema = Schema::from_str("log = [* uint]").unwrap();
+/// let rule_def = schema.lookup_rule("log").unwrap();
+/// // [1, 1000, "x"]
+/// let cbor_bytes = b"\x83\x01\x19\x03\xe8\x61x";
+/// let cbor_value: serde_cbor::Value = serde_cbor::from_slice(cbor_bytes).unwrap();
+/// let value = Value::try_from(cbor_value).unwrap();
+/// let err = validate_cbor_value(rule_def, &value, cbor_bytes, &schema).unwrap_err();
+/// assert_eq!(err.data_range(), Some(5..7));
+/// ```
+pub fn validate_cbor_value(
+    rule_def: &RuleDef,
+    value: &Value,
+    cbor: &[u8],
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
+    do_validate(value, rule_def, ctx).map_err(|e| locate_error(e, cbor, value, ctx))
+}
+
+// Like `validate_cbor_slice`, for a `Session`.
+pub(crate) fn validate_slice_session(
+    rule_def: &RuleDef,
+    cbor: &[u8],
+    ctx: &dyn LookupContext,
+    scratch: &Scratch,
+) -> ValidateResult {
+    let value = Value::try_from(decode_item(cbor)?)?;
+    do_validate_session(&value, rule_def, ctx, scratch)
+        .map_err(|e| locate_error(e, cbor, &value, ctx))
+}
+
+// Decode a single CBOR item, explaining empty or truncated data rather than
+// passing on the decoder's error.
+fn decode_item(cbor: &[u8]) -> Result<CBOR_Value, ValidateError> {
+    if cbor.is_empty() {
+        return Err(ValidateError::EmptyInput);
+    }
+    serde_cbor::from_slice(cbor).map_err(|e| decode_failure(&e, cbor, 0))
+}
+
+// Convert a decoder error for the item starting at `pos`.  If the data ran
+// out, find how much of it the item needs.
+fn decode_failure(e: &serde_cbor::Error, cbor: &[u8], pos: usize) -> ValidateError {
+    if e.is_eof() {
+        if let Err(Skip::Short(needed)) = skip(cbor, pos) {
+            return ValidateError::Truncated(Truncated::new(needed, cbor.len()));
+        }
+    }
+    ValidateError::ValueError(format!("{}", e))
+}
+
+/// Validate hex-encoded CBOR data against a specified rule in a UTF-8 CDDL
+/// schema.
+///
+/// The hex string must not contain whitespace or a `0x` prefix.  Upper- and
+/// lower-case digits are both accepted.
+///
+/// ```
+/// use cddl_cat::validate_cbor_hex;
+///
+/// // {"name": "Bob"}
+/// let cbor_hex = "a1646e616d6563426f62";
+/// validate_cbor_hex("person", "person = { name: tstr }", cbor_hex).unwrap();
+///
+/// let err = validate_cbor_hex("person", "person = { name: tstr }", "a1z4").unwrap_err();
+/// assert_eq!(err.to_string(), "DecodeError(invalid hex character 'z' at offset 2)");
+/// ```
+pub fn validate_cbor_hex(name: &str, cddl: &str, cbor_hex: &str) -> ValidateResult {
+    validate_cbor_encoded(name, cddl, || decode_hex(cbor_hex).map(Cow::Owned))
+}
+
+fn decode_hex(cbor_hex: &str) -> Result<Vec<u8>, ValidateError> {
+    hex::decode(cbor_hex).map_err(|e| {
+        let err = match e {
+            hex::FromHexError::InvalidHexCharacter { c, index } => {
+                DecodeError::new(format!("invalid hex character {:?}", c), Some(index))
+            }
+            hex::FromHexError::OddLength => DecodeError::new("odd number of hex digits", None),
+            e => DecodeError::new(e.to_string(), None),
+        };
+        ValidateError::DecodeError(err)
+    })
+}
+
+/// Validate base64-encoded CBOR data against a specified rule in a UTF-8
+/// CDDL schema.
+///
+/// This uses the standard base64 alphabet (with `+` and `/`), with padding.
+///
+/// ```
+/// use cddl_cat::validate_cbor_base64;
+///
+/// // {"name": "Bob"}
+/// let cbor_base64 = "oWRuYW1lY0JvYg==";
+/// validate_cbor_base64("person", "person = { name: tstr }", cbor_base64).unwrap();
+/// ```
+pub fn validate_cbor_base64(name: &str, cddl: &str, cbor_base64: &str) -> ValidateResult {
+    validate_cbor_encoded(name, cddl, || decode_base64(cbor_base64).map(Cow::Owned))
+}
+
+fn decode_base64(cbor_base64: &str) -> Result<Vec<u8>, ValidateError> {
+    base64::decode(cbor_base64).map_err(|e| {
+        let err = match e {
+            base64::DecodeError::InvalidByte(offset, byte) => DecodeError::new(
+                format!("invalid base64 character {:?}", char::from(byte)),
+                Some(offset),
+            ),
+            base64::DecodeError::InvalidLastSymbol(offset, byte) => DecodeError::new(
+                format!("invalid base64 last character {:?}", char::from(byte)),
+                Some(offset),
+            ),
+            base64::DecodeError::InvalidLength => DecodeError::new("invalid base64 length", None),
+        };
+        ValidateError::DecodeError(err)
+    })
+}
+
+/// Validate a CBOR sequence against a specified rule in a UTF-8 CDDL schema.
+///
+/// A CBOR sequence ([RFC 8742]) is a concatenation of zero or more CBOR data
+/// items.  The items are validated as though they were the elements of an
+/// array, so a group rule with an occurrence can limit the number of items.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::cbor::validate_cbor_seq_bytes;
+///
+/// let cddl_input = "log = (1*3 entry) entry = uint";
+/// // The sequence 1, 2
+/// validate_cbor_seq_bytes("log", cddl_input, b"\x01\x02").unwrap();
+/// // An empty sequence
+/// let err = validate_cbor_seq_bytes("log", cddl_input, b"").unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "Mismatch(expected more array element [1*3 entry] (found 0, need 1 to 3) (sequence has 0 items))"
+/// );
+/// ```
+///
+/// [RFC 8742]: https://tools.ietf.org/html/rfc8742
+pub fn validate_cbor_seq_bytes(name: &str, cddl: &str, cbor: &[u8]) -> ValidateResult {
+    // Parse the CDDL text and flatten it into IVT form.
+    let ctx = BasicContext::from_partial(flatten_partial_from_str(cddl)?);
+
+    // Find the rule name that was requested
+    let rule_def: &RuleDef = ctx.lookup_rule(name)?;
+
+    // Deserialize each of the CBOR items, and convert them into Value trees
+    // for validation.  An empty sequence is allowed, but if the last item
+    // is cut short, say how much is missing.
+    let mut values = Vec::new();
+    for item in serde_cbor::Deserializer::from_slice(cbor).into_iter::<CBOR_Value>() {
+        let item = item.map_err(|e| {
+            let start = (0..values.len()).try_fold(0, |pos, _| skip_item(cbor, pos));
+            match start {
+                Some(start) => decode_failure(&e, cbor, start),
+                None => ValidateError::ValueError(format!("{}", e)),
+            }
+        })?;
+        values.push(Value::try_from(item)?);
+    }
+
+    do_validate_seq(&values, rule_def, &ctx).map_err(|e| match e.path().first() {
+        Some(PathElement::Index(i)) => match item_range(cbor, 0, *i) {
+            Some(range) => e.with_data_range(range),
+            None => e,
+        },
+        _ => e,
+    })
+}
+
+/// Validate CBOR-encoded data against a bare CDDL type expression.
+///
+/// The expression is the right-hand side of a type rule, so no `name = ...`
+/// wrapper is needed.  Because no other rules are available, any rule names
+/// used in the expression will fail to resolve; use
+/// [`validate_cbor_bytes_expr_with`] to supply them.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::cbor::validate_cbor_bytes_expr;
+///
+/// // The CBOR encoding of the integer 1000.
+/// let cbor_bytes = b"\x19\x03\xe8";
+/// validate_cbor_bytes_expr("uint .size 2", cbor_bytes).unwrap();
+/// validate_cbor_bytes_expr("uint .size 1", cbor_bytes).unwrap_err();
+/// ```
+pub fn validate_cbor_bytes_expr(expr: &str, cbor: &[u8]) -> ValidateResult {
+    let ctx = BasicContext::new(Default::default());
+    validate_cbor_bytes_expr_with(expr, cbor, &ctx)
+}
+
+/// Validate CBOR-encoded data against a bare CDDL type expression that may
+/// refer to the rules in `ctx`.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::cbor::validate_cbor_bytes_expr_with;
+/// use cddl_cat::schema::Schema;
+///
+/// let schema = Schema::from_str("age = 0..150").unwrap();
+/// // [43]
+/// let cbor_bytes = b"\x81\x18\x2b";
+/// validate_cbor_bytes_expr_with("[age]", cbor_bytes, &schema).unwrap();
+/// validate_cbor_bytes_expr_with("[name]", cbor_bytes, &schema).unwrap_err();
+/// ```
+pub fn validate_cbor_bytes_expr_with(
+    expr: &str,
+    cbor: &[u8],
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
+    // Parse the type expression and flatten it into IVT form.
+    let rule_def = flatten_type_expr(expr)?;
+    validate_cbor_slice(&rule_def, cbor, ctx)
+}
+
+// Record where an error is in the encoded data, if its path starts inside a
+// top-level array or map.
+//
+// The data has already been decoded successfully, so it's well-formed; this
+// only needs to find where each item ends.  It's done after validation
+// fails, so that decoding valid data costs nothing extra.
+fn locate_error(
+    err: ValidateError,
+    cbor: &[u8],
+    value: &Value,
+    ctx: &dyn LookupContext,
+) -> ValidateError {
+    let elem = match err.path().first() {
+        Some(elem) => elem.clone(),
+        None => return err,
+    };
+    let range = match (container(cbor), elem) {
+        (Some((4, start)), PathElement::Index(i)) => item_range(cbor, start, i),
+        (Some((5, start)), PathElement::Key(_)) => {
+            // The error says which entry it was by its position among the
+            // keys that were validated, which have had tags removed.
+            let tags = &ctx.options().tags;
+            let value = remove_tags(value, tags).ok();
+            let key = match (value.as_deref(), err.entry()) {
+                (Some(Value::Map(m)), Some(entry)) => m.keys().nth(entry),
+                _ => None,
+            };
+            key.and_then(|key| map_entry_range(cbor, start, key, tags))
+        }
+        _ => None,
+    };
+    match range {
+        Some(range) => err.with_data_range(range),
+        None => err,
+    }
+}
+
+// Find the major type of the top-level item, skipping any tags, and the
+// offset of its first element.
+fn container(cbor: &[u8]) -> Option<(u8, usize)> {
+    let mut pos = 0;
+    loop {
+        let (major, _, next) = head(cbor, pos).ok()?;
+        if major != 6 {
+            return Some((major, next));
+        }
+        pos = next;
+    }
+}
+
+// Find the range of the nth item, starting at `pos`.  This works for
+// elements of an array (definite or indefinite length), or for the items
+// of a CBOR sequence.
+fn item_range(cbor: &[u8], mut pos: usize, n: usize) -> Option<Range<usize>> {
+    for _ in 0..n {
+        pos = skip_item(cbor, pos)?;
+    }
+    Some(pos..skip_item(cbor, pos)?)
+}
+
+// Find the byte range of the entry with the given key in a map whose
+// contents start at `pos`.  Each key is decoded and compared with tags
+// removed, as validation saw it.
+fn map_entry_range(
+    cbor: &[u8],
+    mut pos: usize,
+    key: &Value,
+    tags: &TagPolicy,
+) -> Option<Range<usize>> {
+    while pos < cbor.len() && cbor[pos] != BREAK {
+        let key_end = skip_item(cbor, pos)?;
+        let entry_end = skip_item(cbor, key_end)?;
+        let entry_key: CBOR_Value = serde_cbor::from_slice(&cbor[pos..key_end]).ok()?;
+        let entry_key = Value::try_from(entry_key).ok()?;
+        if *remove_tags(&entry_key, tags).ok()? == *key {
+            return Some(pos..entry_end);
+        }
+        pos = entry_end;
+    }
+    None
+}
+
+const BREAK: u8 = 0xff;
+
+// Why an item couldn't be skipped.
+#[derive(Debug, PartialEq)]
+enum Skip {
+    // The data ended early; the item needs at least this many bytes from
+    // the start of the data.
+    Short(usize),
+    // The data isn't well-formed.
+    Malformed,
+}
+
+// Decode the head of a CBOR item, returning the major type, the argument
+// (`None` for indefinite length), and the offset just past the head.
+fn head(cbor: &[u8], pos: usize) -> Result<(u8, Option<u64>, usize), Skip> {
+    let initial = *cbor.get(pos).ok_or(Skip::Short(pos + 1))?;
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+    let len = match info {
+        0..=23 => return Ok((major, Some(u64::from(info)), pos + 1)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        31 => return Ok((major, None, pos + 1)),
+        _ => return Err(Skip::Malformed),
+    };
+    let end = pos + 1 + len;
+    let bytes = cbor.get(pos + 1..end).ok_or(Skip::Short(end))?;
+    let arg = bytes.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b));
+    Ok((major, Some(arg), end))
+}
+
+// Return the offset just past the item starting at `pos`.
+fn skip_item(cbor: &[u8], pos: usize) -> Option<usize> {
+    skip(cbor, pos).ok()
+}
+
+fn skip(cbor: &[u8], pos: usize) -> Result<usize, Skip> {
+    let (major, arg, mut pos) = head(cbor, pos)?;
+    match (major, arg) {
+        // Integers, floats and simple values are all in the head.
+        (0, _) | (1, _) | (7, Some(_)) => Ok(pos),
+        (2, Some(len)) | (3, Some(len)) => {
+            let len = usize::try_from(len).map_err(|_| Skip::Malformed)?;
+            let end = pos.checked_add(len).ok_or(Skip::Malformed)?;
+            if end > cbor.len() {
+                return Err(Skip::Short(end));
+            }
+            Ok(end)
+        }
+        (4, Some(n)) | (5, Some(n)) => {
+            let items = if major == 5 {
+                n.checked_mul(2).ok_or(Skip::Malformed)?
+            } else {
+                n
+            };
+            for _ in 0..items {
+                pos = skip(cbor, pos)?;
+            }
+            Ok(pos)
+        }
+        (6, Some(_)) => skip(cbor, pos),
+        // Indefinite-length strings (in chunks), arrays and maps.
+        (2, None) | (3, None) | (4, None) | (5, None) => {
+            while *cbor.get(pos).ok_or(Skip::Short(pos + 1))? != BREAK {
+                pos = skip(cbor, pos)?;
+            }
+            Ok(pos + 1)
+        }
+        _ => Err(Skip::Malformed),
+    }
 }
@@ -5,42 +5,363 @@
 //! `LookupContext` to perform the name resolution.
 //!
 
+use crate::ast::Occur;
+use crate::flatten::FlattenErrors;
 use crate::ivt::{RuleDef, RulesByName};
-use crate::util::ValidateError;
+use crate::util::{missing_rule, missing_rule_suggest, PathElement, ValidateError};
+use std::collections::BTreeMap;
 
 // The Node reference lives as long as the LookupContext does.
 type LookupResult<'a> = Result<&'a RuleDef, ValidateError>;
 
+/// Options that control validation behavior.
+///
+/// Construct this with `ValidationOptions::default()` and then change the
+/// fields you care about.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationOptions {
+    /// The maximum number of errors kept when collecting multiple errors.
+    ///
+    /// Any further errors are counted but discarded. The default is 100.
+    pub max_errors: usize,
+    /// The maximum length (in bytes) of data rendered into an error message.
+    ///
+    /// Longer data is truncated, and the truncation is indicated in the
+    /// message.  Long parts of the schema (e.g. literals) are limited to
+    /// the same length, by eliding their middle. The default is 64.
+    pub max_value_len: usize,
+    /// The maximum number of nested rule references followed while
+    /// validating.
+    ///
+    /// Recursive rules like `expr = int / [op, expr, expr]` are allowed;
+    /// this limits how deeply the data may nest through them, and stops
+    /// rules that refer to each other without consuming any data
+    /// (`a = b  b = a`). Each rule reference uses some stack, so a very
+    /// large limit may overflow the stack instead. Reaching the limit fails
+    /// with [`ValidateError::RecursionLimit`]. The default is 50.
+    pub max_depth: usize,
+    /// What to do with tagged values in the data.
+    ///
+    /// The default is [`TagPolicy::Strict`].
+    pub tags: TagPolicy,
+    /// The tag numbers allowed in data matched by `any`.
+    ///
+    /// If set, a tagged value anywhere inside data matched by `any` must
+    /// use one of these tags, or it doesn't match. Tags removed by
+    /// [`tags`](ValidationOptions::tags) are not checked. The default is
+    /// `None`, which allows all tags.
+    pub any_tags: Option<Vec<u64>>,
+    /// Treat every map member as non-cut, whether it's written with `:`,
+    /// `^ =>`, or `=>`.
+    ///
+    /// This is a compatibility shim, for comparing results with validators
+    /// that don't implement cuts; it doesn't follow RFC 8610.  With cuts
+    /// ignored, an entry whose key matches a member but whose value doesn't
+    /// may still be matched by a later member, e.g. `{ "a": "x" }` matches
+    /// `{ ? a: int, * tstr => tstr }`.  The default is `false`.
+    pub ignore_cuts: bool,
+    /// Avoid heap allocations while validating, at the cost of validating
+    /// twice when the data doesn't match.
+    ///
+    /// Normally, every alternative that's tried and doesn't match (e.g. the
+    /// first option of `int / tstr`, or an optional map key that's absent)
+    /// builds an error describing why, in case it's the one that gets
+    /// reported.  In lean mode those errors are left blank and nothing
+    /// extra is allocated; if the data turns out not to match, it's
+    /// validated again in the normal way to find the error to return.
+    ///
+    /// Data that matches is then validated without allocating beyond what
+    /// decoding the data needs, with these exceptions:
+    /// - matching a map (or map choice) with more than 64 entries, unless a
+    ///   [`Session`] has already matched one, and looking up a literal key
+    ///   (e.g. `name: tstr`) in such a map,
+    /// - an array that matching each member as far as it goes gets wrong
+    ///   (e.g. `[* int, int]`), which is matched again in every way it
+    ///   could be,
+    /// - data matched by the `.cbor` control operator, which is decoded,
+    /// - tags removed by [`tags`](ValidationOptions::tags), which need a
+    ///   copy of the data,
+    /// - reports (e.g. [`validate_cbor_report`]) and map visitors, which
+    ///   don't use lean mode.
+    ///
+    /// `tests/alloc.rs` checks this with a counting allocator: validating
+    /// a three-message CBOR log in lean mode makes no allocations beyond
+    /// those that decoding it makes.  The default is `false`.
+    ///
+    /// A [`Session`] always validates in lean mode.
+    ///
+    /// [`validate_cbor_report`]: crate::cbor::validate_cbor_report
+    /// [`Session`]: crate::session::Session
+    pub lean: bool,
+}
+
+impl ValidationOptions {
+    const DEFAULT: ValidationOptions = ValidationOptions {
+        max_errors: 100,
+        max_value_len: 64,
+        max_depth: 50,
+        tags: TagPolicy::Strict,
+        any_tags: None,
+        ignore_cuts: false,
+        lean: false,
+    };
+}
+
+/// Information gathered during a successful validation.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The tags found inside data matched by `any`, with the number of
+    /// times each one appeared.
+    ///
+    /// Tags removed by [`ValidationOptions::tags`] are not included.
+    pub any_tags: BTreeMap<u64, usize>,
+    /// The places where data was accepted by a permissive part of the
+    /// schema, in the order they were matched.
+    ///
+    /// This is meant for reviewing how much a schema really checks: it
+    /// lists the data matched by `any`, and the map entries matched by a
+    /// member with a non-literal key (e.g. `* tstr => int`).  If both
+    /// apply to an entry, as in `* tstr => any`, it is listed twice.
+    pub permissive: Vec<PermissiveMatch>,
+    /// The number of times each occurrence (e.g. `? age: int` or `* tstr`)
+    /// matched, in the order they were matched.
+    ///
+    /// An occurrence is listed each time it's matched against a map or
+    /// array, so one inside an array of maps is listed once per map.
+    pub occurrences: Vec<OccurrenceMatch>,
+}
+
+/// The number of times an occurrence matched.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OccurrenceMatch {
+    /// The path to the map or array containing the matches, outermost
+    /// first.
+    pub path: Vec<PathElement>,
+    /// The name of the member's place in the schema, e.g. `thing.age`, or
+    /// `thing.tags[]` for an element of `tags`.
+    ///
+    /// See [`Map::name`](crate::ivt::Map::name).  This is `None` if the
+    /// schema has no names, e.g. if it was built from an anonymous type.
+    pub name: Option<String>,
+    /// A text rendering of the member the occurrence applies to, e.g.
+    /// `"age": Int`.
+    pub member: String,
+    /// The number of matches allowed.
+    pub occur: Occur,
+    /// The occurrence as written in the schema, e.g. `*` or `2*4`.
+    ///
+    /// This can differ from `occur` where the schema allows more than can
+    /// ever match, e.g. `*` on a literal map key, which matches at most
+    /// once.
+    pub written: String,
+    /// The number of matches found.
+    pub count: u64,
+}
+
+/// Some data that was accepted by a permissive part of the schema.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissiveMatch {
+    /// The path from the top of the data, outermost first.
+    ///
+    /// For a map entry, the path ends with the entry's key.
+    pub path: Vec<PathElement>,
+    /// The name of the place in the schema that accepted the data, e.g.
+    /// `thing.extra` or `thing.*` (see
+    /// [`Map::name`](crate::ivt::Map::name)).
+    pub name: Option<String>,
+    /// The part of the schema that accepted the data.
+    pub construct: Permissive,
+}
+
+/// A permissive part of a schema.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Permissive {
+    /// The `any` type.
+    Any,
+    /// A map member with a key that isn't a literal value, e.g.
+    /// `* tstr => int`.
+    ///
+    /// This is a text rendering of the member, e.g. `Tstr: Int`.
+    Wildcard(String),
+}
+
+/// How to handle tagged values (CBOR major type 6) during validation.
+///
+/// CDDL schemas often specify a type like `tstr` where real-world data
+/// contains a tagged value, e.g. tag 0 (a date/time string).
+///
+/// The policy applies everywhere in the data: to the top-level value, to
+/// array elements, and to map keys and values.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagPolicy {
+    /// A tagged value only matches tagged data (e.g. `#6.32(tstr)`) or
+    /// `any`.
+    Strict,
+    /// Ignore all tags, and validate the tagged content instead.
+    Unwrap,
+    /// Ignore only the listed tag numbers; other tags are handled as in
+    /// `Strict`.
+    Allow(Vec<u64>),
+}
+
+impl TagPolicy {
+    /// Returns `true` if this tag number should be ignored.
+    pub(crate) fn ignores(&self, tag: u64) -> bool {
+        match self {
+            TagPolicy::Strict => false,
+            TagPolicy::Unwrap => true,
+            TagPolicy::Allow(tags) => tags.contains(&tag),
+        }
+    }
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions::DEFAULT
+    }
+}
+
 /// A LookupContext contains any external information required for validation.
 ///
-/// Right now, that only includes a function that understands how to resolve
-/// a name reference to an [`ivt::Rule`].
+/// This includes a function that understands how to resolve a name
+/// reference to an [`ivt::Rule`], and the [`ValidationOptions`] to use.
 ///
 /// [`ivt::Rule`]: crate::ivt::Rule
-
 pub trait LookupContext {
     /// Lookup a rule by name.
     fn lookup_rule<'a>(&'a self, name: &str) -> LookupResult<'a>;
+
+    /// The options to use during validation.
+    fn options(&self) -> &ValidationOptions {
+        &ValidationOptions::DEFAULT
+    }
+
+    /// The CDDL text of a rule, if it's known.
+    ///
+    /// This is attached to errors, so they can show the rule that the data
+    /// failed to match; see [`ValidateError::rule_source`].
+    fn rule_source(&self, _name: &str) -> Option<&str> {
+        None
+    }
 }
 
 /// A simple context that owns a set of rules and can lookup rules by name.
 #[allow(missing_docs)]
 pub struct BasicContext {
     pub rules: RulesByName,
+    pub options: ValidationOptions,
+    /// Rules that couldn't be flattened; looking one up returns its error.
+    pub flatten_errors: FlattenErrors,
 }
 
 impl BasicContext {
     /// Create a new BasicContext from a rules map.
     pub fn new(rules: RulesByName) -> BasicContext {
-        BasicContext { rules }
+        BasicContext {
+            rules,
+            options: ValidationOptions::default(),
+            flatten_errors: FlattenErrors::new(),
+        }
+    }
+
+    /// Create a new BasicContext from the result of [`flatten_partial`].
+    ///
+    /// [`flatten_partial`]: crate::flatten::flatten_partial
+    pub fn from_partial((rules, flatten_errors): (RulesByName, FlattenErrors)) -> BasicContext {
+        BasicContext {
+            flatten_errors,
+            ..BasicContext::new(rules)
+        }
     }
 }
 
 impl LookupContext for BasicContext {
     fn lookup_rule<'a>(&'a self, name: &str) -> LookupResult<'a> {
-        match self.rules.get(name) {
-            Some(rule_def) => Ok(rule_def),
-            None => Err(ValidateError::MissingRule(name.into())),
+        lookup_partial(&self.rules, &self.flatten_errors, name)
+    }
+
+    fn options(&self) -> &ValidationOptions {
+        &self.options
+    }
+}
+
+/// A context that replaces one rule of another context.
+///
+/// Looking up the replaced rule returns the new definition, wherever the
+/// reference to it is; every other lookup, and the options, are passed to
+/// the base context.  The base context isn't changed, so this can be used
+/// to try a change to one rule without copying the others.
+///
+/// See [`Schema::with_override`] for a way to make one from CDDL text.
+///
+/// [`Schema::with_override`]: crate::schema::Schema::with_override
+pub struct OverrideContext<'a> {
+    base: &'a dyn LookupContext,
+    name: String,
+    rule_def: RuleDef,
+    source: Option<String>,
+}
+
+impl<'a> OverrideContext<'a> {
+    /// Create a context that looks up `rule_def` for the rule `name`, and
+    /// everything else in `base`.
+    pub fn new(base: &'a dyn LookupContext, name: &str, rule_def: RuleDef) -> OverrideContext<'a> {
+        OverrideContext {
+            base,
+            name: name.to_string(),
+            rule_def,
+            source: None,
+        }
+    }
+
+    /// Set the CDDL text of the new definition, to be attached to errors.
+    pub fn with_source(mut self, source: String) -> OverrideContext<'a> {
+        self.source = Some(source);
+        self
+    }
+}
+
+impl LookupContext for OverrideContext<'_> {
+    fn lookup_rule<'a>(&'a self, name: &str) -> LookupResult<'a> {
+        if name == self.name {
+            Ok(&self.rule_def)
+        } else {
+            self.base.lookup_rule(name)
+        }
+    }
+
+    fn options(&self) -> &ValidationOptions {
+        self.base.options()
+    }
+
+    fn rule_source(&self, name: &str) -> Option<&str> {
+        if name == self.name {
+            self.source.as_deref()
+        } else {
+            self.base.rule_source(name)
+        }
+    }
+}
+
+// Lookup a rule, returning the stored error for a rule that couldn't be
+// flattened.
+pub(crate) fn lookup_partial<'a>(
+    rules: &'a RulesByName,
+    flatten_errors: &FlattenErrors,
+    name: &str,
+) -> LookupResult<'a> {
+    match (rules.get(name), flatten_errors.get(name)) {
+        (Some(rule_def), _) => Ok(rule_def),
+        (None, Some(err)) => Err(err.clone()),
+        (None, None) => {
+            let known = rules.keys().chain(flatten_errors.keys());
+            Err(missing_rule_suggest(name, known))
         }
     }
 }
@@ -55,7 +376,7 @@ pub mod tests {
 
     impl LookupContext for DummyContext {
         fn lookup_rule<'a>(&'a self, name: &str) -> LookupResult<'a> {
-            Err(ValidateError::MissingRule(name.into()))
+            Err(missing_rule(name))
         }
     }
 }
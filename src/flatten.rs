@@ -7,17 +7,46 @@
 //! [`ivt`]: crate::ivt
 //! [`ast`]: crate::ast
 
+#[cfg(feature = "regex")]
 use regex::RegexBuilder;
 
 use crate::ast;
 use crate::ivt::*;
-use crate::parser::{parse_cddl, slice_parse_cddl};
-use crate::util::ValidateError;
+use crate::parser::{parse_cddl, parse_type_expr, slice_parse_cddl};
+use crate::util::{SchemaTooLarge, ValidateError};
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryInto;
 
 /// The result of a flatten operation.
 pub type FlattenResult<T> = std::result::Result<T, ValidateError>;
 
+/// The rules that couldn't be flattened, with the error for each.
+pub type FlattenErrors = BTreeMap<String, ValidateError>;
+
+/// Options that control flattening.
+///
+/// Construct this with `FlattenOptions::default()` and then change the
+/// fields you care about.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlattenOptions {
+    /// The maximum number of nodes any one rule may expand to.
+    ///
+    /// Unwrapping a rule or using it as a generic argument copies it during
+    /// validation, so a small schema can describe a huge tree.  Rules that
+    /// exceed this limit are rejected with [`ValidateError::SchemaTooLarge`].
+    /// The default is 1,000,000.
+    pub max_nodes: usize,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        FlattenOptions {
+            max_nodes: 1_000_000,
+        }
+    }
+}
+
 /// Convert a CDDL schema in UTF-8 form into a structured rule set.
 pub fn flatten_from_str(cddl_input: &str) -> FlattenResult<RulesByName> {
     let cddl = parse_cddl(cddl_input)?;
@@ -33,10 +62,77 @@ pub fn slice_flatten_from_str(cddl_input: &str) -> FlattenResult<RulesWithString
     slice_flatten(&cddl)
 }
 
+/// Convert a CDDL schema in UTF-8 form into a structured rule set, setting
+/// aside any rules that can't be flattened.
+///
+/// See [`flatten_partial`] for details.
+pub fn flatten_partial_from_str(cddl_input: &str) -> FlattenResult<(RulesByName, FlattenErrors)> {
+    let cddl = parse_cddl(cddl_input)?;
+    flatten_partial(&cddl)
+}
+
+/// Convert a bare CDDL type expression into an anonymous rule definition.
+///
+/// The expression is the right-hand side of a type rule, e.g.
+/// `{ name: tstr, * tstr => any }`.  Any rule names used in the expression
+/// are left as references, to be resolved by the [`LookupContext`] that
+/// is used during validation.
+///
+/// [`LookupContext`]: crate::context::LookupContext
+pub fn flatten_type_expr(expr: &str) -> FlattenResult<RuleDef> {
+    let ty = parse_type_expr(expr)?;
+    Ok(RuleDef {
+        generic_parms: Vec::new(),
+        node: flatten_type(&ty)?,
+    })
+}
+
 /// Convert an already-parsed cddl AST into a `(name, rules)` map.
 pub fn flatten(cddl: &ast::Cddl) -> FlattenResult<RulesByName> {
+    flatten_with_options(cddl, &FlattenOptions::default())
+}
+
+/// Convert an already-parsed cddl AST into a `(name, rules)` map, with
+/// the given options.
+pub fn flatten_with_options(
+    cddl: &ast::Cddl,
+    options: &FlattenOptions,
+) -> FlattenResult<RulesByName> {
     // This first pass generates a tree of Nodes from the AST.
-    cddl.rules.iter().map(flatten_rule).collect()
+    let mut rules = RulesByName::new();
+    for rule in &cddl.rules {
+        let (_, flat) = flatten_rule(rule)?;
+        add_rule(&mut rules, rule, flat, extend_rule)?;
+    }
+    let by_name = rules_ref_map(&rules);
+    check_generics(&by_name)?;
+    check_size(by_name, options)?;
+    Ok(rules)
+}
+
+/// Convert an already-parsed cddl AST into a `(name, rules)` map, setting
+/// aside any rules that can't be flattened.
+///
+/// A rule that uses an unsupported construct doesn't stop the rest of the
+/// schema from being flattened: its error is returned alongside the rules
+/// that worked, so a [`LookupContext`] can return that error only if
+/// validation reaches the broken rule.  This allows validating against a
+/// large schema when some corners of it aren't supported.
+///
+/// The CDDL text must still parse, and the size limit still applies to the
+/// rules that were flattened.
+///
+/// [`LookupContext`]: crate::context::LookupContext
+pub fn flatten_partial(cddl: &ast::Cddl) -> FlattenResult<(RulesByName, FlattenErrors)> {
+    let mut rules = RulesByName::new();
+    let mut errors = FlattenErrors::new();
+    for rule in &cddl.rules {
+        let result = flatten_rule(rule).map(|(_, flat)| flat);
+        add_partial(&mut rules, &mut errors, rule, result, extend_rule);
+    }
+    set_aside_generic_mismatches(&mut rules, &mut errors, |rule_def| rule_def);
+    check_size(rules_ref_map(&rules), &FlattenOptions::default())?;
+    Ok((rules, errors))
 }
 
 /// Convert an already-parsed cddl AST into a `(name, (rule, rule-string))` map.
@@ -45,26 +141,387 @@ pub fn flatten(cddl: &ast::Cddl) -> FlattenResult<RulesByName> {
 /// CDDL text alongside the IVT.
 pub fn slice_flatten(cddl: &ast::CddlSlice) -> FlattenResult<RulesWithStrings> {
     // This first pass generates a tree of Nodes from the AST.
-    cddl.rules
+    let mut rules = RulesWithStrings::new();
+    for (rule, s) in &cddl.rules {
+        let (_, flat) = flatten_rule(rule)?;
+        // key = name, value = (Node, copy of cddl text slice)
+        add_rule(&mut rules, rule, (flat, s.clone()), extend_rule_source)?;
+    }
+    let by_name = rules.iter().map(|(k, (v, _))| (k.as_str(), v)).collect();
+    check_generics(&by_name)?;
+    check_size(by_name, &FlattenOptions::default())?;
+    Ok(rules)
+}
+
+/// Convert a CDDL fragment into a `(name, (rule, rule-string))` map of
+/// rules to add to an existing rule set.
+///
+/// This works the same as `slice_flatten`, except that an extension of a
+/// rule the fragment doesn't define first (e.g. `$socket /= int`) is
+/// combined with that rule's definition from `existing`, if there is one.
+/// The names of the rules extended this way are returned too.  The size
+/// limit isn't checked here, since it depends on the rules the fragment is
+/// added to, and neither are generic arguments.
+pub(crate) fn slice_flatten_onto<F>(
+    cddl: &ast::CddlSlice,
+    existing: F,
+) -> FlattenResult<(RulesWithStrings, BTreeSet<String>)>
+where
+    F: Fn(&str) -> Option<(RuleDef, String)>,
+{
+    let mut rules = RulesWithStrings::new();
+    let mut extended = BTreeSet::new();
+    for (rule, s) in &cddl.rules {
+        let (_, flat) = flatten_rule(rule)?;
+        if is_extension(rule) && !rules.contains_key(&rule.name) {
+            if let Some(base) = existing(&rule.name) {
+                rules.insert(rule.name.clone(), base);
+                extended.insert(rule.name.clone());
+            }
+        }
+        add_rule(&mut rules, rule, (flat, s.clone()), extend_rule_source)?;
+    }
+    Ok((rules, extended))
+}
+
+/// Convert an already-parsed cddl AST into a `(name, (rule, rule-string))`
+/// map, setting aside any rules that can't be flattened.
+///
+/// This works the same as `flatten_partial`, but preserves a copy of the
+/// original CDDL text alongside the IVT.
+pub fn slice_flatten_partial(
+    cddl: &ast::CddlSlice,
+) -> FlattenResult<(RulesWithStrings, FlattenErrors)> {
+    let mut rules = RulesWithStrings::new();
+    let mut errors = FlattenErrors::new();
+    for (rule, s) in &cddl.rules {
+        let result = flatten_rule(rule).map(|(_, flat)| (flat, s.clone()));
+        add_partial(&mut rules, &mut errors, rule, result, extend_rule_source);
+    }
+    set_aside_generic_mismatches(&mut rules, &mut errors, |(rule_def, _)| rule_def);
+    let by_name = rules.iter().map(|(k, (v, _))| (k.as_str(), v)).collect();
+    check_size(by_name, &FlattenOptions::default())?;
+    Ok((rules, errors))
+}
+
+// Combines the definition of a rule so far with an extension of it.
+type Extend<T> = fn(&ast::Rule, T, T) -> FlattenResult<T>;
+
+// Record one flattened rule.  A rule that extends another (e.g.
+// `$socket /= int`) is combined with the definition so far, if there is
+// one; any other rule replaces an earlier definition of the same name.
+fn add_rule<T>(
+    rules: &mut BTreeMap<String, T>,
+    rule: &ast::Rule,
+    flat: T,
+    extend: Extend<T>,
+) -> FlattenResult<()> {
+    let flat = match rules.remove(&rule.name) {
+        Some(base) if is_extension(rule) => extend(rule, base, flat)?,
+        _ => flat,
+    };
+    rules.insert(rule.name.clone(), flat);
+    Ok(())
+}
+
+// Record one flattened rule, or its error.  A later definition of the same
+// name replaces an earlier one, whether or not either of them worked.  An
+// extension of a rule that didn't work is ignored, so the rule keeps its
+// first error.
+fn add_partial<T>(
+    rules: &mut BTreeMap<String, T>,
+    errors: &mut FlattenErrors,
+    rule: &ast::Rule,
+    result: FlattenResult<T>,
+    extend: Extend<T>,
+) {
+    let name = rule.name.as_str();
+    let result = if !is_extension(rule) {
+        result
+    } else if errors.contains_key(name) {
+        return;
+    } else {
+        match (rules.remove(name), result) {
+            (Some(base), Ok(flat)) => extend(rule, base, flat),
+            (_, result) => result,
+        }
+    };
+    match result {
+        Ok(flat) => {
+            errors.remove(name);
+            rules.insert(name.to_string(), flat);
+        }
+        Err(e) => {
+            rules.remove(name);
+            errors.insert(name.to_string(), e);
+        }
+    }
+}
+
+pub(crate) fn rules_ref_map(rules: &RulesByName) -> BTreeMap<&str, &RuleDef> {
+    rules.iter().map(|(k, v)| (k.as_str(), v)).collect()
+}
+
+/// Check that each rule reference has as many generic arguments as the
+/// rule has generic parameters, as the IVT builder does.
+pub(crate) fn check_generics(rules: &BTreeMap<&str, &RuleDef>) -> FlattenResult<()> {
+    if generic_mismatches(rules).is_empty() {
+        Ok(())
+    } else {
+        Err(ValidateError::GenericError)
+    }
+}
+
+// Set aside the rules that fail `check_generics`, for partial flattening.
+fn set_aside_generic_mismatches<T>(
+    rules: &mut BTreeMap<String, T>,
+    errors: &mut FlattenErrors,
+    rule_def: fn(&T) -> &RuleDef,
+) {
+    let by_name = rules
+        .iter()
+        .map(|(k, v)| (k.as_str(), rule_def(v)))
+        .collect();
+    for name in generic_mismatches(&by_name) {
+        rules.remove(&name);
+        errors.insert(name, ValidateError::GenericError);
+    }
+}
+
+// The names of the rules containing a reference with the wrong number of
+// generic arguments.  A generic parameter takes none, and references to
+// rules that aren't defined aren't checked here.
+fn generic_mismatches(rules: &BTreeMap<&str, &RuleDef>) -> Vec<String> {
+    fn arity_ok(node: &Node, rules: &BTreeMap<&str, &RuleDef>, parms: &[String]) -> bool {
+        if let Node::Rule(r) | Node::Unwrap(r) | Node::Choiceify(r) = node {
+            let expected = if parms.contains(&r.name) {
+                Some(0)
+            } else {
+                rules.get(r.name.as_str()).map(|d| d.generic_parms.len())
+            };
+            if expected.map_or(false, |n| n != r.generic_args.len()) {
+                return false;
+            }
+        }
+        node.children()
+            .into_iter()
+            .all(|child| arity_ok(child, rules, parms))
+    }
+
+    rules
         .iter()
-        .map(|(rule, s)| {
-            let (name, flat) = flatten_rule(rule)?;
-            // key = name, value = (Node, copy of cddl text slice)
-            Ok((name, (flat, s.clone())))
-        })
+        .filter(|(_, rule_def)| !arity_ok(&rule_def.node, rules, &rule_def.generic_parms))
+        .map(|(name, _)| name.to_string())
         .collect()
 }
 
+/// Check that no rule expands to more than `options.max_nodes` nodes.
+pub(crate) fn check_size(
+    rules: BTreeMap<&str, &RuleDef>,
+    options: &FlattenOptions,
+) -> FlattenResult<()> {
+    let mut counter = SizeCounter {
+        rules: &rules,
+        limit: options.max_nodes,
+        sizes: BTreeMap::new(),
+        stack: Vec::new(),
+    };
+    for (name, rule_def) in &rules {
+        let parm_sizes = vec![1; rule_def.generic_parms.len()];
+        counter.rule_size(name, &parm_sizes).map_err(|rule| {
+            ValidateError::SchemaTooLarge(SchemaTooLarge::new(rule, counter.limit))
+        })?;
+    }
+    Ok(())
+}
+
+// Counts the nodes each rule expands to, as validation would see them:
+// a rule reference counts the rule's whole tree, and a generic parameter
+// counts the tree of the argument it stands for.
+//
+// Sizes of rules without generic parameters are cached, so a long chain of
+// rules is counted in linear time.  Generic rules are counted once per use,
+// but counting stops as soon as any rule passes the limit.
+struct SizeCounter<'a> {
+    rules: &'a BTreeMap<&'a str, &'a RuleDef>,
+    limit: usize,
+    sizes: BTreeMap<&'a str, usize>,
+    // The rules currently being counted; a recursive reference to one of
+    // these counts as a single node, since its size is limited by the data.
+    stack: Vec<&'a str>,
+}
+
+// On failure, the name of the rule that passed the limit.
+type SizeResult = Result<usize, String>;
+
+impl<'a> SizeCounter<'a> {
+    fn rule_size(&mut self, name: &'a str, parm_sizes: &[usize]) -> SizeResult {
+        if let Some(size) = self.sizes.get(name) {
+            return Ok(*size);
+        }
+        let rule_def = self.rules[name];
+        let parms: BTreeMap<&str, usize> = rule_def
+            .generic_parms
+            .iter()
+            .map(String::as_str)
+            .zip(parm_sizes.iter().cloned())
+            .collect();
+
+        self.stack.push(name);
+        let size = self.node_size(&rule_def.node, &parms, name);
+        self.stack.pop();
+        let size = size?;
+
+        if rule_def.generic_parms.is_empty() {
+            self.sizes.insert(name, size);
+        }
+        Ok(size)
+    }
+
+    fn node_size(
+        &mut self,
+        node: &'a Node,
+        parms: &BTreeMap<&str, usize>,
+        current: &str,
+    ) -> SizeResult {
+        let limit = self.limit;
+        let mut size: usize = 1;
+        let add = |size: &mut usize, more: usize| {
+            *size = size.saturating_add(more);
+            if *size > limit {
+                Err(current.to_string())
+            } else {
+                Ok(())
+            }
+        };
+
+        match node {
+            Node::Rule(r) | Node::Unwrap(r) | Node::Choiceify(r) => {
+                if let Some(parm_size) = parms.get(r.name.as_str()) {
+                    return Ok(*parm_size);
+                }
+                let mut arg_sizes = Vec::new();
+                for arg in &r.generic_args {
+                    arg_sizes.push(self.node_size(arg, parms, current)?);
+                }
+                match self.rules.get_key_value(r.name.as_str()) {
+                    Some((name, _)) if !self.stack.contains(name) => {
+                        let name: &'a str = name;
+                        // A generic rule is only too large because of the
+                        // arguments it was given, so blame the caller.
+                        let target = self.rule_size(name, &arg_sizes).map_err(|rule| {
+                            if rule == name && !arg_sizes.is_empty() {
+                                current.to_string()
+                            } else {
+                                rule
+                            }
+                        })?;
+                        add(&mut size, target)?;
+                    }
+                    // Recursive or unknown rules only count their arguments.
+                    _ => add(&mut size, arg_sizes.iter().sum())?,
+                }
+            }
+            _ => {
+                for child in node.children() {
+                    let child_size = self.node_size(child, parms, current)?;
+                    add(&mut size, child_size)?;
+                }
+            }
+        }
+        Ok(size)
+    }
+}
+
+fn is_extension(rule: &ast::Rule) -> bool {
+    matches!(
+        rule.val,
+        ast::RuleVal::ExtendType(_) | ast::RuleVal::ExtendGroup(_)
+    )
+}
+
+// Add the choices of `ext` to the definition of a rule: type choices for
+// `/=`, or group choices for `//=`.
+fn extend_rule(rule: &ast::Rule, base: RuleDef, ext: RuleDef) -> FlattenResult<RuleDef> {
+    let name = &rule.name;
+    if base.generic_parms != ext.generic_parms {
+        return Err(ValidateError::Structural(format!(
+            "rule '{}' is extended with different generic parameters",
+            name
+        )));
+    }
+    let group = matches!(rule.val, ast::RuleVal::ExtendGroup(_));
+    let mut options = Vec::new();
+    for node in vec![base.node, ext.node] {
+        if group {
+            push_group_choices(node, &mut options);
+        } else {
+            push_type_choices(name, node, &mut options)?;
+        }
+    }
+    Ok(RuleDef {
+        generic_parms: base.generic_parms,
+        node: Node::Choice(Choice { options }),
+    })
+}
+
+fn is_group(node: &Node) -> bool {
+    matches!(node, Node::Group(_) | Node::KeyValue(_) | Node::Occur(_))
+}
+
+fn push_type_choices(name: &str, node: Node, options: &mut Vec<Node>) -> FlattenResult<()> {
+    match node {
+        Node::Choice(c) if !c.options.iter().any(is_group) => options.extend(c.options),
+        node if !is_group(&node) && !matches!(node, Node::Choice(_)) => options.push(node),
+        _ => {
+            return Err(ValidateError::Structural(format!(
+                "can't add type choices to group '{}'",
+                name
+            )))
+        }
+    }
+    Ok(())
+}
+
+// Each group choice is a Group, as flatten_group makes them.  Anything
+// else is a single member (which may be a bare type, as in an array).
+fn push_group_choices(node: Node, options: &mut Vec<Node>) {
+    match node {
+        Node::Choice(c) if c.options.iter().all(|o| matches!(o, Node::Group(_))) => {
+            options.extend(c.options)
+        }
+        Node::Group(_) => options.push(node),
+        node => options.push(Node::Group(Group {
+            members: vec![node],
+        })),
+    }
+}
+
+// Like extend_rule, keeping the CDDL text of both definitions.
+fn extend_rule_source(
+    rule: &ast::Rule,
+    base: (RuleDef, String),
+    ext: (RuleDef, String),
+) -> FlattenResult<(RuleDef, String)> {
+    let rule_def = extend_rule(rule, base.0, ext.0)?;
+    // A rule from a schema that wasn't parsed has no text to keep.
+    if base.1.is_empty() {
+        return Ok((rule_def, ext.1));
+    }
+    Ok((rule_def, format!("{}\n{}", base.1, ext.1)))
+}
+
 /// flatten an ast::Rule to an ivt::Node
 ///
 /// Returns (name, node) where the name is the name of the rule (which may
 /// be referenced in other places.
 fn flatten_rule(rule: &ast::Rule) -> FlattenResult<(String, RuleDef)> {
     use ast::RuleVal;
-    let node = match &rule.val {
-        RuleVal::AssignType(t) => flatten_type(t)?,
-        RuleVal::AssignGroup(g) => flatten_groupentry(g)?,
+    let mut node = match &rule.val {
+        RuleVal::AssignType(t) | RuleVal::ExtendType(t) => flatten_type(t)?,
+        RuleVal::AssignGroup(g) | RuleVal::ExtendGroup(g) => flatten_groupentry(g)?,
     };
+    name_nodes(&mut node, &rule.name);
     let ruledef = RuleDef {
         generic_parms: rule.generic_parms.clone(),
         node,
@@ -72,6 +529,73 @@ fn flatten_rule(rule: &ast::Rule) -> FlattenResult<(String, RuleDef)> {
     Ok((rule.name.clone(), ruledef))
 }
 
+/// Name the maps, arrays and map members in a rule by their place in it.
+///
+/// See [`Map::name`] for how the names are made.
+pub(crate) fn name_nodes(node: &mut Node, name: &str) {
+    match node {
+        Node::Map(m) => {
+            for member in &mut m.members {
+                name_member(member, name, name);
+            }
+            m.name = Some(name.to_string());
+        }
+        Node::Array(a) => {
+            let element = format!("{}[]", name);
+            for member in &mut a.members {
+                name_member(member, name, &element);
+            }
+            a.name = Some(name.to_string());
+        }
+        // A group rule names its members as a map would.
+        Node::Group(_) | Node::KeyValue(_) => name_member(node, name, name),
+        Node::Choice(c) => {
+            for option in &mut c.options {
+                name_nodes(option, name);
+            }
+        }
+        // A generic argument is validated in place of the parameter, but
+        // the parameter's place is in a different rule; this is the closest
+        // place we know of.
+        Node::Rule(r) => {
+            for arg in &mut r.generic_args {
+                name_nodes(arg, name);
+            }
+        }
+        Node::Occur(o) => name_nodes(&mut o.node, name),
+        Node::Control(Control::Size(ctl)) => name_nodes(&mut ctl.target, name),
+        Node::Control(Control::Bits(ctl)) => name_nodes(&mut ctl.target, name),
+        Node::Control(Control::Cbor(ctl)) => name_nodes(&mut ctl.node, name),
+        Node::Control(Control::Default(ctl)) => name_nodes(&mut ctl.target, name),
+        Node::Tagged(t) => name_nodes(&mut t.node, name),
+        _ => {}
+    }
+}
+
+// Name a member of a map, array or group.  `parent` is the name of the
+// container, and `element` is the name of a member without a key.
+fn name_member(node: &mut Node, parent: &str, element: &str) {
+    match node {
+        Node::KeyValue(kv) => {
+            let name = format!("{}.{}", parent, kv.key_name());
+            name_nodes(&mut kv.value, &name);
+            kv.name = Some(name);
+        }
+        Node::Occur(o) => name_member(&mut o.node, parent, element),
+        Node::Group(g) => {
+            for member in &mut g.members {
+                name_member(member, parent, element);
+            }
+        }
+        Node::Choice(c) => {
+            for option in &mut c.options {
+                name_member(option, parent, element);
+            }
+        }
+        _ => name_nodes(node, element),
+    }
+}
+
 fn flatten_type(ty: &ast::Type) -> FlattenResult<Node> {
     let options: FlattenResult<Vec<Node>> = ty.0.iter().map(flatten_type1).collect();
     let options = options?;
@@ -98,18 +622,27 @@ fn flatten_type1(ty1: &ast::Type1) -> FlattenResult<Node> {
 // type.
 //
 // TODO:
-// .bits
-// .regexp
-// .cbor .cborseq
+// .cborseq
 // .within .and
-// .lt .le .gt .ge .eq .ne. default
+// .lt .le .gt .ge .eq .ne
 // According to RFC 8610 3.8, new control operators may arrive later.
 //
 fn flatten_control(ctl: &ast::TypeControl) -> FlattenResult<Node> {
-    let ctl_result = match ctl.op.as_str() {
-        "size" => control_size(ctl)?,
-        "regexp" => control_regex(ctl)?,
-        "cbor" => control_cbor(ctl)?,
+    use ast::ControlOp;
+    if let Some(feature) = ctl.op.missing_feature() {
+        return Err(ValidateError::Unsupported(format!(
+            "control '.{}' requires the '{}' feature",
+            ctl.op.name(),
+            feature
+        )));
+    }
+    let ctl_result = match ctl.op {
+        ControlOp::Size => control_size(ctl)?,
+        ControlOp::Bits => control_bits(ctl)?,
+        #[cfg(feature = "regex")]
+        ControlOp::Regexp => control_regex(ctl)?,
+        ControlOp::Cbor => control_cbor(ctl)?,
+        ControlOp::Default => control_default(ctl)?,
         _ => return Err(ValidateError::Unsupported("control operator".into())),
     };
 
@@ -118,20 +651,13 @@ fn flatten_control(ctl: &ast::TypeControl) -> FlattenResult<Node> {
 
 // Handle the "size" control operator:
 // <target> .size <integer literal>
+// <target> .size (<integer range>)
 // The only allowed targets are bstr, tstr, and unsigned integers.
 //
 fn control_size(ctl: &ast::TypeControl) -> FlattenResult<Control> {
     let target = flatten_type2(&ctl.target)?;
     let size = flatten_type2(&ctl.arg)?;
-
-    // The only allowed limit types are:
-    // A positive literal integer
-    // A named rule (which should resolve to a literal integer)
-    match size {
-        Node::Literal(Literal::Int(_)) => {}
-        Node::Rule(_) => {}
-        _ => return Err(ValidateError::Unsupported(".size limit type".into())),
-    };
+    check_size_limit(&size)?;
 
     Ok(Control::Size(CtlOpSize {
         target: Box::new(target),
@@ -139,10 +665,39 @@ fn control_size(ctl: &ast::TypeControl) -> FlattenResult<Control> {
     }))
 }
 
+// Handle the "bits" control operator:
+// <target> .bits <type>
+// The only allowed targets are bstr and unsigned integers; like .size, the
+// target is checked during validation, since it may be a rule name.
+//
+fn control_bits(ctl: &ast::TypeControl) -> FlattenResult<Control> {
+    let target = flatten_type2(&ctl.target)?;
+    let bits = flatten_type2(&ctl.arg)?;
+
+    Ok(Control::Bits(CtlOpBits {
+        target: Box::new(target),
+        bits: Box::new(bits),
+    }))
+}
+
+// The only allowed limit types are:
+// A positive literal integer
+// A range of integers, e.g. (1..10)
+// A named rule (which should resolve to one of the above)
+pub(crate) fn check_size_limit(size: &Node) -> FlattenResult<()> {
+    match size {
+        Node::Literal(Literal::Int(_)) => Ok(()),
+        Node::Range(_) => Ok(()),
+        Node::Rule(_) => Ok(()),
+        _ => Err(ValidateError::Unsupported(".size limit type".into())),
+    }
+}
+
 // Handle the "size" control operator:
 // <target> .size <integer literal>
 // The only allowed targets are bstr, tstr, and unsigned integers.
 //
+#[cfg(feature = "regex")]
 fn control_regex(ctl: &ast::TypeControl) -> FlattenResult<Control> {
     let target = flatten_type2(&ctl.target)?;
     let regexp_node = flatten_type2(&ctl.arg)?;
@@ -192,6 +747,24 @@ fn control_cbor(ctl: &ast::TypeControl) -> FlattenResult<Control> {
     Ok(Control::Cbor(CtlOpCbor { node }))
 }
 
+// Handle the "default" control operator:
+// <target> .default <literal>
+// The default value doesn't affect validation, so it's only kept for
+// `Schema::defaults_for`.
+//
+fn control_default(ctl: &ast::TypeControl) -> FlattenResult<Control> {
+    let target = flatten_type2(&ctl.target)?;
+    let default = match flatten_type2(&ctl.arg)? {
+        Node::Literal(l) => l,
+        _ => return Err(ValidateError::Unsupported(".default value type".into())),
+    };
+
+    Ok(Control::Default(CtlOpDefault {
+        target: Box::new(target),
+        default,
+    }))
+}
+
 // The only way a range start or end can be specified is with a literal
 // value, or with a typename.  We will accept either of those, and throw
 // an error otherwise.  Let the validator worry about whether a typename
@@ -204,10 +777,20 @@ fn range_point(point: &ast::Type2) -> FlattenResult<Node> {
             "bad type on range operator".into(),
         )),
     }?;
-    // Check that the node that came back is a Rule or Literal.
-    // Anything else (i.e. PreludeType) should cause an error.
+    check_range_point(node)
+}
+
+// Check that a range start or end is a Rule or a numeric Literal.
+// Anything else (i.e. PreludeType) should cause an error.  Ranges are only
+// defined between numbers, so a range like `"a".."z"` is an error too.
+pub(crate) fn check_range_point(node: Node) -> FlattenResult<Node> {
     match node {
-        Node::Rule(_) | Node::Literal(_) => Ok(node),
+        Node::Literal(Literal::Int(_)) | Node::Literal(Literal::Float(_)) => Ok(node),
+        Node::Literal(l) => Err(ValidateError::Structural(format!(
+            "non-numeric range operator ({})",
+            l
+        ))),
+        Node::Rule(_) => Ok(node),
         _ => Err(ValidateError::Structural(
             "bad type on range operator".into(),
         )),
@@ -253,15 +836,121 @@ fn flatten_type2(ty2: &ast::Type2) -> FlattenResult<Node> {
         Type2::Unwrap(r) => Ok(Node::Unwrap(flatten_rule_generic(r)?)),
         Type2::ChoiceifyInline(g) => flatten_choiceify_inline(g),
         Type2::Choiceify(r) => flatten_choiceify(r),
+        Type2::Tagged(t) => flatten_tagged(t),
+        Type2::Major(m) => flatten_major(m),
+        Type2::Any => Ok(Node::PreludeType(PreludeType::Any)),
     }
 }
 
-fn flatten_typename(name: &str) -> FlattenResult<Node> {
+fn flatten_tagged(tagged: &ast::TypeTagged) -> FlattenResult<Node> {
+    Ok(Node::Tagged(Tagged {
+        tag: tagged.tag,
+        node: Box::new(flatten_type(&tagged.ty)?),
+    }))
+}
+
+// A data item given by its CBOR major type, e.g. `#1` or `#7.25`.
+//
+// The additional information is only supported where it picks out a type
+// we already know: a tag number, or one of the simple values.
+fn flatten_major(major: &ast::TypeMajor) -> FlattenResult<Node> {
+    let any = || Node::PreludeType(PreludeType::Any);
+    let result = match (major.major, major.info) {
+        (0, None) => Node::PreludeType(PreludeType::Uint),
+        (1, None) => Node::PreludeType(PreludeType::Nint),
+        (2, None) => Node::PreludeType(PreludeType::Bstr),
+        (3, None) => Node::PreludeType(PreludeType::Tstr),
+        (4, None) => ArrayBuilder::new()
+            .element(any(), Some(OccurLimit::ZeroOrMore))
+            .build(),
+        (5, None) => MapBuilder::new()
+            .member(any(), any(), Some(OccurLimit::ZeroOrMore))
+            .build(),
+        (6, tag) => Node::Tagged(Tagged {
+            tag,
+            node: Box::new(any()),
+        }),
+        (7, None) => Node::Choice(Choice {
+            options: vec![
+                Node::PreludeType(PreludeType::Bool),
+                Node::PreludeType(PreludeType::Nil),
+                Node::PreludeType(PreludeType::Float),
+            ],
+        }),
+        (7, Some(20)) => literal_bool(false),
+        (7, Some(21)) => literal_bool(true),
+        (7, Some(22)) => Node::PreludeType(PreludeType::Nil),
+        (7, Some(25)) | (7, Some(26)) | (7, Some(27)) => Node::PreludeType(PreludeType::Float),
+        (m, Some(info)) if m <= 7 => {
+            let msg = format!("major type #{}.{}", m, info);
+            return Err(ValidateError::Unsupported(msg));
+        }
+        // CBOR only has major types 0 to 7.
+        (m, _) => {
+            let msg = format!("major type #{}", m);
+            return Err(ValidateError::Structural(msg));
+        }
+    };
+    Ok(result)
+}
+
+// The types in the standard prelude (RFC 8610 appendix D), in the order
+// they're listed there.
+pub(crate) const PRELUDE_NAMES: &[&str] = &[
+    "any",
+    "uint",
+    "nint",
+    "int",
+    "bstr",
+    "bytes",
+    "tstr",
+    "text",
+    "tdate",
+    "time",
+    "number",
+    "biguint",
+    "bignint",
+    "bigint",
+    "integer",
+    "unsigned",
+    "decfrac",
+    "bigfloat",
+    "eb64url",
+    "eb64legacy",
+    "eb16",
+    "encoded-cbor",
+    "uri",
+    "b64url",
+    "b64legacy",
+    "regexp",
+    "mime-message",
+    "cbor-any",
+    "float16",
+    "float32",
+    "float64",
+    "float16-32",
+    "float32-64",
+    "float",
+    "false",
+    "true",
+    "bool",
+    "nil",
+    "null",
+    "undefined",
+];
+
+pub(crate) fn flatten_typename(name: &str) -> FlattenResult<Node> {
     let unsupported = |s: &str| -> FlattenResult<Node> {
         let msg = format!("prelude type '{}'", s);
         Err(ValidateError::Unsupported(msg))
     };
 
+    // Anything outside the standard prelude is assumed to be a rule or
+    // group identifier.  No further validation is done at this time.
+    if !PRELUDE_NAMES.contains(&name) {
+        return Ok(Node::Rule(Rule::new_name(name)));
+    }
+
     let result = match name {
         "any" => Node::PreludeType(PreludeType::Any),
         "nil" | "null" => Node::PreludeType(PreludeType::Nil),
@@ -272,6 +961,7 @@ fn flatten_typename(name: &str) -> FlattenResult<Node> {
         "uint" => Node::PreludeType(PreludeType::Uint),
         "nint" => Node::PreludeType(PreludeType::Nint),
         "float" => Node::PreludeType(PreludeType::Float),
+        "number" => Node::PreludeType(PreludeType::Number),
         "tstr" | "text" => Node::PreludeType(PreludeType::Tstr),
         "bstr" | "bytes" => Node::PreludeType(PreludeType::Bstr),
 
@@ -321,8 +1011,7 @@ fn flatten_typename(name: &str) -> FlattenResult<Node> {
         // bigint = biguint / bignint
         // integer = int / bigint
         // unsigned = uint / biguint
-        // number = int / float
-        "bigint" | "integer" | "unsigned" | "number" => return unsupported(name),
+        "bigint" | "integer" | "unsigned" => return unsupported(name),
 
         // Other miscellaneous prelude types:
         // decfrac = #6.4([e10: int, m: integer])
@@ -330,10 +1019,8 @@ fn flatten_typename(name: &str) -> FlattenResult<Node> {
         // undefined = #7.23
         "decfrac" | "bigfloat" | "undefined" => return unsupported(name),
 
-        // We failed to find this string in the standard prelude, so we will
-        // assume it's a rule or group identifier.  No further validation is
-        // done at this time.
-        _ => Node::Rule(Rule::new_name(name)),
+        // Anything else in the prelude isn't handled yet.
+        _ => return unsupported(name),
     };
     Ok(result)
 }
@@ -378,16 +1065,73 @@ fn flatten_name_generic(name_generic: &ast::NameGeneric) -> FlattenResult<Node>
 /// Flatten a group into a Map.
 fn flatten_map(group: &ast::Group) -> FlattenResult<Node> {
     let kvs = flatten_group(group)?;
-    Ok(Node::Map(Map { members: kvs }))
+    let kvs: FlattenResult<Vec<Node>> = kvs.into_iter().map(limit_literal_key_occur).collect();
+    Ok(Node::Map(Map {
+        members: kvs?,
+        name: None,
+    }))
+}
+
+// A map can only contain each key once, so a member with a literal key can
+// match at most one map entry, regardless of its occurrence.  Clamp the upper
+// limit to 1, and reject lower limits that can never be satisfied.
+//
+// This only examines members that appear directly within the map (including
+// inline groups); a named group could be used in either a map or an array,
+// so it can't be adjusted here.
+pub(crate) fn limit_literal_key_occur(node: Node) -> FlattenResult<Node> {
+    match node {
+        Node::Occur(o) => {
+            let literal_key = match o.node.as_ref() {
+                Node::KeyValue(kv) => matches!(kv.key.as_ref(), Node::Literal(_)),
+                _ => false,
+            };
+            if !literal_key {
+                return Ok(Node::Occur(o));
+            }
+            match o.limits() {
+                (lower, _) if lower > 1 => {
+                    let msg = format!("unsatisfiable occurrence on literal map key: {}", o);
+                    Err(ValidateError::Structural(msg))
+                }
+                (_, upper) if upper <= 1 => Ok(Node::Occur(o)),
+                (0, _) => Ok(Node::Occur(Occur {
+                    at_most_once: true,
+                    ..o
+                })),
+                // A lower limit of 1 means the member is simply required.
+                _ => Ok(*o.node),
+            }
+        }
+        Node::Group(g) => {
+            let members: FlattenResult<Vec<Node>> =
+                g.members.into_iter().map(limit_literal_key_occur).collect();
+            Ok(Node::Group(Group { members: members? }))
+        }
+        Node::Choice(c) => {
+            let options: FlattenResult<Vec<Node>> =
+                c.options.into_iter().map(limit_literal_key_occur).collect();
+            Ok(Node::Choice(Choice { options: options? }))
+        }
+        _ => Ok(node),
+    }
 }
 
 /// Flatten a group into a Map.
 fn flatten_array(group: &ast::Group) -> FlattenResult<Node> {
     let kvs = flatten_group(group)?;
-    Ok(Node::Array(Array { members: kvs }))
+    Ok(Node::Array(Array {
+        members: kvs,
+        name: None,
+    }))
 }
 
 // Returns an ivt::Group node, or a vector of other nodes.
+//
+// Groups are flattened the same way regardless of whether they are used in a
+// map or an array; members keep their keys as KeyValue nodes.  The validator
+// knows which context it's in when it reaches the group, and ignores the
+// keys in an array context.
 fn flatten_group(group: &ast::Group) -> FlattenResult<Vec<Node>> {
     let group_choices = &group.0;
     if group_choices.len() == 1 {
@@ -518,7 +1262,10 @@ fn flatten_choiceify(name: &ast::NameGeneric) -> FlattenResult<Node> {
 
 fn flatten_choiceify_inline(group: &ast::Group) -> FlattenResult<Node> {
     let kvs = flatten_group(group)?;
-    Ok(Node::ChoiceifyInline(Array { members: kvs }))
+    Ok(Node::ChoiceifyInline(Array {
+        members: kvs,
+        name: None,
+    }))
 }
 
 // Useful utilities for testing the flatten code.
@@ -558,22 +1305,25 @@ mod test_utils {
     // Given a list of names and Nodes, build a rules map.
     // Note: This requires Node instead of Into<Node> because we want to allow
     // multiple types of Node, which must be done by the caller.
+    //
+    // Like flattening, this names the nodes in each rule.
     pub fn make_rules(mut list: Vec<(&str, Node)>) -> RulesByName {
         list.drain(..)
-            .map(|(s, n)| (s.to_string(), RuleDef::from(n)))
+            .map(|(s, mut n)| {
+                name_nodes(&mut n, s);
+                (s.to_string(), RuleDef::from(n))
+            })
             .collect()
     }
 
     // Given a single name/Node pair, build a rules map.
     pub fn make_rule<T: Into<Node>>(name: &str, node: T) -> RulesByName {
-        let node: Node = node.into();
-        let mut result = BTreeMap::new();
-        result.insert(name.to_string(), RuleDef::from(node));
-        result
+        make_rules(vec![(name, node.into())])
     }
 
     // Given a single name/Node pair, build a rules map.
-    pub fn make_generic_rule(name: &str, generic_parms: &[&str], node: Node) -> RulesByName {
+    pub fn make_generic_rule(name: &str, generic_parms: &[&str], mut node: Node) -> RulesByName {
+        name_nodes(&mut node, name);
         let mut result = BTreeMap::new();
         let generic_parms: Vec<String> = generic_parms.iter().map(|s| String::from(*s)).collect();
         result.insert(
@@ -615,6 +1365,7 @@ mod test_utils {
     pub fn make_map() -> Map {
         Map {
             members: Vec::new(),
+            name: None,
         }
     }
 
@@ -622,6 +1373,7 @@ mod test_utils {
     pub fn make_array() -> Array {
         Array {
             members: Vec::new(),
+            name: None,
         }
     }
 
@@ -700,11 +1452,7 @@ mod test_utils {
 
     // Shorthand for creating a key-value pair, with explicit cut setting.
     pub fn kv(k: Node, v: Node, cut: KvCut) -> KeyValue {
-        KeyValue {
-            key: Box::new(k),
-            value: Box::new(v),
-            cut: cut.into(),
-        }
+        KeyValue::new(k, v, cut.into())
     }
 }
 
@@ -742,6 +1490,74 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_flatten_extend() {
+        // Each /= adds to the choices of the rule, whether or not it was
+        // already defined.
+        let cddl_input = r#"$a /= int  $a /= tstr / nil  b = 1  b /= 2"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let choice = |options| Node::Choice(Choice { options });
+        let expected = make_rules(vec![
+            (
+                "$a",
+                choice(vec![
+                    Node::PreludeType(PreludeType::Int),
+                    tstr(),
+                    Node::PreludeType(PreludeType::Nil),
+                ]),
+            ),
+            ("b", choice(vec![1.literal(), 2.literal()])),
+        ]);
+        assert_eq!(result, expected);
+
+        // A later = replaces everything before it.
+        let result = flatten_from_str("b = 1  b /= 2  b = 3").unwrap();
+        assert_eq!(result, make_rule("b", 3.literal()));
+
+        let err = flatten_from_str("g = (x: int)  g /= tstr").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Structural(can't add type choices to group 'g')"
+        );
+        let err = flatten_from_str("p<T> = [T]  p /= int").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Structural(rule 'p' is extended with different generic parameters)"
+        );
+
+        // The CDDL text of every part is kept.
+        let rules = slice_flatten_from_str("$a /= int\n$a /= tstr").unwrap();
+        assert_eq!(rules["$a"].1, "$a /= int\n$a /= tstr");
+
+        // Group choices are added with //=.
+        let result = flatten_from_str("$$g //= (x: int)  $$g //= y: tstr").unwrap();
+        let x = kv("x".literal(), Node::PreludeType(PreludeType::Int), Cut);
+        let y = kv("y".literal(), tstr(), Cut);
+        let expected = make_rule(
+            "$$g",
+            choice(vec![
+                Node::Group(Group {
+                    members: vec![x.into()],
+                }),
+                Node::Group(Group {
+                    members: vec![y.into()],
+                }),
+            ]),
+        );
+        assert_eq!(result, expected);
+        let err = flatten_from_str("$$g //= (x: int)  $$g /= tstr").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Structural(can't add type choices to group '$$g')"
+        );
+
+        // A broken part breaks the whole rule.
+        let cddl = parse_cddl("$a /= int  $a /= uint .lt 3  $a /= tstr").unwrap();
+        let (rules, errors) = flatten_partial(&cddl).unwrap();
+        assert!(rules.is_empty());
+        assert_eq!(errors["$a"].to_string(), "Unsupported control operator");
+    }
+
     #[test]
     fn test_flatten_type_reference() {
         let cddl_input = r#"thing = foo"#;
@@ -778,6 +1594,76 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_flatten_literal_key_occur() {
+        let occur = |limit, node: KeyValue| Node::Occur(Occur::new(limit, node.into()));
+
+        // Occurrences on literal keys are limited to at most one match, but
+        // keep their spelling.
+        let narrowed = |limit, node: KeyValue| {
+            Node::Occur(Occur {
+                at_most_once: true,
+                ..Occur::new(limit, node.into())
+            })
+        };
+        let cddl_input = r#"thing = { * foo: tstr, + "bar" => tstr, 0*5 baz: tstr }"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let expected = make_map()
+            .append(narrowed(
+                OccurLimit::ZeroOrMore,
+                kv("foo".literal(), tstr(), Cut),
+            ))
+            .append(kv("bar".literal(), tstr(), NoCut))
+            .append(narrowed(
+                OccurLimit::Numbered(0, 5),
+                kv("baz".literal(), tstr(), Cut),
+            ));
+        assert_eq!(result, make_rule("thing", expected));
+
+        // Other keys, and array members, are left alone.
+        let cddl_input = r#"thing = { * tstr => tstr }"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let expected = make_map().append(occur(OccurLimit::ZeroOrMore, kv(tstr(), tstr(), NoCut)));
+        assert_eq!(result, make_rule("thing", expected));
+
+        let cddl_input = r#"thing = [ * foo: tstr ]"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let expected = make_array().append(occur(
+            OccurLimit::ZeroOrMore,
+            kv("foo".literal(), tstr(), Cut),
+        ));
+        assert_eq!(result, make_rule("thing", expected));
+
+        // A lower limit above 1 can never be satisfied.
+        let cddl_input = r#"thing = { tag: int, (2*3 foo: tstr) }"#;
+        let err = flatten_from_str(cddl_input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Structural(unsatisfiable occurrence on literal map key: 2*3 "foo": Tstr)"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_array_choice_occur() {
+        // The occurrence applies to the whole choice.
+        let cddl_input = r#"thing = [* (int / tstr)]"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let choice = Node::Choice(Choice {
+            options: vec![Node::PreludeType(PreludeType::Int), tstr()],
+        });
+        let expected = make_array().append(Node::Occur(Occur::new(OccurLimit::ZeroOrMore, choice)));
+        assert_eq!(result, make_rule("thing", expected));
+
+        let cddl_input = r#"thing = [1*3 (a / b)]"#;
+        let result = flatten_from_str(cddl_input).unwrap();
+        let choice = Node::Choice(Choice {
+            options: vec![Rule::new_name("a").into(), Rule::new_name("b").into()],
+        });
+        let expected =
+            make_array().append(Node::Occur(Occur::new(OccurLimit::Numbered(1, 3), choice)));
+        assert_eq!(result, make_rule("thing", expected));
+    }
+
     #[test]
     fn test_flatten_generic() {
         let cddl_input = "message<t, v> = [t, v]";
@@ -791,6 +1677,107 @@ mod tests {
                 .into(),
         );
         assert_eq!(result, expected);
+
+        // References must give each rule as many generic arguments as it
+        // has parameters; generic parameters take none.
+        let bad = [
+            "a = b  b<T> = [T]",
+            "a = b<int, int>  b<T> = [T]",
+            "a = b<int>  b = [int]",
+            "a<T> = [T<int>]",
+            "a = [~b]  b<T> = (T)",
+            "a = [{ b<int> }]  b = (x: int)",
+        ];
+        for cddl_input in &bad {
+            let err = flatten_from_str(cddl_input).unwrap_err();
+            assert!(matches!(err, ValidateError::GenericError), "{}", cddl_input);
+            let cddl = slice_parse_cddl(cddl_input).unwrap();
+            let err = slice_flatten(&cddl).unwrap_err();
+            assert!(matches!(err, ValidateError::GenericError), "{}", cddl_input);
+        }
+        flatten_from_str("a = b<int>  b<T> = [T]").unwrap();
+        // A parameter hides a rule of the same name.
+        flatten_from_str("a<b> = [b]  b<T> = [T]").unwrap();
+        // Undefined rules are left for validation to report.
+        flatten_from_str("a = b<int>").unwrap();
+
+        // In a partial schema, only the rule with the bad reference is set
+        // aside.
+        let (rules, errors) = flatten_partial_from_str("a = b  b<T> = [T]  c = int").unwrap();
+        assert_eq!(rules.keys().collect::<Vec<_>>(), vec!["b", "c"]);
+        assert!(matches!(errors["a"], ValidateError::GenericError));
+    }
+
+    #[test]
+    fn test_flatten_names() {
+        // The names of the maps, arrays and members in a rule, in order.
+        let names = |cddl_input: &str| -> Vec<String> {
+            let rules = flatten_from_str(cddl_input).unwrap();
+            let mut names = Vec::new();
+            rules["thing"].node.walk(|node, _| {
+                let name = match node {
+                    Node::Map(m) => &m.name,
+                    Node::Array(a) => &a.name,
+                    Node::KeyValue(kv) => &kv.name,
+                    _ => return,
+                };
+                names.push(name.clone().unwrap());
+            });
+            names
+        };
+
+        assert_eq!(
+            names("thing = { items: [* { id: uint, tags: [* tstr] }] }"),
+            vec![
+                "thing",
+                "thing.items",
+                "thing.items",
+                "thing.items[]",
+                "thing.items[].id",
+                "thing.items[].tags",
+                "thing.items[].tags",
+            ]
+        );
+        assert_eq!(
+            names("thing = [x: int, [1, h'00' => { a: 1 }]] / { ? 7: [], * tstr => {} }"),
+            vec![
+                "thing",
+                "thing.x",
+                "thing[]",
+                "thing[].h'00'",
+                "thing[].h'00'",
+                "thing[].h'00'.a",
+                "thing",
+                "thing.7",
+                "thing.7",
+                "thing.*",
+                "thing.*",
+            ]
+        );
+        // Group rules and generic arguments.
+        assert_eq!(
+            names("thing = (a: { b: int }, c: pair<[int]>)  pair<T> = [T, T]"),
+            vec!["thing.a", "thing.a", "thing.a.b", "thing.c", "thing.c"]
+        );
+
+        // Anonymous types have no names.
+        let result = flatten_type_expr("{ foo: [tstr] }").unwrap();
+        match result.node {
+            Node::Map(m) => assert_eq!(m.name, None),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_flatten_type_expr() {
+        let result = flatten_type_expr("{ foo: tstr }").unwrap();
+        let expected = make_map().append(kv("foo".literal(), tstr(), Cut));
+        assert_eq!(result.node, expected.into());
+        assert!(result.generic_parms.is_empty());
+
+        // Rule references are preserved for later lookup.
+        let result = flatten_type_expr("foo").unwrap();
+        assert_eq!(result.node, Rule::from("foo").into());
     }
 
     #[test]
@@ -806,4 +1793,174 @@ mod tests {
         );
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_control_default() {
+        let result = flatten_from_str("retries = uint .default 3").unwrap();
+        let expected = make_rule(
+            "retries",
+            Control::Default(CtlOpDefault {
+                target: Box::new(Node::PreludeType(PreludeType::Uint)),
+                default: Literal::Int(3),
+            }),
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_occurrence() {
+        let rules = flatten_from_str("a = { name: tstr, ? age: int, 2*5 tstr => any }").unwrap();
+        let members = match &rules["a"].node {
+            Node::Map(m) => &m.members,
+            _ => panic!("expected map"),
+        };
+        let described: Vec<String> = members
+            .iter()
+            .map(|member| match member.occurrence() {
+                (Some(occur), node) => format!("{}: {} ({})", occur, node, occur.describe()),
+                (None, node) => node.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                r#""name": Tstr"#,
+                r#"?: "age": Int (at most 1)"#,
+                "2*5: Tstr: Any (2 to 5)",
+            ]
+        );
+        assert_eq!(members[2].to_string(), "2*5 Tstr: Any");
+    }
+
+    #[test]
+    fn test_control_op_feature() {
+        // Operators that need a disabled cargo feature are rejected here,
+        // rather than failing when data is validated.
+        let result = flatten_from_str(r#"name = tstr .regexp "[a-z]+""#);
+        if cfg!(feature = "regex") {
+            result.unwrap();
+        } else {
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "Unsupported control '.regexp' requires the 'regex' feature"
+            );
+        }
+
+        let result = flatten_from_str("wrapped = bstr .cbor uint");
+        if cfg!(feature = "serde_cbor") {
+            result.unwrap();
+        } else {
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "Unsupported control '.cbor' requires the 'serde_cbor' feature"
+            );
+        }
+    }
+
+    #[test]
+    fn test_flatten_partial() {
+        let cddl_input = "a = [b]  b = int  c = uint .lt 10  d = [c]";
+        flatten_from_str(cddl_input).unwrap_err();
+        let (rules, errors) = flatten_partial_from_str(cddl_input).unwrap();
+        assert_eq!(rules.keys().collect::<Vec<_>>(), vec!["a", "b", "d"]);
+        assert_eq!(errors.keys().collect::<Vec<_>>(), vec!["c"]);
+        assert_eq!(errors["c"].to_string(), "Unsupported control operator");
+
+        // The slice version gives the same answer, with the rule text.
+        let cddl = slice_parse_cddl(cddl_input).unwrap();
+        let (rules, errors) = slice_flatten_partial(&cddl).unwrap();
+        assert_eq!(rules["a"].1, "a = [b]");
+        assert_eq!(errors.keys().collect::<Vec<_>>(), vec!["c"]);
+
+        // The last definition of a rule wins, whether or not it works.
+        let (rules, errors) = flatten_partial_from_str("a = int  a = uint .lt 1").unwrap();
+        assert!(rules.is_empty());
+        assert_eq!(errors.len(), 1);
+        let (rules, errors) = flatten_partial_from_str("a = uint .lt 1  a = int").unwrap();
+        assert_eq!(rules.len(), 1);
+        assert!(errors.is_empty());
+
+        // Parse errors and the size limit still apply to the whole schema.
+        flatten_partial_from_str("a = [  b = uint .lt 1").unwrap_err();
+        let mut arg = "uint".to_string();
+        for _ in 0..64 {
+            arg = format!("pair<{}>", arg);
+        }
+        let cddl_input = format!("pair<T> = [T, T]  big = {}  c = uint .lt 1", arg);
+        let err = flatten_partial_from_str(&cddl_input).unwrap_err();
+        assert!(matches!(err, ValidateError::SchemaTooLarge(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_schema_too_large() {
+        use std::time::{Duration, Instant};
+
+        // Each rule unwraps the next one twice, doubling the tree size.
+        let mut cddl_input = String::new();
+        for n in 0..64 {
+            cddl_input += &format!("a{} = [~a{}, ~a{}]\n", n, n + 1, n + 1);
+        }
+        cddl_input += "a64 = [uint]\n";
+        let start = Instant::now();
+        let err = flatten_from_str(&cddl_input).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        match err {
+            ValidateError::SchemaTooLarge(e) => {
+                // a46 is the first rule to expand to over a million nodes.
+                assert_eq!(e.rule(), "a46");
+                assert_eq!(e.limit(), 1_000_000);
+            }
+            _ => panic!("expected SchemaTooLarge, got {:?}", err),
+        }
+
+        // Generic arguments are copied for each use of the parameter.
+        let mut arg = "uint".to_string();
+        for _ in 0..64 {
+            arg = format!("pair<{}>", arg);
+        }
+        let cddl_input = format!("pair<T> = [T, T]\nbig = {}", arg);
+        let err = flatten_from_str(&cddl_input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SchemaTooLarge(rule 'big' expands to more than 1000000 nodes)"
+        );
+
+        // A limit of exactly the expanded size is allowed.
+        let cddl = parse_cddl("a = [uint, uint]  b = [~a, ~a]").unwrap();
+        let mut options = FlattenOptions { max_nodes: 9 };
+        flatten_with_options(&cddl, &options).unwrap();
+        options.max_nodes = 8;
+        let err = flatten_with_options(&cddl, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SchemaTooLarge(rule 'b' expands to more than 8 nodes)"
+        );
+
+        // Recursive rules are limited by the data, not the schema.
+        flatten_with_options(&parse_cddl("tree = [* tree]").unwrap(), &options).unwrap();
+    }
+
+    #[test]
+    fn test_flatten_error_category() {
+        use crate::util::ErrorCategory;
+
+        // Every error from flattening is a problem with the schema.
+        let inputs = [
+            "thing = [",
+            "thing = uint .lt 1",
+            "thing = time",
+            "thing = tstr .size tstr",
+            "thing = { 2*3 foo: tstr }",
+            "thing = uint .regexp \"a\"",
+            "thing = 1..tstr",
+        ];
+        for input in &inputs {
+            let err = flatten_from_str(input).unwrap_err();
+            assert_eq!(err.category(), ErrorCategory::Schema, "{}: {}", input, err);
+        }
+        let options = FlattenOptions { max_nodes: 2 };
+        let err =
+            flatten_with_options(&parse_cddl("a = [1, 2, 3]").unwrap(), &options).unwrap_err();
+        assert_eq!(err.category(), ErrorCategory::Schema);
+    }
 }
@@ -5,12 +5,37 @@
 //!
 //! This module doesn't know anything about validating specific types (e.g.
 //! CBOR or JSON), but it helps make writing those validators easier.
+//!
+//! Rules can also be built directly, without writing any CDDL, using
+//! [`RulesBuilder`] and its helpers.
+//!
+//! # Stable output
+//!
+//! The `Debug` form of a set of rules (e.g. `format!("{:#?}", rules)`) only
+//! depends on the CDDL text and the version of this crate, so it can be
+//! hashed to notice when a schema changes.  It's the same on every run and
+//! every platform:
+//! - rules are kept in a `BTreeMap`, so they're listed in name order,
+//! - group members, array and map members, and choice options are listed
+//!   in the order they're written,
+//! - integers are stored as `i128` or `u64`, never as a platform-sized
+//!   type, and
+//! - nothing is rendered from a pointer or a hash.
+//!
+//! `tests/golden.rs` checks this against a saved rendering.
 
 use crate::ast;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
 use strum_macros::{Display, IntoStaticStr};
 
+mod builder;
+pub use builder::{
+    choice, generic_ref, literal, range, rule_ref, ArrayBuilder, GroupBuilder, MapBuilder,
+    RulesBuilder,
+};
+
 /// The definition of a CDDL rule.
 ///
 /// Each rule has a name, some (optional) generic parameters, and a
@@ -41,7 +66,7 @@ pub type RulesWithStrings = BTreeMap<String, (RuleDef, String)>;
 /// One of the types named in the CDDL prelude.
 ///
 /// The following types are defined in [RFC8610 appendix D]:
-/// `any`, `bool`, `int`, `uint`, `float`, `tstr`, `bstr`.
+/// `any`, `bool`, `int`, `uint`, `float`, `number`, `tstr`, `bstr`.
 /// There are more that aren't supported by this crate yet.
 ///
 /// [RFC8610 appendix D]: https://tools.ietf.org/html/rfc8610#appendix-D
@@ -54,14 +79,20 @@ pub enum PreludeType {
     Nil,
     /// A boolean value: true or false
     Bool,
-    /// A positive or negative integer
+    /// A positive or negative integer, from -2^64 to 2^64-1
+    ///
+    /// This follows CBOR, which can encode integers in that range without
+    /// using tags.  JSON numbers beyond the range of `i64` and `u64` are
+    /// read as floats, so they don't match.
     Int,
-    /// An integer >= 0
+    /// An integer from 0 to 2^64-1
     Uint,
-    /// An integer < 0
+    /// An integer from -2^64 to -1
     Nint,
     /// A floating-point value
     Float,
+    /// An int or a float
+    Number,
     /// A text string
     Tstr,
     /// A byte string
@@ -69,6 +100,14 @@ pub enum PreludeType {
 }
 
 /// A literal value, e.g. `7`, `1.3`, or ``"foo"``.
+///
+/// A `Float` literal matches a floating-point value if the two are equal
+/// after both are converted to `f64`.  Data encoded as a smaller float
+/// (e.g. CBOR float16) is widened first, which is exact; so `1.5` or `0.25`
+/// match at any width.  A literal that a smaller float can't represent
+/// exactly won't match that encoding: the float16 value closest to `3.14`
+/// is really 3.140625.  Use a range (e.g. `3.13..3.15`) to accept nearby
+/// values.  Integers never match float literals, or vice versa.
 #[derive(Debug, Clone, PartialEq)]
 #[allow(missing_docs)]
 pub enum Literal {
@@ -89,7 +128,13 @@ impl fmt::Display for Literal {
             // decimal, which can be confused for an integer.
             Literal::Float(fl) => write!(f, "{}", fl),
             Literal::Text(s) => write!(f, "\"{}\"", s),
-            Literal::Bytes(_) => write!(f, "LiteralBytes"),
+            Literal::Bytes(b) => {
+                write!(f, "h'")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
         }
     }
 }
@@ -150,7 +195,31 @@ impl Rule {
     }
 }
 
+/// Displays the rule name, with any generic arguments, e.g. `pair<tstr>`
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.generic_args.is_empty() {
+            let args: Vec<String> = self.generic_args.iter().map(Node::to_string).collect();
+            write!(f, "<{}>", args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `name` is a socket (e.g. `$socket`), which is allowed to
+/// be referenced without being defined.  An empty socket matches nothing.
+pub(crate) fn is_socket(name: &str) -> bool {
+    name.starts_with('$')
+}
+
 /// A Choice validates if any one of a set of options validates.
+///
+/// The options are in schema order, and are tried in that order; the first
+/// one that validates wins.  If none do, the error from the option that got
+/// deepest into the data is reported (counting the map keys it matched).
+/// For a group choice inside a map, the option that matched the most map
+/// entries is described instead, listing everything it was missing.
 #[derive(Debug, Clone, PartialEq)]
 #[allow(missing_docs)]
 pub struct Choice {
@@ -189,6 +258,10 @@ pub struct KeyValue {
     pub key: Box<Node>,
     pub value: Box<Node>,
     pub cut: IsCut,
+    /// The name of this member's place in the schema, e.g. `thing.id`.
+    ///
+    /// See [`Map::name`] for how names are made.
+    pub name: Option<String>,
 }
 
 pub(crate) type IsCut = bool;
@@ -200,6 +273,17 @@ impl KeyValue {
             key: Box::new(key),
             value: Box::new(value),
             cut,
+            name: None,
+        }
+    }
+
+    // The key as it's written in member names (see `Map::name`): a text
+    // key without its quotes, another literal key as it is, or `*`.
+    pub(crate) fn key_name(&self) -> Cow<'_, str> {
+        match self.key.as_ref() {
+            Node::Literal(Literal::Text(key)) => key.into(),
+            Node::Literal(key) => key.to_string().into(),
+            _ => "*".into(),
         }
     }
 }
@@ -210,11 +294,17 @@ impl fmt::Display for KeyValue {
     }
 }
 
-// Implement Debug by hand so we can format it like a map.
+// Implement Debug by hand so we can format it like a map.  A cut is shown
+// after the value, so that rules that only differ in their cuts look
+// different.
 impl fmt::Debug for KeyValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut formatter = f.debug_tuple("KeyValue");
-        formatter.field(&self.key).field(&self.value).finish()
+        formatter.field(&self.key).field(&self.value);
+        if self.cut {
+            formatter.field(&"cut");
+        }
+        formatter.finish()
     }
 }
 
@@ -231,19 +321,22 @@ impl fmt::Debug for KeyValue {
 /// ```
 ///
 /// [RFC8610]: https://tools.ietf.org/html/rfc8610
-
 // Re-use the Occur limit type that the parser uses
 pub type OccurLimit = ast::Occur;
 
 /// Occurences specify how many times a value can appear.
 ///
 /// This implementation wraps the Node that the occurrence applies to.
-
 #[derive(Debug, Clone, PartialEq)]
 #[allow(missing_docs)]
 pub struct Occur {
+    /// The occurrence as written in the schema, e.g. `*` or `2*4`.
     pub limit: OccurLimit,
     pub node: Box<Node>,
+    /// Set where the node can never match more than once, e.g. `*` on a
+    /// literal map key, since a key can only appear once.  The matcher
+    /// then treats `limit` as `?`; see [`limits`](Occur::limits).
+    pub at_most_once: bool,
 }
 
 impl Occur {
@@ -252,21 +345,24 @@ impl Occur {
         Occur {
             limit,
             node: Box::new(node),
+            at_most_once: false,
         }
     }
 
-    /// Get the CDDL symbol for this occurrence.
+    /// Get the CDDL symbol for this occurrence, as written in the schema.
     ///
     /// Returns `?`, `*`, `+`, or `n*m`
     pub fn symbol(&self) -> String {
-        match self.limit {
-            OccurLimit::Optional => "?".into(),
-            OccurLimit::ZeroOrMore => "*".into(),
-            OccurLimit::OneOrMore => "+".into(),
-            OccurLimit::Numbered(n, m) => match (n, m) {
-                (0, std::usize::MAX) => format!("{}*", n),
-                (_, _) => format!("{}*{}", n, m),
-            },
+        self.limit.to_string()
+    }
+
+    /// The occurrence the matcher applies: `limit`, or `?` if the node
+    /// can match at most once.
+    pub fn effective_limit(&self) -> OccurLimit {
+        if self.at_most_once {
+            OccurLimit::Optional
+        } else {
+            self.limit.clone()
         }
     }
 
@@ -279,13 +375,8 @@ impl Occur {
     /// optional "?"     => [0, 1]
     /// zero-or-more "*" => [0, MAX]
     /// one-or-more "+"  => [1, MAX]
-    pub fn limits(&self) -> (usize, usize) {
-        match self.limit {
-            OccurLimit::Optional => (0, 1),
-            OccurLimit::ZeroOrMore => (0, std::usize::MAX),
-            OccurLimit::OneOrMore => (1, std::usize::MAX),
-            OccurLimit::Numbered(n, m) => (n, m),
-        }
+    pub fn limits(&self) -> (u64, u64) {
+        self.effective_limit().limits()
     }
 }
 
@@ -300,6 +391,19 @@ impl fmt::Display for Occur {
 #[allow(missing_docs)]
 pub struct Map {
     pub members: Vec<Node>,
+    /// A name for this map, from its place in the rule that defines it.
+    ///
+    /// A map that is a whole rule takes the rule's name.  A map nested
+    /// inside it is named by the path to it: `.key` for a map member and
+    /// `[]` for an array element, so in
+    /// `thing = { items: [* { id: uint }] }` the inner map is
+    /// `thing.items[]` and its member is `thing.items[].id`.  Members with a
+    /// key that isn't a literal value use `*` instead.
+    ///
+    /// Error messages and reports use these names to say where in the
+    /// schema something happened, even though no rule has that name.  This
+    /// is `None` for nodes that aren't part of a named rule.
+    pub name: Option<String>,
 }
 
 /// A context-free group of key-value pairs.
@@ -342,6 +446,10 @@ pub struct Group {
 #[allow(missing_docs)]
 pub struct Array {
     pub members: Vec<Node>,
+    /// A name for this array, from its place in the rule that defines it.
+    ///
+    /// Its elements are named by adding `[]`.  See [`Map::name`].
+    pub name: Option<String>,
 }
 
 /// A range of numbers.
@@ -370,25 +478,33 @@ impl fmt::Display for Range {
 ///
 /// A control operator constrains a type by adding an additional condition
 /// that must be met. For example, "tstr .size 10" permits only strings of
-/// 10 bytes or less.  See RFC 8610 section 3.8 for details.
+/// 10 bytes.  See RFC 8610 section 3.8 for details.
 #[non_exhaustive]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Control {
     /// Limit the size in bytes.
     Size(CtlOpSize),
+    /// Limit which bits may be set.
+    Bits(CtlOpBits),
     /// Apply a regular expression to a text string.
+    ///
+    /// This requires the `regex` feature.
+    #[cfg(feature = "regex")]
     Regexp(CtlOpRegexp),
     /// Validate a nested CBOR bytestring
     Cbor(CtlOpCbor),
+    /// Give a default value, which doesn't change what matches.
+    Default(CtlOpDefault),
 }
 
 /// Control Operator `.size`
 ///
 /// `.size` is defined in RFC 8610 3.8.1.
-/// It sets an upper limit, measured in bytes.
+/// It limits the size, measured in bytes.
 ///
-/// For example, "tstr .size 10" permits only strings of
-/// 10 bytes or less.  See RFC 8610 section 3.8 for details.
+/// For example, "tstr .size 10" permits only strings of 10 bytes, and
+/// "uint .size 2" permits only integers that fit in 2 bytes (0 to 65535).
+/// See RFC 8610 section 3.8 for details.
 #[derive(Debug, Clone, PartialEq)]
 pub struct CtlOpSize {
     /// The type that is size-constrained.
@@ -397,19 +513,59 @@ pub struct CtlOpSize {
     /// `tstr`, `bstr`, and unsigned integers.
     pub target: Box<Node>,
     /// The size limit, in bytes.
+    ///
+    /// This is either a single size, or a range of permitted sizes, e.g.
+    /// `bstr .size (1..63)`.  A single size is the exact size of a text or
+    /// byte string, and the largest size of an unsigned integer.
     pub size: Box<Node>,
 }
 
+/// Control Operator `.bits`
+///
+/// `.bits` is defined in RFC 8610 3.8.2.
+/// Each bit that is set in the target must be a bit number allowed by the
+/// control type.  Bits are numbered from the least significant bit of an
+/// integer, or from the least significant bit of the first byte of a byte
+/// string (so bit 8 is the least significant bit of the second byte).
+///
+/// For example, with `rwx = &(r: 2, w: 1, x: 0)`, `uint .bits rwx` permits
+/// the integers 0 to 7.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CtlOpBits {
+    /// The type whose bits are limited: `uint` or `bstr`.
+    pub target: Box<Node>,
+    /// The allowed bit numbers.
+    ///
+    /// This is an ordinary type, matched against each bit number in turn.
+    /// It's usually a group of named values turned into a choice with `&`,
+    /// so the same rule can also describe a single value, e.g. a field
+    /// containing one of the bit numbers.
+    pub bits: Box<Node>,
+}
+
 /// Control Operator `.regexp`
 ///
 /// `.regexp` is defined in RFC 8610 3.8.3.
 ///
-#[derive(Debug, Clone)]
+#[cfg(feature = "regex")]
+#[derive(Clone)]
 pub struct CtlOpRegexp {
     /// The regular expression, in compiled form.
     pub(crate) re: regex::Regex,
 }
 
+// Implement Debug by hand, so the output doesn't change with the regex
+// crate's formatting.
+#[cfg(feature = "regex")]
+impl fmt::Debug for CtlOpRegexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtlOpRegexp")
+            .field("re", &self.re.as_str())
+            .finish()
+    }
+}
+
+#[cfg(feature = "regex")]
 impl PartialEq for CtlOpRegexp {
     fn eq(&self, other: &Self) -> bool {
         // We only need to compare the string form,
@@ -431,6 +587,35 @@ pub struct CtlOpCbor {
     pub(crate) node: Box<Node>,
 }
 
+/// Control Operator `.default`
+///
+/// `.default` is defined in RFC 8610 3.8.6.
+/// It gives the value an optional map member is assumed to have when it's
+/// absent, e.g. `? retries: uint .default 3`.  Only the target type is
+/// checked during validation; the defaults of a map's members can be found
+/// with [`Schema::defaults_for`](crate::schema::Schema::defaults_for).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CtlOpDefault {
+    /// The type of the value.
+    pub target: Box<Node>,
+    /// The default value.
+    pub default: Literal,
+}
+
+/// Tagged data, e.g. `#6.32(tstr)`.
+///
+/// This matches a CBOR tagged value with the right tag number, whose
+/// content matches the nested node.  Tags that are removed by
+/// [`TagPolicy`](crate::context::TagPolicy) aren't there to be checked, so
+/// for those the nested node is matched against the data directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tagged {
+    /// The tag number, or `None` if any tag is allowed.
+    pub tag: Option<u64>,
+    /// The type of the tagged content.
+    pub node: Box<Node>,
+}
+
 /// Any node in the Intermediate Validation Tree.
 #[derive(Debug, Clone, PartialEq, IntoStaticStr)]
 #[allow(missing_docs)]
@@ -449,6 +634,62 @@ pub enum Node {
     Control(Control),
     Choiceify(Rule),
     ChoiceifyInline(Array),
+    Tagged(Tagged),
+}
+
+impl Node {
+    /// Returns the occurrence of a group member, and the member itself.
+    ///
+    /// For `? age: int` this returns the `?` and the `age: int` key-value
+    /// pair.  A member without an occurrence, like `name: tstr`, must
+    /// appear exactly once; for those this returns `None` and the node
+    /// itself.
+    pub fn occurrence(&self) -> (Option<&OccurLimit>, &Node) {
+        match self {
+            Node::Occur(o) => (Some(&o.limit), &o.node),
+            _ => (None, self),
+        }
+    }
+
+    /// Returns the nodes directly contained by this node.
+    ///
+    /// The generic arguments of a rule reference are included.  The target
+    /// of a rule reference is not, since it lives in a different tree.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Literal(_) | Node::PreludeType(_) => Vec::new(),
+            Node::Rule(r) | Node::Unwrap(r) | Node::Choiceify(r) => r.generic_args.iter().collect(),
+            Node::Choice(c) => c.options.iter().collect(),
+            Node::Map(m) => m.members.iter().collect(),
+            Node::Array(a) | Node::ChoiceifyInline(a) => a.members.iter().collect(),
+            Node::Group(g) => g.members.iter().collect(),
+            Node::KeyValue(kv) => vec![&kv.key, &kv.value],
+            Node::Occur(o) => vec![&o.node],
+            Node::Range(r) => vec![&r.start, &r.end],
+            Node::Control(Control::Size(s)) => vec![&s.target, &s.size],
+            Node::Control(Control::Bits(b)) => vec![&b.target, &b.bits],
+            #[cfg(feature = "regex")]
+            Node::Control(Control::Regexp(_)) => Vec::new(),
+            Node::Control(Control::Cbor(c)) => vec![&c.node],
+            Node::Control(Control::Default(d)) => vec![&d.target],
+            Node::Tagged(t) => vec![&t.node],
+        }
+    }
+
+    /// Visit this node and every node beneath it, in depth-first order.
+    ///
+    /// The visitor is also given the depth of each node; this node has
+    /// depth 1.
+    pub fn walk<F: FnMut(&Node, usize)>(&self, mut visitor: F) {
+        self.walk_inner(&mut visitor, 1);
+    }
+
+    fn walk_inner<F: FnMut(&Node, usize)>(&self, visitor: &mut F, depth: usize) {
+        visitor(self, depth);
+        for child in self.children() {
+            child.walk_inner(visitor, depth + 1);
+        }
+    }
 }
 
 impl fmt::Display for Node {
@@ -457,6 +698,8 @@ impl fmt::Display for Node {
             Node::Literal(l) => write!(f, "{}", l),
             Node::PreludeType(p) => write!(f, "{}", p),
             Node::KeyValue(kv) => write!(f, "{}", kv),
+            Node::Occur(o) => write!(f, "{}", o),
+            Node::Rule(r) => write!(f, "{}", r),
             _ => {
                 let variant: &str = self.into();
                 write!(f, "{}", variant)
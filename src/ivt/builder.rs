@@ -0,0 +1,526 @@
+//! Tools for constructing IVT rules directly, without any CDDL text.
+//!
+//! Rules built here go through the same name resolution and checks as
+//! rules that are parsed and flattened, so they behave the same way
+//! during validation.
+
+use super::*;
+use crate::flatten::{
+    check_range_point, check_size, check_size_limit, flatten_typename, limit_literal_key_occur,
+    name_nodes, rules_ref_map, FlattenOptions, FlattenResult,
+};
+use crate::parser::is_ident;
+use crate::util::{missing_rule_suggest, ValidateError};
+
+/// Create a literal value node, e.g. `literal(7)` or `literal("foo")`.
+pub fn literal<T: Into<Literal>>(value: T) -> Node {
+    Node::Literal(value.into())
+}
+
+/// Create a reference to a rule or prelude type, e.g. `rule_ref("tstr")`.
+///
+/// Names from the standard prelude are resolved to prelude types when the
+/// rules are built, just like they are when CDDL text is flattened.
+pub fn rule_ref(name: &str) -> Node {
+    Node::Rule(Rule::new_name(name))
+}
+
+/// Create a reference to a generic rule, e.g. `pair<tstr, uint>`.
+pub fn generic_ref(name: &str, generic_args: Vec<Node>) -> Node {
+    Node::Rule(Rule::new(name, generic_args))
+}
+
+/// Create a choice between several options, e.g. `tstr / int`.
+pub fn choice(options: Vec<Node>) -> Node {
+    Node::Choice(Choice { options })
+}
+
+/// Create a range between two literals or rule references.
+///
+/// The range is inclusive (`..`) or exclusive (`...`) of the end value.
+pub fn range(start: Node, end: Node, inclusive: bool) -> Node {
+    Node::Range(Range {
+        start: Box::new(start),
+        end: Box::new(end),
+        inclusive,
+    })
+}
+
+// The members of a map, array or group, with their occurrences applied.
+#[derive(Debug, Default)]
+struct Members(Vec<Node>);
+
+impl Members {
+    fn push(&mut self, node: Node, occur: Option<OccurLimit>) {
+        let node = match occur {
+            Some(limit) => Node::Occur(Occur::new(limit, node)),
+            None => node,
+        };
+        self.0.push(node);
+    }
+
+    fn push_member(&mut self, key: Node, value: Node, occur: Option<OccurLimit>) {
+        // Literal keys are written `key: value` in CDDL, which implies a cut.
+        // Other keys can only be written `key => value`, which doesn't.
+        let cut = matches!(key, Node::Literal(_));
+        let kv = Node::KeyValue(KeyValue::new(key, value, cut));
+        self.push(kv, occur);
+    }
+}
+
+/// A builder for a map node, e.g. `{ name: tstr, ? age: uint }`.
+#[derive(Debug, Default)]
+pub struct MapBuilder {
+    members: Members,
+}
+
+impl MapBuilder {
+    /// Start building an empty map.
+    pub fn new() -> MapBuilder {
+        MapBuilder::default()
+    }
+
+    /// Add a key-value member, with an optional occurrence.
+    ///
+    /// Literal keys use cut semantics, like `key: value` in CDDL; other keys
+    /// don't, like `key => value`.
+    pub fn member(mut self, key: Node, value: Node, occur: Option<OccurLimit>) -> MapBuilder {
+        self.members.push_member(key, value, occur);
+        self
+    }
+
+    /// Add a group (usually a reference to a group rule) to the map.
+    pub fn group(mut self, group: Node, occur: Option<OccurLimit>) -> MapBuilder {
+        self.members.push(group, occur);
+        self
+    }
+
+    /// Finish building the map.
+    pub fn build(self) -> Node {
+        Node::Map(Map {
+            members: self.members.0,
+            name: None,
+        })
+    }
+}
+
+/// A builder for an array node, e.g. `[ tstr, * uint ]`.
+#[derive(Debug, Default)]
+pub struct ArrayBuilder {
+    members: Members,
+}
+
+impl ArrayBuilder {
+    /// Start building an empty array.
+    pub fn new() -> ArrayBuilder {
+        ArrayBuilder::default()
+    }
+
+    /// Add an element, with an optional occurrence.
+    ///
+    /// The element may also be a group, which is expanded in place.
+    pub fn element(mut self, value: Node, occur: Option<OccurLimit>) -> ArrayBuilder {
+        self.members.push(value, occur);
+        self
+    }
+
+    /// Finish building the array.
+    pub fn build(self) -> Node {
+        Node::Array(Array {
+            members: self.members.0,
+            name: None,
+        })
+    }
+}
+
+/// A builder for a group node, e.g. `( name: tstr, ? age: uint )`.
+///
+/// A group can be used as a rule of its own, or inside a map or array.
+#[derive(Debug, Default)]
+pub struct GroupBuilder {
+    members: Members,
+}
+
+impl GroupBuilder {
+    /// Start building an empty group.
+    pub fn new() -> GroupBuilder {
+        GroupBuilder::default()
+    }
+
+    /// Add a key-value member, with an optional occurrence.
+    ///
+    /// Keys work the same way as in [`MapBuilder::member`].
+    pub fn member(mut self, key: Node, value: Node, occur: Option<OccurLimit>) -> GroupBuilder {
+        self.members.push_member(key, value, occur);
+        self
+    }
+
+    /// Add a member without a key, with an optional occurrence.
+    pub fn entry(mut self, value: Node, occur: Option<OccurLimit>) -> GroupBuilder {
+        self.members.push(value, occur);
+        self
+    }
+
+    /// Finish building the group.
+    pub fn build(self) -> Node {
+        Node::Group(Group {
+            members: self.members.0,
+        })
+    }
+}
+
+/// A builder for a complete set of rules.
+///
+/// The rules are checked when [`build`] is called: rule names must be
+/// valid CDDL identifiers and defined only once, every rule reference must
+/// name a defined rule (or a prelude type) with the right number of
+/// generic arguments, and map members, ranges, controls and the size of
+/// each rule must pass the same checks as flattened CDDL.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::ivt::{literal, rule_ref, MapBuilder, OccurLimit, RulesBuilder};
+/// use cddl_cat::schema::Schema;
+///
+/// let person = MapBuilder::new()
+///     .member(literal("name"), rule_ref("tstr"), None)
+///     .member(literal("age"), rule_ref("age"), Some(OccurLimit::Optional))
+///     .build();
+/// let rules = RulesBuilder::new()
+///     .rule("person", person)
+///     .rule("age", rule_ref("uint"))
+///     .build()
+///     .unwrap();
+/// let schema = Schema::from_rules(rules);
+/// ```
+///
+/// [`build`]: RulesBuilder::build
+#[derive(Debug, Default)]
+pub struct RulesBuilder {
+    rules: Vec<(String, RuleDef)>,
+}
+
+impl RulesBuilder {
+    /// Start building an empty set of rules.
+    pub fn new() -> RulesBuilder {
+        RulesBuilder::default()
+    }
+
+    /// Add a rule.
+    pub fn rule(self, name: &str, node: Node) -> RulesBuilder {
+        self.generic_rule(name, &[], node)
+    }
+
+    /// Add a rule with generic parameters, e.g. `pair<K, V> = [K, V]`.
+    ///
+    /// The parameters can be used inside the rule with [`rule_ref`].
+    pub fn generic_rule(mut self, name: &str, generic_parms: &[&str], node: Node) -> RulesBuilder {
+        let rule_def = RuleDef {
+            generic_parms: generic_parms.iter().map(|s| s.to_string()).collect(),
+            node,
+        };
+        self.rules.push((name.to_string(), rule_def));
+        self
+    }
+
+    /// Check the rules and resolve their references.
+    pub fn build(self) -> FlattenResult<RulesByName> {
+        // The number of generic parameters for each rule name.
+        let mut parm_counts = BTreeMap::new();
+        for (name, rule_def) in &self.rules {
+            let names = std::iter::once(name).chain(&rule_def.generic_parms);
+            if let Some(bad) = names.into_iter().find(|n| !is_ident(n)) {
+                let msg = format!("invalid rule name '{}'", bad);
+                return Err(ValidateError::Structural(msg));
+            }
+            if parm_counts
+                .insert(name.clone(), rule_def.generic_parms.len())
+                .is_some()
+            {
+                let msg = format!("rule '{}' is already defined", name);
+                return Err(ValidateError::Structural(msg));
+            }
+        }
+
+        let rules = self
+            .rules
+            .into_iter()
+            .map(|(name, rule_def)| {
+                let resolver = Resolver {
+                    parm_counts: &parm_counts,
+                    generic_parms: &rule_def.generic_parms,
+                };
+                let mut node = resolver.resolve(rule_def.node)?;
+                name_nodes(&mut node, &name);
+                let rule_def = RuleDef {
+                    generic_parms: rule_def.generic_parms,
+                    node,
+                };
+                Ok((name, rule_def))
+            })
+            .collect::<FlattenResult<RulesByName>>()?;
+        check_size(rules_ref_map(&rules), &FlattenOptions::default())?;
+        Ok(rules)
+    }
+}
+
+// Resolves the rule references in one rule.
+struct Resolver<'a> {
+    parm_counts: &'a BTreeMap<String, usize>,
+    generic_parms: &'a [String],
+}
+
+impl Resolver<'_> {
+    fn resolve(&self, node: Node) -> FlattenResult<Node> {
+        let result = match node {
+            Node::Literal(_) | Node::PreludeType(_) => node,
+            Node::Rule(r) => self.resolve_name(r)?,
+            Node::Unwrap(r) => Node::Unwrap(self.resolve_group_name(r)?),
+            Node::Choiceify(r) => Node::Choiceify(self.resolve_group_name(r)?),
+            Node::Choice(c) => {
+                if c.options.is_empty() {
+                    return Err(ValidateError::Structural("choice with no options".into()));
+                }
+                Node::Choice(Choice {
+                    options: self.resolve_all(c.options)?,
+                })
+            }
+            Node::Map(m) => {
+                let members: FlattenResult<Vec<Node>> = self
+                    .resolve_all(m.members)?
+                    .into_iter()
+                    .map(limit_literal_key_occur)
+                    .collect();
+                Node::Map(Map {
+                    members: members?,
+                    name: m.name,
+                })
+            }
+            Node::Array(a) => Node::Array(Array {
+                members: self.resolve_all(a.members)?,
+                name: a.name,
+            }),
+            Node::ChoiceifyInline(a) => Node::ChoiceifyInline(Array {
+                members: self.resolve_all(a.members)?,
+                name: a.name,
+            }),
+            Node::Group(g) => Node::Group(Group {
+                members: self.resolve_all(g.members)?,
+            }),
+            Node::KeyValue(kv) => {
+                let key = self.resolve(*kv.key)?;
+                let value = self.resolve(*kv.value)?;
+                Node::KeyValue(KeyValue::new(key, value, kv.cut))
+            }
+            Node::Occur(o) => {
+                let node = self.resolve(*o.node)?;
+                Node::Occur(Occur {
+                    node: Box::new(node),
+                    ..o
+                })
+            }
+            Node::Range(r) => Node::Range(Range {
+                start: Box::new(check_range_point(self.resolve(*r.start)?)?),
+                end: Box::new(check_range_point(self.resolve(*r.end)?)?),
+                inclusive: r.inclusive,
+            }),
+            Node::Control(Control::Size(ctl)) => {
+                let target = self.resolve(*ctl.target)?;
+                let size = self.resolve(*ctl.size)?;
+                check_size_limit(&size)?;
+                Node::Control(Control::Size(CtlOpSize {
+                    target: Box::new(target),
+                    size: Box::new(size),
+                }))
+            }
+            Node::Control(Control::Bits(ctl)) => Node::Control(Control::Bits(CtlOpBits {
+                target: Box::new(self.resolve(*ctl.target)?),
+                bits: Box::new(self.resolve(*ctl.bits)?),
+            })),
+            #[cfg(feature = "regex")]
+            Node::Control(Control::Regexp(ctl)) => Node::Control(Control::Regexp(ctl)),
+            Node::Control(Control::Cbor(ctl)) => Node::Control(Control::Cbor(CtlOpCbor {
+                node: Box::new(self.resolve(*ctl.node)?),
+            })),
+            Node::Control(Control::Default(ctl)) => Node::Control(Control::Default(CtlOpDefault {
+                target: Box::new(self.resolve(*ctl.target)?),
+                default: ctl.default,
+            })),
+            Node::Tagged(t) => Node::Tagged(Tagged {
+                tag: t.tag,
+                node: Box::new(self.resolve(*t.node)?),
+            }),
+        };
+        Ok(result)
+    }
+
+    fn resolve_all(&self, nodes: Vec<Node>) -> FlattenResult<Vec<Node>> {
+        nodes.into_iter().map(|n| self.resolve(n)).collect()
+    }
+
+    // Resolve a name to a prelude type, a generic parameter, or a rule.
+    fn resolve_name(&self, rule: Rule) -> FlattenResult<Node> {
+        match flatten_typename(&rule.name)? {
+            Node::Rule(_) => Ok(Node::Rule(self.resolve_rule(rule)?)),
+            prelude => {
+                if !rule.generic_args.is_empty() {
+                    return Err(ValidateError::GenericError);
+                }
+                Ok(prelude)
+            }
+        }
+    }
+
+    // The unwrap and choiceify operators only work on group rules, not
+    // prelude types.
+    fn resolve_group_name(&self, rule: Rule) -> FlattenResult<Rule> {
+        match flatten_typename(&rule.name) {
+            Ok(Node::Rule(_)) => self.resolve_rule(rule),
+            _ => Err(ValidateError::GenericError),
+        }
+    }
+
+    fn resolve_rule(&self, rule: Rule) -> FlattenResult<Rule> {
+        let expected = if self.generic_parms.contains(&rule.name) {
+            0
+        } else {
+            match self.parm_counts.get(&rule.name) {
+                Some(count) => *count,
+                None => return Err(missing_rule_suggest(&rule.name, self.parm_counts.keys())),
+            }
+        };
+        if rule.generic_args.len() != expected {
+            return Err(ValidateError::GenericError);
+        }
+        let generic_args = self.resolve_all(rule.generic_args)?;
+        Ok(Rule::new(&rule.name, generic_args))
+    }
+}
+
+macro_rules! literal_from {
+    ($variant:ident, $($t:ty),+) => {
+        $(
+            impl From<$t> for Literal {
+                fn from(value: $t) -> Literal {
+                    Literal::$variant(value.into())
+                }
+            }
+        )+
+    };
+}
+
+literal_from!(Bool, bool);
+literal_from!(Int, i32, u32, i64, u64, i128);
+literal_from!(Float, f64);
+literal_from!(Text, &str, String);
+literal_from!(Bytes, &[u8], Vec<u8>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flatten::flatten_from_str;
+
+    #[test]
+    fn test_same_as_flatten() {
+        let cddl_input = r#"
+            person = { name: tstr, ? age: age, * tstr => any }
+            age = 0..150
+            pair<K, V> = [K, V]
+            pairs = [* pair<tstr, uint>]
+            flag = true / "yes" / 1
+            common = ( id: uint, 1*3 tag: bstr .size 8 )
+        "#;
+        let person = MapBuilder::new()
+            .member(literal("name"), rule_ref("tstr"), None)
+            .member(literal("age"), rule_ref("age"), Some(OccurLimit::Optional))
+            .member(
+                rule_ref("tstr"),
+                rule_ref("any"),
+                Some(OccurLimit::ZeroOrMore),
+            )
+            .build();
+        let pair = ArrayBuilder::new()
+            .element(rule_ref("K"), None)
+            .element(rule_ref("V"), None)
+            .build();
+        let pairs = ArrayBuilder::new()
+            .element(
+                generic_ref("pair", vec![rule_ref("tstr"), rule_ref("uint")]),
+                Some(OccurLimit::ZeroOrMore),
+            )
+            .build();
+        let size = Node::Control(Control::Size(CtlOpSize {
+            target: Box::new(rule_ref("bstr")),
+            size: Box::new(literal(8)),
+        }));
+        let common = GroupBuilder::new()
+            .member(literal("id"), rule_ref("uint"), None)
+            .member(literal("tag"), size, Some(OccurLimit::Numbered(1, 3)))
+            .build();
+        let rules = RulesBuilder::new()
+            .rule("person", person)
+            .rule("age", range(literal(0), literal(150), true))
+            .generic_rule("pair", &["K", "V"], pair)
+            .rule("pairs", pairs)
+            .rule(
+                "flag",
+                choice(vec![rule_ref("true"), literal("yes"), literal(1)]),
+            )
+            .rule("common", common)
+            .build()
+            .unwrap();
+        assert_eq!(rules, flatten_from_str(cddl_input).unwrap());
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let build = |name: &str, node: Node| RulesBuilder::new().rule(name, node).build();
+
+        let err = build("a", rule_ref("nope")).unwrap_err();
+        assert_eq!(err.to_string(), "MissingRule(nope)");
+        let err = build("rule", rule_ref("rulle")).unwrap_err();
+        assert_eq!(err.to_string(), "MissingRule(rulle); did you mean 'rule'?");
+        let err = build("my rule", rule_ref("tstr")).unwrap_err();
+        assert_eq!(err.to_string(), "Structural(invalid rule name 'my rule')");
+        let err = RulesBuilder::new()
+            .rule("a", rule_ref("tstr"))
+            .rule("a", rule_ref("uint"))
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Structural(rule 'a' is already defined)");
+        let err = build("a", choice(vec![])).unwrap_err();
+        assert_eq!(err.to_string(), "Structural(choice with no options)");
+
+        // Generic arguments must match the rule's parameters.
+        let err = build("a", generic_ref("tstr", vec![rule_ref("uint")])).unwrap_err();
+        assert_eq!(err.to_string(), "GenericError");
+        let err = RulesBuilder::new()
+            .generic_rule(
+                "a",
+                &["T"],
+                ArrayBuilder::new().element(rule_ref("T"), None).build(),
+            )
+            .rule("b", rule_ref("a"))
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "GenericError");
+
+        // Ranges and map keys get the same checks as flattened CDDL.
+        let err = build("a", range(rule_ref("uint"), literal(5), true)).unwrap_err();
+        assert_eq!(err.to_string(), "Structural(bad type on range operator)");
+        let map = MapBuilder::new()
+            .member(
+                literal("a"),
+                rule_ref("uint"),
+                Some(OccurLimit::Numbered(2, 3)),
+            )
+            .build();
+        let err = build("a", map).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Structural(unsatisfiable occurrence on literal map key: 2*3 "a": Uint)"#
+        );
+        let err = build("a", rule_ref("time")).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported prelude type 'time'");
+    }
+}
@@ -11,11 +11,22 @@
 //! validate_json_str("person", cddl_input, &json_str).unwrap();
 //! ```
 //!
+//! As with the [`cbor`](crate::cbor) functions, the rule is looked up
+//! before any JSON is parsed, so a misspelled rule name fails quickly.
+//! Text that is empty or only whitespace fails with
+//! [`ValidateError::EmptyInput`].
+//!
+//! JSON `null` matches the CDDL type `nil`.  `serde_json` serializes `()`,
+//! unit structs, and `Option::None` as `null`.  A `None` struct field is
+//! still serialized as a map member, so it won't match an optional member
+//! like `? nickname: tstr`; use `nickname: tstr / nil` instead, or skip
+//! the field with `#[serde(skip_serializing_if = "Option::is_none")]`.
+//!
 
 #![cfg(feature = "serde_json")]
 
 use crate::context::{BasicContext, LookupContext};
-use crate::flatten::flatten_from_str;
+use crate::flatten::{flatten_partial_from_str, flatten_type_expr};
 use crate::ivt::RuleDef;
 use crate::util::{ValidateError, ValidateResult};
 use crate::validate::do_validate;
@@ -91,24 +102,78 @@ pub fn validate_json(
 /// Validate JSON-encoded data against a specified rule in a UTF-8 CDDL schema.
 pub fn validate_json_str(name: &str, cddl: &str, json: &str) -> ValidateResult {
     // Parse the CDDL text and flatten it into IVT form.
-    let flat_cddl = flatten_from_str(cddl)?;
-    let ctx = BasicContext::new(flat_cddl);
+    let ctx = BasicContext::from_partial(flatten_partial_from_str(cddl)?);
 
     // Find the rule definition that was requested
-    let rule_def: &RuleDef = ctx
-        .rules
-        .get(name)
-        .ok_or_else(|| ValidateError::MissingRule(name.into()))?;
+    let rule_def: &RuleDef = ctx.lookup_rule(name)?;
 
     // Deserialize the JSON bytes
-    let json_value: JSON_Value =
-        serde_json::from_str(json).map_err(|e| ValidateError::ValueError(format!("{}", e)))?;
+    let json_value = decode_json(json)?;
 
     // Convert the JSON tree into a Value tree for validation
     let value = Value::try_from(json_value)?;
     do_validate(&value, rule_def, &ctx)
 }
 
+// Parse JSON text, rejecting text that is empty or only whitespace with a
+// clearer error than the parser's.
+pub(crate) fn decode_json(json: &str) -> Result<JSON_Value, ValidateError> {
+    if json
+        .trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        .is_empty()
+    {
+        return Err(ValidateError::EmptyInput);
+    }
+    serde_json::from_str(json).map_err(|e| ValidateError::ValueError(format!("{}", e)))
+}
+
+/// Validate JSON-encoded data against a bare CDDL type expression.
+///
+/// The expression is the right-hand side of a type rule, so no `name = ...`
+/// wrapper is needed.  Because no other rules are available, any rule names
+/// used in the expression will fail to resolve; use
+/// [`validate_json_str_expr_with`] to supply them.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::json::validate_json_str_expr;
+///
+/// let json_str = r#"{ "name": "Bob", "age": 43 }"#;
+/// validate_json_str_expr("{ name: tstr, * tstr => any }", json_str).unwrap();
+/// ```
+pub fn validate_json_str_expr(expr: &str, json: &str) -> ValidateResult {
+    let ctx = BasicContext::new(Default::default());
+    validate_json_str_expr_with(expr, json, &ctx)
+}
+
+/// Validate JSON-encoded data against a bare CDDL type expression that may
+/// refer to the rules in `ctx`.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::json::validate_json_str_expr_with;
+/// use cddl_cat::schema::Schema;
+///
+/// let schema = Schema::from_str("age = 0..150").unwrap();
+/// let json_str = r#"{ "name": "Bob", "age": 43 }"#;
+/// validate_json_str_expr_with("{ name: tstr, age: age }", json_str, &schema).unwrap();
+/// ```
+pub fn validate_json_str_expr_with(
+    expr: &str,
+    json: &str,
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
+    // Parse the type expression and flatten it into IVT form.
+    let rule_def = flatten_type_expr(expr)?;
+
+    // Deserialize the JSON bytes
+    let json_value = decode_json(json)?;
+
+    // Convert the JSON tree into a Value tree for validation
+    let value = Value::try_from(json_value)?;
+    do_validate(&value, &rule_def, ctx)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
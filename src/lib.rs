@@ -15,6 +15,10 @@
 //! - Supports CBOR and JSON encodings, controlled by the `serde_cbor` and
 //!   `serde_json` features.
 //!
+//! - The `.regexp` control operator needs the `regex` feature, and `.cbor`
+//!   needs `serde_cbor`. A schema that uses them without the feature is
+//!   rejected when it is flattened.
+//!
 //! - An "Intermediate Validation Tree" ([`ivt`](crate::ivt)) is constructed
 //!   from the CDDL AST; this removes some of the CDDL syntax detail resulting
 //!   in a simplified tree that can be more easily validated. The IVT is
@@ -84,13 +88,18 @@
 //! validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap();
 //! ```
 //! Supported prelude types:
-//! - `any`, `uint`, `nint`, `int`, `bstr`, `bytes`, `tstr`, `text`
+//! - `any`, `uint`, `nint`, `int`, `number`, `bstr`, `bytes`, `tstr`, `text`
 //! - `float`, `float16`, `float32`, `float64`, `float16-32`, `float32-64` \
-//! Note: float sizes are not validated.
+//!   Note: float sizes are not validated.
 //!
 //! Supported CDDL features:
-//! - Basic prelude types (integers, floats, bool, nil, text strings, byte strings)
-//! - Literal int, float, bool, UTF-8 text strings
+//! - Basic prelude types (integers, floats, bool, nil, text strings, byte strings) \
+//!   Note: `bool`, `true` and `false` only match booleans, not the integers 0
+//!   and 1.
+//! - Literal int, float (including hexfloat, e.g. `0x1.8p3`), bool, UTF-8 text strings \
+//!   Note: float literals are compared after converting both sides to f64, so
+//!   `3.14` doesn't match a float16-encoded 3.14 (which is really 3.140625);
+//!   use a range like `3.13..3.15` instead.
 //! - Byte strings in UTF-8, hex, or base64
 //! - Arrays and maps
 //! - Rule lookups by name
@@ -102,19 +111,34 @@
 //! - Turn a group into a choice (`&`)
 //! - Map keys with cut syntax (`^ =>`)
 //! - Generic types
-//! - Control operators `.size` and `.regexp`
+//! - Tagged data (e.g. `#6.32(tstr)`), major types (e.g. `#1`) and `#` \
+//!   Note: a tagged value only matches tagged data or `any`, unless
+//!   `ValidationOptions::tags` removes the tag first.
+//! - Extend type with `/=`, and group with `//=`
+//! - Type sockets with `$`, and group sockets with `$$` \
+//!   Note: a socket with no plugs matches nothing.
+//! - Control operators `.size`, `.bits`, `.regexp`, `.cbor` and `.default`
 //!
 //! Unimplemented CDDL features:
-//! - Extend type with `/=`
-//! - Extend group with `//=`
-//! - Type sockets with `$`
-//! - Group sockets with `$$`
-//! - Control operators other than those above (e.g. `.bits`, `.lt`, `.gt`...)
+//! - Control operators other than those above (e.g. `.lt`, `.gt`...)
 //! - Group enumeration with `&`
-//! - Tagged data with `#`
-//! - Hexfloat literals (e.g. `0x1.921fb5p+1`)
 //! - Prelude types that invoke CBOR tags (e.g. `tdate` or `biguint`)
 //!
+//! [`capabilities()`] reports which constructs a build supports at runtime.
+//!
+//! Limits:
+//! - Validation follows at most 50 nested rule references
+//!   ([`ValidationOptions::max_depth`]). Recursive rules like
+//!   `expr = int / [op, expr, expr]` are fine; the limit is on how deeply
+//!   the data nests through them.
+//! - A rule may expand to at most 1,000,000 IVT nodes
+//!   ([`FlattenOptions::max_nodes`]).
+//! - At most 100 errors are kept when collecting errors
+//!   ([`ValidationOptions::max_errors`]).
+//!
+//! [`ValidationOptions::max_depth`]: context::ValidationOptions::max_depth
+//! [`ValidationOptions::max_errors`]: context::ValidationOptions::max_errors
+//! [`FlattenOptions::max_nodes`]: flatten::FlattenOptions::max_nodes
 //! [RFC8610]: https://tools.ietf.org/html/rfc8610
 //! [CBOR]: https://cbor.io/
 
@@ -123,27 +147,46 @@
 #![warn(clippy::cast_possible_truncation)]
 
 pub mod ast;
+pub mod capabilities;
 pub mod context;
 pub mod flatten;
 pub mod ivt;
+pub mod lint;
 pub mod parser;
+pub mod schema;
+pub mod self_check;
+pub mod session;
 pub mod util;
 #[doc(inline)]
-pub use util::{ValidateError, ValidateResult};
+pub use util::{ErrorCategory, ErrorKind, ValidateError, ValidateErrors, ValidateResult};
 pub(crate) mod validate;
 pub mod value;
+pub mod visit;
 
 #[cfg(feature = "serde_cbor")]
 pub mod cbor;
 #[cfg(feature = "serde_cbor")]
 #[doc(inline)]
-pub use cbor::{validate_cbor, validate_cbor_bytes};
+pub use cbor::{
+    validate_cbor, validate_cbor_base64, validate_cbor_bytes, validate_cbor_bytes_collect,
+    validate_cbor_bytes_expr, validate_cbor_bytes_expr_with, validate_cbor_hex,
+    validate_cbor_owned, validate_cbor_report, validate_cbor_seq_bytes, validate_cbor_slice,
+    validate_cbor_value,
+};
 
 #[cfg(feature = "serde_json")]
 pub mod json;
 #[cfg(feature = "serde_json")]
 #[doc(inline)]
-pub use json::{validate_json, validate_json_str};
+pub use json::{
+    validate_json, validate_json_str, validate_json_str_expr, validate_json_str_expr_with,
+};
 
+#[doc(inline)]
+pub use capabilities::capabilities;
 #[doc(inline)]
 pub use parser::parse_cddl;
+#[doc(inline)]
+pub use schema::compile_cddl;
+#[doc(inline)]
+pub use self_check::self_check;
@@ -0,0 +1,669 @@
+//! This module contains checks for CDDL schemas that are legal, but
+//! probably don't mean what the author intended.
+//!
+//! # Examples
+//! ```
+//! use cddl_cat::lint::lint_cddl;
+//!
+//! let warnings = lint_cddl(r#"thing = { * "tag": tstr }"#).unwrap();
+//! assert_eq!(
+//!     warnings[0].to_string(),
+//!     r#"thing: occurrence "*" on literal map key "tag" can't match more than once"#
+//! );
+//! ```
+//!
+//! Warnings implement `serde::Serialize`, so they can be written as JSON
+//! for other tools.  [`render_text`] writes them in the `file:line:col:`
+//! format understood by most editors.
+
+use crate::ast;
+use crate::parser::slice_parse_cddl;
+use crate::util::ValidateError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// The kind of problem found.
+///
+/// When serialized, each kind is a stable kebab-case string.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintKind {
+    /// An occurrence on a literal map key allows or requires more than one
+    /// match, e.g. `{ * "tag": tstr }`.
+    LiteralKeyOccurrence,
+    /// A map member's keys are all matched first by an earlier wildcard
+    /// member, e.g. `{ * tstr => any, "name": tstr }`.
+    ShadowedMember,
+    /// A control operator needs a cargo feature that isn't enabled in this
+    /// build, e.g. `.regexp` without the `regex` feature.
+    UnavailableControl,
+}
+
+impl LintKind {
+    /// The stable name of this kind, e.g. `literal-key-occurrence`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintKind::LiteralKeyOccurrence => "literal-key-occurrence",
+            LintKind::ShadowedMember => "shadowed-member",
+            LintKind::UnavailableControl => "unavailable-control",
+        }
+    }
+}
+
+/// How serious a problem is.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The schema probably doesn't mean what the author intended.
+    Warning,
+    /// The schema can never match any data.
+    Error,
+}
+
+impl Severity {
+    /// The stable name of this severity, e.g. `warning`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A location in the CDDL text.
+///
+/// Lines and columns start at 1; columns are counted in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// The line number.
+    pub line: usize,
+    /// The column number.
+    pub column: usize,
+}
+
+impl Position {
+    // Find the position of a byte offset in the text.
+    fn from_offset(text: &str, offset: usize) -> Position {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// A warning about a CDDL schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintWarning {
+    kind: LintKind,
+    severity: Severity,
+    rule: String,
+    position: Option<Position>,
+    message: String,
+}
+
+impl LintWarning {
+    /// The kind of problem.
+    pub fn kind(&self) -> LintKind {
+        self.kind
+    }
+
+    /// How serious the problem is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The name of the rule containing the problem.
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    /// The position of the rule containing the problem.
+    ///
+    /// This is only available if the schema was checked with [`lint_cddl`].
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
+    /// A description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Position", 2)?;
+        s.serialize_field("line", &self.line)?;
+        s.serialize_field("column", &self.column)?;
+        s.end()
+    }
+}
+
+/// Serializes as a struct with the fields `kind`, `severity`, `rule`,
+/// `position` (`line` and `column`, or none), and `message`.
+impl Serialize for LintWarning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("LintWarning", 5)?;
+        s.serialize_field("kind", self.kind.name())?;
+        s.serialize_field("severity", self.severity.name())?;
+        s.serialize_field("rule", &self.rule)?;
+        s.serialize_field("position", &self.position)?;
+        s.serialize_field("message", &self.message)?;
+        s.end()
+    }
+}
+
+/// Render warnings as text, one per line, in the style of compiler errors.
+///
+/// Each line starts with `file:line:col:` so that editors can jump to the
+/// problem, e.g.
+/// `schema.cddl:3:1: warning: thing: occurrence "*" on literal map key ...`
+pub fn render_text(warnings: &[LintWarning], file: &str) -> String {
+    let mut out = String::new();
+    for w in warnings {
+        match w.position {
+            Some(p) => out.push_str(&format!("{}:{}:{}: ", file, p.line, p.column)),
+            None => out.push_str(&format!("{}: ", file)),
+        }
+        out.push_str(&format!("{}: {}\n", w.severity.name(), w));
+    }
+    out
+}
+
+/// Parse a CDDL schema and check it for problems.
+///
+/// Returns an error if the schema can't be parsed.
+pub fn lint_cddl(cddl_input: &str) -> Result<Vec<LintWarning>, ValidateError> {
+    let cddl = slice_parse_cddl(cddl_input)?;
+    let mut linter = Linter::default();
+    // Each rule's text is a slice of the input, so we can find where it
+    // starts by searching forward from the end of the previous rule.
+    let mut offset = 0;
+    for (rule, text) in &cddl.rules {
+        linter.position = cddl_input[offset..].find(text.as_str()).map(|n| {
+            offset += n;
+            let position = Position::from_offset(cddl_input, offset);
+            offset += text.len();
+            position
+        });
+        linter.rule(rule);
+    }
+    Ok(linter.warnings)
+}
+
+/// Check an already-parsed CDDL schema for problems.
+pub fn lint(cddl: &ast::Cddl) -> Vec<LintWarning> {
+    let mut linter = Linter::default();
+    for rule in &cddl.rules {
+        linter.rule(rule);
+    }
+    linter.warnings
+}
+
+#[derive(Default)]
+struct Linter {
+    // The name of the rule currently being examined.
+    rule: String,
+    // The position of the rule currently being examined.
+    position: Option<Position>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    fn warn(&mut self, kind: LintKind, severity: Severity, message: String) {
+        self.warnings.push(LintWarning {
+            kind,
+            severity,
+            rule: self.rule.clone(),
+            position: self.position,
+            message,
+        });
+    }
+
+    fn rule(&mut self, rule: &ast::Rule) {
+        self.rule = rule.name.clone();
+        match &rule.val {
+            ast::RuleVal::AssignType(ty) | ast::RuleVal::ExtendType(ty) => self.ty(ty),
+            ast::RuleVal::AssignGroup(grpent) | ast::RuleVal::ExtendGroup(grpent) => {
+                self.grpent(grpent, false)
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &ast::Type) {
+        for ty1 in &ty.0 {
+            self.ty1(ty1);
+        }
+    }
+
+    fn ty1(&mut self, ty1: &ast::Type1) {
+        match ty1 {
+            ast::Type1::Simple(ty2) => self.ty2(ty2),
+            ast::Type1::Range(r) => {
+                self.ty2(&r.start);
+                self.ty2(&r.end);
+            }
+            ast::Type1::Control(ctl) => {
+                if let Some(feature) = ctl.op.missing_feature() {
+                    self.warn(
+                        LintKind::UnavailableControl,
+                        Severity::Error,
+                        format!(
+                            "control '.{}' requires the '{}' feature",
+                            ctl.op.name(),
+                            feature
+                        ),
+                    );
+                }
+                self.ty2(&ctl.target);
+                self.ty2(&ctl.arg);
+            }
+        }
+    }
+
+    fn ty2(&mut self, ty2: &ast::Type2) {
+        match ty2 {
+            ast::Type2::Parethesized(ty) => self.ty(ty),
+            ast::Type2::Map(g) => self.group(g, true),
+            ast::Type2::Array(g) | ast::Type2::ChoiceifyInline(g) => self.group(g, false),
+            ast::Type2::Typename(ng) | ast::Type2::Unwrap(ng) | ast::Type2::Choiceify(ng) => {
+                for arg in &ng.generic_args {
+                    self.ty1(arg);
+                }
+            }
+            ast::Type2::Tagged(t) => self.ty(&t.ty),
+            ast::Type2::Value(_) | ast::Type2::Major(_) | ast::Type2::Any => {}
+        }
+    }
+
+    // `in_map` is true if the group entries are members of a map.
+    fn group(&mut self, group: &ast::Group, in_map: bool) {
+        for grpchoice in &group.0 {
+            if in_map {
+                self.shadowed_members(grpchoice);
+            }
+            for grpent in &grpchoice.0 {
+                self.grpent(grpent, in_map);
+            }
+        }
+    }
+
+    fn grpent(&mut self, grpent: &ast::GrpEnt, in_map: bool) {
+        match &grpent.val {
+            ast::GrpEntVal::Member(member) => {
+                if let (true, Some(occur), Some(key)) = (in_map, &grpent.occur, &member.key) {
+                    self.literal_key_occur(occur, key);
+                }
+                if let Some(ast::MemberKey {
+                    val: ast::MemberKeyVal::Type1(ty1),
+                    ..
+                }) = &member.key
+                {
+                    self.ty1(ty1);
+                }
+                self.ty(&member.value);
+            }
+            ast::GrpEntVal::Groupname(_) => {}
+            ast::GrpEntVal::Parenthesized(g) => self.group(g, in_map),
+        }
+    }
+
+    // A map can only contain a literal key once, so an occurrence allowing
+    // more than one match is misleading (and requiring more than one match
+    // is impossible).
+    fn literal_key_occur(&mut self, occur: &ast::Occur, key: &ast::MemberKey) {
+        let key = match &key.val {
+            ast::MemberKeyVal::Bareword(s) => format!("{:?}", s),
+            ast::MemberKeyVal::Value(v) => literal_string(v),
+            ast::MemberKeyVal::Type1(ast::Type1::Simple(ast::Type2::Value(v))) => literal_string(v),
+            ast::MemberKeyVal::Type1(_) => return,
+        };
+        let (lower, upper) = occur.limits();
+        if lower > 1 {
+            self.warn(
+                LintKind::LiteralKeyOccurrence,
+                Severity::Error,
+                format!(
+                    "occurrence \"{}\" on literal map key {} can never be satisfied",
+                    occur, key
+                ),
+            );
+        } else if upper > 1 {
+            self.warn(
+                LintKind::LiteralKeyOccurrence,
+                Severity::Warning,
+                format!(
+                    "occurrence \"{}\" on literal map key {} can't match more than once",
+                    occur, key
+                ),
+            );
+        }
+    }
+}
+
+impl Linter {
+    // Map members are matched in order, and a member with an occurrence
+    // like `*` takes every key it can.  So a later member whose keys are all
+    // of the wildcard's key type only gets the entries whose values the
+    // wildcard rejected; if the wildcard accepts the same values, none at
+    // all.
+    //
+    // Only members of the same group choice are compared; members of
+    // nested or named groups are not.
+    fn shadowed_members(&mut self, grpchoice: &ast::GrpChoice) {
+        let mut wildcards: Vec<(&ast::Occur, KeyType, &ast::Type)> = Vec::new();
+        for grpent in &grpchoice.0 {
+            let member = match &grpent.val {
+                ast::GrpEntVal::Member(m) => m,
+                _ => continue,
+            };
+            let key = match member.key.as_ref().and_then(|k| map_key(&k.val)) {
+                Some(key) => key,
+                None => continue,
+            };
+            let wildcard = wildcards
+                .iter()
+                .find(|(_, wild_key, _)| wild_key.contains(&key.0));
+            if let Some((occur, wild_key, wild_value)) = wildcard {
+                let (lower, _) = grpent.occur.as_ref().map_or((1, 1), ast::Occur::limits);
+                let takes_all = is_any(wild_value) || **wild_value == member.value;
+                let wild = format!("{} {}", occur, wild_key.name());
+                if !takes_all {
+                    self.warn(
+                        LintKind::ShadowedMember,
+                        Severity::Warning,
+                        format!(
+                            "map key {} overlaps the earlier {} => ...; which member matches an entry depends on their order",
+                            key.1, wild
+                        ),
+                    );
+                } else if lower > 0 {
+                    self.warn(
+                        LintKind::ShadowedMember,
+                        Severity::Error,
+                        format!(
+                            "map key {} is always taken by the earlier {} => ..., so it can never be satisfied",
+                            key.1, wild
+                        ),
+                    );
+                } else {
+                    self.warn(
+                        LintKind::ShadowedMember,
+                        Severity::Warning,
+                        format!(
+                            "map key {} is always taken by the earlier {} => ..., so it can never match",
+                            key.1, wild
+                        ),
+                    );
+                }
+            }
+            if let (Some(occur), MapKey::Type(key_type)) = (&grpent.occur, &key.0) {
+                if occur.limits().1 == u64::MAX {
+                    wildcards.push((occur, *key_type, &member.value));
+                }
+            }
+        }
+    }
+}
+
+// The broad type of a map key, as far as we can tell from the syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyType {
+    Any,
+    Text,
+    Bytes,
+    Int,
+    Uint,
+    Nint,
+    Float,
+}
+
+impl KeyType {
+    fn from_name(name: &str) -> Option<KeyType> {
+        let key_type = match name {
+            "any" => KeyType::Any,
+            "tstr" | "text" => KeyType::Text,
+            "bstr" | "bytes" => KeyType::Bytes,
+            "int" => KeyType::Int,
+            "uint" => KeyType::Uint,
+            "nint" => KeyType::Nint,
+            "float" | "float16" | "float32" | "float64" | "float16-32" | "float32-64" => {
+                KeyType::Float
+            }
+            _ => return None,
+        };
+        Some(key_type)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            KeyType::Any => "any",
+            KeyType::Text => "tstr",
+            KeyType::Bytes => "bstr",
+            KeyType::Int => "int",
+            KeyType::Uint => "uint",
+            KeyType::Nint => "nint",
+            KeyType::Float => "float",
+        }
+    }
+
+    // Returns true if every key matching `key` also matches this type.
+    fn contains(&self, key: &MapKey) -> bool {
+        let key_type = match key {
+            MapKey::Type(t) => *t,
+            MapKey::Bareword => KeyType::Text,
+            MapKey::Literal(ast::Value::Text(_)) => KeyType::Text,
+            MapKey::Literal(ast::Value::Bytes(_)) => KeyType::Bytes,
+            MapKey::Literal(ast::Value::Uint(_)) => KeyType::Uint,
+            MapKey::Literal(ast::Value::Nint(_)) => KeyType::Nint,
+            MapKey::Literal(ast::Value::Float(_)) => KeyType::Float,
+        };
+        match (self, key_type) {
+            (KeyType::Any, _) => true,
+            (KeyType::Int, KeyType::Uint) | (KeyType::Int, KeyType::Nint) => true,
+            (a, b) => *a == b,
+        }
+    }
+}
+
+enum MapKey<'a> {
+    Type(KeyType),
+    Bareword,
+    Literal(&'a ast::Value),
+}
+
+// Find out what kind of keys a map member matches, and describe it.
+// Returns None for keys that are too complicated to compare.
+fn map_key(key: &ast::MemberKeyVal) -> Option<(MapKey<'_>, String)> {
+    match key {
+        ast::MemberKeyVal::Bareword(s) => Some((MapKey::Bareword, format!("{:?}", s))),
+        ast::MemberKeyVal::Value(v)
+        | ast::MemberKeyVal::Type1(ast::Type1::Simple(ast::Type2::Value(v))) => {
+            Some((MapKey::Literal(v), literal_string(v)))
+        }
+        ast::MemberKeyVal::Type1(ast::Type1::Simple(ast::Type2::Typename(ng)))
+            if ng.generic_args.is_empty() =>
+        {
+            let key_type = KeyType::from_name(&ng.name)?;
+            Some((MapKey::Type(key_type), ng.name.clone()))
+        }
+        ast::MemberKeyVal::Type1(_) => None,
+    }
+}
+
+fn is_any(ty: &ast::Type) -> bool {
+    matches!(
+        ty.0.as_slice(),
+        [ast::Type1::Simple(ast::Type2::Typename(ng))] if ng.name == "any" && ng.generic_args.is_empty()
+    )
+}
+
+fn literal_string(value: &ast::Value) -> String {
+    match value {
+        ast::Value::Text(s) => format!("{:?}", s),
+        ast::Value::Uint(n) => n.to_string(),
+        ast::Value::Nint(n) => n.to_string(),
+        ast::Value::Float(n) => n.to_string(),
+        ast::Value::Bytes(b) => format!("{:?}", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_strings(cddl_input: &str) -> Vec<String> {
+        let warnings = lint_cddl(cddl_input).unwrap();
+        warnings.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_literal_key_occur() {
+        let none: Vec<String> = vec![];
+
+        assert_eq!(lint_strings(r#"a = { ? "tag": tstr }"#), none);
+        assert_eq!(lint_strings(r#"a = { * tstr => tstr }"#), none);
+        assert_eq!(lint_strings(r#"a = [ * "tag": tstr ]"#), none);
+        assert_eq!(lint_strings(r#"a = { 0*1 tag: tstr }"#), none);
+
+        assert_eq!(
+            lint_strings(r#"a = { + tag: tstr }"#),
+            vec![r#"a: occurrence "+" on literal map key "tag" can't match more than once"#]
+        );
+        assert_eq!(
+            lint_strings(r#"a = { foo: [ { 1*5 7 => int } ] }"#),
+            vec![r#"a: occurrence "1*5" on literal map key 7 can't match more than once"#]
+        );
+        assert_eq!(
+            lint_strings(r#"a = { (b: int, 2* "c" ^ => int) }"#),
+            vec![r#"a: occurrence "2*" on literal map key "c" can never be satisfied"#]
+        );
+
+        let warnings = lint_cddl("a = int  b = { * x: int }").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule(), "b");
+    }
+
+    #[test]
+    fn test_shadowed_member() {
+        let none: Vec<String> = vec![];
+
+        assert_eq!(lint_strings(r#"a = { name: tstr, * tstr => any }"#), none);
+        assert_eq!(lint_strings(r#"a = { * tstr => any, 1: int }"#), none);
+        assert_eq!(lint_strings(r#"a = { ? tstr => any, name: tstr }"#), none);
+        assert_eq!(lint_strings(r#"a = { * uint => any, -1: int }"#), none);
+        assert_eq!(lint_strings(r#"a = [ * tstr => any, name: tstr ]"#), none);
+        assert_eq!(lint_strings(r#"a = { * tstr => any // name: tstr }"#), none);
+
+        assert_eq!(
+            lint_strings(r#"a = { * tstr => any, "name": tstr }"#),
+            vec![
+                r#"a: map key "name" is always taken by the earlier * tstr => ..., so it can never be satisfied"#
+            ]
+        );
+        assert_eq!(
+            lint_strings(r#"a = { + int => tstr, ? uint => tstr }"#),
+            vec![
+                r#"a: map key uint is always taken by the earlier + int => ..., so it can never match"#
+            ]
+        );
+        assert_eq!(
+            lint_strings(r#"a = { * any => any, ? 3: int }"#),
+            vec![
+                r#"a: map key 3 is always taken by the earlier * any => ..., so it can never match"#
+            ]
+        );
+        assert_eq!(
+            lint_strings(r#"a = { * tstr => int, * tstr => tstr }"#),
+            vec![
+                r#"a: map key tstr overlaps the earlier * tstr => ...; which member matches an entry depends on their order"#
+            ]
+        );
+
+        let warnings = lint_cddl(r#"a = { * text => any, name: tstr }"#).unwrap();
+        assert_eq!(warnings[0].kind(), LintKind::ShadowedMember);
+        assert_eq!(warnings[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    fn test_unavailable_control() {
+        let warnings = lint_strings(r#"a = { name: tstr .regexp "[a-z]+" }"#);
+        if cfg!(feature = "regex") {
+            assert!(warnings.is_empty());
+        } else {
+            assert_eq!(
+                warnings,
+                vec!["a: control '.regexp' requires the 'regex' feature"]
+            );
+        }
+
+        let warnings = lint_cddl("a = bstr .cbor uint").unwrap();
+        if cfg!(feature = "serde_cbor") {
+            assert!(warnings.is_empty());
+        } else {
+            assert_eq!(warnings[0].kind(), LintKind::UnavailableControl);
+            assert_eq!(warnings[0].severity(), Severity::Error);
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        let cddl_input = "a = { * x: int }\n; comment\n\n  b = int  c = { 2* \"é\": int }\n";
+        let warnings = lint_cddl(cddl_input).unwrap();
+        let positions: Vec<_> = warnings.iter().map(|w| w.position().unwrap()).collect();
+        assert_eq!(
+            positions,
+            vec![
+                Position { line: 1, column: 1 },
+                Position {
+                    line: 4,
+                    column: 12
+                },
+            ]
+        );
+        assert_eq!(warnings[0].severity(), Severity::Warning);
+        assert_eq!(warnings[1].severity(), Severity::Error);
+
+        let expected = "\
+schema.cddl:1:1: warning: a: occurrence \"*\" on literal map key \"x\" can't match more than once
+schema.cddl:4:12: error: c: occurrence \"2*\" on literal map key \"é\" can never be satisfied
+";
+        assert_eq!(render_text(&warnings, "schema.cddl"), expected);
+
+        // Without the source text, there are no positions.
+        let cddl = crate::parser::parse_cddl(cddl_input).unwrap();
+        let warnings = lint(&cddl);
+        assert_eq!(warnings[0].position(), None);
+        assert_eq!(
+            render_text(&warnings[..1], "schema.cddl"),
+            "schema.cddl: warning: a: occurrence \"*\" on literal map key \"x\" can't match more than once\n"
+        );
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_serialize() {
+        let warnings = lint_cddl("a = { * x: int }").unwrap();
+        let json = serde_json::to_value(&warnings).unwrap();
+        let expected = serde_json::json!([{
+            "kind": "literal-key-occurrence",
+            "severity": "warning",
+            "rule": "a",
+            "position": { "line": 1, "column": 1 },
+            "message": "occurrence \"*\" on literal map key \"x\" can't match more than once",
+        }]);
+        assert_eq!(json, expected);
+
+        let warnings = lint(&crate::parser::parse_cddl("a = { * x: int }").unwrap());
+        let json = serde_json::to_value(&warnings).unwrap();
+        assert_eq!(json[0]["position"], serde_json::Value::Null);
+    }
+}
@@ -14,11 +14,11 @@
 use escape8259::unescape;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while1},
+    bytes::complete::{tag, take_till, take_while1},
     character::complete::{
-        anychar, char as charx, digit0, digit1, hex_digit1, multispace1, not_line_ending, one_of,
+        anychar, char as charx, digit0, digit1, hex_digit1, multispace1, one_of,
     },
-    combinator::{all_consuming, map, map_res, opt, recognize, value as valuex},
+    combinator::{all_consuming, map, map_res, opt, peek, recognize, value as valuex},
     multi::{many0, many1, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
 };
@@ -75,18 +75,21 @@ where
 
 // This varies a bit from the RFC, again, with respect to whitespace.
 #[rustfmt::skip]
-fn comment(input: &str) -> JResult<&str, &str> {
-    // semicolon, anything, terminated by CR or CR+LF.
+fn comment(input: &str) -> JResult<'_, &str, &str> {
+    // semicolon, anything, terminated by LF (which may be preceded by a CR),
+    // or by the end of the input.  A CR is part of the comment text, so a
+    // lone CR doesn't end the comment (and a CR without a LF at the end of
+    // the file is still accepted).
     preceded(
         charx(';'),
-        not_line_ending
+        take_till(|c| c == '\n')
     )(input)
 }
 
 // Any amount of whitespace (including none) (including comments)
 // Note: this counts tab characters as whitespace, which differs from RFC8610.
 #[rustfmt::skip]
-fn ws(input: &str) -> JResult<&str, &str> {
+fn ws(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         many0(
             alt((
@@ -101,7 +104,7 @@ fn ws(input: &str) -> JResult<&str, &str> {
 
 // An optional comma and any whitespace surrounding it.
 #[rustfmt::skip]
-fn optcom(input: &str) -> JResult<&str, ()> {
+fn optcom(input: &str) -> JResult<'_, &str, ()> {
     valuex((), pair(
         ws,
         opt(pair(
@@ -127,14 +130,14 @@ fn ealpha_char(c: char) -> bool {
 }
 
 #[rustfmt::skip]
-fn ealpha(input: &str) -> JResult<&str, &str> {
+fn ealpha(input: &str) -> JResult<'_, &str, &str> {
     take_while1(ealpha_char)
     (input)
 }
 
 // The tail characters of an ident: *("-" / ".") (EALPHA / DIGIT)
 #[rustfmt::skip]
-fn ident_tail(input: &str) -> JResult<&str, &str> {
+fn ident_tail(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         preceded(
             opt(many1(one_of("-."))),
@@ -148,7 +151,7 @@ fn ident_tail(input: &str) -> JResult<&str, &str> {
 }
 
 #[rustfmt::skip]
-fn ident(input: &str) -> JResult<&str, &str> {
+fn ident(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         preceded(
             ealpha,
@@ -163,7 +166,7 @@ fn ident(input: &str) -> JResult<&str, &str> {
 //      / "0b" 1*BINDIG
 //      / "0"
 #[rustfmt::skip]
-fn uint_hex(input: &str) -> JResult<&str, &str> {
+fn uint_hex(input: &str) -> JResult<'_, &str, &str> {
     preceded(
         tag("0x"),
         hex_digit1
@@ -172,7 +175,7 @@ fn uint_hex(input: &str) -> JResult<&str, &str> {
 }
 
 #[rustfmt::skip]
-fn uint_binary(input: &str) -> JResult<&str, &str> {
+fn uint_binary(input: &str) -> JResult<'_, &str, &str> {
     preceded(
         tag("0b"),
         recognize(many1(one_of("01")))
@@ -181,7 +184,7 @@ fn uint_binary(input: &str) -> JResult<&str, &str> {
 }
 
 #[rustfmt::skip]
-fn uint_decimal(input: &str) -> JResult<&str, &str> {
+fn uint_decimal(input: &str) -> JResult<'_, &str, &str> {
     alt((
         tag("0"),
         recognize(
@@ -206,7 +209,7 @@ struct RawUint<'a> {
 
 // Parse the string for uint; return the slice and the intended base (radix).
 #[rustfmt::skip]
-fn uint(input: &str) -> JResult<&str, RawUint> {
+fn uint(input: &str) -> JResult<'_, &str, RawUint<'_>> {
     alt((
         map(uint_hex, |slice| RawUint{slice, base: 16}),
         map(uint_binary, |slice| RawUint{slice, base: 2}),
@@ -219,7 +222,7 @@ fn uint(input: &str) -> JResult<&str, RawUint> {
 
 // Extract an unsigned integer using the correct base (radix).
 #[rustfmt::skip]
-fn uint_u64(input: &str) -> JResult<&str, u64> {
+fn uint_u64(input: &str) -> JResult<'_, &str, u64> {
     map_res_fail(uint, |raw| {
         u64::from_str_radix(raw.slice, raw.base)
         .map_err(|_| {
@@ -239,7 +242,7 @@ struct RawInt<'a> {
 
 // A signed integer
 #[rustfmt::skip]
-fn int(input: &str) -> JResult<&str, RawInt> {
+fn int(input: &str) -> JResult<'_, &str, RawInt<'_>> {
     let f = pair(
         opt(charx('-')),
         uint
@@ -257,7 +260,7 @@ fn int(input: &str) -> JResult<&str, RawInt> {
 // "." fraction
 // fraction = 1*DIGIT
 #[rustfmt::skip]
-fn dot_fraction(input: &str) -> JResult<&str, &str> {
+fn dot_fraction(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         pair(
             charx('.'),
@@ -270,7 +273,7 @@ fn dot_fraction(input: &str) -> JResult<&str, &str> {
 // "e" exponent
 // exponent = ["+"/"-"] 1*DIGIT
 #[rustfmt::skip]
-fn e_exponent(input: &str) -> JResult<&str, &str> {
+fn e_exponent(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         tuple((
             charx('e'),
@@ -283,11 +286,15 @@ fn e_exponent(input: &str) -> JResult<&str, &str> {
 
 // A helper function for converting string -> Value::Float,
 // and mapping to the right error type
+//
+// CDDL has no syntax for infinity or NaN, so a literal that's too large
+// to fit in an f64 (e.g. `1e999`) is an error, rather than quietly
+// becoming infinity.
 #[rustfmt::skip]
-fn parse_float(s: &str) -> Result<Value, CowParseError> {
+fn parse_float(s: &str) -> Result<Value, CowParseError<'_>> {
     match s.parse::<f64>() {
-        Ok(fl) => Ok(Value::Float(fl)),
-        Err(_) => Err(parse_error(ErrorKind::MalformedFloat, s)),
+        Ok(fl) if fl.is_finite() => Ok(Value::Float(fl)),
+        _ => Err(parse_error(ErrorKind::MalformedFloat, s)),
     }
 }
 
@@ -296,49 +303,64 @@ fn parse_float(s: &str) -> Result<Value, CowParseError> {
 fn parse_int(raw: RawInt) -> Result<Value, CowParseError> {
     // Note: the string slice doesn't contain the '-' character, so we
     // need to handle that ourselves.
-    let posint = u64::from_str_radix(raw.slice, raw.base)
+    let magnitude = u128::from_str_radix(raw.slice, raw.base)
         .map_err(|_| parse_error(ErrorKind::MalformedInteger, raw.slice))?;
 
     if raw.neg {
-        // i64 has a larger positive range than negative range, so if we
-        // survive the conversion to i64 then unary `-` must succeed.
-        let negint: i64 = try_into_int(posint, raw.slice)?;
-        Ok(Value::Nint(-negint))
+        // CBOR negative integers go down to -2^64, so that's the limit
+        // we accept here. Anything that survives this check can be
+        // negated without overflowing an i128.
+        if magnitude > 1 << 64 {
+            return Err(parse_error(ErrorKind::MalformedInteger, raw.slice));
+        }
+        Ok(Value::Nint(-(magnitude as i128)))
     } else {
+        let posint: u64 = try_into_int(magnitude, raw.slice)?;
         Ok(Value::Uint(posint))
     }
 }
 
-// A rough approximation of nom's offset() function, allowing us to
-// do something like nom's recognize() without losing the inner types.
-fn offset(whole: &str, part: &str) -> usize {
-    part.as_ptr() as usize - whole.as_ptr() as usize
+// Find where the remaining input starts, like nom's offset() function,
+// allowing us to do something like nom's recognize() without losing the
+// inner types.
+//
+// `rest` should be a suffix of `whole`, as returned by a parser given
+// `whole`.  If it isn't (e.g. the parser returned a static string), this
+// returns None rather than making up an offset.
+fn offset(whole: &str, rest: &str) -> Option<usize> {
+    let start = whole.as_ptr() as usize;
+    let rest_start = rest.as_ptr() as usize;
+    let index = rest_start.checked_sub(start)?;
+    if index.checked_add(rest.len())? != whole.len() {
+        return None;
+    }
+    // A suffix of a valid string always starts on a char boundary.
+    debug_assert!(whole.is_char_boundary(index));
+    Some(index)
 }
 
 // This is similar to nom's `recognize` function.
 // The difference is that it doesn't throw away the inner parser's result;
 // it returns a tuple (slice, result) so you can have both.
-fn recognizer<'a, O, E, F>(
-    mut parser: F,
-) -> impl FnMut(&'a str) -> nom::IResult<&'a str, (&'a str, O), E>
+fn recognizer<'a, O, F>(mut parser: F) -> impl FnMut(&'a str) -> JResult<'a, &'a str, (&'a str, O)>
 where
-    E: nom::error::ParseError<&'a str>,
-    F: FnMut(&'a str) -> nom::IResult<&'a str, O, E>,
+    F: FnMut(&'a str) -> JResult<'a, &'a str, O>,
 {
-    move |input: &'a str| match parser(input) {
-        Ok((i, output)) => {
-            let index = offset(input, i);
-            let output_slice = &input[..index];
-            let output_tuple = (output_slice, output);
-            Ok((i, output_tuple))
+    move |input: &'a str| {
+        let (i, output) = parser(input)?;
+        match offset(input, i) {
+            Some(index) => Ok((i, (&input[..index], output))),
+            None => Err(nom::Err::Failure(parse_error(
+                ErrorKind::Internal,
+                "recognizer: remaining input isn't part of the input",
+            ))),
         }
-        Err(e) => Err(e),
     }
 }
 
 // int ["." fraction] ["e" exponent ]
 // (must have at least one of the latter two to be a float)
-fn float_or_int(input: &str) -> JResult<&str, Value> {
+fn float_or_int(input: &str) -> JResult<'_, &str, Value> {
     let f = recognizer(tuple((int, opt(dot_fraction), opt(e_exponent))));
     map_res_fail(f, |(recognized, output)| {
         let (firstint, frac, exp) = output;
@@ -354,6 +376,60 @@ fn float_or_int(input: &str) -> JResult<&str, Value> {
     })(input)
 }
 
+// hexfloat = ["-"] "0x" 1*HEXDIG ["." 1*HEXDIG] "p" exponent
+#[rustfmt::skip]
+fn hexfloat(input: &str) -> JResult<'_, &str, Value> {
+    let f = recognizer(tuple((
+        opt(charx('-')),
+        preceded(
+            tag("0x"),
+            hex_digit1
+        ),
+        opt(
+            preceded(
+                charx('.'),
+                hex_digit1
+            )
+        ),
+        preceded(
+            charx('p'),
+            recognize(
+                pair(
+                    opt(one_of("+-")),
+                    digit1
+                )
+            )
+        ),
+    )));
+    map_res_fail(f, |(recognized, (neg, int, frac, exp))| {
+        let frac = frac.unwrap_or("");
+        let malformed = || parse_error(ErrorKind::MalformedFloat, recognized);
+
+        // The mantissa is all the hex digits, with the point moved to the
+        // end by adjusting the exponent to match.
+        let digits = [int, frac].concat();
+        let mantissa = u128::from_str_radix(&digits, 16).map_err(|_| malformed())?;
+        let exp: i32 = exp.parse().map_err(|_| malformed())?;
+        let exp = i32::try_from(frac.len())
+            .ok()
+            .and_then(|len| len.checked_mul(4))
+            .and_then(|shift| exp.checked_sub(shift))
+            .ok_or_else(malformed)?;
+
+        // Scale in two steps, so that a power of two that's out of range
+        // doesn't spoil a result that isn't.
+        let half = exp / 2;
+        let magnitude = mantissa as f64 * 2f64.powi(half) * 2f64.powi(exp - half);
+        // Like decimal floats, hexfloats can't overflow to infinity.
+        if !magnitude.is_finite() {
+            return Err(malformed());
+        }
+        let fl = if neg.is_some() { -magnitude } else { magnitude };
+        Ok(Value::Float(fl))
+    })
+    (input)
+}
+
 // bytes = [bsqual] %x27 *BCHAR %x27
 // BCHAR = %x20-26 / %x28-5B / %x5D-10FFFD / SESC / CRLF
 // bsqual = "h" / "b64"
@@ -371,7 +447,7 @@ fn is_unescaped_bchar(c: char) -> bool {
         (0x20 ..= 0x26),
         (0x28 ..= 0x5B),
         (0x5D ..= 0x7E),
-        (0x80 ..= 0x10FFD),
+        (0x80 ..= 0x10FFFD),
     ];
     let cv = c as u32;
 
@@ -380,46 +456,81 @@ fn is_unescaped_bchar(c: char) -> bool {
 
 // One or more unescaped byte-string characters
 #[rustfmt::skip]
-fn unescaped_bchar(input: &str) -> JResult<&str, &str> {
+fn unescaped_bchar(input: &str) -> JResult<'_, &str, &str> {
     take_while1(is_unescaped_bchar)
     (input)
 }
 
-// FIXME: should also permit included CRLF
+// CRLF = %x0A / %x0D.0A
+#[rustfmt::skip]
+fn crlf(input: &str) -> JResult<'_, &str, &str> {
+    alt((
+        tag("\n"),
+        tag("\r\n"),
+    ))
+    (input)
+}
+
 // Zero or more byte-string characters
 #[rustfmt::skip]
-fn bchar(input: &str) -> JResult<&str, &str> {
+fn bchar(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         many0(
             alt((
                 unescaped_bchar,
-                sesc
+                sesc,
+                crlf,
             ))
         )
     )
     (input)
 }
 
+// Unescape the contents of a UTF-8 byte string.
+//
+// These use the same escapes as text strings, plus `\'`.  Unlike text
+// strings they may also contain `"` and line breaks, so those are escaped
+// before the JSON-style unescaping.
+fn unescape_bchar(s: &str) -> Result<String, CowParseError<'_>> {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\'') => escaped.push('\''),
+                Some(next) => {
+                    escaped.push('\\');
+                    escaped.push(next);
+                }
+                None => escaped.push('\\'),
+            },
+            '"' => escaped.push_str("\\\""),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    unescape(&escaped).map_err(|_| parse_error(ErrorKind::MalformedText, s))
+}
+
 // This is basically identical to `text_literal` except that
 // it uses single-quotes.
 #[rustfmt::skip]
-fn bytestring_utf8(input: &str) -> JResult<&str, String> {
+fn bytestring_utf8(input: &str) -> JResult<'_, &str, String> {
     let f = delimited(
         tag("'"),
         bchar,
         tag("'")
     );
 
-    map_res_fail(f, |s| {
-        unescape(s).map_err(|_| parse_error(ErrorKind::MalformedText, s) )
-    })
+    map_res_fail(f, unescape_bchar)
     (input)
 }
 
 // This doesn't check that only the right characters are used;
 // that will be done later during parsing of the string.
 #[rustfmt::skip]
-fn bytestring_hex(input: &str) -> JResult<&str, &str> {
+fn bytestring_hex(input: &str) -> JResult<'_, &str, &str> {
     delimited(
         tag("h'"),
         bchar,
@@ -430,7 +541,7 @@ fn bytestring_hex(input: &str) -> JResult<&str, &str> {
 // This doesn't check that only the right characters are used;
 // that will be done later during parsing of the string.
 #[rustfmt::skip]
-fn bytestring_base64(input: &str) -> JResult<&str, &str> {
+fn bytestring_base64(input: &str) -> JResult<'_, &str, &str> {
     delimited(
         tag("b64'"),
         bchar,
@@ -440,7 +551,7 @@ fn bytestring_base64(input: &str) -> JResult<&str, &str> {
 
 // A helper function for parsing hex digits to bytes, while
 // ignoring whitespace and mapping to the right error type.
-fn parse_hex(s: &str) -> Result<Vec<u8>, CowParseError> {
+fn parse_hex(s: &str) -> Result<Vec<u8>, CowParseError<'_>> {
     // strip whitespace
     // FIXME: this consumes more chars than the RFC says we should.
     let s: String = s.chars().filter(|c| !c.is_ascii_whitespace()).collect();
@@ -448,16 +559,30 @@ fn parse_hex(s: &str) -> Result<Vec<u8>, CowParseError> {
     hex::decode(&s).map_err(|_| parse_error(ErrorKind::MalformedHex, s))
 }
 
+// A helper function for parsing base64 to bytes, mapping to the right
+// error type.  CDDL uses the "base64url" alphabet.
+fn parse_base64(s: &str) -> Result<Vec<u8>, CowParseError<'_>> {
+    base64::decode_config(s, base64::URL_SAFE)
+        .map_err(|_| parse_error(ErrorKind::MalformedBase64, s))
+}
+
+// A name followed directly by a quote can only be a qualifier, so if it got
+// this far it's one we don't know, e.g. `q'abcd'`.  Fail here rather than
+// let the name be parsed as a type, which gives a less useful error later.
+// Like other parse errors, this keeps the input from where it went wrong.
+#[rustfmt::skip]
+fn bytestring_unknown(input: &str) -> JResult<'_, &str, Vec<u8>> {
+    terminated(ident, peek(charx('\'')))(input)?;
+    Err(nom::Err::Failure(parse_error(ErrorKind::UnknownQualifier, input)))
+}
+
 #[rustfmt::skip]
-fn bytestring(input: &str) -> JResult<&str, Vec<u8>> {
+fn bytestring(input: &str) -> JResult<'_, &str, Vec<u8>> {
     alt((
         map(bytestring_utf8, |s| s.as_bytes().into()),
         map_res_fail(bytestring_hex, parse_hex),
-        map_res_fail(bytestring_base64, |s| {
-            base64::decode_config(s, base64::URL_SAFE).map_err(|_| {
-                parse_error(ErrorKind::MalformedBase64, s)
-            })
-        }),
+        map_res_fail(bytestring_base64, parse_base64),
+        bytestring_unknown,
     ))
     (input)
 }
@@ -472,7 +597,7 @@ fn is_unescaped_schar(c: char) -> bool {
         (0x20 ..= 0x21),
         (0x23 ..= 0x5B),
         (0x5D ..= 0x7E),
-        (0x80 ..= 0x10FFD),
+        (0x80 ..= 0x10FFFD),
     ];
     let cv = c as u32;
 
@@ -481,14 +606,14 @@ fn is_unescaped_schar(c: char) -> bool {
 
 // One or more unescaped text characters
 #[rustfmt::skip]
-fn unescaped_schar(input: &str) -> JResult<&str, &str> {
+fn unescaped_schar(input: &str) -> JResult<'_, &str, &str> {
     take_while1(is_unescaped_schar)
     (input)
 }
 
 // A single escaped character
 #[rustfmt::skip]
-fn sesc(input: &str) -> JResult<&str, &str> {
+fn sesc(input: &str) -> JResult<'_, &str, &str> {
     // FIXME: allow only (%x20-7E / %x80-10FFFD)
     preceded(charx('\\'), recognize(anychar))
     (input)
@@ -496,7 +621,7 @@ fn sesc(input: &str) -> JResult<&str, &str> {
 
 // Zero or more text characters
 #[rustfmt::skip]
-fn schar(input: &str) -> JResult<&str, &str> {
+fn schar(input: &str) -> JResult<'_, &str, &str> {
     recognize(
         many0(
             alt((
@@ -509,7 +634,7 @@ fn schar(input: &str) -> JResult<&str, &str> {
 }
 
 #[rustfmt::skip]
-fn text_literal(input: &str) -> JResult<&str, String> {
+fn text_literal(input: &str) -> JResult<'_, &str, String> {
     let f = delimited(
         charx('"'),
         schar,
@@ -525,8 +650,9 @@ fn text_literal(input: &str) -> JResult<&str, String> {
 // value = number / text / bytes
 // number = hexfloat / (int ["." fraction] ["e" exponent ])
 #[rustfmt::skip]
-fn value(input: &str) -> JResult<&str, Value> {
+fn value(input: &str) -> JResult<'_, &str, Value> {
     alt((
+        hexfloat,
         float_or_int,
         map(text_literal, Value::Text),
         map(bytestring, Value::Bytes),
@@ -535,7 +661,7 @@ fn value(input: &str) -> JResult<&str, Value> {
 
 // Match the ": Y" part of an "X : Y" memberkey.
 #[rustfmt::skip]
-fn grpent_memberkey_tail(input: &str) -> JResult<&str, Type> {
+fn grpent_memberkey_tail(input: &str) -> JResult<'_, &str, Type> {
     preceded(
         pair(
             tag(":"),
@@ -618,7 +744,7 @@ fn assemble_basic_member(key: Type1, value: Type) -> Result<Member, CowParseErro
 // we attempt to match that syntax before trying the others.
 //
 #[rustfmt::skip]
-fn grpent_member(input: &str) -> JResult<&str, Member> {
+fn grpent_member(input: &str) -> JResult<'_, &str, Member> {
 
     // The leading Type1 is required for this parser.
     let (input, first_type1) = terminated(type1, ws)(input)?;
@@ -688,7 +814,7 @@ fn grpent_member(input: &str) -> JResult<&str, Member> {
 }
 
 #[rustfmt::skip]
-fn grpent_parens(input: &str) -> JResult<&str, Group> {
+fn grpent_parens(input: &str) -> JResult<'_, &str, Group> {
     delimited(
         charx('('),
         delimited(
@@ -701,7 +827,7 @@ fn grpent_parens(input: &str) -> JResult<&str, Group> {
 }
 
 #[rustfmt::skip]
-fn grpent_val(input: &str) -> JResult<&str, GrpEntVal> {
+fn grpent_val(input: &str) -> JResult<'_, &str, GrpEntVal> {
     alt((
         map(grpent_member, GrpEntVal::Member),
         map(ident, |s| GrpEntVal::Groupname(s.into())),
@@ -710,9 +836,9 @@ fn grpent_val(input: &str) -> JResult<&str, GrpEntVal> {
     (input)
 }
 
-// A helper function that does u64->usize conversion, returning
+// A helper function that does integer conversion, returning
 // CowParseError(MalformedInteger) on failure.
-fn try_into_int<T, U>(x: T, source: &str) -> Result<U, CowParseError>
+fn try_into_int<T, U>(x: T, source: &str) -> Result<U, CowParseError<'_>>
 where
     U: TryFrom<T>,
 {
@@ -723,28 +849,22 @@ where
 //       / "+"
 //       / "?"
 #[rustfmt::skip]
-fn occur_star(input: &str) -> JResult<&str, Occur> {
+fn occur_star(input: &str) -> JResult<'_, &str, Occur> {
     let f = tuple((
         opt(uint_u64),
         tag("*"),
         opt(uint_u64),
     ));
     // FIXME: it's really not the parser's business to be inventing an upper
-    // limit here.  Plus, the use of usize::MAX is kind of gross.
+    // limit here.  Plus, the use of u64::MAX is kind of gross.
     // The parser should leave these as Option and leave it to others to
     // decide what to do with that.
     map_res(f, |tup| -> Result<Occur, CowParseError> {
         if tup.0.is_none() && tup.2.is_none() {
             Ok(Occur::ZeroOrMore)
         } else {
-            let lower: usize = match tup.0 {
-                Some(n) => try_into_int(n, input)?,
-                None => 0,
-            };
-            let upper: usize = match tup.2 {
-                Some(n) => try_into_int(n, input)?,
-                None => std::usize::MAX,
-            };
+            let lower = tup.0.unwrap_or(0);
+            let upper = tup.2.unwrap_or(u64::MAX);
             Ok(Occur::Numbered(lower, upper))
         }
     })
@@ -752,7 +872,7 @@ fn occur_star(input: &str) -> JResult<&str, Occur> {
 }
 
 #[rustfmt::skip]
-fn occur(input: &str) -> JResult<&str, Occur> {
+fn occur(input: &str) -> JResult<'_, &str, Occur> {
     alt((
         occur_star,
         valuex(Occur::OneOrMore, tag("+")),
@@ -766,7 +886,7 @@ fn occur(input: &str) -> JResult<&str, Occur> {
 //        / [occur S] "(" S group S ")"
 
 #[rustfmt::skip]
-fn grpent(input: &str) -> JResult<&str, GrpEnt> {
+fn grpent(input: &str) -> JResult<'_, &str, GrpEnt> {
     let f = pair(
         opt(terminated(occur, ws)),
         grpent_val
@@ -777,7 +897,7 @@ fn grpent(input: &str) -> JResult<&str, GrpEnt> {
 
 // grpchoice = zero-or-more "grpent optional-comma"
 #[rustfmt::skip]
-fn grpchoice(input: &str) -> JResult<&str, GrpChoice> {
+fn grpchoice(input: &str) -> JResult<'_, &str, GrpChoice> {
     let f = many0(
         terminated(grpent, optcom)
     );
@@ -787,7 +907,7 @@ fn grpchoice(input: &str) -> JResult<&str, GrpChoice> {
 
 // group = grpchoice *(S "//" S grpchoice)
 #[rustfmt::skip]
-fn group(input: &str) -> JResult<&str, Group> {
+fn group(input: &str) -> JResult<'_, &str, Group> {
 
     // It would have been great to write this as
     //  separated_list1(
@@ -818,7 +938,7 @@ fn group(input: &str) -> JResult<&str, Group> {
 
 // "(" S type S ")"
 #[rustfmt::skip]
-fn type2_parens(input: &str) -> JResult<&str, Type> {
+fn type2_parens(input: &str) -> JResult<'_, &str, Type> {
     delimited(
         charx('('),
         delimited(
@@ -832,7 +952,7 @@ fn type2_parens(input: &str) -> JResult<&str, Type> {
 
 // "{" S group S "}"
 #[rustfmt::skip]
-fn type2_map(input: &str) -> JResult<&str, Group> {
+fn type2_map(input: &str) -> JResult<'_, &str, Group> {
     delimited(
         charx('{'),
         delimited(
@@ -846,7 +966,7 @@ fn type2_map(input: &str) -> JResult<&str, Group> {
 
 // "[" S group S "]"
 #[rustfmt::skip]
-fn type2_array(input: &str) -> JResult<&str, Group> {
+fn type2_array(input: &str) -> JResult<'_, &str, Group> {
     delimited(
         charx('['),
         delimited(
@@ -860,7 +980,7 @@ fn type2_array(input: &str) -> JResult<&str, Group> {
 
 // "~" S typename [genericarg]
 #[rustfmt::skip]
-fn type2_unwrap(input: &str) -> JResult<&str, NameGeneric> {
+fn type2_unwrap(input: &str) -> JResult<'_, &str, NameGeneric> {
     preceded(
         tag("~"),
         preceded(
@@ -876,7 +996,7 @@ fn type2_unwrap(input: &str) -> JResult<&str, NameGeneric> {
 // what that operator should be called, and "group choice" already means
 // something different.
 #[rustfmt::skip]
-fn type2_choiceify(input: &str) -> JResult<&str, NameGeneric> {
+fn type2_choiceify(input: &str) -> JResult<'_, &str, NameGeneric> {
     preceded(
         tag("&"),
         preceded(
@@ -889,7 +1009,7 @@ fn type2_choiceify(input: &str) -> JResult<&str, NameGeneric> {
 
 // "&" S "(" S group S ")"
 #[rustfmt::skip]
-fn type2_choiceify_inline(input: &str) -> JResult<&str, Group> {
+fn type2_choiceify_inline(input: &str) -> JResult<'_, &str, Group> {
     preceded(
         tag("&"),
         preceded(
@@ -908,6 +1028,51 @@ fn type2_choiceify_inline(input: &str) -> JResult<&str, Group> {
     (input)
 }
 
+// "#" "6" ["." uint] "(" S type S ")"
+#[rustfmt::skip]
+fn type2_tagged(input: &str) -> JResult<'_, &str, TypeTagged> {
+    let f = preceded(
+        tag("#6"),
+        pair(
+            opt(
+                preceded(
+                    charx('.'),
+                    uint_u64
+                )
+            ),
+            delimited(
+                pair(charx('('), ws),
+                ty,
+                pair(ws, charx(')'))
+            )
+        )
+    );
+    map(f, |(tag, ty)| TypeTagged { tag, ty })
+    (input)
+}
+
+// "#" DIGIT ["." uint]
+#[rustfmt::skip]
+fn type2_major(input: &str) -> JResult<'_, &str, TypeMajor> {
+    let f = preceded(
+        charx('#'),
+        pair(
+            one_of("0123456789"),
+            opt(
+                preceded(
+                    charx('.'),
+                    uint_u64
+                )
+            )
+        )
+    );
+    map(f, |(digit, info)| TypeMajor {
+        major: digit as u8 - b'0',
+        info,
+    })
+    (input)
+}
+
 // type2 = value
 //       / typename [genericarg]
 //       / "(" S type S ")"
@@ -920,23 +1085,51 @@ fn type2_choiceify_inline(input: &str) -> JResult<&str, Group> {
 //       / "#" DIGIT ["." uint]
 //       / "#"
 #[rustfmt::skip]
-fn type2(input: &str) -> JResult<&str, Type2> {
-    alt((
-        map(value, Type2::Value),
-        map(name_generic, Type2::Typename),
-        map(type2_parens, Type2::Parethesized),
-        map(type2_map, Type2::Map),
-        map(type2_array, Type2::Array),
-        map(type2_unwrap, Type2::Unwrap),
-        map(type2_choiceify_inline, Type2::ChoiceifyInline),
-        map(type2_choiceify, Type2::Choiceify),
-    ))
-    (input)
+fn type2(input: &str) -> JResult<'_, &str, Type2> {
+    // This is the hottest parser in the grammar, so rather than trying
+    // every alternative in turn, peek at the first character to decide
+    // which alternatives could possibly match.
+    //
+    // The only ambiguity is that a name may be the qualifier of a byte
+    // string (e.g. "h" or "b64"), so both are tried in that case, in the
+    // same order as the grammar.
+    let result = match input.chars().next() {
+        Some('-') | Some('0'..='9') | Some('"') | Some('\'') => {
+            map(value, Type2::Value)(input)
+        }
+        Some(c) if ealpha_char(c) => alt((
+            map(bytestring, |b| Type2::Value(Value::Bytes(b))),
+            map(name_generic, Type2::Typename),
+        ))(input),
+        Some('(') => map(type2_parens, Type2::Parethesized)(input),
+        Some('{') => map(type2_map, Type2::Map)(input),
+        Some('[') => map(type2_array, Type2::Array)(input),
+        Some('~') => map(type2_unwrap, Type2::Unwrap)(input),
+        Some('&') => alt((
+            map(type2_choiceify_inline, Type2::ChoiceifyInline),
+            map(type2_choiceify, Type2::Choiceify),
+        ))(input),
+        Some('#') => alt((
+            map(type2_tagged, Type2::Tagged),
+            map(type2_major, Type2::Major),
+            map(charx('#'), |_| Type2::Any),
+        ))(input),
+        _ => Err(nom::Err::Error(parse_error(ErrorKind::Unparseable, input))),
+    };
+
+    // Report recoverable errors the same way that trying every
+    // alternative would have (the last alternative fails immediately).
+    match result {
+        Err(nom::Err::Error(_)) => {
+            Err(nom::Err::Error(parse_error(ErrorKind::Unparseable, input)))
+        }
+        other => other,
+    }
 }
 
 // Returns the string containing the control identifier
 #[rustfmt::skip]
-fn control_op(input: &str) -> JResult<&str, &str> {
+fn control_op(input: &str) -> JResult<'_, &str, &str> {
     preceded(
         tag("."),
         ident
@@ -947,7 +1140,7 @@ fn control_op(input: &str) -> JResult<&str, &str> {
 // Returns the range or control operator string
 // (either ".." or "..." or the control identifier)
 #[rustfmt::skip]
-fn range_or_control_op(input: &str) -> JResult<&str, (&str, Type2)> {
+fn range_or_control_op(input: &str) -> JResult<'_, &str, (&str, Type2)> {
     pair(
         alt((
             tag("..."),
@@ -964,7 +1157,7 @@ fn range_or_control_op(input: &str) -> JResult<&str, (&str, Type2)> {
 
 // type1 = type2 [S (rangeop / ctlop) S type2]
 #[rustfmt::skip]
-fn type1(input: &str) -> JResult<&str, Type1> {
+fn type1(input: &str) -> JResult<'_, &str, Type1> {
     let f = pair(
         type2,
         opt(
@@ -997,7 +1190,7 @@ fn type1(input: &str) -> JResult<&str, Type1> {
 
 // type = type1 [ / type1 ... ]  (skipping over type1 for now)
 #[rustfmt::skip]
-fn ty(input: &str) -> JResult<&str, Type> {
+fn ty(input: &str) -> JResult<'_, &str, Type> {
     let f = separated_list1(
         delimited(ws, tag("/"), ws),
         type1
@@ -1023,25 +1216,35 @@ fn ty(input: &str) -> JResult<&str, Type> {
 // This is the right side of a rule: one of:
 //     assignt S type
 //     assigng S grpent
+// where assignt is "=" or "/=", and assigng is "=" or "//=".
 #[rustfmt::skip]
-fn rule_val(input: &str) -> JResult<&str, RuleVal> {
-    let f = separated_pair(
-        tag("="),
-        ws,
+fn rule_val(input: &str) -> JResult<'_, &str, RuleVal> {
+    let assign = preceded(
+        pair(tag("="), ws),
         alt((
             map(ty, RuleVal::AssignType),
             map(grpent, RuleVal::AssignGroup)
         ))
     );
-    // We're just throwing away the operator for now, but we'll need it
-    // later when we implement extend operators /= //=
-    map(f, |(_op, val)| val )
+    let extend_type = preceded(
+        pair(tag("/="), ws),
+        map(ty, RuleVal::ExtendType)
+    );
+    let extend_group = preceded(
+        pair(tag("//="), ws),
+        map(grpent, RuleVal::ExtendGroup)
+    );
+    alt((
+        assign,
+        extend_type,
+        extend_group,
+    ))
     (input)
 }
 
 // genericparm = "<" S id S *("," S id S ) ">"
 #[rustfmt::skip]
-fn generic_parm(input: &str) -> JResult<&str, Vec<&str>> {
+fn generic_parm(input: &str) -> JResult<'_, &str, Vec<&str>> {
     delimited(
         pair(tag("<"), ws),
         separated_list1(
@@ -1053,7 +1256,7 @@ fn generic_parm(input: &str) -> JResult<&str, Vec<&str>> {
 
 // genericarg = "<" S type1 S *("," S type1 S ) ">"
 #[rustfmt::skip]
-fn generic_arg(input: &str) -> JResult<&str, Vec<Type1>> {
+fn generic_arg(input: &str) -> JResult<'_, &str, Vec<Type1>> {
     delimited(
         pair(tag("<"), ws),
         separated_list1(
@@ -1065,7 +1268,7 @@ fn generic_arg(input: &str) -> JResult<&str, Vec<Type1>> {
 
 // A type or group name, followed by optional generic arguments.
 #[rustfmt::skip]
-fn name_generic(input: &str) -> JResult<&str, NameGeneric> {
+fn name_generic(input: &str) -> JResult<'_, &str, NameGeneric> {
     let f = pair(ident, opt(generic_arg));
     map(f, |(name, generic)| {
         // Replace None with empty Vec.
@@ -1079,7 +1282,7 @@ fn name_generic(input: &str) -> JResult<&str, NameGeneric> {
 }
 
 #[rustfmt::skip]
-fn rule(input: &str) -> JResult<&str, Rule> {
+fn rule(input: &str) -> JResult<'_, &str, Rule> {
     let f = separated_pair(
         pair(
             ident,
@@ -1098,7 +1301,7 @@ fn rule(input: &str) -> JResult<&str, Rule> {
 
 // cddl = S 1*(rule S)
 #[rustfmt::skip]
-fn cddl(input: &str) -> JResult<&str, Cddl> {
+fn cddl(input: &str) -> JResult<'_, &str, Cddl> {
     let f = preceded(ws,
         many1(
             terminated(rule, ws)
@@ -1109,7 +1312,7 @@ fn cddl(input: &str) -> JResult<&str, Cddl> {
 }
 
 #[rustfmt::skip]
-fn cddl_slice(input: &str) -> JResult<&str, CddlSlice> {
+fn cddl_slice(input: &str) -> JResult<'_, &str, CddlSlice> {
     let f = preceded(ws,
         many1(
             terminated(
@@ -1142,6 +1345,23 @@ pub fn parse_cddl(input: &str) -> Result<Cddl, ParseError> {
     Ok(result.1)
 }
 
+/// An entry point for parsing a bare CDDL type expression.
+///
+/// This parses only the right-hand side of a type rule (e.g.
+/// `{ name: tstr, * tstr => any }` or `uint .size 2`), without requiring
+/// it to be wrapped in a `name = ...` rule.
+///
+/// # Examples
+/// ```
+/// use cddl_cat::parser::parse_type_expr;
+///
+/// assert!(parse_type_expr("uint .size 2").is_ok());
+/// ```
+pub fn parse_type_expr(input: &str) -> Result<Type, ParseError> {
+    let result = all_consuming(delimited(ws, ty, ws))(input)?;
+    Ok(result.1)
+}
+
 /// An entry point for parsing CDDL text, preserving rule strings
 ///
 /// This operates exactly like [`parse_cddl`], but stores a copy of the rule's
@@ -1151,6 +1371,11 @@ pub fn slice_parse_cddl(input: &str) -> Result<CddlSlice, ParseError> {
     Ok(result.1)
 }
 
+// Returns true if the whole string is a valid CDDL identifier.
+pub(crate) fn is_ident(input: &str) -> bool {
+    all_consuming(ident)(input).is_ok()
+}
+
 // Useful utilities for testing the parser.
 #[cfg(test)]
 #[macro_use]
@@ -1243,7 +1468,7 @@ mod test_utils {
             if self >= 0 {
                 Value::Uint(self as u64)
             } else {
-                Value::Nint(self)
+                Value::Nint(self.into())
             }
         }
     }
@@ -1371,6 +1596,37 @@ mod tests {
         assert_eq!(remainder, "");
     }
 
+    #[test]
+    fn test_comment_at_eof() {
+        let inputs = [
+            // comment at EOF, with and without a newline
+            "a = int\n; end of file",
+            "a = int\n; end of file\n",
+            "a = int\n;",
+            // rule then comment at EOF
+            "a = int ; end of file",
+            "a = int; end of file\n",
+            // CRLF line endings
+            "a = int\r\nb = tstr\r\n",
+            "a = int ; one\r\nb = tstr ; two\r\n",
+            "a = int\r\n; end of file\r\n",
+            "a = int ; end of file\r",
+            "a = int\r\n; end of file\r",
+            "a = { b: int ; one\r\n c: tstr ; two\r\n }\r\n",
+        ];
+        for input in &inputs {
+            let result = parse_cddl(input);
+            assert!(result.is_ok(), "{:?}: {:?}", input, result);
+            let result = slice_parse_cddl(input);
+            assert!(result.is_ok(), "{:?}: {:?}", input, result);
+        }
+
+        // A comment only ends at a newline, so a lone CR can't start new
+        // rules in the middle of it.
+        let result = parse_cddl("a = int ; one\rb = tstr\n").unwrap();
+        assert_eq!(result.rules.len(), 1);
+    }
+
     #[test]
     fn test_ident() {
         assert_eq!(ident("a"), Ok(("", "a")));
@@ -1402,6 +1658,16 @@ mod tests {
         assert_eq!(float_or_int("1e"), Ok(("e", Value::Uint(1))));
         assert_eq!(float_or_int("1."), Ok((".", Value::Uint(1))));
         assert!(float_or_int("abc").is_err());
+        // Floats don't overflow to infinity.
+        assert_eq!(
+            float_or_int("1e999"),
+            Err(nom::Err::Failure(parse_error(
+                ErrorKind::MalformedFloat,
+                "1e999"
+            )))
+        );
+        assert!(float_or_int("-1e999").is_err());
+        assert_eq!(float_or_int("1e-999"), Ok(("", Value::Float(0.0))));
 
         assert_eq!(float_or_int("0x100"), Ok(("", Value::Uint(256))));
         assert_eq!(float_or_int("0b101"), Ok(("", Value::Uint(5))));
@@ -1411,12 +1677,77 @@ mod tests {
         assert_eq!(float_or_int("-0x100"), Ok(("", Value::Nint(-256))));
         assert_eq!(float_or_int("-0b101"), Ok(("", Value::Nint(-5))));
 
+        // Integer boundaries: uint stops at 2^64-1, nint at -2^64.
+        assert_eq!(
+            float_or_int("18446744073709551615"),
+            Ok(("", Value::Uint(u64::MAX)))
+        );
+        assert!(float_or_int("18446744073709551616").is_err());
+        assert_eq!(
+            float_or_int("-9223372036854775808"),
+            Ok(("", Value::Nint(i64::MIN.into())))
+        );
+        assert_eq!(
+            float_or_int("-9223372036854775809"),
+            Ok(("", Value::Nint(i128::from(i64::MIN) - 1)))
+        );
+        assert_eq!(
+            float_or_int("-18446744073709551616"),
+            Ok(("", Value::Nint(-(1 << 64))))
+        );
+        assert_eq!(
+            float_or_int("-0x10000000000000000"),
+            Ok(("", Value::Nint(-(1 << 64))))
+        );
+        assert!(float_or_int("-18446744073709551617").is_err());
+        assert!(float_or_int("-0x10000000000000001").is_err());
+
         // While this is allowed in the CDDL grammar, it doesn't make logical sense
         // so we want to return an error.
         assert!(float_or_int("0b1e99").is_err());
         assert!(float_or_int("0b1.1").is_err());
     }
 
+    #[test]
+    fn test_hexfloat() {
+        assert_eq!(hexfloat("0x1.8p3"), Ok(("", Value::Float(12.0))));
+        assert_eq!(hexfloat("0x1p-2"), Ok(("", Value::Float(0.25))));
+        assert_eq!(hexfloat("0xAp+1"), Ok(("", Value::Float(20.0))));
+        assert_eq!(hexfloat("-0x1.8p3"), Ok(("", Value::Float(-12.0))));
+        assert_eq!(
+            hexfloat("0x1.921fb54442d18p1"),
+            Ok(("", Value::Float(std::f64::consts::PI)))
+        );
+        assert_eq!(hexfloat("0x1p-1074"), Ok(("", Value::Float(5e-324))));
+        assert_eq!(
+            hexfloat("0x1p1023"),
+            Ok(("", Value::Float(2f64.powi(1023))))
+        );
+
+        // Hexfloat comes first, so it isn't taken for an int.
+        assert_eq!(value("0x1.8p3"), Ok(("", Value::Float(12.0))));
+        assert_eq!(value("-0x1p0"), Ok(("", Value::Float(-1.0))));
+        assert_eq!(value("0x18"), Ok(("", Value::Uint(24))));
+        assert_eq!(value("0x1p"), Ok(("p", Value::Uint(1))));
+
+        // The "p" exponent isn't optional.
+        assert!(hexfloat("0x1.8").is_err());
+        assert!(hexfloat("0x.8p1").is_err());
+        assert!(value("0x1.8").is_err());
+
+        // Too many digits to keep.
+        assert_eq!(
+            hexfloat("0x123456789abcdef0123456789abcdef01p0"),
+            Err(nom::Err::Failure(parse_error(
+                ErrorKind::MalformedFloat,
+                "0x123456789abcdef0123456789abcdef01p0"
+            )))
+        );
+        assert!(hexfloat("0x1p99999999999").is_err());
+        assert!(hexfloat("0x1p1024").is_err());
+        assert!(hexfloat("-0x1p1024").is_err());
+    }
+
     #[test]
     fn test_bytestring() {
         let result1 = bytestring("'abc'");
@@ -1433,11 +1764,26 @@ mod tests {
         // bytestring in UTF-8 with escapes
         assert_eq!(bytestring(r#"'a\nb'"#), Ok(("", "a\nb".into())));
         assert_eq!(bytestring(r#"'\uD834\uDD1E'"#), Ok(("", "𝄞".into())));
+        assert_eq!(bytestring(r#"'it\'s'"#), Ok(("", "it's".into())));
+        assert_eq!(bytestring(r#"'\\'"#), Ok(("", "\\".into())));
+
+        // Any other printable characters, including spaces, digits and
+        // double quotes
+        assert_eq!(bytestring("'hello world'"), Ok(("", "hello world".into())));
+        assert_eq!(bytestring("'a1!'"), Ok(("", "a1!".into())));
+        assert_eq!(bytestring(r#"'say "hi"'"#), Ok(("", r#"say "hi""#.into())));
+        assert_eq!(bytestring("'😀'"), Ok(("", "😀".into())));
+        // and line breaks
+        assert_eq!(bytestring("'a\nb\r\nc'"), Ok(("", "a\nb\r\nc".into())));
+        assert!(bytestring("'a\tb'").is_err());
+        assert!(bytestring("'a\rb'").is_err());
 
         // Non-text bytes
         let result2 = vec![0u8, 0xFF, 1, 0x7F];
         assert_eq!(Ok(("", result2.clone())), bytestring("h'00FF017f'"));
-        assert_eq!(Ok(("", result2)), bytestring("b64'AP8Bfw=='"));
+        assert_eq!(Ok(("", result2.clone())), bytestring("b64'AP8Bfw=='"));
+        // Padding may be left out.
+        assert_eq!(Ok(("", result2)), bytestring("b64'AP8Bfw'"));
 
         // Empty inputs
         assert_eq!(Ok(("", vec![])), bytestring("h''"));
@@ -1462,6 +1808,12 @@ mod tests {
             ErrorKind::MalformedBase64
         );
 
+        // A single leftover character can't encode a byte.
+        assert_eq!(
+            fail_kind(bytestring("b64'AP8BfwAAA'").unwrap_err()),
+            ErrorKind::MalformedBase64
+        );
+
         // wrong flavor of base64: CDDL requires the "base64url" encoding.
         assert_eq!(
             // base64 encoding of FBEF00 using the wrong encoder.
@@ -1473,6 +1825,25 @@ mod tests {
             fail_kind(bytestring("b64'////'").unwrap_err()),
             ErrorKind::MalformedBase64
         );
+
+        // Only "h" and "b64" are qualifiers.
+        assert_eq!(
+            fail_kind(bytestring("q'abcd'").unwrap_err()),
+            ErrorKind::UnknownQualifier
+        );
+        assert_eq!(
+            fail_kind(bytestring("hex'00'").unwrap_err()),
+            ErrorKind::UnknownQualifier
+        );
+        // The error points at the qualifier.
+        let input = "[1, x'00']";
+        match bytestring(&input[4..]).unwrap_err() {
+            nom::Err::Failure(e) => assert_eq!(offset(input, &e.ctx), Some(4)),
+            e => panic!("{:?}", e),
+        }
+        // A name that isn't followed by a quote isn't a byte string at all.
+        bytestring("q 'abcd'").unwrap_err();
+        assert!(matches!(bytestring("q").unwrap_err(), nom::Err::Error(_)));
     }
 
     #[test]
@@ -1480,6 +1851,8 @@ mod tests {
         assert!(is_unescaped_schar('A'));
         assert!(is_unescaped_schar('の'));
         assert!(is_unescaped_schar(std::char::from_u32(0x10FF0).unwrap()));
+        assert!(is_unescaped_schar('😀'));
+        assert!(is_unescaped_schar(std::char::from_u32(0x10FFFD).unwrap()));
         assert!(!is_unescaped_schar(0x7F as char));
 
         assert_eq!(unescaped_schar("Aの"), Ok(("", "Aの")));
@@ -1562,9 +1935,13 @@ mod tests {
         assert_eq!(occur("+"), Ok(("", Occur::OneOrMore)));
         assert_eq!(occur("*"), Ok(("", Occur::ZeroOrMore)));
         assert_eq!(occur("*9"), Ok(("", Occur::Numbered(0, 9))));
-        assert_eq!(occur("7*"), Ok(("", Occur::Numbered(7, std::usize::MAX))));
+        assert_eq!(occur("7*"), Ok(("", Occur::Numbered(7, u64::MAX))));
         assert_eq!(occur("7*9"), Ok(("", Occur::Numbered(7, 9))));
         assert_eq!(occur("0b100*0x10"), Ok(("", Occur::Numbered(4, 16))));
+        assert_eq!(
+            occur("0*5000000000"),
+            Ok(("", Occur::Numbered(0, 5_000_000_000)))
+        );
     }
 
     #[test]
@@ -1628,17 +2005,63 @@ mod tests {
             result.unwrap().1,
             Type1::Control(TypeControl {
                 target: "uint".into(),
-                op: "size".to_string(),
+                op: ControlOp::Size,
                 arg: 3.literal().into(),
             })
         );
 
+        let result = type1("tstr .regexp \"a+\"");
+        match result.unwrap().1 {
+            Type1::Control(ctl) => assert_eq!(ctl.op, ControlOp::Regexp),
+            _ => panic!("expected control"),
+        }
+
+        let result = type1("tstr .nonsense 1");
+        match result.unwrap().1 {
+            Type1::Control(ctl) => {
+                assert_eq!(ctl.op, ControlOp::Other("nonsense".into()));
+                assert_eq!(ctl.op.name(), "nonsense");
+            }
+            _ => panic!("expected control"),
+        }
+
         // RFC8610 2.2.2.1 points out that "min..max" is not a range, but an identifier
         // (because '.' is a valid ident character).
         let result = type2("min..max");
         assert_eq!(result.unwrap().1, "min..max".into());
     }
 
+    #[test]
+    fn test_type2() {
+        assert_eq!(type2("-1").unwrap().1, Type2::Value(Value::Nint(-1)));
+        assert_eq!(
+            type2("'a'").unwrap().1,
+            Type2::Value(Value::Bytes(b"a".to_vec()))
+        );
+        assert_eq!(
+            type2("h'00'").unwrap().1,
+            Type2::Value(Value::Bytes(vec![0]))
+        );
+        assert_eq!(
+            type2("b64'AQI='").unwrap().1,
+            Type2::Value(Value::Bytes(vec![1, 2]))
+        );
+
+        // Names that look like the start of a byte string prefix.
+        assert_eq!(type2("h").unwrap().1, "h".into());
+        assert_eq!(type2("hello").unwrap().1, "hello".into());
+        assert_eq!(type2("b64").unwrap().1, "b64".into());
+        assert_eq!(type2("bstr").unwrap().1, "bstr".into());
+        assert_eq!(type2("$foo").unwrap().1, "$foo".into());
+
+        // A malformed byte string is an error, not a name.
+        type2("h'0g'").unwrap_err();
+
+        type2("").unwrap_err();
+        type2("!").unwrap_err();
+        type2(")").unwrap_err();
+    }
+
     #[test]
     fn test_grpchoice() {
         let result = grpchoice("abc").unwrap();
@@ -1693,6 +2116,60 @@ mod tests {
             type2("&(a:1)").unwrap().1,
             Type2::ChoiceifyInline(gen_group(vec![kv(bareword("a"), 1.literal(), Cut),]))
         );
+
+        // Errors are reported the same way as for the other alternatives.
+        let err = type2("&!").unwrap_err();
+        assert_eq!(
+            err,
+            nom::Err::Error(parse_error(ErrorKind::Unparseable, "&!"))
+        );
+    }
+
+    #[test]
+    fn tagged() {
+        assert_eq!(
+            type2("#6.32(tstr)").unwrap().1,
+            Type2::Tagged(TypeTagged {
+                tag: Some(32),
+                ty: "tstr".into(),
+            })
+        );
+        assert_eq!(
+            type2("#6( uint )").unwrap().1,
+            Type2::Tagged(TypeTagged {
+                tag: None,
+                ty: "uint".into(),
+            })
+        );
+        assert_eq!(
+            type2("#6.1"),
+            Ok((
+                "",
+                Type2::Major(TypeMajor {
+                    major: 6,
+                    info: Some(1),
+                })
+            ))
+        );
+        assert_eq!(
+            type2("#7.25").unwrap().1,
+            Type2::Major(TypeMajor {
+                major: 7,
+                info: Some(25),
+            })
+        );
+        assert_eq!(
+            type2("#1"),
+            Ok((
+                "",
+                Type2::Major(TypeMajor {
+                    major: 1,
+                    info: None,
+                })
+            ))
+        );
+        assert_eq!(type2("#"), Ok(("", Type2::Any)));
+        type2("#6.99999999999999999999(tstr)").unwrap_err();
     }
 
     #[test]
@@ -1730,6 +2207,32 @@ mod tests {
                 val: RuleVal::AssignType(gen_array(vec!["t", "v"]).into())
             }
         );
+
+        let result = rule("$foo /= bar").unwrap().1;
+        assert_eq!(
+            result,
+            Rule {
+                name: "$foo".into(),
+                generic_parms: vec![],
+                val: RuleVal::ExtendType("bar".into())
+            }
+        );
+
+        // Only types can be added with /=.
+        rule("foo /= (bar, baz)").unwrap_err();
+
+        let result = rule("$$foo //= (bar, baz)").unwrap().1;
+        assert_eq!(
+            result,
+            Rule {
+                name: "$$foo".into(),
+                generic_parms: vec![],
+                val: RuleVal::ExtendGroup(GrpEnt {
+                    occur: None,
+                    val: GrpEntVal::Parenthesized(gen_group(vec!["bar", "baz"])),
+                })
+            }
+        );
     }
 
     #[test]
@@ -1751,11 +2254,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_type_expr() {
+        let result = parse_type_expr(" tstr / int ").unwrap();
+        assert_eq!(result, Type(vec!["tstr".into(), "int".into()]));
+
+        let result = parse_type_expr("{ a: tstr }").unwrap();
+        assert_eq!(result, gen_map(vec![kv(bareword("a"), "tstr", Cut)]).into());
+
+        // A complete rule is not a type expression.
+        parse_type_expr("a = tstr").unwrap_err();
+        parse_type_expr("").unwrap_err();
+    }
+
     #[test]
     fn test_cddl_slice() {
         let result = slice_parse_cddl(" foo = { a: tstr } bar = \n[ int ] ").unwrap();
         assert_eq!(result.rules[0].1, "foo = { a: tstr }");
         assert_eq!(result.rules[1].1, "bar = \n[ int ]");
+
+        // Rules at the very start and end of the input.
+        let result = slice_parse_cddl("a = int").unwrap();
+        assert_eq!(result.rules[0].1, "a = int");
+        let result = slice_parse_cddl("a = int b = [tstr]").unwrap();
+        assert_eq!(result.rules[0].1, "a = int");
+        assert_eq!(result.rules[1].1, "b = [tstr]");
+        let result = slice_parse_cddl("a = \"ü\"\n").unwrap();
+        assert_eq!(result.rules[0].1, "a = \"ü\"");
+    }
+
+    #[test]
+    fn test_offset() {
+        let whole = "abcdé";
+        assert_eq!(offset(whole, whole), Some(0));
+        assert_eq!(offset(whole, &whole[2..]), Some(2));
+        assert_eq!(offset(whole, &whole[4..]), Some(4));
+        assert_eq!(offset(whole, &whole[whole.len()..]), Some(whole.len()));
+
+        // Slices that aren't a suffix of the whole.
+        assert_eq!(offset(whole, &whole[1..3]), None);
+        assert_eq!(offset(&whole[2..], whole), None);
+        assert_eq!(offset(&whole[..3], &whole[2..]), None);
+        let other = String::from("cdé");
+        assert_eq!(offset(whole, &other), None);
+        assert_eq!(offset(whole, ""), None);
+    }
+
+    #[test]
+    fn test_recognizer() {
+        // Zero-length recognitions.
+        let (rest, (recognized, _)) = recognizer(ws)("abc").unwrap();
+        assert_eq!((rest, recognized), ("abc", ""));
+        let (rest, (recognized, _)) = recognizer(ws)("").unwrap();
+        assert_eq!((rest, recognized), ("", ""));
+
+        // Recognizing all of the input, or part of it.
+        let (rest, (recognized, _)) = recognizer(ws)("  ; comment").unwrap();
+        assert_eq!((rest, recognized), ("", "  ; comment"));
+        let (rest, (recognized, value)) = recognizer(value)("17 18").unwrap();
+        assert_eq!((rest, recognized, value), (" 18", "17", Value::Uint(17)));
+
+        // A parser that returns something other than the rest of its input.
+        fn bogus(_input: &str) -> JResult<'_, &str, ()> {
+            Ok(("bogus", ()))
+        }
+        let err = recognizer(bogus)("abc").unwrap_err();
+        match err {
+            nom::Err::Failure(e) => assert_eq!(e.kind, ErrorKind::Internal),
+            _ => panic!("expected Failure, got {:?}", err),
+        }
     }
 
     // FIXME: these are things I discovered while validating cbor.  Move them to their own tests?
@@ -1785,10 +2352,28 @@ mod tests {
         let err = parse_cddl("x=9999999999999999999999999999999").unwrap_err();
         assert_eq!(err.kind, ErrorKind::MalformedInteger);
 
+        let err = parse_cddl("x=-18446744073709551617").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MalformedInteger);
+
+        let err = parse_cddl("x=-18446744073709551617..0").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MalformedInteger);
+
+        let err = parse_cddl("x=bstr .size -18446744073709551617").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MalformedInteger);
+
         let err = parse_cddl(r#"x="\ud800""#).unwrap_err();
         assert_eq!(err.kind, ErrorKind::MalformedText);
 
         let err = parse_cddl("x=h'61 62 6'").unwrap_err();
         assert_eq!(err.kind, ErrorKind::MalformedHex);
+
+        let err = parse_cddl("x = q'abcd'").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnknownQualifier);
+        assert_eq!(err.to_string(), "UnknownQualifier(q'abcd')");
+        let err = parse_cddl("x = [uint, b64x'AQI=']").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnknownQualifier);
+        assert_eq!(err.ctx, "b64x'AQI=']");
+        let err = parse_cddl("x = { H'00' => uint }").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnknownQualifier);
     }
 }
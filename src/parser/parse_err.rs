@@ -7,7 +7,7 @@ use thiserror::Error;
 
 /// The "kind" of error generated during CDDL parsing.
 #[non_exhaustive]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     /// An integer didn't parse correctly.
     MalformedInteger,
@@ -19,12 +19,16 @@ pub enum ErrorKind {
     MalformedText,
     /// A malformed base64 byte string
     MalformedBase64,
+    /// A byte string qualifier other than `h` or `b64`, e.g. `q'abcd'`.
+    UnknownQualifier,
     /// A nonspecific parsing error.
     Unparseable,
+    /// A bug in the parser.
+    Internal,
 }
 
 /// An error that occurred during CDDL parsing.
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 // thiserror will generate a Display implementation.
 #[error("{kind:?}({ctx})")]
 pub struct ParseError {
@@ -0,0 +1,1061 @@
+//! This module defines the [`Schema`] type.
+//!
+//! A `Schema` is a set of flattened CDDL rules, along with some
+//! information about the structure of those rules (e.g. which rules refer
+//! to which other rules).  It can be used as a [`LookupContext`] for
+//! validation.
+//!
+//! Flattening doesn't depend on the [`ValidationOptions`], so the same
+//! rules can be validated with different options by making a
+//! [`SchemaView`] for each set of options; the views share the rules,
+//! and can be used from several threads at once.
+//!
+//! # Examples
+//! ```
+//! use cddl_cat::schema::Schema;
+//!
+//! let cddl_input = r#"
+//!     msg = { header: common-header, body: tstr }
+//!     common-header = { id: uint }
+//! "#;
+//! let schema = Schema::from_str(cddl_input).unwrap();
+//! assert!(schema.dependencies("msg").unwrap().contains("common-header"));
+//! assert!(schema.dependents("common-header").unwrap().contains("msg"));
+//! assert_eq!(schema.roots().into_iter().collect::<Vec<_>>(), vec!["msg"]);
+//! ```
+
+use crate::context::{lookup_partial, LookupContext, OverrideContext, ValidationOptions};
+use crate::flatten::{
+    check_generics, check_size, rules_ref_map, slice_flatten_from_str, slice_flatten_onto,
+    slice_flatten_partial, FlattenErrors, FlattenOptions, FlattenResult,
+};
+use crate::ivt::{is_socket, Control, Node, RuleDef, RulesByName, RulesWithStrings};
+use crate::parser::slice_parse_cddl;
+use crate::session::Session;
+use crate::util::{missing_rule_suggest, ValidateError};
+use crate::value::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// The set of rule names referenced by each rule.
+type RuleGraph = BTreeMap<String, BTreeSet<String>>;
+
+type QueryResult<'a> = Result<BTreeSet<&'a str>, ValidateError>;
+
+/// A set of flattened CDDL rules.
+///
+/// The rule dependency graph is computed when the `Schema` is created, and
+/// updated incrementally when rules are added or removed.
+///
+/// The flattened rules don't depend on any [`ValidationOptions`], so they
+/// can be shared: cloning a `Schema` is cheap, and
+/// [`with_options`](Schema::with_options) makes a [`SchemaView`] that
+/// validates with other options, without copying or re-flattening the
+/// rules.  Adding or removing rules in a `Schema` that's shared this way
+/// copies its rules first, so other clones and views don't see the change.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    data: Arc<SchemaData>,
+}
+
+// The contents of a `Schema`, shared by its clones and views.
+#[derive(Debug, Clone, Default)]
+struct SchemaData {
+    rules: RulesByName,
+    stats: SchemaStats,
+    // The CDDL text of each rule, if it was parsed from text.
+    sources: BTreeMap<String, String>,
+    // Rules that couldn't be flattened.
+    flatten_errors: FlattenErrors,
+    // Rules referenced directly by each rule.
+    references: RuleGraph,
+    // Rules that directly reference each rule.
+    referenced_by: RuleGraph,
+}
+
+impl Schema {
+    /// Parse and flatten a CDDL schema.
+    ///
+    /// A rule that can't be flattened (e.g. because it uses an unsupported
+    /// control operator) doesn't make this fail.  Instead, its error is
+    /// kept, and returned when validation reaches that rule; see
+    /// [`flatten_errors`](Schema::flatten_errors).  This still fails if the
+    /// text can't be parsed.
+    // This isn't the FromStr trait because it would be awkward to use
+    // `str::parse` with our error type.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(cddl_input: &str) -> FlattenResult<Schema> {
+        let start = Instant::now();
+        let cddl = slice_parse_cddl(cddl_input)?;
+        let parsed = Instant::now();
+        let (rules, flatten_errors) = slice_flatten_partial(&cddl)?;
+        let (rules, sources) = split_sources(rules);
+        let flattened = Instant::now();
+
+        let mut data = SchemaData::from_rules(rules);
+        data.sources = sources;
+        data.flatten_errors = flatten_errors;
+        data.stats.parse_time = Some(parsed - start);
+        data.stats.flatten_time = Some(flattened - parsed);
+        Ok(Schema {
+            data: Arc::new(data),
+        })
+    }
+
+    /// Create a `Schema` from a set of already-flattened rules.
+    ///
+    /// Because there is no CDDL text, errors won't include the text of the
+    /// rule that failed (see [`ValidateError::rule_source`]).
+    pub fn from_rules(rules: RulesByName) -> Schema {
+        Schema {
+            data: Arc::new(SchemaData::from_rules(rules)),
+        }
+    }
+
+    /// Parse and flatten a CDDL fragment, and add its rules to this schema.
+    ///
+    /// The new rules may refer to each other, and to rules already in the
+    /// schema.  Only the new rules are examined; existing rules are not
+    /// re-flattened.  A rule in the fragment may extend an existing rule
+    /// with `/=` or `//=` (e.g. to add a plug to a socket), and the schema
+    /// then holds the combined definition.
+    ///
+    /// Unlike [`from_str`](Schema::from_str), every new rule must flatten.
+    /// This fails without changing the schema if the fragment can't be
+    /// parsed or flattened, if it redefines an existing rule, if it refers
+    /// to a rule that doesn't exist, or if the rules together are too large
+    /// (see [`FlattenOptions::max_nodes`]).
+    pub fn add_rules(&mut self, cddl_fragment: &str) -> Result<(), ValidateError> {
+        let cddl = slice_parse_cddl(cddl_fragment)?;
+        let (new_rules, extended) = slice_flatten_onto(&cddl, |name| {
+            let rule_def = self.data.rules.get(name)?.clone();
+            let source = self.data.sources.get(name).cloned().unwrap_or_default();
+            Some((rule_def, source))
+        })?;
+        let (new_rules, new_sources) = split_sources(new_rules);
+
+        // Check everything before making any changes.
+        let mut new_refs = BTreeMap::new();
+        for (name, rule_def) in &new_rules {
+            if self.is_defined(name) && !extended.contains(name) {
+                return Err(ValidateError::Structural(format!(
+                    "rule '{}' is already defined",
+                    name
+                )));
+            }
+            let refs = rule_refs(rule_def);
+            for r in &refs {
+                // A socket may be left empty.
+                if !self.is_defined(r) && !new_rules.contains_key(r) && !is_socket(r) {
+                    let known = self.data.rules.keys().chain(new_rules.keys());
+                    return Err(missing_rule_suggest(r, known));
+                }
+            }
+            new_refs.insert(name.clone(), refs);
+        }
+        // A new rule may make an existing one too large, or the other way
+        // around, so the limit applies to all of them together.  The same
+        // goes for the number of generic arguments each rule is given.
+        let mut merged = rules_ref_map(&self.data.rules);
+        merged.extend(
+            new_rules
+                .iter()
+                .map(|(name, rule_def)| (name.as_str(), rule_def)),
+        );
+        check_generics(&merged)?;
+        check_size(merged, &FlattenOptions::default())?;
+
+        let data = Arc::make_mut(&mut self.data);
+        for (name, rule_def) in new_rules {
+            // An extended rule is replaced by its combined definition.
+            data.unlink(&name);
+            let refs = new_refs.remove(&name).unwrap_or_default();
+            data.insert(name, rule_def, refs);
+        }
+        data.sources.extend(new_sources);
+        Ok(())
+    }
+
+    /// Remove a rule from this schema, returning its definition.
+    ///
+    /// This fails without changing the schema if the rule doesn't exist, or
+    /// if another rule still refers to it.
+    pub fn remove_rule(&mut self, name: &str) -> Result<RuleDef, ValidateError> {
+        self.check_name(name)?;
+        if let Some(users) = self.data.referenced_by.get(name) {
+            let users: Vec<String> = users
+                .iter()
+                .filter(|user| *user != name)
+                .map(|user| format!("'{}'", user))
+                .collect();
+            if !users.is_empty() {
+                return Err(ValidateError::Structural(format!(
+                    "rule '{}' is still referenced by {}",
+                    name,
+                    users.join(", ")
+                )));
+            }
+        }
+
+        let data = Arc::make_mut(&mut self.data);
+        let rule_def = data.unlink(name).unwrap();
+        data.sources.remove(name);
+        Ok(rule_def)
+    }
+
+    /// Make a context that validates with this schema, but with one rule
+    /// replaced by the definition in `cddl_fragment`.
+    ///
+    /// The fragment must define only the rule `name`, which must already
+    /// exist in the schema (though it may be a rule that couldn't be
+    /// flattened).  The new definition may refer to itself, and to any other
+    /// rule in the schema; like [`add_rules`](Schema::add_rules), this
+    /// fails if it refers to a rule that doesn't exist.  Existing rules
+    /// that refer to `name` use the new definition.
+    ///
+    /// The schema itself isn't changed: its rules are shared with the
+    /// context rather than copied, and its dependency graph (e.g.
+    /// [`dependents`](Schema::dependents)) still describes the original
+    /// rules.  The fragment is parsed and flattened each time this is
+    /// called, so to validate many items with the same replacement, keep
+    /// the context rather than calling this for each one.
+    ///
+    /// ```
+    /// use cddl_cat::context::LookupContext;
+    /// use cddl_cat::schema::Schema;
+    ///
+    /// let schema = Schema::from_str("thing = { id: id }  id = uint").unwrap();
+    /// let ctx = schema.with_override("id", "id = tstr").unwrap();
+    /// assert_eq!(ctx.rule_source("id"), Some("id = tstr"));
+    /// assert_eq!(schema.rule_source("id"), Some("id = uint"));
+    /// assert!(std::ptr::eq(
+    ///     ctx.lookup_rule("thing").unwrap(),
+    ///     schema.lookup_rule("thing").unwrap()
+    /// ));
+    /// ```
+    pub fn with_override(
+        &self,
+        name: &str,
+        cddl_fragment: &str,
+    ) -> Result<OverrideContext<'_>, ValidateError> {
+        if !self.is_defined(name) {
+            return Err(self.lookup_rule(name).unwrap_err());
+        }
+        let (mut rules, mut sources) = split_sources(slice_flatten_from_str(cddl_fragment)?);
+        let rule_def = match rules.remove(name) {
+            Some(rule_def) if rules.is_empty() => rule_def,
+            _ => {
+                return Err(ValidateError::Structural(format!(
+                    "an override must define only rule '{}'",
+                    name
+                )))
+            }
+        };
+        for r in rule_refs(&rule_def) {
+            if r != name && !self.is_defined(&r) && !is_socket(&r) {
+                return Err(self.lookup_rule(&r).unwrap_err());
+            }
+        }
+        let source = sources.remove(name).unwrap_or_default();
+        Ok(OverrideContext::new(self, name, rule_def).with_source(source))
+    }
+
+    /// Validate CBOR data against the rule `rule`, with the rule `name`
+    /// replaced by the definition in `cddl_fragment`.
+    ///
+    /// This is [`with_override`](Schema::with_override) followed by
+    /// [`validate_cbor_slice`], for a single validation.
+    ///
+    /// ```
+    /// use cddl_cat::schema::Schema;
+    ///
+    /// let schema = Schema::from_str("thing = [payload]  payload = bstr").unwrap();
+    /// // [h'0102']
+    /// let cbor_bytes = b"\x81\x42\x01\x02";
+    /// let err = schema
+    ///     .validate_with_override("thing", cbor_bytes, "payload", "payload = bstr .size 1")
+    ///     .unwrap_err();
+    /// assert_eq!(err.to_string(), "Mismatch(expected bstr over .size limit at [0])");
+    /// ```
+    ///
+    /// [`validate_cbor_slice`]: crate::cbor::validate_cbor_slice
+    #[cfg(feature = "serde_cbor")]
+    pub fn validate_with_override(
+        &self,
+        rule: &str,
+        cbor: &[u8],
+        name: &str,
+        cddl_fragment: &str,
+    ) -> crate::ValidateResult {
+        let ctx = self.with_override(name, cddl_fragment)?;
+        let rule_def = ctx.lookup_rule(rule)?;
+        crate::cbor::validate_cbor_slice(rule_def, cbor, &ctx)
+    }
+
+    /// Validate CBOR-encoded data against the rule `rule`.
+    ///
+    /// The schema is parsed and flattened only once, so this is cheaper
+    /// than [`validate_cbor_bytes`] when validating many items.
+    ///
+    /// ```
+    /// use cddl_cat::compile_cddl;
+    ///
+    /// let schema = compile_cddl("thing = [* uint]").unwrap();
+    /// schema.validate_cbor("thing", b"\x82\x01\x02").unwrap();
+    /// let err = schema.validate_cbor("thing", b"\x81\x61x").unwrap_err();
+    /// assert_eq!(err.to_string(), "Mismatch(expected uint at [0])");
+    /// ```
+    ///
+    /// [`validate_cbor_bytes`]: crate::cbor::validate_cbor_bytes
+    #[cfg(feature = "serde_cbor")]
+    pub fn validate_cbor(&self, rule: &str, cbor: &[u8]) -> crate::ValidateResult {
+        let rule_def = self.lookup_rule(rule)?;
+        crate::cbor::validate_cbor_slice(rule_def, cbor, self)
+    }
+
+    /// Validate JSON-encoded data against the rule `rule`.
+    ///
+    /// Like [`validate_cbor`](Schema::validate_cbor), this reuses the
+    /// flattened rules.
+    #[cfg(feature = "serde_json")]
+    pub fn validate_json(&self, rule: &str, json: &str) -> crate::ValidateResult {
+        let rule_def = self.lookup_rule(rule)?;
+        let value = crate::json::decode_json(json)?;
+        crate::json::validate_json(rule_def, &value, self)
+    }
+
+    /// Returns the default values of the members of the map rule `rule`,
+    /// by member key.
+    ///
+    /// A member has a default if its value type uses `.default`, either
+    /// directly or through a rule name.  Members of groups included in the
+    /// map are found too.  This is useful for filling in optional members
+    /// that are absent from the data.
+    ///
+    /// ```
+    /// use cddl_cat::schema::Schema;
+    /// use cddl_cat::value::Value;
+    ///
+    /// let cddl_input = "config = { host: tstr, ? retries: uint .default 3 }";
+    /// let schema = Schema::from_str(cddl_input).unwrap();
+    /// let defaults = schema.defaults_for("config").unwrap();
+    /// assert_eq!(defaults[&Value::Text("retries".into())], Value::Integer(3));
+    /// assert_eq!(defaults.len(), 1);
+    /// ```
+    pub fn defaults_for(&self, rule: &str) -> Result<BTreeMap<Value, Value>, ValidateError> {
+        let mut seen = BTreeSet::new();
+        let members = match self.follow_rules(&self.lookup_rule(rule)?.node, &mut seen)? {
+            Node::Map(m) => &m.members,
+            Node::Group(g) => &g.members,
+            _ => return Err(ValidateError::Structural(format!("'{}' isn't a map", rule))),
+        };
+        let mut defaults = BTreeMap::new();
+        for member in members {
+            self.member_defaults(member, &mut defaults, &mut seen)?;
+        }
+        Ok(defaults)
+    }
+
+    /// Make a view of this schema that validates with the given options.
+    ///
+    /// The view shares this schema's rules rather than copying them, so
+    /// it's cheap to make one for each set of options, or for each
+    /// validation.  Views can be sent to other threads.
+    ///
+    /// ```
+    /// # #[cfg(feature = "serde_cbor")]
+    /// use cddl_cat::cbor::validate_cbor_slice;
+    /// use cddl_cat::context::{LookupContext, TagPolicy, ValidationOptions};
+    /// use cddl_cat::schema::Schema;
+    ///
+    /// let schema = Schema::from_str("thing = [* uint]").unwrap();
+    /// let mut options = ValidationOptions::default();
+    /// options.tags = TagPolicy::Unwrap;
+    /// let unwrap_tags = schema.with_options(options);
+    ///
+    /// // [1(2)]
+    /// let cbor_bytes = b"\x81\xc1\x02";
+    /// let rule_def = schema.lookup_rule("thing").unwrap();
+    /// # #[cfg(feature = "serde_cbor")]
+    /// validate_cbor_slice(rule_def, cbor_bytes, &schema).unwrap_err();
+    /// # #[cfg(feature = "serde_cbor")]
+    /// validate_cbor_slice(rule_def, cbor_bytes, &unwrap_tags).unwrap();
+    /// ```
+    pub fn with_options(&self, options: ValidationOptions) -> SchemaView {
+        SchemaView {
+            schema: self.clone(),
+            options,
+        }
+    }
+
+    /// Make a [`Session`], for validating many documents against this
+    /// schema with the default options.
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+
+    /// Returns the flattened rules.
+    pub fn rules(&self) -> &RulesByName {
+        &self.data.rules
+    }
+
+    /// Returns the rules that couldn't be flattened, with the error for
+    /// each.
+    ///
+    /// These rules aren't in [`rules`](Schema::rules).  Validation only
+    /// fails because of one if it reaches that rule, so a rule whose
+    /// [`dependencies`](Schema::dependencies) don't include any of these is
+    /// fully usable.
+    ///
+    /// ```
+    /// use cddl_cat::schema::Schema;
+    ///
+    /// let schema = Schema::from_str("a = [b]  b = int  c = uint .lt 10").unwrap();
+    /// assert_eq!(schema.flatten_errors().keys().collect::<Vec<_>>(), vec!["c"]);
+    /// let broken = |dep: &&str| schema.flatten_errors().contains_key(*dep);
+    /// assert!(!schema.dependencies("a").unwrap().iter().any(broken));
+    /// ```
+    pub fn flatten_errors(&self) -> &FlattenErrors {
+        &self.data.flatten_errors
+    }
+
+    /// Returns statistics about this schema.
+    pub fn stats(&self) -> &SchemaStats {
+        &self.data.stats
+    }
+
+    /// Returns the names of all rules referenced by the named rule, directly
+    /// or indirectly.
+    ///
+    /// The result may contain names that are not defined in this schema.
+    /// A recursive rule will appear in its own dependencies.
+    pub fn dependencies(&self, name: &str) -> QueryResult<'_> {
+        self.check_name(name)?;
+        Ok(walk(&self.data.references, name))
+    }
+
+    /// Returns the names of all rules that refer to the named rule, directly
+    /// or indirectly.
+    ///
+    /// A recursive rule will appear in its own dependents.
+    pub fn dependents(&self, name: &str) -> QueryResult<'_> {
+        self.check_name(name)?;
+        Ok(walk(&self.data.referenced_by, name))
+    }
+
+    /// Returns the names of all rules that aren't referenced by any other
+    /// rule.
+    pub fn roots(&self) -> BTreeSet<&str> {
+        self.data
+            .rules
+            .keys()
+            .filter(|name| match self.data.referenced_by.get(*name) {
+                None => true,
+                // A rule that only refers to itself is still a root.
+                Some(users) => users.iter().all(|user| user == *name),
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), ValidateError> {
+        self.lookup_rule(name).map(|_| ())
+    }
+
+    // Whether a rule is defined, even if it couldn't be flattened.
+    fn is_defined(&self, name: &str) -> bool {
+        self.data.rules.contains_key(name) || self.data.flatten_errors.contains_key(name)
+    }
+
+    // Follow rule references (without generic arguments) to the node they
+    // lead to.  Each rule is only followed once, so recursive rules end.
+    fn follow_rules<'a>(
+        &'a self,
+        mut node: &'a Node,
+        seen: &mut BTreeSet<&'a str>,
+    ) -> Result<&'a Node, ValidateError> {
+        while let Node::Rule(r) = node {
+            if !r.generic_args.is_empty() || !seen.insert(&r.name) {
+                break;
+            }
+            node = &self.lookup_rule(&r.name)?.node;
+        }
+        Ok(node)
+    }
+
+    // Add the default value of a map member, or of the members of a group
+    // inside the map.
+    fn member_defaults<'a>(
+        &'a self,
+        member: &'a Node,
+        defaults: &mut BTreeMap<Value, Value>,
+        seen: &mut BTreeSet<&'a str>,
+    ) -> Result<(), ValidateError> {
+        match self.follow_rules(member, seen)? {
+            Node::Occur(o) => self.member_defaults(&o.node, defaults, seen)?,
+            Node::Group(g) => {
+                for member in &g.members {
+                    self.member_defaults(member, defaults, seen)?;
+                }
+            }
+            Node::KeyValue(kv) => {
+                if let Node::Literal(key) = kv.key.as_ref() {
+                    let value = self.follow_rules(&kv.value, &mut BTreeSet::new())?;
+                    if let Node::Control(Control::Default(ctl)) = value {
+                        defaults.insert(Value::from(key), Value::from(&ctl.default));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl SchemaData {
+    fn from_rules(rules: RulesByName) -> SchemaData {
+        let mut data = SchemaData::default();
+        for (name, rule_def) in rules {
+            let refs = rule_refs(&rule_def);
+            data.insert(name, rule_def, refs);
+        }
+        data
+    }
+
+    // Remove a rule (if it exists) from the rules, the dependency graph,
+    // and the stats.
+    fn unlink(&mut self, name: &str) -> Option<RuleDef> {
+        for r in self.references.remove(name).unwrap_or_default() {
+            if let Some(users) = self.referenced_by.get_mut(&r) {
+                users.remove(name);
+                if users.is_empty() {
+                    self.referenced_by.remove(&r);
+                }
+            }
+        }
+        let rule_def = self.rules.remove(name)?;
+        self.stats.remove(&rule_def, &self.rules);
+        Some(rule_def)
+    }
+
+    // Add a rule that is known not to exist yet.
+    fn insert(&mut self, name: String, rule_def: RuleDef, refs: BTreeSet<String>) {
+        for r in &refs {
+            self.referenced_by
+                .entry(r.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+        self.references.insert(name.clone(), refs);
+        self.stats.add(&rule_def);
+        self.rules.insert(name, rule_def);
+    }
+}
+
+/// Parse and flatten a CDDL schema, so it can be used for many validations.
+///
+/// This is the same as [`Schema::from_str`].
+pub fn compile_cddl(cddl_input: &str) -> FlattenResult<Schema> {
+    Schema::from_str(cddl_input)
+}
+
+/// A [`Schema`] with its own validation options.
+///
+/// This is made by [`Schema::with_options`], and shares the rules of the
+/// schema it was made from.
+#[derive(Debug, Clone)]
+pub struct SchemaView {
+    schema: Schema,
+    options: ValidationOptions,
+}
+
+impl SchemaView {
+    /// Returns the schema this view shares its rules with.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Make a [`Session`], for validating many documents with this view's
+    /// options.
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+}
+
+impl LookupContext for SchemaView {
+    fn lookup_rule<'a>(&'a self, name: &str) -> Result<&'a RuleDef, ValidateError> {
+        self.schema.lookup_rule(name)
+    }
+
+    fn options(&self) -> &ValidationOptions {
+        &self.options
+    }
+
+    fn rule_source(&self, name: &str) -> Option<&str> {
+        self.schema.rule_source(name)
+    }
+}
+
+/// Size and timing information about a [`Schema`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SchemaStats {
+    /// The number of rules.
+    pub rule_count: usize,
+    /// The total number of IVT nodes in all rules.
+    pub node_count: usize,
+    /// The nesting depth of the deepest IVT node.
+    ///
+    /// This doesn't follow rule references.
+    pub max_depth: usize,
+    /// The time spent parsing the CDDL text, if the `Schema` was parsed.
+    pub parse_time: Option<Duration>,
+    /// The time spent flattening the CDDL syntax tree, if the `Schema` was
+    /// parsed.
+    pub flatten_time: Option<Duration>,
+}
+
+impl SchemaStats {
+    fn add(&mut self, rule_def: &RuleDef) {
+        let (nodes, depth) = rule_size(rule_def);
+        self.rule_count += 1;
+        self.node_count += nodes;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    // `rules` is the set of rules remaining after the removal.
+    fn remove(&mut self, rule_def: &RuleDef, rules: &RulesByName) {
+        let (nodes, depth) = rule_size(rule_def);
+        self.rule_count -= 1;
+        self.node_count -= nodes;
+        if depth == self.max_depth {
+            // Another rule may be just as deep, so we have to look.
+            self.max_depth = rules.values().map(|r| rule_size(r).1).max().unwrap_or(0);
+        }
+    }
+}
+
+// Returns the number of nodes in a rule, and the depth of the deepest one.
+fn rule_size(rule_def: &RuleDef) -> (usize, usize) {
+    let mut nodes = 0;
+    let mut max_depth = 0;
+    rule_def.node.walk(|_, depth| {
+        nodes += 1;
+        max_depth = max_depth.max(depth);
+    });
+    (nodes, max_depth)
+}
+
+impl LookupContext for Schema {
+    fn lookup_rule<'a>(&'a self, name: &str) -> Result<&'a RuleDef, ValidateError> {
+        lookup_partial(&self.data.rules, &self.data.flatten_errors, name)
+    }
+
+    fn rule_source(&self, name: &str) -> Option<&str> {
+        self.data.sources.get(name).map(String::as_str)
+    }
+}
+
+// Separate the rules from their CDDL text.
+fn split_sources(rules: RulesWithStrings) -> (RulesByName, BTreeMap<String, String>) {
+    let mut sources = BTreeMap::new();
+    let rules = rules
+        .into_iter()
+        .map(|(name, (rule_def, source))| {
+            sources.insert(name.clone(), source);
+            (name, rule_def)
+        })
+        .collect();
+    (rules, sources)
+}
+
+// Find every node reachable from `start` in the graph (not including
+// `start` itself, unless there is a cycle).
+fn walk<'a>(graph: &'a RuleGraph, start: &str) -> BTreeSet<&'a str> {
+    let mut found = BTreeSet::new();
+    let mut pending: Vec<&str> = vec![start];
+    while let Some(name) = pending.pop() {
+        if let Some(edges) = graph.get(name) {
+            for edge in edges {
+                if found.insert(edge.as_str()) {
+                    pending.push(edge);
+                }
+            }
+        }
+    }
+    found
+}
+
+// Collect the names of all the rules referenced by a rule.
+//
+// References to the rule's own generic parameters are not rule references,
+// so they are skipped.
+fn rule_refs(rule_def: &RuleDef) -> BTreeSet<String> {
+    let parms = &rule_def.generic_parms;
+    let mut refs = BTreeSet::new();
+    rule_def.node.walk(|n, _| match n {
+        Node::Rule(r) | Node::Unwrap(r) | Node::Choiceify(r) if !parms.contains(&r.name) => {
+            refs.insert(r.name.clone());
+        }
+        _ => {}
+    });
+    refs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<'a>(list: &[&'a str]) -> BTreeSet<&'a str> {
+        list.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let cddl_input = r#"
+            msg-a = { header: common-header, body: [* item] }
+            msg-b = { header: common-header, ~extra }
+            common-header = { id: id-type, ? parent: common-header }
+            id-type = uint
+            item = pair<tstr, id-type>
+            pair<K, V> = [K, V]
+            extra = { &colors => tstr }
+            colors = ( red: 1, blue: 2 )
+            unused = int
+        "#;
+        let schema = Schema::from_str(cddl_input).unwrap();
+
+        assert_eq!(
+            schema.dependencies("msg-a").unwrap(),
+            names(&["common-header", "id-type", "item", "pair"])
+        );
+        assert_eq!(
+            schema.dependencies("msg-b").unwrap(),
+            names(&["colors", "common-header", "extra", "id-type"])
+        );
+        // Generic parameters aren't rules.
+        assert_eq!(schema.dependencies("pair").unwrap(), names(&[]));
+        assert_eq!(schema.dependencies("unused").unwrap(), names(&[]));
+
+        assert_eq!(
+            schema.dependents("common-header").unwrap(),
+            names(&["common-header", "msg-a", "msg-b"])
+        );
+        assert_eq!(
+            schema.dependents("id-type").unwrap(),
+            names(&["common-header", "item", "msg-a", "msg-b"])
+        );
+        assert_eq!(schema.dependents("msg-a").unwrap(), names(&[]));
+
+        assert_eq!(schema.roots(), names(&["msg-a", "msg-b", "unused"]));
+
+        let err = schema.dependencies("msg-c").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "MissingRule(msg-c); did you mean 'msg-a' or 'msg-b'?"
+        );
+        schema.dependents("nothing").unwrap_err();
+    }
+
+    #[test]
+    fn test_stats() {
+        let schema = Schema::from_str("a = [* b]  b = { c: int, d: [tstr] }").unwrap();
+        let stats = schema.stats();
+        assert_eq!(stats.rule_count, 2);
+        // a: Array, Occur, Rule
+        // b: Map, KeyValue, Literal, PreludeType, KeyValue, Literal, Array, PreludeType
+        assert_eq!(stats.node_count, 11);
+        assert_eq!(stats.max_depth, 4);
+        assert!(stats.parse_time.is_some());
+        assert!(stats.flatten_time.is_some());
+
+        let schema = Schema::from_rules(schema.rules().clone());
+        assert_eq!(schema.stats().node_count, 11);
+        assert_eq!(schema.stats().parse_time, None);
+    }
+
+    #[test]
+    fn test_add_remove_rules() {
+        let ext = r#"ext = plugin-a / plugin-b  plugin-a = [1, tstr]  plugin-b = [2, header]"#;
+
+        // A fragment may fill in references that are missing from the schema.
+        let mut schema = Schema::from_str("msg = { header: header, ? ext: [* ext] }").unwrap();
+        schema.add_rules("header = uint").unwrap();
+        schema.add_rules(ext).unwrap();
+        assert_eq!(schema.roots(), names(&["msg"]));
+        assert_eq!(
+            schema.dependents("header").unwrap(),
+            names(&["ext", "msg", "plugin-b"])
+        );
+        assert_eq!(schema.stats().rule_count, 5);
+        let full = Schema::from_rules(schema.rules().clone());
+        assert_eq!(schema.stats().node_count, full.stats().node_count);
+        assert_eq!(schema.stats().max_depth, full.stats().max_depth);
+
+        // Failures leave the schema unchanged.
+        let err = schema
+            .add_rules("plugin-c = [3, heade]  x = int")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "MissingRule(heade); did you mean 'header'?"
+        );
+        let err = schema
+            .add_rules("plugin-c = int  header = tstr")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Structural(rule 'header' is already defined)"
+        );
+        schema.add_rules("plugin-c = [").unwrap_err();
+        assert_eq!(schema.rules().len(), 5);
+
+        // A socket may be used without any plugs.
+        let mut with_socket = schema.clone();
+        with_socket.add_rules("plugin-s = [* $plugin-ext]").unwrap();
+        assert!(schema.dependents("plugin-c").is_err());
+
+        let err = schema.remove_rule("header").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Structural(rule 'header' is still referenced by 'msg', 'plugin-b')"
+        );
+        schema.remove_rule("plugin-x").unwrap_err();
+        assert_eq!(schema.rules().len(), 5);
+
+        // A rule can be removed once the rules using it are gone.
+        schema
+            .add_rules("plugin-tmp = int  uses-tmp = [plugin-tmp]")
+            .unwrap();
+        schema.remove_rule("plugin-tmp").unwrap_err();
+        schema.remove_rule("uses-tmp").unwrap();
+
+        schema.remove_rule("plugin-tmp").unwrap();
+        schema.remove_rule("msg").unwrap();
+        assert_eq!(schema.roots(), names(&["ext"]));
+        schema.remove_rule("ext").unwrap();
+        assert_eq!(schema.roots(), names(&["plugin-a", "plugin-b"]));
+        schema.remove_rule("plugin-b").unwrap();
+        assert_eq!(schema.dependents("header").unwrap(), names(&[]));
+        schema.remove_rule("header").unwrap();
+
+        let full = Schema::from_rules(schema.rules().clone());
+        assert_eq!(schema.stats().rule_count, 1);
+        assert_eq!(schema.stats().node_count, full.stats().node_count);
+        assert_eq!(schema.stats().max_depth, full.stats().max_depth);
+
+        // A recursive rule can be removed.
+        schema.add_rules("tree = [* tree]").unwrap();
+        schema.remove_rule("tree").unwrap();
+        assert_eq!(schema.roots(), names(&["plugin-a"]));
+    }
+
+    #[test]
+    fn test_add_rules_extend() {
+        let option_count = |schema: &Schema, name: &str| match &schema.rules()[name].node {
+            Node::Choice(c) => c.options.len(),
+            _ => 1,
+        };
+
+        // A plug can be added to a socket that's already there.
+        let mut schema = Schema::from_str(r#"thing = { cur: $c }  $c /= "USD""#).unwrap();
+        schema.add_rules(r#"$c /= "EUR""#).unwrap();
+        schema.add_rules(r#"$c /= gbp  gbp = "GBP""#).unwrap();
+        assert_eq!(option_count(&schema, "$c"), 3);
+        assert_eq!(
+            schema.rule_source("$c"),
+            Some("$c /= \"USD\"\n$c /= \"EUR\"\n$c /= gbp")
+        );
+        assert_eq!(schema.dependencies("$c").unwrap(), names(&["gbp"]));
+        assert_eq!(schema.dependents("gbp").unwrap(), names(&["$c", "thing"]));
+        assert_eq!(schema.stats().rule_count, 3);
+        let full = Schema::from_rules(schema.rules().clone());
+        assert_eq!(schema.stats().node_count, full.stats().node_count);
+        assert_eq!(schema.stats().max_depth, full.stats().max_depth);
+
+        // The same goes for group sockets.
+        let mut schema = Schema::from_str("thing = [$$g]  $$g //= (1, tstr)").unwrap();
+        schema.add_rules("$$g //= (2, uint)").unwrap();
+        schema.add_rules("$$g //= (3, bool)").unwrap();
+        assert_eq!(option_count(&schema, "$$g"), 3);
+
+        // An extension that doesn't fit leaves the schema unchanged, as
+        // does redefining a rule.
+        schema.add_rules("$$g /= int").unwrap_err();
+        schema.add_rules("$$g = (4, int)").unwrap_err();
+        assert_eq!(option_count(&schema, "$$g"), 3);
+    }
+
+    #[test]
+    fn test_add_rules_too_large() {
+        // Each rule unwraps the next one twice, doubling the tree size.  The
+        // limit applies the same as if the rules were all added at once.
+        let mut schema = Schema::from_str("a64 = [uint]").unwrap();
+        for n in (47..64).rev() {
+            schema
+                .add_rules(&format!("a{} = [~a{}, ~a{}]", n, n + 1, n + 1))
+                .unwrap();
+        }
+        let err = schema.add_rules("a46 = [~a47, ~a47]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SchemaTooLarge(rule 'a46' expands to more than 1000000 nodes)"
+        );
+        assert_eq!(schema.rules().len(), 18);
+    }
+
+    #[test]
+    fn test_add_rules_generic_arity() {
+        // Generic arguments are checked against the rules already there,
+        // and the other way around.
+        let mut schema = Schema::from_str("pair<T> = [T, T]  thing = other").unwrap();
+        let err = schema.add_rules("a = pair").unwrap_err();
+        assert_eq!(err.to_string(), "GenericError");
+        let err = schema.add_rules("other<T> = [T]").unwrap_err();
+        assert_eq!(err.to_string(), "GenericError");
+        assert_eq!(schema.rules().len(), 2);
+        schema.add_rules("a = pair<int>").unwrap();
+    }
+
+    #[test]
+    fn test_flatten_errors() {
+        let cddl_input = r#"
+            msg = { header: header, ? ext: ext }
+            header = { id: uint }
+            ext = { limit: uint .lt 10, ? more: ext }
+        "#;
+        let schema = Schema::from_str(cddl_input).unwrap();
+        assert_eq!(schema.rules().len(), 2);
+        assert_eq!(
+            schema.flatten_errors()["ext"].to_string(),
+            "Unsupported control operator"
+        );
+        assert_eq!(
+            schema.dependencies("msg").unwrap(),
+            names(&["ext", "header"])
+        );
+        assert_eq!(schema.roots(), names(&["msg"]));
+
+        // Looking up the broken rule returns its error, not MissingRule.
+        let err = schema.lookup_rule("ext").unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported control operator");
+        let err = schema.dependencies("ext").unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported control operator");
+        schema.lookup_rule("header").unwrap();
+
+        // The broken rule is still defined, so it can be referred to but
+        // not redefined.
+        let mut schema = schema;
+        schema.add_rules("other = [* ext]").unwrap();
+        let err = schema.add_rules("ext = int").unwrap_err();
+        assert_eq!(err.to_string(), "Structural(rule 'ext' is already defined)");
+        // New rules must all flatten.
+        let err = schema.add_rules("small = uint .lt 10").unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported control operator");
+
+        // A text that doesn't parse is still an error.
+        Schema::from_str("a = [").unwrap_err();
+    }
+
+    #[test]
+    fn test_override() {
+        let cddl_input = r#"
+            msg = { header: header, ? ext: ext }
+            header = { id: uint }
+            ext = { limit: uint .lt 10, ? more: ext }
+        "#;
+        let schema = Schema::from_str(cddl_input).unwrap();
+
+        // A broken rule can be replaced, and the replacement may refer to
+        // itself and to other rules.
+        let ctx = schema
+            .with_override("ext", "ext = { limit: uint, ? more: ext, h: header }")
+            .unwrap();
+        let ext = ctx.lookup_rule("ext").unwrap();
+        assert_eq!(
+            rule_refs(ext),
+            names(&["ext", "header"])
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        );
+        assert_eq!(
+            ctx.rule_source("ext"),
+            Some("ext = { limit: uint, ? more: ext, h: header }")
+        );
+        assert!(std::ptr::eq(
+            ctx.lookup_rule("header").unwrap(),
+            schema.lookup_rule("header").unwrap()
+        ));
+        assert_eq!(ctx.options(), schema.options());
+
+        // The schema is unchanged.
+        schema.lookup_rule("ext").unwrap_err();
+        assert_eq!(
+            schema.dependencies("ext").unwrap_err().to_string(),
+            "Unsupported control operator"
+        );
+
+        let err =
+            |name: &str, cddl: &str| schema.with_override(name, cddl).err().unwrap().to_string();
+        assert_eq!(
+            err("hedaer", "hedaer = int"),
+            "MissingRule(hedaer); did you mean 'header'?"
+        );
+        assert_eq!(
+            err("header", "header = int  extra = int"),
+            "Structural(an override must define only rule 'header')"
+        );
+        assert_eq!(
+            err("header", "ext = int"),
+            "Structural(an override must define only rule 'header')"
+        );
+        assert_eq!(err("header", "header = [id]"), "MissingRule(id)");
+        assert_eq!(
+            err("header", "header = uint .lt 10"),
+            "Unsupported control operator"
+        );
+        // Like add_rules, this allows referring to a broken rule.
+        schema.with_override("header", "header = [? ext]").unwrap();
+    }
+
+    #[test]
+    fn test_recursive_root() {
+        let schema = Schema::from_str("tree = [* tree]").unwrap();
+        assert_eq!(schema.dependencies("tree").unwrap(), names(&["tree"]));
+        assert_eq!(schema.roots(), names(&["tree"]));
+    }
+
+    #[test]
+    fn test_defaults_for() {
+        let cddl_input = r#"
+            config = { host: tstr, ? port: port, ? retries: uint .default 3, common }
+            port = uint .default 8080
+            common = ( ? verbose: bool .default false, ? 1 => tstr .default "x" )
+            list = [* uint .default 1]
+            tree = { ? child: tree, ? depth: uint .default 0 }
+        "#;
+        let schema = Schema::from_str(cddl_input).unwrap();
+        let text = |s: &str| Value::Text(s.into());
+        let defaults = schema.defaults_for("config").unwrap();
+        let expected: BTreeMap<Value, Value> = vec![
+            (text("port"), Value::Integer(8080)),
+            (text("retries"), Value::Integer(3)),
+            (text("verbose"), Value::Bool(false)),
+            (Value::Integer(1), text("x")),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(defaults, expected);
+
+        let defaults = schema.defaults_for("common").unwrap();
+        assert_eq!(defaults.len(), 2);
+        let defaults = schema.defaults_for("tree").unwrap();
+        assert_eq!(defaults[&text("depth")], Value::Integer(0));
+
+        schema.defaults_for("list").unwrap_err();
+        schema.defaults_for("port").unwrap_err();
+        schema.defaults_for("nope").unwrap_err();
+    }
+}
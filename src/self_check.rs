@@ -0,0 +1,246 @@
+//! This module checks a CDDL syntax tree against a CDDL description of
+//! itself.
+//!
+//! The syntax tree is converted into data (maps, arrays, and strings), and
+//! that data is validated against [`AST_CDDL`], which describes the
+//! structure of every type in the [`ast`](crate::ast) module.  This is a
+//! sanity check that the tree has the expected shape; it also exercises
+//! most of the validator at once.
+//!
+//! # Examples
+//! ```
+//! use cddl_cat::{parse_cddl, self_check};
+//!
+//! let cddl = parse_cddl("person = { name: tstr, ? age: uint }").unwrap();
+//! self_check(&cddl).unwrap();
+//! ```
+
+use crate::ast;
+use crate::context::BasicContext;
+use crate::flatten::flatten_from_str;
+use crate::util::ValidateResult;
+use crate::validate::do_validate;
+use crate::value::Value;
+
+/// The CDDL describing a syntax tree, as checked by [`self_check`].
+///
+/// Each type in the [`ast`](crate::ast) module has a rule of the same name
+/// (e.g. `grpent` for [`GrpEnt`](crate::ast::GrpEnt)), and the whole tree
+/// is a `cddl`.
+pub const AST_CDDL: &str = include_str!("self_check.cddl");
+
+// Each level of nesting in a schema takes about ten rule references in
+// AST_CDDL, so the default limit only allows a few levels.
+const MAX_DEPTH: usize = 500;
+
+/// Validate a CDDL syntax tree against [`AST_CDDL`].
+///
+/// This only fails if the tree doesn't have the structure that the parser
+/// would produce, e.g. if a rule is built by hand with an empty
+/// [`Type`](crate::ast::Type).
+pub fn self_check(cddl: &ast::Cddl) -> ValidateResult {
+    let mut ctx = BasicContext::new(flatten_from_str(AST_CDDL)?);
+    ctx.options.max_depth = MAX_DEPTH;
+    let rule_def = &ctx.rules["cddl"];
+    do_validate(&cddl_value(cddl), rule_def, &ctx)
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    Value::Map(entries.into_iter().map(|(k, v)| (text(k), v)).collect())
+}
+
+// An enum variant containing some data.
+fn variant(name: &str, value: Value) -> Value {
+    map(vec![(name, value)])
+}
+
+fn array<T, F: Fn(&T) -> Value>(items: &[T], f: F) -> Value {
+    Value::Array(items.iter().map(f).collect())
+}
+
+fn cddl_value(cddl: &ast::Cddl) -> Value {
+    map(vec![("rules", array(&cddl.rules, rule_value))])
+}
+
+fn rule_value(rule: &ast::Rule) -> Value {
+    let val = match &rule.val {
+        ast::RuleVal::AssignType(ty) => variant("AssignType", type_value(ty)),
+        ast::RuleVal::AssignGroup(grpent) => variant("AssignGroup", grpent_value(grpent)),
+        ast::RuleVal::ExtendType(ty) => variant("ExtendType", type_value(ty)),
+        ast::RuleVal::ExtendGroup(grpent) => variant("ExtendGroup", grpent_value(grpent)),
+    };
+    map(vec![
+        ("name", text(&rule.name)),
+        ("generic_parms", array(&rule.generic_parms, |s| text(s))),
+        ("val", val),
+    ])
+}
+
+fn type_value(ty: &ast::Type) -> Value {
+    array(&ty.0, type1_value)
+}
+
+fn type1_value(ty1: &ast::Type1) -> Value {
+    match ty1 {
+        ast::Type1::Simple(ty2) => variant("Simple", type2_value(ty2)),
+        ast::Type1::Range(r) => variant(
+            "Range",
+            map(vec![
+                ("start", type2_value(&r.start)),
+                ("end", type2_value(&r.end)),
+                ("inclusive", Value::Bool(r.inclusive)),
+            ]),
+        ),
+        ast::Type1::Control(ctl) => variant(
+            "Control",
+            map(vec![
+                ("target", type2_value(&ctl.target)),
+                ("arg", type2_value(&ctl.arg)),
+                ("op", text(ctl.op.name())),
+            ]),
+        ),
+    }
+}
+
+fn type2_value(ty2: &ast::Type2) -> Value {
+    match ty2 {
+        ast::Type2::Value(v) => variant("Value", value_value(v)),
+        ast::Type2::Typename(ng) => variant("Typename", name_generic_value(ng)),
+        ast::Type2::Parethesized(ty) => variant("Parethesized", type_value(ty)),
+        ast::Type2::Map(g) => variant("Map", group_value(g)),
+        ast::Type2::Array(g) => variant("Array", group_value(g)),
+        ast::Type2::Unwrap(ng) => variant("Unwrap", name_generic_value(ng)),
+        ast::Type2::ChoiceifyInline(g) => variant("ChoiceifyInline", group_value(g)),
+        ast::Type2::Choiceify(ng) => variant("Choiceify", name_generic_value(ng)),
+        ast::Type2::Tagged(t) => {
+            let mut entries = vec![("ty", type_value(&t.ty))];
+            if let Some(tag) = t.tag {
+                entries.push(("tag", Value::Integer(tag as i128)));
+            }
+            variant("Tagged", map(entries))
+        }
+        ast::Type2::Major(m) => {
+            let mut entries = vec![("major", Value::Integer(m.major as i128))];
+            if let Some(info) = m.info {
+                entries.push(("info", Value::Integer(info as i128)));
+            }
+            variant("Major", map(entries))
+        }
+        ast::Type2::Any => text("Any"),
+    }
+}
+
+fn name_generic_value(ng: &ast::NameGeneric) -> Value {
+    map(vec![
+        ("name", text(&ng.name)),
+        ("generic_args", array(&ng.generic_args, type1_value)),
+    ])
+}
+
+fn group_value(group: &ast::Group) -> Value {
+    array(&group.0, |grpchoice| array(&grpchoice.0, grpent_value))
+}
+
+fn grpent_value(grpent: &ast::GrpEnt) -> Value {
+    let val = match &grpent.val {
+        ast::GrpEntVal::Member(member) => variant("Member", member_value(member)),
+        ast::GrpEntVal::Groupname(name) => variant("Groupname", text(name)),
+        ast::GrpEntVal::Parenthesized(g) => variant("Parenthesized", group_value(g)),
+    };
+    let mut entries = vec![("val", val)];
+    if let Some(occur) = &grpent.occur {
+        entries.push(("occur", occur_value(occur)));
+    }
+    map(entries)
+}
+
+fn occur_value(occur: &ast::Occur) -> Value {
+    match occur {
+        ast::Occur::Optional => text("Optional"),
+        ast::Occur::ZeroOrMore => text("ZeroOrMore"),
+        ast::Occur::OneOrMore => text("OneOrMore"),
+        ast::Occur::Numbered(n, m) => variant(
+            "Numbered",
+            Value::Array(vec![Value::Integer(*n as i128), Value::Integer(*m as i128)]),
+        ),
+    }
+}
+
+fn member_value(member: &ast::Member) -> Value {
+    let mut entries = vec![("value", type_value(&member.value))];
+    if let Some(key) = &member.key {
+        let val = match &key.val {
+            ast::MemberKeyVal::Type1(ty1) => variant("Type1", type1_value(ty1)),
+            ast::MemberKeyVal::Bareword(s) => variant("Bareword", text(s)),
+            ast::MemberKeyVal::Value(v) => variant("Value", value_value(v)),
+        };
+        let key = map(vec![("val", val), ("cut", Value::Bool(key.cut))]);
+        entries.push(("key", key));
+    }
+    map(entries)
+}
+
+fn value_value(value: &ast::Value) -> Value {
+    match value {
+        ast::Value::Text(s) => variant("Text", text(s)),
+        ast::Value::Uint(n) => variant("Uint", Value::Integer(*n as i128)),
+        ast::Value::Nint(n) => variant("Nint", Value::Integer(*n)),
+        ast::Value::Float(f) => variant("Float", Value::from_float(*f)),
+        ast::Value::Bytes(b) => variant("Bytes", Value::Bytes(b.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_cddl;
+    use crate::util::ErrorMatch;
+
+    #[test]
+    fn test_self_check() {
+        let inputs = [
+            "a = int",
+            "a = 1..10 / 1.5 / h'00ff' / -3 / \"x\"  b<T> = [* T]",
+            "a = tstr .size (1..10)  b = bstr .cbor a",
+            r#"msg = {
+                "type" ^ => "ping" / "pong",
+                ? body: [* pair<tstr, { id: uint }>],
+                (x: int // y: tstr),
+                2*5 tstr => any,
+            }
+            pair<K, V> = [K, V]"#,
+            "a = &(b: 1, c: 2)  b = &c  c = (d: int)  d = [~e]  e = [int]",
+            "a = $b  $b /= int  $b /= tstr / nil  c = { * $$d }  $$d //= (e: int)",
+            "a = #6.32(tstr) / #6(int)  b = #0 / #7.25 / #",
+            "a = { b: { c: { d: { e: { f: [[[[int]]]] } } } } }",
+        ];
+        for input in &inputs {
+            let cddl = parse_cddl(input).unwrap();
+            let result = self_check(&cddl);
+            assert!(result.is_ok(), "{}: {:?}", input, result);
+        }
+
+        // The bundled schema checks itself.
+        self_check(&parse_cddl(AST_CDDL).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_self_check_malformed() {
+        // Trees the parser would never produce.
+        let empty_type = ast::Cddl {
+            rules: vec![ast::Rule {
+                name: "a".into(),
+                generic_parms: Vec::new(),
+                val: ast::RuleVal::AssignType(ast::Type(Vec::new())),
+            }],
+        };
+        self_check(&empty_type).err_mismatch();
+
+        let no_rules = ast::Cddl { rules: Vec::new() };
+        self_check(&no_rules).err_mismatch();
+    }
+}
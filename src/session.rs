@@ -0,0 +1,119 @@
+//! This module defines the [`Session`] type, for validating many
+//! documents against the same schema.
+//!
+//! Each validation needs some working memory.  The single-shot functions
+//! (e.g. [`validate_cbor_slice`]) start from nothing each time; a
+//! `Session` keeps that memory from one document to the next, and always
+//! validates in [lean mode](crate::context::ValidationOptions::lean), so
+//! that a small document that matches is validated without allocating
+//! beyond what decoding it needs.
+//!
+//! A document that doesn't match is validated again to find the error,
+//! just as in lean mode, so the results are the same as the single-shot
+//! functions give.
+//!
+//! A session can't be shared between threads; make one per thread.
+//!
+//! # Examples
+//! ```
+//! use cddl_cat::context::LookupContext;
+//! use cddl_cat::schema::Schema;
+//!
+//! let schema = Schema::from_str("reading = [sensor: tstr, value: int]").unwrap();
+//! let rule_def = schema.lookup_rule("reading").unwrap();
+//! let session = schema.session();
+//!
+//! // ["t1", 20], ["t2", 21] and ["t3", "x"]
+//! let docs: &[&[u8]] = &[b"\x82\x62t1\x14", b"\x82\x62t2\x15", b"\x82\x62t3\x61x"];
+//! # #[cfg(feature = "serde_cbor")]
+//! let results: Vec<_> = session.validate_cbor_iter(rule_def, docs).collect();
+//! # #[cfg(feature = "serde_cbor")]
+//! assert!(results[0].is_ok() && results[1].is_ok());
+//! # #[cfg(feature = "serde_cbor")]
+//! assert_eq!(
+//!     results[2].as_ref().unwrap_err().to_string(),
+//!     "Mismatch(expected int at [1])"
+//! );
+//! ```
+//!
+//! [`validate_cbor_slice`]: crate::cbor::validate_cbor_slice
+
+use crate::context::LookupContext;
+use crate::ivt::RuleDef;
+use crate::util::ValidateResult;
+use crate::validate::{do_validate_session, Scratch};
+use crate::value::Value;
+
+#[cfg(feature = "serde_cbor")]
+use serde_cbor::Value as CBOR_Value;
+#[cfg(any(feature = "serde_cbor", feature = "serde_json"))]
+use std::convert::TryFrom;
+
+/// Validates documents one after another, reusing working memory.
+///
+/// This is made by [`Schema::session`](crate::schema::Schema::session), or
+/// by [`Session::new`] for any other [`LookupContext`].
+pub struct Session<'a> {
+    ctx: &'a dyn LookupContext,
+    scratch: Scratch,
+}
+
+impl<'a> Session<'a> {
+    /// Make a session that looks up rules in `ctx`, and validates with its
+    /// options.
+    pub fn new(ctx: &'a dyn LookupContext) -> Session<'a> {
+        Session {
+            ctx,
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// Validate a value against a rule.
+    pub fn validate(&self, rule_def: &RuleDef, value: &Value) -> ValidateResult {
+        do_validate_session(value, rule_def, self.ctx, &self.scratch)
+    }
+
+    /// Validate already-parsed CBOR data against a rule.
+    ///
+    /// This is like [`validate_cbor`](crate::cbor::validate_cbor).
+    #[cfg(feature = "serde_cbor")]
+    pub fn validate_cbor(&self, rule_def: &RuleDef, value: &CBOR_Value) -> ValidateResult {
+        self.validate(rule_def, &Value::try_from(value)?)
+    }
+
+    /// Validate CBOR-encoded data against a rule.
+    ///
+    /// This is like [`validate_cbor_slice`](crate::cbor::validate_cbor_slice),
+    /// and its errors record where they are in the data in the same way.
+    #[cfg(feature = "serde_cbor")]
+    pub fn validate_cbor_slice(&self, rule_def: &RuleDef, cbor: &[u8]) -> ValidateResult {
+        crate::cbor::validate_slice_session(rule_def, cbor, self.ctx, &self.scratch)
+    }
+
+    /// Validate each of a series of CBOR-encoded documents against a rule.
+    ///
+    /// The documents are validated as the iterator is advanced, giving one
+    /// result for each.
+    #[cfg(feature = "serde_cbor")]
+    pub fn validate_cbor_iter<'s, I>(
+        &'s self,
+        rule_def: &'s RuleDef,
+        docs: I,
+    ) -> impl Iterator<Item = ValidateResult> + 's
+    where
+        I: IntoIterator,
+        I::IntoIter: 's,
+        I::Item: AsRef<[u8]>,
+    {
+        docs.into_iter()
+            .map(move |doc| self.validate_cbor_slice(rule_def, doc.as_ref()))
+    }
+
+    /// Validate already-parsed JSON data against a rule.
+    ///
+    /// This is like [`validate_json`](crate::json::validate_json).
+    #[cfg(feature = "serde_json")]
+    pub fn validate_json(&self, rule_def: &RuleDef, value: &serde_json::Value) -> ValidateResult {
+        self.validate(rule_def, &Value::try_from(value)?)
+    }
+}
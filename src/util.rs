@@ -1,91 +1,1087 @@
 //! This module defines error and result types.
 //!
 
+use crate::context::LookupContext;
 use crate::parser;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
 use std::result::Result;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// A basic error type that contains a string.
+///
+/// Validation stops at the first error.  If several members of a map fail
+/// to match, the error is from the first of them in the schema; entries
+/// matching the same member are tried in key order.  When an error lists
+/// several problems (e.g. for the closest option of a map choice), they are
+/// in the same order.
 #[allow(missing_docs)]
 #[non_exhaustive]
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 pub enum ValidateError {
     /// An error during CDDL parsing.
-    #[error(transparent)]
     ParseError(#[from] parser::ParseError),
     /// A logical error in the CDDL structure.
-    #[error("Structural({0})")]
     Structural(String),
     /// A data mismatch during validation.
     // The difference between Mismatch and MapCut is that they trigger
     // slightly different internal behavior; to a human reader they mean
     // the same thing so we will Display them the same way.
-    #[error("Mismatch(expected {})", .0.expected)]
     Mismatch(Mismatch),
+    /// A required map key was not found.
+    MissingMapKey(MissingMapKey),
     /// A map key-value cut error.
-    #[error("Mismatch(expected {})", .0.expected)]
-    MapCut(Mismatch),
+    ///
+    /// This contains the `Mismatch` or `MissingMapKey` error that happened
+    /// after the map key was matched.
+    MapCut(Box<ValidateError>),
     /// A CDDL rule lookup failed.
-    #[error("MissingRule({0})")]
-    MissingRule(String),
+    MissingRule(MissingRule),
     /// A CDDL feature that is unsupported.
-    #[error("Unsupported {0}")]
     Unsupported(String),
     /// A data value that can't be validated by CDDL.
-    #[error("ValueError({0})")]
     ValueError(String),
+    /// Data encoded as text (e.g. hex or base64) couldn't be decoded.
+    DecodeError(DecodeError),
     /// A generic type parameter was used incorrectly.
-    #[error("GenericError")]
     GenericError,
+    /// A schema rule expands to more nodes than the configured limit.
+    SchemaTooLarge(SchemaTooLarge),
+    /// There was no data to validate: the input was empty (or, for a text
+    /// format, only whitespace).
+    EmptyInput,
+    /// The encoded data ended in the middle of an item.
+    Truncated(Truncated),
+    /// Validation followed more nested rule references than
+    /// [`ValidationOptions::max_depth`] allows.
+    ///
+    /// This contains the limit.
+    ///
+    /// [`ValidationOptions::max_depth`]: crate::context::ValidationOptions::max_depth
+    RecursionLimit(usize),
+}
+
+/// Whether an error is a problem with the schema or with the data.
+///
+/// Schema errors mean the schema (or the code building it) needs fixing;
+/// they don't depend on the data, so retrying with other data won't help.
+/// Data errors are the expected outcome of validating data that doesn't
+/// fit the schema, including data that can't be decoded at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The schema is invalid or uses something unsupported.
+    Schema,
+    /// The data doesn't match the schema, or couldn't be decoded.
+    Data,
+}
+
+/// What went wrong, for callers that want to handle some errors specially.
+///
+/// This is finer-grained than [`ErrorCategory`]: in particular, it tells
+/// apart the different ways data can fail to match.  The details (the
+/// path, the missing key, the rule name) are in the error itself.
+///
+/// More kinds may be added later, so matches on this need a wildcard arm.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A value didn't have the type or value the schema expected.
+    TypeMismatch,
+    /// A required map key wasn't found.
+    MissingMapKey,
+    /// A map had a key that nothing in the schema matched.
+    UnexpectedMapKey,
+    /// An array (or a CBOR sequence) had too many or too few elements.
+    ArrayLengthMismatch,
+    /// A rule the schema refers to isn't defined.
+    MissingRule,
+    /// The schema uses something that isn't supported.
+    Unsupported,
+    /// The schema couldn't be parsed, or doesn't make sense.
+    InvalidSchema,
+    /// The data couldn't be decoded, or can't be validated by CDDL.
+    InvalidData,
+    /// Validation followed too many nested rule references.
+    RecursionLimit,
+}
+
+impl fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidateError::ParseError(e) => write!(f, "{}", e)?,
+            ValidateError::Structural(msg) => write!(f, "Structural({})", msg)?,
+            ValidateError::Mismatch(m) => write!(f, "Mismatch({})", m)?,
+            ValidateError::MissingMapKey(m) => write!(f, "{}", m)?,
+            ValidateError::MapCut(e) => return e.fmt(f),
+            ValidateError::MissingRule(m) => write!(f, "{}", m)?,
+            ValidateError::Unsupported(msg) => write!(f, "Unsupported {}", msg)?,
+            ValidateError::ValueError(msg) => write!(f, "ValueError({})", msg)?,
+            ValidateError::DecodeError(e) => write!(f, "{}", e)?,
+            ValidateError::GenericError => write!(f, "GenericError")?,
+            ValidateError::SchemaTooLarge(e) => write!(f, "{}", e)?,
+            ValidateError::EmptyInput => write!(f, "EmptyInput")?,
+            ValidateError::Truncated(e) => write!(f, "{}", e)?,
+            ValidateError::RecursionLimit(limit) => {
+                write!(f, "RecursionLimit(hit recursion limit of {} rules)", limit)?
+            }
+        }
+        // The alternate form (`{:#}`) also shows where in the schema the
+        // error happened, the CDDL text of the rule, and where it is in the
+        // encoded data, if they're known.
+        if f.alternate() {
+            if let Some(name) = self.schema_name() {
+                write!(f, "\nin {}", name)?;
+            }
+            let schema_path = self.schema_path();
+            if !schema_path.is_empty() {
+                write!(f, "\nvia ")?;
+                write_schema_path(f, schema_path)?;
+            }
+            if let Some(source) = self.rule_source() {
+                write!(f, "\nin rule: {}", source)?;
+            }
+            if let Some(range) = self.data_range() {
+                write!(f, "\nin data at bytes {}..{}", range.start, range.end)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl ValidateError {
+    /// Whether this is a problem with the schema or with the data.
+    ///
+    /// A [`RecursionLimit`](ValidateError::RecursionLimit) error counts as
+    /// a data error, since it's usually reached by deeply nested data.  A
+    /// schema whose rules refer to each other without consuming any data
+    /// (`a = b  b = a`) reaches it too; [`lint`](crate::lint) can't yet
+    /// find those.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ValidateError::ParseError(_)
+            | ValidateError::Structural(_)
+            | ValidateError::MissingRule(_)
+            | ValidateError::Unsupported(_)
+            | ValidateError::GenericError
+            | ValidateError::SchemaTooLarge(_) => ErrorCategory::Schema,
+            ValidateError::Mismatch(_)
+            | ValidateError::MissingMapKey(_)
+            | ValidateError::ValueError(_)
+            | ValidateError::DecodeError(_)
+            | ValidateError::EmptyInput
+            | ValidateError::Truncated(_)
+            | ValidateError::RecursionLimit(_) => ErrorCategory::Data,
+            ValidateError::MapCut(e) => e.category(),
+        }
+    }
+
+    /// What kind of problem this is.
+    ///
+    /// ```
+    /// # #[cfg(feature = "serde_json")]
+    /// # {
+    /// use cddl_cat::{validate_json_str, ErrorKind};
+    ///
+    /// let cddl_input = "thing = { name: tstr }";
+    /// let err = validate_json_str("thing", cddl_input, r#"{"name": 1}"#).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::TypeMismatch);
+    /// let err = validate_json_str("thing", cddl_input, r#"{"age": 1}"#).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::MissingMapKey);
+    /// # }
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ValidateError::Mismatch(m) => m.kind,
+            ValidateError::MissingMapKey(_) => ErrorKind::MissingMapKey,
+            ValidateError::MapCut(e) => e.kind(),
+            ValidateError::MissingRule(_) => ErrorKind::MissingRule,
+            ValidateError::Unsupported(_) => ErrorKind::Unsupported,
+            ValidateError::ParseError(_)
+            | ValidateError::Structural(_)
+            | ValidateError::GenericError
+            | ValidateError::SchemaTooLarge(_) => ErrorKind::InvalidSchema,
+            ValidateError::ValueError(_)
+            | ValidateError::DecodeError(_)
+            | ValidateError::EmptyInput
+            | ValidateError::Truncated(_) => ErrorKind::InvalidData,
+            ValidateError::RecursionLimit(_) => ErrorKind::RecursionLimit,
+        }
+    }
+
+    /// The CDDL text of the rule where a data mismatch happened.
+    ///
+    /// This is the innermost rule that the data was being validated
+    /// against, as written in the schema.  It's only available if the
+    /// rules were looked up in a [`LookupContext`] that keeps the schema
+    /// text, such as a [`Schema`] created with [`Schema::from_str`].
+    ///
+    /// [`LookupContext`]: crate::context::LookupContext
+    /// [`Schema`]: crate::schema::Schema
+    /// [`Schema::from_str`]: crate::schema::Schema::from_str
+    pub fn rule_source(&self) -> Option<&str> {
+        match self {
+            ValidateError::Mismatch(m) => m.place.as_ref()?.rule_source.as_deref(),
+            ValidateError::MissingMapKey(m) => m.place.as_ref()?.rule_source.as_deref(),
+            ValidateError::MapCut(e) => e.rule_source(),
+            _ => None,
+        }
+    }
+
+    /// The name of the place in the schema where a data mismatch happened,
+    /// e.g. `thing.items[].id`.
+    ///
+    /// Maps, arrays and map members are named by their path from the top
+    /// of the rule that defines them (see [`Map::name`]), so this can point
+    /// into a rule with deeply nested types, where the rule text and the
+    /// path through the data don't say which part failed.  It's the
+    /// innermost such place, the same as [`rule_source`] is the innermost
+    /// rule.
+    ///
+    /// [`Map::name`]: crate::ivt::Map::name
+    /// [`rule_source`]: ValidateError::rule_source
+    pub fn schema_name(&self) -> Option<&str> {
+        match self {
+            ValidateError::Mismatch(m) => m.place.as_ref()?.name.as_deref(),
+            ValidateError::MissingMapKey(m) => m.place.as_ref()?.name.as_deref(),
+            ValidateError::MapCut(e) => e.schema_name(),
+            _ => None,
+        }
+    }
+
+    /// The path through the schema to a data mismatch, from the rule that
+    /// was being validated against.
+    ///
+    /// This is the schema's side of the error's data path: it lists the
+    /// rules that were followed, the map members, array members and choice
+    /// options that were being matched, outermost first.  A member that
+    /// comes from a group or an unwrapped rule follows the name of that
+    /// rule, so the path says where the member was written even when that's
+    /// a different rule from the map's.  In the alternate form (`{:#}`) it
+    /// is shown like `[0] > message/1.body > reading.flags`.
+    ///
+    /// The path is empty for errors that aren't data mismatches, and when
+    /// validating in [lean mode](crate::context::ValidationOptions::lean) it
+    /// comes from the second, normal validation.
+    pub fn schema_path(&self) -> &[SchemaElement] {
+        match self {
+            ValidateError::Mismatch(m) => m.place.as_ref().map_or(&[], |p| &p.path),
+            ValidateError::MissingMapKey(m) => m.place.as_ref().map_or(&[], |p| &p.path),
+            ValidateError::MapCut(e) => e.schema_path(),
+            _ => &[],
+        }
+    }
+
+    /// The byte range of the encoded data that contains a data mismatch.
+    ///
+    /// This is the range of the element or entry at the start of the
+    /// error's path: an element of a top-level array, an entry (key and
+    /// value) of a top-level map, or an item of a CBOR sequence.  It's only
+    /// available when validating encoded CBOR, e.g. with
+    /// [`validate_cbor_bytes`](crate::cbor::validate_cbor_bytes).
+    pub fn data_range(&self) -> Option<Range<usize>> {
+        match self {
+            ValidateError::Mismatch(m) => m.data_range.clone(),
+            ValidateError::MissingMapKey(m) => m.data_range.clone(),
+            ValidateError::MapCut(e) => e.data_range(),
+            _ => None,
+        }
+    }
+
+    // Prevent warnings if serde_cbor is disabled.
+    #[allow(dead_code)]
+    pub(crate) fn with_data_range(self, range: Range<usize>) -> ValidateError {
+        match self {
+            ValidateError::Mismatch(mut m) => {
+                m.data_range = Some(range);
+                ValidateError::Mismatch(m)
+            }
+            ValidateError::MissingMapKey(mut m) => {
+                m.data_range = Some(range);
+                ValidateError::MissingMapKey(m)
+            }
+            ValidateError::MapCut(e) => ValidateError::MapCut(Box::new(e.with_data_range(range))),
+            _ => self,
+        }
+    }
+
+    /// Record the name of the place in the schema where this error
+    /// happened, unless a place nested inside it has already been recorded.
+    pub(crate) fn in_schema<F>(mut self, name: F) -> ValidateError
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        if let Some(place) = self.place_mut() {
+            if place.as_ref().map_or(true, |p| p.name.is_none()) {
+                if let Some(name) = name() {
+                    place.get_or_insert_with(Box::default).name = Some(name);
+                }
+            }
+        }
+        self
+    }
+
+    /// Record that this error happened inside a part of the schema.  This
+    /// does for the schema path what [`at`](ValidateError::at) does for the
+    /// data path, except that the path is built innermost first, until
+    /// [`finish`](ValidateError::finish) puts it in order.
+    pub(crate) fn via(mut self, elem: SchemaElement) -> ValidateError {
+        if let Some(place) = self.place_mut() {
+            place.get_or_insert_with(Box::default).path.push(elem);
+        }
+        self
+    }
+
+    /// Finish an error that's being returned from validation: put its
+    /// schema path in order, and record the CDDL text of the innermost rule
+    /// on that path that has any.
+    ///
+    /// This is left until the end because most errors never get this far:
+    /// they're from choice options that didn't match, or are replaced by a
+    /// better error.
+    pub(crate) fn finish(mut self, lookup: &dyn LookupContext) -> ValidateError {
+        if let Some(Some(place)) = self.place_mut() {
+            let source = place.path.iter().find_map(|elem| match elem {
+                SchemaElement::Rule(name) => lookup.rule_source(name),
+                _ => None,
+            });
+            place.rule_source = source.map(String::from);
+            place.path.reverse();
+        }
+        self
+    }
+
+    fn place_mut(&mut self) -> Option<&mut Option<Box<SchemaPlace>>> {
+        match self {
+            ValidateError::Mismatch(m) => Some(&mut m.place),
+            ValidateError::MissingMapKey(m) => Some(&mut m.place),
+            ValidateError::MapCut(e) => e.place_mut(),
+            _ => None,
+        }
+    }
+
     /// Identify whether this error is fatal
     ///
     /// A "fatal" error is one that should fail the entire validation, even if
     /// it occurs inside a choice or occurrence that might otherwise succeed.
     pub(crate) fn is_fatal(&self) -> bool {
-        !matches!(self, ValidateError::Mismatch(_) | ValidateError::MapCut(_))
+        !matches!(
+            self,
+            ValidateError::Mismatch(_) | ValidateError::MissingMapKey(_) | ValidateError::MapCut(_)
+        )
     }
 
-    /// Convert a MapCut error to a Mismatch error; otherwise return the original error.
+    /// Convert a MapCut error to the error it contains; otherwise return the original error.
     pub(crate) fn erase_mapcut(self) -> ValidateError {
         match self {
-            ValidateError::MapCut(m) => ValidateError::Mismatch(m),
+            ValidateError::MapCut(e) => *e,
+            ValidateError::Mismatch(m) if m.cut => {
+                ValidateError::Mismatch(Mismatch { cut: false, ..m })
+            }
             _ => self,
         }
     }
 
+    /// Identify whether this error is a data mismatch that allows validation
+    /// to try other alternatives.
     pub(crate) fn is_mismatch(&self) -> bool {
-        matches!(self, ValidateError::Mismatch(_))
+        match self {
+            ValidateError::Mismatch(m) => !m.cut,
+            ValidateError::MissingMapKey(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The hint attached to a mismatch, if any.
+    pub(crate) fn hint(&self) -> Option<&'static str> {
+        match self {
+            ValidateError::Mismatch(m) => m.hint,
+            _ => None,
+        }
+    }
+
+    /// Set the kind of a mismatch; other errors are returned unchanged.
+    pub(crate) fn with_kind(self, kind: ErrorKind) -> ValidateError {
+        match self {
+            ValidateError::Mismatch(m) => ValidateError::Mismatch(Mismatch { kind, ..m }),
+            _ => self,
+        }
+    }
+
+    /// Attach a hint to a mismatch; other errors are returned unchanged.
+    pub(crate) fn with_hint(self, hint: &'static str) -> ValidateError {
+        match self {
+            ValidateError::Mismatch(m) => ValidateError::Mismatch(m.with_hint(hint)),
+            _ => self,
+        }
+    }
+
+    /// The path from the top of the data to this error.
+    ///
+    /// This is empty if the error happened at the top level (or isn't a
+    /// mismatch at all).
+    pub(crate) fn path(&self) -> &[PathElement] {
+        match self {
+            ValidateError::Mismatch(m) => &m.path,
+            ValidateError::MissingMapKey(m) => &m.path,
+            ValidateError::MapCut(e) => e.path(),
+            _ => &[],
+        }
+    }
+
+    /// Record that this error happened inside the value of a map entry,
+    /// given its position in the map (in key order) and its rendered key.
+    ///
+    /// Rendered keys may be shortened, so the position is what says which
+    /// entry it was; see [`entry`](ValidateError::entry).
+    pub(crate) fn at_entry(self, entry: usize, key: String) -> ValidateError {
+        let err = self.at(PathElement::Key(key));
+        match u32::try_from(entry) {
+            Ok(entry) => err.with_entry(entry),
+            Err(_) => err,
+        }
+    }
+
+    fn with_entry(self, entry: u32) -> ValidateError {
+        match self {
+            ValidateError::Mismatch(mut m) => {
+                m.entry = Some(entry);
+                ValidateError::Mismatch(m)
+            }
+            ValidateError::MissingMapKey(mut m) => {
+                m.entry = Some(entry);
+                ValidateError::MissingMapKey(m)
+            }
+            ValidateError::MapCut(e) => ValidateError::MapCut(Box::new(e.with_entry(entry))),
+            _ => self,
+        }
+    }
+
+    /// The position (in key order) of the map entry that the error's path
+    /// starts with, if it starts with a map key.
+    pub(crate) fn entry(&self) -> Option<usize> {
+        match self {
+            ValidateError::Mismatch(m) => m.entry.map(|e| e as usize),
+            ValidateError::MissingMapKey(m) => m.entry.map(|e| e as usize),
+            ValidateError::MapCut(e) => e.entry(),
+            _ => None,
+        }
+    }
+
+    /// Record that this error happened inside a map value or array element.
+    pub(crate) fn at(self, elem: PathElement) -> ValidateError {
+        match self {
+            ValidateError::Mismatch(mut m) => {
+                m.path.insert(0, elem);
+                ValidateError::Mismatch(m)
+            }
+            ValidateError::MissingMapKey(mut m) => {
+                m.path.insert(0, elem);
+                ValidateError::MissingMapKey(m)
+            }
+            ValidateError::MapCut(e) => ValidateError::MapCut(Box::new(e.at(elem))),
+            _ => self,
+        }
+    }
+}
+
+/// One step along the path from the top of the data to an error.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathElement {
+    /// A map key.
+    ///
+    /// This is a text rendering of the key, e.g. `"name"` or `17`, made by
+    /// [`render_value`].
+    Key(String),
+    /// An array index.
+    Index(usize),
+}
+
+impl fmt::Display for PathElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathElement::Key(k) => write!(f, "[{}]", k),
+            PathElement::Index(i) => write!(f, "[{}]", i),
+        }
+    }
+}
+
+// Write " at [a][b]" for a non-empty path.
+fn write_path(f: &mut fmt::Formatter<'_>, path: &[PathElement]) -> fmt::Result {
+    if !path.is_empty() {
+        write!(f, " at ")?;
+        for elem in path {
+            write!(f, "{}", elem)?;
+        }
+    }
+    Ok(())
+}
+
+/// One step along the path through the schema to an error.
+///
+/// See [`ValidateError::schema_path`].
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaElement {
+    /// A rule reference, by the name of the rule.  This includes rules
+    /// that are groups, and unwrapped (`~`) rules.
+    Rule(Arc<str>),
+    /// A map member, or a named array member, by its key.  A key that
+    /// isn't a literal value is `*`.
+    Member(Arc<str>),
+    /// An array member, by its position in the array's definition.
+    Index(usize),
+    /// An option of a choice, counting from 0.
+    Choice(usize),
+}
+
+impl fmt::Display for SchemaElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaElement::Rule(name) => write!(f, "{}", name),
+            SchemaElement::Member(key) => write!(f, ".{}", key),
+            SchemaElement::Index(i) => write!(f, "[{}]", i),
+            SchemaElement::Choice(i) => write!(f, "/{}", i),
+        }
     }
 }
 
+// Write a schema path, with " > " before each rule but the first element.
+fn write_schema_path(f: &mut fmt::Formatter<'_>, path: &[SchemaElement]) -> fmt::Result {
+    for (i, elem) in path.iter().enumerate() {
+        if i > 0 && matches!(elem, SchemaElement::Rule(_)) {
+            write!(f, " > ")?;
+        }
+        write!(f, "{}", elem)?;
+    }
+    Ok(())
+}
+
 /// A data mismatch during validation.
 ///
 /// If the CDDL specified an `int` and the data contained a string, this is
 /// the error that would result.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// If the mismatch happened inside a map or array, the path lists the map
+/// keys and array indices that lead to it, e.g.
+/// `Mismatch(expected int at ["people"][3]["age"])`.  Map keys are taken
+/// from the data, not the schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Mismatch {
-    expected: String,
+    expected: Cow<'static, str>,
+    path: Vec<PathElement>,
+    hint: Option<&'static str>,
+    place: Option<Box<SchemaPlace>>,
+    data_range: Option<Range<usize>>,
+    // The position (in key order) of the map entry at the start of the
+    // path, if the path starts with a map key.  A u32 keeps
+    // ValidateError from growing.
+    entry: Option<u32>,
+    // Set instead of wrapping the error in a MapCut when validating in
+    // lean mode, since the Box would need an allocation.
+    cut: bool,
+    kind: ErrorKind,
+}
+
+impl Mismatch {
+    /// A description of what was expected.
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+
+    /// The path to the mismatched data, outermost first.
+    pub fn path(&self) -> &[PathElement] {
+        &self.path
+    }
+
+    /// A suggestion for how the schema or the data might be fixed, if the
+    /// cause of the mismatch looks like a common mistake.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint
+    }
+
+    pub(crate) fn with_hint(mut self, hint: &'static str) -> Mismatch {
+        self.hint = Some(hint);
+        self
+    }
+}
+
+// Where in the schema a mismatch happened.  This is boxed to keep
+// ValidateError small, and because it's often unknown.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct SchemaPlace {
+    // This is filled in by ValidateError::finish.
+    rule_source: Option<String>,
+    name: Option<String>,
+    path: Vec<SchemaElement>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}", self.expected)?;
+        write_path(f, &self.path)?;
+        if let Some(hint) = &self.hint {
+            write!(f, " ({})", hint)?;
+        }
+        Ok(())
+    }
 }
 
 /// Shortcut for creating mismatch errors.
 #[doc(hidden)]
-pub fn mismatch<E: Into<String>>(expected: E) -> ValidateError {
+pub fn mismatch<E: Into<Cow<'static, str>>>(expected: E) -> ValidateError {
     ValidateError::Mismatch(Mismatch {
         expected: expected.into(),
+        path: Vec::new(),
+        hint: None,
+        place: None,
+        data_range: None,
+        entry: None,
+        cut: false,
+        kind: ErrorKind::TypeMismatch,
+    })
+}
+
+// A mismatch with no description, for lean mode.  This doesn't allocate.
+pub(crate) fn lean_mismatch() -> ValidateError {
+    mismatch("")
+}
+
+// A mismatch from a map member with a cut, for lean mode.  This behaves
+// like a MapCut error, without the Box.
+pub(crate) fn lean_cut() -> ValidateError {
+    match lean_mismatch() {
+        ValidateError::Mismatch(m) => ValidateError::Mismatch(Mismatch { cut: true, ..m }),
+        _ => unreachable!(),
+    }
+}
+
+/// A required map key was not found.
+///
+/// This is different from a [`Mismatch`], which happens when the key is
+/// present but its value doesn't match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingMapKey {
+    key: String,
+    expected: String,
+    path: Vec<PathElement>,
+    place: Option<Box<SchemaPlace>>,
+    data_range: Option<Range<usize>>,
+    // The position (in key order) of the map entry at the start of the
+    // path, if the path starts with a map key.  A u32 keeps
+    // ValidateError from growing.
+    entry: Option<u32>,
+}
+
+impl MissingMapKey {
+    /// A description of the key that was not found.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// A description of the value that was expected for that key.
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+
+    /// The path to the map that is missing the key, outermost first.
+    pub fn path(&self) -> &[PathElement] {
+        &self.path
+    }
+}
+
+impl fmt::Display for MissingMapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MissingMapKey({}: {})", self.key, self.expected)?;
+        write_path(f, &self.path)
+    }
+}
+
+/// Shortcut for creating missing map key errors.
+pub(crate) fn missing_map_key<K, E>(key: K, expected: E) -> ValidateError
+where
+    K: fmt::Display,
+    E: fmt::Display,
+{
+    ValidateError::MissingMapKey(MissingMapKey {
+        key: key.to_string(),
+        expected: expected.to_string(),
+        path: Vec::new(),
+        place: None,
+        data_range: None,
+        entry: None,
+    })
+}
+
+/// A CDDL rule lookup failed.
+///
+/// If rules with similar names exist, they will be offered as suggestions,
+/// e.g. `MissingRule(Person); did you mean 'person'?`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingRule {
+    name: String,
+    suggestions: Vec<String>,
+}
+
+impl MissingRule {
+    /// The rule name that could not be found.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of existing rules that are similar to the missing one.
+    ///
+    /// The closest matches come first.
+    pub fn suggestions(&self) -> &[String] {
+        &self.suggestions
+    }
+}
+
+impl fmt::Display for MissingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MissingRule({})", self.name)?;
+        let mut suggestions = self.suggestions.iter();
+        if let Some(first) = suggestions.next() {
+            write!(f, "; did you mean '{}'", first)?;
+            for s in suggestions {
+                write!(f, " or '{}'", s)?;
+            }
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Data encoded as text (e.g. hex or base64) couldn't be decoded.
+///
+/// If the problem is a bad character, the offset of that character is
+/// included, e.g. `DecodeError(invalid hex character 'z' at offset 3)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    message: String,
+    offset: Option<usize>,
+}
+
+impl DecodeError {
+    #[cfg(feature = "serde_cbor")]
+    pub(crate) fn new<S: Into<String>>(message: S, offset: Option<usize>) -> DecodeError {
+        DecodeError {
+            message: message.into(),
+            offset,
+        }
+    }
+
+    /// A description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The byte offset of the bad character in the input text, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DecodeError({}", self.message)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The encoded data ended in the middle of an item.
+///
+/// This usually means that only part of a message was received.  The
+/// number of bytes needed is a lower bound: it's the length of everything
+/// up to the first missing byte whose position is known, so more may be
+/// needed once that data arrives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Truncated {
+    needed: usize,
+    available: usize,
+}
+
+impl Truncated {
+    #[cfg(feature = "serde_cbor")]
+    pub(crate) fn new(needed: usize, available: usize) -> Truncated {
+        Truncated { needed, available }
+    }
+
+    /// The number of bytes the data needs, at least.
+    pub fn needed(&self) -> usize {
+        self.needed
+    }
+
+    /// The number of bytes that were available.
+    pub fn available(&self) -> usize {
+        self.available
+    }
+}
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Truncated(need at least {} bytes, found {})",
+            self.needed, self.available
+        )
+    }
+}
+
+/// A schema rule expands to more nodes than the configured limit.
+///
+/// The size counts every node that validation might visit, so rules that
+/// are unwrapped or used as generic arguments count once per use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaTooLarge {
+    rule: String,
+    limit: usize,
+}
+
+impl SchemaTooLarge {
+    pub(crate) fn new<S: Into<String>>(rule: S, limit: usize) -> SchemaTooLarge {
+        SchemaTooLarge {
+            rule: rule.into(),
+            limit,
+        }
+    }
+
+    /// The name of the rule that exceeded the limit.
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    /// The node limit that was exceeded.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for SchemaTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SchemaTooLarge(rule '{}' expands to more than {} nodes)",
+            self.rule, self.limit
+        )
+    }
+}
+
+/// Shortcut for creating missing-rule errors.
+#[doc(hidden)]
+pub fn missing_rule<S: Into<String>>(name: S) -> ValidateError {
+    ValidateError::MissingRule(MissingRule {
+        name: name.into(),
+        suggestions: Vec::new(),
     })
 }
 
+/// Create a missing-rule error, suggesting similar names from `known`.
+pub(crate) fn missing_rule_suggest<'a, I>(name: &str, known: I) -> ValidateError
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    // Allow roughly one typo for every three characters.
+    let max_distance = std::cmp::max(1, name.chars().count() / 3);
+    let mut close: Vec<(usize, &String)> = known
+        .into_iter()
+        .map(|k| (edit_distance(name, k), k))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+    close.sort();
+    ValidateError::MissingRule(MissingRule {
+        name: name.into(),
+        suggestions: close.into_iter().take(3).map(|(_, k)| k.clone()).collect(),
+    })
+}
+
+/// Compute the edit (Levenshtein) distance between two names.
+///
+/// Differences in letter case don't count; a name that differs only in
+/// case is the best possible suggestion.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().flat_map(char::to_lowercase).collect();
+    let b: Vec<char> = b.chars().flat_map(char::to_lowercase).collect();
+
+    // Compute one row of the distance matrix at a time.
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            let insert = row[j] + 1;
+            let delete = prev[j + 1] + 1;
+            row.push(substitute.min(insert).min(delete));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
 /// A validation that doesn't return anything.
 pub type ValidateResult = Result<(), ValidateError>;
 
+/// A list of validation errors.
+///
+/// The number of errors kept is limited (see
+/// [`ValidationOptions::max_errors`]); any further errors are only counted.
+///
+/// [`ValidationOptions::max_errors`]: crate::context::ValidationOptions::max_errors
+#[derive(Debug)]
+pub struct ValidateErrors {
+    errors: Vec<ValidateError>,
+    max_errors: usize,
+    omitted: usize,
+}
+
+impl ValidateErrors {
+    /// Create an empty list that will keep at most `max_errors` errors.
+    pub fn new(max_errors: usize) -> ValidateErrors {
+        ValidateErrors {
+            errors: Vec::new(),
+            max_errors,
+            omitted: 0,
+        }
+    }
+
+    /// Add an error to the list, or count it if the list is full.
+    pub fn push(&mut self, error: ValidateError) {
+        if self.errors.len() < self.max_errors {
+            self.errors.push(error);
+        } else {
+            self.omitted += 1;
+        }
+    }
+
+    /// The errors that were kept.
+    pub fn errors(&self) -> &[ValidateError] {
+        &self.errors
+    }
+
+    /// The number of errors that were discarded because the list was full.
+    pub fn omitted(&self) -> usize {
+        self.omitted
+    }
+
+    /// Returns `true` if no errors were added.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.omitted == 0
+    }
+}
+
+impl fmt::Display for ValidateErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for e in &self.errors {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{}", e)?;
+        }
+        if self.omitted > 0 {
+            if !first {
+                writeln!(f)?;
+            }
+            write!(f, "...and {} more", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidateErrors {}
+
+/// Render data for use in an error message, truncated to `max_len` bytes.
+///
+/// If the text is truncated, the number of elided bytes is appended, e.g.
+/// `"aaaaaaaa...(+312 bytes)`.  Only the part that's kept is stored, so
+/// rendering a large value doesn't need a copy of all of it.
+///
+/// Map keys in error paths ([`PathElement::Key`]) are rendered this way,
+/// with [`ValidationOptions::max_value_len`] as the limit.
+///
+/// [`ValidationOptions::max_value_len`]: crate::context::ValidationOptions::max_value_len
+pub fn render_value<T: fmt::Debug>(value: &T, max_len: usize) -> String {
+    use std::fmt::Write;
+
+    let mut prefix = Prefix {
+        text: String::new(),
+        limit: max_len,
+        total: 0,
+    };
+    let _ = write!(prefix, "{:?}", value);
+    let mut rendered = prefix.text;
+    if prefix.total > rendered.len() {
+        let elided = prefix.total - rendered.len();
+        rendered.push_str(&format!("...(+{} bytes)", elided));
+    }
+    rendered
+}
+
+// A writer that keeps the first `limit` bytes written to it (without
+// splitting a character), and counts the rest.
+struct Prefix {
+    text: String,
+    limit: usize,
+    total: usize,
+}
+
+impl fmt::Write for Prefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.total += s.len();
+        let room = self.limit - self.text.len();
+        if s.len() <= room {
+            self.text.push_str(s);
+        } else {
+            let mut end = room;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.text.push_str(&s[..end]);
+            // Nothing after a split character may be added.
+            self.limit = self.text.len();
+        }
+        Ok(())
+    }
+}
+
+/// Render part of the schema (e.g. a literal) for use in an error message.
+///
+/// If it's longer than `max_len` bytes, the middle is elided so that both
+/// ends still show, e.g. `"-----BEGIN...(+1900 bytes)...END-----"`.
+pub(crate) fn render_schema<T: fmt::Display>(value: &T, max_len: usize) -> String {
+    let rendered = value.to_string();
+    if rendered.len() <= max_len {
+        return rendered;
+    }
+    let mut head = max_len / 2;
+    while !rendered.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = rendered.len() - (max_len - max_len / 2);
+    while !rendered.is_char_boundary(tail) {
+        tail += 1;
+    }
+    format!(
+        "{}...(+{} bytes)...{}",
+        &rendered[..head],
+        tail - head,
+        &rendered[tail..]
+    )
+}
+
 // Some utility functions that are helpful when testing whether the right
 // error was returned.
 #[doc(hidden)]
 pub trait ErrorMatch {
     fn err_mismatch(&self);
+    fn err_missing_map_key(&self);
     fn err_missing_rule(&self);
     fn err_generic(&self);
     fn err_parse(&self);
@@ -101,6 +1097,14 @@ impl ErrorMatch for ValidateResult {
         }
     }
 
+    #[track_caller]
+    fn err_missing_map_key(&self) {
+        match self {
+            Err(ValidateError::MissingMapKey(_)) => (),
+            _ => panic!("expected MissingMapKey, got {:?}", self),
+        }
+    }
+
     #[track_caller]
     fn err_missing_rule(&self) {
         match self {
@@ -146,4 +1150,86 @@ mod tests {
         let e = ValidateError::Structural("".into());
         assert!(e.is_fatal());
     }
+
+    #[test]
+    fn test_render_value() {
+        assert_eq!(render_value(&"abc", 10), r#""abc""#);
+        assert_eq!(render_value(&"abcdefghij", 6), r#""abcde...(+6 bytes)"#);
+        // Don't split a multi-byte character.
+        assert_eq!(render_value(&"水水", 3), r#""...(+7 bytes)"#);
+        assert_eq!(render_value(&12345, 0), "...(+5 bytes)");
+        assert_eq!(render_value(&vec![1; 1000], 6), "[1, 1,...(+2994 bytes)");
+    }
+
+    #[test]
+    fn test_render_schema() {
+        assert_eq!(render_schema(&"abc", 3), "abc");
+        assert_eq!(render_schema(&"abcdefghij", 6), "abc...(+4 bytes)...hij");
+        assert_eq!(render_schema(&"abcdefghij", 5), "ab...(+5 bytes)...hij");
+        // Don't split a multi-byte character.
+        assert_eq!(render_schema(&"水水水", 4), "...(+9 bytes)...");
+        assert_eq!(render_schema(&"a水水b", 6), "a...(+6 bytes)...b");
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let mut errors = ValidateErrors::new(2);
+        assert!(errors.is_empty());
+        assert_eq!(errors.to_string(), "");
+
+        errors.push(mismatch("int"));
+        assert_eq!(errors.to_string(), "Mismatch(expected int)");
+
+        errors.push(mismatch("tstr"));
+        errors.push(mismatch("bstr"));
+        errors.push(mismatch("bool"));
+        assert_eq!(errors.errors().len(), 2);
+        assert_eq!(errors.omitted(), 2);
+        assert_eq!(
+            errors.to_string(),
+            "Mismatch(expected int)\nMismatch(expected tstr)\n...and 2 more"
+        );
+
+        let mut errors = ValidateErrors::new(0);
+        errors.push(mismatch("int"));
+        assert!(!errors.is_empty());
+        assert_eq!(errors.to_string(), "...and 1 more");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("person", "Person"), 0);
+        assert_eq!(edit_distance("person", "persons"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_missing_rule_suggest() {
+        let known: Vec<String> = vec!["person".into(), "persons".into(), "address".into()];
+
+        let e = missing_rule_suggest("Person", &known);
+        assert_eq!(
+            e.to_string(),
+            "MissingRule(Person); did you mean 'person' or 'persons'?"
+        );
+
+        let e = missing_rule_suggest("adress", &known);
+        assert_eq!(
+            e.to_string(),
+            "MissingRule(adress); did you mean 'address'?"
+        );
+
+        let e = missing_rule_suggest("thing", &known);
+        assert_eq!(e.to_string(), "MissingRule(thing)");
+        match e {
+            ValidateError::MissingRule(m) => {
+                assert_eq!(m.name(), "thing");
+                assert!(m.suggestions().is_empty());
+            }
+            _ => panic!("expected MissingRule"),
+        }
+    }
 }
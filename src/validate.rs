@@ -2,31 +2,165 @@
 //!
 //! More precisely, it validates data that can be represented by [`Value`] trees.
 
-use crate::context::LookupContext;
+#[cfg(feature = "serde_cbor")]
+use crate::context::ValidationReport;
+use crate::context::{LookupContext, OccurrenceMatch, Permissive, PermissiveMatch, TagPolicy};
 use crate::ivt::*;
-use crate::util::{mismatch, ValidateError, ValidateResult};
+use crate::util::{
+    lean_cut, lean_mismatch, mismatch, missing_map_key, render_schema, render_value, ErrorKind,
+    PathElement, SchemaElement, ValidateError, ValidateResult,
+};
 use crate::value::Value;
+use crate::visit::MapVisitor;
+use float_ord::FloatOrd;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::BTreeMap; // used in Value::Map
-use std::collections::HashMap;
-use std::collections::VecDeque;
-use std::convert::TryInto;
+use std::collections::BTreeSet;
+use std::convert::{TryFrom, TryInto};
 use std::mem::discriminant;
+use std::rc::Rc;
+use std::sync::Arc;
 
 // A map from generic parameter name to the type being used here.
 #[derive(Clone, Debug, Default)]
 struct GenericMap<'a> {
-    map: HashMap<String, &'a Node>,
+    // The parameter names, and the types given for them (in the same order).
+    // There are only ever a few, so they're searched in place rather than
+    // collected into a map.
+    parms: &'a [String],
+    args: &'a [Node],
     // Because we captured this map at a previous time, we may need to look up
     // generic types from that previous context.  We carry a copy of that
     // Context with us to do those lookups.
     past_ctx: Option<&'a Context<'a>>,
 }
 
+impl<'a> GenericMap<'a> {
+    fn get(&self, name: &str) -> Option<&'a Node> {
+        let index = self.parms.iter().position(|parm| parm == name)?;
+        self.args.get(index)
+    }
+}
+
 #[derive(Clone)]
 struct Context<'a> {
     lookup: &'a dyn LookupContext,
     generic_map: GenericMap<'a>,
-    depth: u32,
+    depth: usize,
+    // If we're making a report, what we've found so far.
+    report: Option<&'a RefCell<ReportLog>>,
+    // Whether errors are being left blank (see ValidationOptions::lean).
+    lean: bool,
+    scratch: &'a Scratch,
+}
+
+// The things found while making a report, in the order they were found.
+#[derive(Debug, Default)]
+struct ReportLog {
+    any_tags: Vec<u64>,
+    permissive: Vec<PermissiveMatch>,
+    occurrences: Vec<OccurrenceMatch>,
+}
+
+/// Buffers that can be reused from one validation to the next.
+///
+/// A [`Session`] keeps one of these between validations; other validations
+/// make a new one, which doesn't allocate until it's used.
+///
+/// [`Session`]: crate::session::Session
+#[derive(Debug, Default)]
+pub(crate) struct Scratch {
+    // Spare buffers for the entry sets of maps with more than 64 entries.
+    words: RefCell<Vec<Vec<u64>>>,
+    // The results for arrays that greedy matching couldn't decide (see
+    // validate_array_part2), and the errors collected from arrays and maps
+    // (see collect_errors).  These point into the data, so they're only
+    // kept for one validation.
+    arrays: RefCell<BTreeMap<MatchKey, ValidateResult>>,
+    collected: RefCell<BTreeMap<MatchKey, Vec<ValidateError>>>,
+    // The rule names and member keys used in schema paths, so each error
+    // can share them rather than having its own copy.
+    names: RefCell<BTreeSet<Arc<str>>>,
+}
+
+impl Scratch {
+    // Keep a few buffers, enough for maps nested a few deep.
+    const MAX_SPARE: usize = 8;
+
+    fn take_words(&self) -> Vec<u64> {
+        self.words.borrow_mut().pop().unwrap_or_default()
+    }
+
+    fn name(&self, name: &str) -> Arc<str> {
+        let mut names = self.names.borrow_mut();
+        match names.get(name) {
+            Some(name) => name.clone(),
+            None => {
+                let name: Arc<str> = name.into();
+                names.insert(name.clone());
+                name
+            }
+        }
+    }
+
+    // Forget the results from a previous validation.
+    fn start(&self) {
+        self.arrays.borrow_mut().clear();
+        self.collected.borrow_mut().clear();
+    }
+
+    fn give_words(&self, set: EntrySet) {
+        if let EntrySet::Large(mut words) = set {
+            let mut spare = self.words.borrow_mut();
+            if spare.len() < Scratch::MAX_SPARE {
+                words.clear();
+                spare.push(words);
+            }
+        }
+    }
+}
+
+// What the result of matching some data depends on: the data, the part of
+// the schema, and what the generic parameters in scope stand for.  The
+// generic parameters are told apart by where their arguments were written,
+// for each context that they're looked up in.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct MatchKey {
+    values: *const Value,
+    len: usize,
+    schema: *const (),
+    generics: Vec<(*const String, *const Node)>,
+    depth: usize,
+    lean: bool,
+}
+
+impl MatchKey {
+    fn new<T>(schema: &T, values: &[Value], ctx: &Context) -> MatchKey {
+        let mut generics = Vec::new();
+        let mut generic_ctx = Some(ctx);
+        while let Some(c) = generic_ctx.filter(|c| !c.generic_map.parms.is_empty()) {
+            generics.push((c.generic_map.parms.as_ptr(), c.generic_map.args.as_ptr()));
+            generic_ctx = c.generic_map.past_ctx;
+        }
+        MatchKey {
+            values: values.as_ptr(),
+            len: values.len(),
+            schema: schema as *const T as *const (),
+            generics,
+            depth: ctx.depth,
+            lean: ctx.lean,
+        }
+    }
+}
+
+// The length of each part of the report log, so that a failed match can
+// forget what it found.
+#[derive(Clone, Copy, Default)]
+struct ReportMark {
+    any_tags: usize,
+    permissive: usize,
+    occurrences: usize,
 }
 
 impl std::fmt::Debug for Context<'_> {
@@ -40,16 +174,33 @@ impl std::fmt::Debug for Context<'_> {
 struct NodeContext<'a> {
     node: &'a Node,
     ctx: Context<'a>,
+    // The name of the rule, if the node came from one (rather than from a
+    // generic argument).
+    rule: Option<&'a str>,
 }
 
-impl<'a> Context<'a> {
-    // Set a maximum depth, to avoid infinite recursion in the case of
-    // circular rule references.
-    const MAX_DEPTH: u32 = 50;
+impl NodeContext<'_> {
+    // Attach the rule's name to an error that happened inside it.
+    fn in_rule(&self, result: ValidateResult) -> ValidateResult {
+        result.map_err(|e| self.rule_error(e))
+    }
+
+    fn rule_error(&self, e: ValidateError) -> ValidateError {
+        match self.rule {
+            Some(name) if !self.ctx.lean => e.via(SchemaElement::Rule(self.ctx.scratch.name(name))),
+            _ => e,
+        }
+    }
+}
 
-    fn inc_depth(&self) -> TempResult<u32> {
-        if self.depth >= Self::MAX_DEPTH {
-            Err(ValidateError::Structural("hit recursion limit".into()))
+impl<'a> Context<'a> {
+    // Limit the depth, to avoid infinite recursion in the case of circular
+    // rule references (e.g. `a = b  b = a`), and very deep recursion in the
+    // case of deeply nested data.
+    fn inc_depth(&self) -> TempResult<usize> {
+        let max_depth = self.lookup.options().max_depth;
+        if self.depth >= max_depth {
+            Err(ValidateError::RecursionLimit(max_depth))
         } else {
             Ok(self.depth + 1)
         }
@@ -72,7 +223,7 @@ impl<'a> Context<'a> {
         // First, check to see if the "rule name" is actually a generic parameter.
         // TODO: this would be a lot easier if this were pre-processed by the flattener
         // so that "rule lookup" and "generic type lookup" were two separate Node variants.
-        let generic_node: Option<&'a Node> = self.generic_map.map.get(&rule.name).cloned();
+        let generic_node: Option<&'a Node> = self.generic_map.get(&rule.name);
         if let Some(node) = generic_node {
             // If we stored a past_ctx along with the generic args, then pass that
             // context along with the node that is substituting for this generic
@@ -81,10 +232,22 @@ impl<'a> Context<'a> {
                 Some(ctx) => ctx.clone(),
                 None => self.blank()?,
             };
-            return Ok(NodeContext { node, ctx });
+            return Ok(NodeContext {
+                node,
+                ctx,
+                rule: None,
+            });
         }
 
-        let rule_def: &RuleDef = self.lookup.lookup_rule(&rule.name)?;
+        let rule_def: &RuleDef = match self.lookup.lookup_rule(&rule.name) {
+            // A socket that nothing was plugged into is an empty choice,
+            // which matches nothing.
+            Err(ValidateError::MissingRule(_)) if is_socket(&rule.name) => {
+                let msg = || format!("{} (an empty socket)", rule.name);
+                return Err(self.error(|| mismatch(msg())));
+            }
+            result => result?,
+        };
 
         // Create a new context containing a new generic parameter map.
         let ctx = self.derive(rule_def, rule)?;
@@ -92,6 +255,7 @@ impl<'a> Context<'a> {
         Ok(NodeContext {
             node: &rule_def.node,
             ctx,
+            rule: Some(&rule.name),
         })
     }
 
@@ -101,6 +265,9 @@ impl<'a> Context<'a> {
             lookup: self.lookup,
             generic_map: GenericMap::default(),
             depth: self.inc_depth()?,
+            report: self.report,
+            lean: self.lean,
+            scratch: self.scratch,
         })
     }
 
@@ -113,19 +280,11 @@ impl<'a> Context<'a> {
             return Err(ValidateError::GenericError);
         }
 
-        // Zip the two Vecs:
-        // 1. rule_def.generic_parms
-        // 2. rule.generic_args
-        // and put them into a HashMap so we can do lookups from parm -> arg.
-        let map: HashMap<String, &'a Node> = rule_def
-            .generic_parms
-            .iter()
-            .cloned()
-            .zip(rule.generic_args.iter())
-            .collect();
-
+        // Pair up rule_def.generic_parms with rule.generic_args, so we can
+        // do lookups from parm -> arg.
         let generic_map = GenericMap {
-            map,
+            parms: &rule_def.generic_parms,
+            args: &rule.generic_args,
             past_ctx: Some(self),
         };
 
@@ -133,189 +292,741 @@ impl<'a> Context<'a> {
             lookup: self.lookup,
             generic_map,
             depth: self.inc_depth()?,
+            report: self.report,
+            lean: self.lean,
+            scratch: self.scratch,
+        })
+    }
+
+    // Make an error that's only needed if the whole validation fails.  In
+    // lean mode it's left blank instead, so nothing is allocated; if the
+    // validation does fail, it runs again to build the real error.
+    //
+    // This is only for mismatches: making a fatal error blank would change
+    // what gets tried next.
+    fn error<F>(&self, f: F) -> ValidateError
+    where
+        F: FnOnce() -> ValidateError,
+    {
+        if self.lean {
+            lean_mismatch()
+        } else {
+            f()
+        }
+    }
+
+    // Add detail to an error, e.g. where in the data it happened.  In lean
+    // mode errors don't carry any detail, so this does nothing.
+    fn detail<F>(&self, e: ValidateError, f: F) -> ValidateError
+    where
+        F: FnOnce(ValidateError) -> ValidateError,
+    {
+        if self.lean {
+            e
+        } else {
+            f(e)
+        }
+    }
+
+    // Record the part of the schema that an error happened in (see
+    // `ValidateError::schema_path`).
+    fn via<F>(&self, result: ValidateResult, elem: F) -> ValidateResult
+    where
+        F: FnOnce() -> SchemaElement,
+    {
+        result.map_err(|e| self.detail(e, |e| e.via(elem())))
+    }
+
+    // Whether data matched by `any` needs to be looked at, rather than
+    // accepted without looking.
+    fn inspects_any(&self) -> bool {
+        self.report.is_some() || self.lookup.options().any_tags.is_some()
+    }
+
+    fn log_any_tag(&self, tag: u64) {
+        if let Some(report) = self.report {
+            report.borrow_mut().any_tags.push(tag);
+        }
+    }
+
+    // Log some data accepted by a permissive part of the schema.  The path
+    // is filled in by `report_at` as we return to the top of the data.
+    fn log_permissive<F>(&self, construct: F)
+    where
+        F: FnOnce() -> Permissive,
+    {
+        if let Some(report) = self.report {
+            report.borrow_mut().permissive.push(PermissiveMatch {
+                path: Vec::new(),
+                name: None,
+                construct: construct(),
+            });
+        }
+    }
+
+    fn log_occurrence(&self, occur: &Occur, count: u64) {
+        if let Some(report) = self.report {
+            report.borrow_mut().occurrences.push(OccurrenceMatch {
+                path: Vec::new(),
+                name: member_name(&occur.node).map(String::from),
+                member: occur.node.to_string(),
+                occur: occur.effective_limit(),
+                written: occur.symbol(),
+                count,
+            });
+        }
+    }
+
+    fn report_mark(&self) -> ReportMark {
+        self.report.map_or_else(ReportMark::default, |r| {
+            let r = r.borrow();
+            ReportMark {
+                any_tags: r.any_tags.len(),
+                permissive: r.permissive.len(),
+                occurrences: r.occurrences.len(),
+            }
         })
     }
+
+    fn forget_permissive(&self, mark: ReportMark) {
+        if let Some(report) = self.report {
+            report.borrow_mut().permissive.truncate(mark.permissive);
+        }
+    }
+
+    fn forget_report(&self, mark: ReportMark) {
+        if let Some(report) = self.report {
+            let mut report = report.borrow_mut();
+            report.any_tags.truncate(mark.any_tags);
+            report.permissive.truncate(mark.permissive);
+            report.occurrences.truncate(mark.occurrences);
+        }
+    }
+
+    // Record the name of the place in the schema that was being matched:
+    // on an error (see `ValidateError::schema_name`), or on anything logged
+    // for the report since `mark`.  Names recorded further down are kept.
+    fn in_schema<F>(&self, mark: ReportMark, result: ValidateResult, name: F) -> ValidateResult
+    where
+        F: Fn() -> Option<String>,
+    {
+        match result {
+            Err(e) => Err(self.detail(e, |e| e.in_schema(name))),
+            Ok(()) => {
+                if let Some(report) = self.report {
+                    let report = &mut *report.borrow_mut();
+                    let permissive = report.permissive[mark.permissive..].iter_mut();
+                    let occurrences = report.occurrences[mark.occurrences..].iter_mut();
+                    let names = permissive
+                        .map(|m| &mut m.name)
+                        .chain(occurrences.map(|m| &mut m.name));
+                    for entry in names.filter(|n| n.is_none()) {
+                        *entry = name();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Record that everything logged since `mark` was inside a map value or
+    // array element.  This does for the report what `ValidateError::at`
+    // does for errors.
+    fn report_at<F>(&self, mark: ReportMark, elem: F)
+    where
+        F: FnOnce() -> PathElement,
+    {
+        if let Some(report) = self.report {
+            let report = &mut *report.borrow_mut();
+            let permissive = &mut report.permissive[mark.permissive..];
+            let occurrences = &mut report.occurrences[mark.occurrences..];
+            if !permissive.is_empty() || !occurrences.is_empty() {
+                let elem = elem();
+                let paths = permissive.iter_mut().map(|m| &mut m.path);
+                for path in paths.chain(occurrences.iter_mut().map(|m| &mut m.path)) {
+                    path.insert(0, elem.clone());
+                }
+            }
+        }
+    }
 }
 
+// Prevent warnings if both serde_cbor and serde_json are disabled.
+#[allow(dead_code)]
 pub(crate) fn do_validate(
     value: &Value,
     rule_def: &RuleDef,
     ctx: &dyn LookupContext,
+) -> ValidateResult {
+    let lean = ctx.options().lean;
+    validate_top(value, rule_def, ctx, None, lean, &Scratch::default())
+}
+
+/// Validate a value against a rule, reusing the buffers in `scratch`, and
+/// always in lean mode.
+pub(crate) fn do_validate_session(
+    value: &Value,
+    rule_def: &RuleDef,
+    ctx: &dyn LookupContext,
+    scratch: &Scratch,
+) -> ValidateResult {
+    validate_top(value, rule_def, ctx, None, true, scratch)
+}
+
+/// Validate a value against a rule, and report what was found along the way.
+#[cfg(feature = "serde_cbor")]
+pub(crate) fn do_validate_report(
+    value: &Value,
+    rule_def: &RuleDef,
+    ctx: &dyn LookupContext,
+) -> TempResult<ValidationReport> {
+    let log = RefCell::new(ReportLog::default());
+    validate_top(value, rule_def, ctx, Some(&log), false, &Scratch::default())?;
+
+    let log = log.into_inner();
+    let mut report = ValidationReport::default();
+    for tag in log.any_tags {
+        *report.any_tags.entry(tag).or_insert(0) += 1;
+    }
+    report.permissive = log.permissive;
+    report.occurrences = log.occurrences;
+    Ok(report)
+}
+
+/// Validate a value against a rule, and keep going after a mismatch to find
+/// as many errors as possible.
+///
+/// If the value doesn't match, it's explained by `collect_errors`, which
+/// sets aside each map value or array element that's wrong and carries on
+/// with the rest, in a single pass.  A fatal error is returned by itself.
+/// An empty list means the value is valid.
+#[cfg(feature = "serde_cbor")]
+pub(crate) fn do_validate_collect(
+    value: &Value,
+    rule_def: &RuleDef,
+    ctx: &dyn LookupContext,
+) -> Vec<ValidateError> {
+    if !rule_def.generic_parms.is_empty() {
+        return vec![ValidateError::GenericError];
+    }
+    let value = match remove_tags(value, &ctx.options().tags) {
+        Ok(value) => value,
+        Err(e) => return vec![e],
+    };
+    let scratch = Scratch::default();
+    let ctx = Context {
+        lookup: ctx,
+        generic_map: GenericMap::default(),
+        depth: 0,
+        report: None,
+        lean: true,
+        scratch: &scratch,
+    };
+    let node = &rule_def.node;
+    let errors = match validate(&value, node, &ctx) {
+        Ok(()) => Vec::new(),
+        Err(e) if e.is_fatal() => vec![e],
+        Err(_) => {
+            let ctx = Context {
+                lean: false,
+                ..ctx.clone()
+            };
+            collect_errors(&value, node, &ctx).unwrap_or_else(|e| vec![e])
+        }
+    };
+    errors.into_iter().map(|e| e.finish(ctx.lookup)).collect()
+}
+
+// Prevent warnings if both serde_cbor and serde_json are disabled.
+#[allow(dead_code)]
+fn validate_top(
+    value: &Value,
+    rule_def: &RuleDef,
+    ctx: &dyn LookupContext,
+    report: Option<&RefCell<ReportLog>>,
+    lean: bool,
+    scratch: &Scratch,
 ) -> ValidateResult {
     // If the rule_def passed in requires generic parameters, we should
     // return an error, because we don't have any way to specify them.
     if !rule_def.generic_parms.is_empty() {
         return Err(ValidateError::GenericError);
     }
+    scratch.start();
 
+    // There's no point being lean while making a report, since the report
+    // needs allocations anyway.
     let ctx = Context {
         lookup: ctx,
         generic_map: GenericMap::default(),
         depth: 0,
+        report,
+        lean: report.is_none() && lean,
+        scratch,
     };
     let node = &rule_def.node;
-    validate(value, node, &ctx)
+    let value = remove_tags(value, &ctx.lookup.options().tags)?;
+    validate_lean(&ctx, |ctx| validate(&value, node, ctx)).map_err(|e| e.finish(ctx.lookup))
+}
+
+// Run a validation, and if it fails in lean mode, run it again without
+// lean mode to find out why.
+//
+// Lean mode only changes the contents of mismatch errors, never which
+// errors happen, so both runs succeed or fail in the same way.  Fatal
+// errors are built in full either way.
+fn validate_lean<F>(ctx: &Context, f: F) -> ValidateResult
+where
+    F: Fn(&Context) -> ValidateResult,
+{
+    match f(ctx) {
+        Err(e) if ctx.lean && !e.is_fatal() => f(&Context {
+            lean: false,
+            ..ctx.clone()
+        }),
+        result => result,
+    }
+}
+
+/// Validate a sequence of values (e.g. a CBOR sequence) against a rule.
+///
+/// The sequence is validated as though it were the contents of an array,
+/// so the rule may be a group with occurrences, e.g. `log = (1*1000 entry)`.
+#[cfg(feature = "serde_cbor")]
+pub(crate) fn do_validate_seq(
+    values: &[Value],
+    rule_def: &RuleDef,
+    ctx: &dyn LookupContext,
+) -> ValidateResult {
+    if !rule_def.generic_parms.is_empty() {
+        return Err(ValidateError::GenericError);
+    }
+
+    let ctx = Context {
+        lookup: ctx,
+        generic_map: GenericMap::default(),
+        depth: 0,
+        report: None,
+        lean: ctx.options().lean,
+        scratch: &Scratch::default(),
+    };
+    let tags = &ctx.lookup.options().tags;
+    let stripped: Vec<Value>;
+    let values = if *tags == TagPolicy::Strict {
+        values
+    } else {
+        stripped = values
+            .iter()
+            .map(|v| remove_tags(v, tags).map(Cow::into_owned))
+            .collect::<TempResult<_>>()?;
+        &stripped
+    };
+    let result = validate_lean(&ctx, |ctx| {
+        let mut working_array = WorkingArray::new(values);
+        let result = match validate_array_member(&rule_def.node, &mut working_array, ctx) {
+            Ok(()) if working_array.is_empty() => Ok(()),
+            Ok(()) => match working_array.take_leftover_error() {
+                Some(e) => Err(e),
+                None => Err(mismatch("shorter sequence").with_kind(ErrorKind::ArrayLengthMismatch)),
+            },
+            Err(e) => Err(e),
+        };
+        // As in an array, an occurrence may need to match fewer items.
+        match result {
+            Err(e) if !e.is_fatal() => {
+                if array_fits(std::slice::from_ref(&rule_def.node), values, ctx)? {
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+            result => result,
+        }
+    });
+    // Add the number of items to any top-level mismatch error, since a
+    // wrong count is the most likely problem.
+    result.map_err(|e| {
+        let kind = e.kind();
+        match e {
+            ValidateError::Mismatch(m) if m.path().is_empty() => mismatch(format!(
+                "{} (sequence has {} items)",
+                m.expected(),
+                values.len()
+            ))
+            .with_kind(kind),
+            _ => e.finish(ctx.lookup),
+        }
+    })
 }
 
 type ValueMap = BTreeMap<Value, Value>;
 
+// Remove the tags that the TagPolicy says to ignore.
+//
+// This is done once, before validation starts, so that the policy applies
+// everywhere in the same way (including to map keys, which are sometimes
+// found by lookup rather than by validation.)
+pub(crate) fn remove_tags<'a>(value: &'a Value, policy: &TagPolicy) -> TempResult<Cow<'a, Value>> {
+    if *policy == TagPolicy::Strict {
+        return Ok(Cow::Borrowed(value));
+    }
+    strip_tags(value, policy).map(Cow::Owned)
+}
+
+fn strip_tags(value: &Value, policy: &TagPolicy) -> TempResult<Value> {
+    let result = match value {
+        Value::Tag(tag, inner) if policy.ignores(*tag) => strip_tags(inner, policy)?,
+        Value::Tag(tag, inner) => Value::Tag(*tag, Box::new(strip_tags(inner, policy)?)),
+        Value::Array(a) => {
+            let array: TempResult<_> = a.iter().map(|v| strip_tags(v, policy)).collect();
+            Value::Array(array?)
+        }
+        Value::Map(m) => {
+            let mut map = ValueMap::new();
+            for (k, v) in m {
+                let k = strip_tags(k, policy)?;
+                if map.contains_key(&k) {
+                    return Err(ValidateError::ValueError(format!(
+                        "duplicate map key {:?} after removing tags",
+                        k
+                    )));
+                }
+                map.insert(k, strip_tags(v, policy)?);
+            }
+            Value::Map(map)
+        }
+        _ => value.clone(),
+    };
+    Ok(result)
+}
+
 // A Result that returns some temporary value.
 type TempResult<T> = Result<T, ValidateError>;
 
 /// This struct allows us to maintain a map that is consumed during validation.
-struct WorkingMap {
-    map: ValueMap,
-    // A stack of lists; each list contains maybe-discarded elements,
-    // in (key, value) form.
-    snaps: VecDeque<VecDeque<(Value, Value)>>,
+///
+/// Rather than copying the map, this keeps track of which of its entries
+/// haven't been matched yet.
+struct WorkingMap<'v> {
+    map: &'v ValueMap,
+    // The entries that haven't been matched, by position in `map`.
+    unmatched: EntrySet,
+    // Errors from map values that stopped an occurrence from matching more
+    // keys, with the position of the entry, in schema member order.  If one
+    // of those entries is left over at the end, the first such error
+    // explains why.
+    value_errors: Vec<(usize, ValidateError)>,
+    // The position of the entry that caused the last key-value member to
+    // fail, if it failed because of that entry's contents.
+    failed_entry: Option<usize>,
+    // The keys of a large map, in order, so that a literal key can be found
+    // by position without visiting every entry.  Built when first needed.
+    keys: Vec<&'v Value>,
+    // The number of entries a group matched before it failed, the last time
+    // a group failed.  This is used to find the closest option of a choice.
+    failed_group_matches: usize,
+    // If someone is visiting this map, a log of which members matched which
+    // keys, so that it can be replayed once matching is finished.
+    events: Option<Vec<MapEvent>>,
+    // Where entry sets come from, and go back to.
+    scratch: &'v Scratch,
 }
 
-impl WorkingMap {
-    /// Makes a copy of an existing map's table.
-    fn new(value_map: &ValueMap) -> WorkingMap {
+// The state of a WorkingMap, so that a failed match can be undone.
+struct MapSnapshot {
+    unmatched: EntrySet,
+    events: usize,
+    value_errors: usize,
+}
+
+// Something that happened while matching a map, that a MapVisitor should
+// hear about.
+enum MapEvent {
+    Matched(KeyValue, Value),
+    Unmatched(KeyValue),
+}
+
+impl<'v> WorkingMap<'v> {
+    fn new(map: &'v ValueMap, scratch: &'v Scratch) -> WorkingMap<'v> {
         WorkingMap {
-            map: value_map.clone(),
-            snaps: VecDeque::new(),
+            map,
+            unmatched: EntrySet::full(map.len(), scratch),
+            value_errors: Vec::new(),
+            failed_entry: None,
+            keys: Vec::new(),
+            failed_group_matches: 0,
+            events: None,
+            scratch,
+        }
+    }
+
+    // A copy of the entries that haven't been matched yet, without any
+    // history.
+    fn trial(&self) -> WorkingMap<'v> {
+        WorkingMap {
+            map: self.map,
+            unmatched: self.unmatched.copy(self.scratch),
+            value_errors: Vec::new(),
+            failed_entry: None,
+            keys: Vec::new(),
+            failed_group_matches: 0,
+            events: None,
+            scratch: self.scratch,
         }
     }
 
     // When we start speculatively matching map elements (e.g. in a Choice
     // or Occur containing groups), we may fail the match partway through, and
-    // need to rewind to the most recent snapshot.
+    // need to rewind to the snapshot.
     //
     // It's possible for nested snapshots to exist; for example if we have a
-    // group-of-choices nested inside a group-of-choices.
+    // group-of-choices nested inside a group-of-choices.  A snapshot that's
+    // no longer needed can just be dropped.
     //
-    // If one array is nested inside another, the inner array will get its
-    // own WorkingArray so snapshots aren't necessary in that case.
-    fn snapshot(&mut self) {
-        self.snaps.push_back(VecDeque::new());
+    // If one map is nested inside another, the inner map will get its
+    // own WorkingMap so snapshots aren't necessary in that case.
+    fn snapshot(&self) -> MapSnapshot {
+        MapSnapshot {
+            unmatched: self.unmatched.copy(self.scratch),
+            events: self.events.as_ref().map_or(0, Vec::len),
+            value_errors: self.value_errors.len(),
+        }
+    }
+
+    // Restore the map to the point when the snapshot was taken.
+    //
+    // Returns the number of entries that were put back.
+    fn rewind(&mut self, snap: MapSnapshot) -> usize {
+        let restored = snap.unmatched.len() - self.unmatched.len();
+        let unmatched = std::mem::replace(&mut self.unmatched, snap.unmatched);
+        self.scratch.give_words(unmatched);
+        // Forget anything that happened since the snapshot.
+        if let Some(events) = &mut self.events {
+            events.truncate(snap.events);
+        }
+        self.value_errors.truncate(snap.value_errors);
+        restored
+    }
+
+    // Drop a snapshot that's no longer needed.
+    fn forget(&self, snap: MapSnapshot) {
+        self.scratch.give_words(snap.unmatched);
+    }
+
+    // The entries that haven't been matched yet, in key order, with their
+    // positions.
+    fn entries<'a>(&'a self) -> impl Iterator<Item = (usize, &'v Value, &'v Value)> + 'a {
+        self.map
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| self.unmatched.contains(*index))
+            .map(|(index, (key, value))| (index, key, value))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.unmatched.len() == 0
+    }
+
+    // Find the entry with a literal key, if it hasn't been matched yet.
+    //
+    // A small map (whose entry set is a single word) is searched directly,
+    // which is cheap and doesn't allocate.  A larger map is searched by
+    // key, using a list of the keys built the first time it's needed.
+    fn find_literal(&mut self, literal: &Literal) -> Option<(usize, &'v Value, &'v Value)> {
+        let index = match self.unmatched {
+            EntrySet::Small(_) => {
+                return self
+                    .entries()
+                    .find(|(_, key, _)| literal_equals(literal, key))
+            }
+            EntrySet::Large(_) => {
+                if self.keys.is_empty() {
+                    self.keys.extend(self.map.keys());
+                }
+                self.keys
+                    .binary_search_by(|key| compare_literal(key, literal))
+                    .ok()?
+            }
+        };
+        if !self.unmatched.contains(index) {
+            return None;
+        }
+        let key = self.keys[index];
+        Some((index, key, &self.map[key]))
     }
 
-    // Restore the map to the point when we last called snapshot()
-    fn rewind(&mut self) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic.
-        let mut top_snap = self.snaps.pop_back().unwrap();
-        // Drain the elements (order not important), and insert them back into
-        // the working map.
-        self.map.extend(top_snap.drain(..));
+    // Remove an entry from the working map.
+    fn remove(&mut self, index: usize) {
+        self.unmatched.remove(index);
+    }
+
+    // Remember that a member matched a key, if anyone is listening.
+    fn log_matched(&mut self, kv: &KeyValue, key: &Value) {
+        if let Some(events) = &mut self.events {
+            events.push(MapEvent::Matched(kv.clone(), key.clone()));
+        }
+    }
+
+    // Remember that a member didn't match anything, if anyone is listening.
+    fn log_unmatched(&mut self, kv: &KeyValue) {
+        if let Some(events) = &mut self.events {
+            events.push(MapEvent::Unmatched(kv.clone()));
+        }
+    }
+}
+
+impl Drop for WorkingMap<'_> {
+    fn drop(&mut self) {
+        let unmatched = std::mem::replace(&mut self.unmatched, EntrySet::Small(0));
+        self.scratch.give_words(unmatched);
     }
+}
+
+// A set of map entries, by position.  A set for a map of up to 64 entries
+// is a single word, so copying it doesn't need an allocation; larger sets
+// reuse buffers from a `Scratch` when they can.
+enum EntrySet {
+    Small(u64),
+    Large(Vec<u64>),
+}
 
-    // We completed a match, so we can retire the most recent snapshot.
-    fn commit(&mut self) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic.
-        // This throws away the list that was popped; those values were
-        // successfully matched and are no longer needed.
-        self.snaps.pop_back().unwrap();
+impl EntrySet {
+    // A set containing all of `len` entries.
+    fn full(len: usize, scratch: &Scratch) -> EntrySet {
+        // The lowest n bits, for n <= 64.
+        let low_bits = |n: usize| if n == 64 { u64::MAX } else { (1 << n) - 1 };
+        if len <= 64 {
+            return EntrySet::Small(low_bits(len));
+        }
+        let mut words = scratch.take_words();
+        words.resize(len / 64, u64::MAX);
+        if len % 64 != 0 {
+            words.push(low_bits(len % 64));
+        }
+        EntrySet::Large(words)
     }
 
-    // Peek at the value correspending to a given key (if any)
-    fn peek_at(&self, key: &Value) -> Option<&Value> {
-        self.map.get(key)
+    fn copy(&self, scratch: &Scratch) -> EntrySet {
+        match self {
+            EntrySet::Small(word) => EntrySet::Small(*word),
+            EntrySet::Large(words) => {
+                let mut copy = scratch.take_words();
+                copy.extend_from_slice(words);
+                EntrySet::Large(copy)
+            }
+        }
     }
 
-    // Remove a value from the working map.
-    // If there is an active snapshot, stash the key/value pair there until
-    // we're certain we've matched the entire group.
-    fn remove(&mut self, key: &Value) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic (we've already peeked at this value in order to match
-        // it.)
-        let value = self.map.remove(key).unwrap();
-        // If there is a current snapshot, preserve this element
-        // for later rewind.
-        if let Some(snap) = self.snaps.back_mut() {
-            snap.push_back((key.clone(), value));
+    fn words(&self) -> &[u64] {
+        match self {
+            EntrySet::Small(word) => std::slice::from_ref(word),
+            EntrySet::Large(words) => words,
         }
     }
+
+    fn contains(&self, index: usize) -> bool {
+        self.words()[index / 64] & 1 << (index % 64) != 0
+    }
+
+    fn remove(&mut self, index: usize) {
+        let word = match self {
+            EntrySet::Small(word) => word,
+            EntrySet::Large(words) => &mut words[index / 64],
+        };
+        *word &= !(1 << (index % 64));
+    }
+
+    fn len(&self) -> usize {
+        self.words().iter().map(|w| w.count_ones() as usize).sum()
+    }
 }
 
-/// This struct allows us to maintain a copy of an array that is consumed
-/// during validation.
+/// This struct allows us to maintain an array that is consumed during
+/// validation.
+///
+/// Elements are always consumed from the front, so this only needs to keep
+/// track of how many have been consumed.
 #[derive(Debug)]
-struct WorkingArray {
+struct WorkingArray<'v> {
     // The elements in the Value Array
-    array: VecDeque<Value>,
-    // A stack of lists; each list contains maybe-discarded elements.
-    snaps: VecDeque<VecDeque<Value>>,
+    array: &'v [Value],
+    // The index of the first element that hasn't been matched yet.
+    next: usize,
+    // The most recent error from an element that stopped an occurrence
+    // from matching more elements.  If that element is left over at the
+    // end, this explains why.
+    element_error: Option<ValidateError>,
 }
 
-impl WorkingArray {
-    /// Makes a copy of an existing map's table.
-    fn new(array: &[Value]) -> WorkingArray {
-        let deque: VecDeque<Value> = array.iter().cloned().collect();
+impl<'v> WorkingArray<'v> {
+    fn new(array: &'v [Value]) -> WorkingArray<'v> {
         WorkingArray {
-            array: deque,
-            snaps: VecDeque::new(),
+            array,
+            next: 0,
+            element_error: None,
         }
     }
 
     // When we start speculatively matching array elements (e.g. in a Choice
     // or Occur containing groups), we may fail the match partway through, and
-    // need to rewind to the most recent snapshot.
+    // need to rewind to the snapshot.
     //
     // It's possible for nested snapshots to exist; for example if we have a
     // group-of-choices nested inside a group-of-choices.
     //
     // If one array is nested inside another, the inner array will get its
     // own WorkingArray so snapshots aren't necessary in that case.
-    fn snapshot(&mut self) {
-        let new_snap: VecDeque<Value> = VecDeque::new();
-        self.snaps.push_back(new_snap);
+    fn snapshot(&self) -> usize {
+        self.next
     }
 
-    // Restore the array to the point when we last called snapshot()
-    fn rewind(&mut self) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic.
-        let mut top_snap = self.snaps.pop_back().unwrap();
-        // drain the elements in LIFO order, and push them back into
-        // the working array.
-        for element in top_snap.drain(..).rev() {
-            self.array.push_front(element);
-        }
+    // Restore the array to the point when the snapshot was taken.
+    fn rewind(&mut self, snap: usize) {
+        self.next = snap;
     }
 
-    // We completed a match, so we can retire the most recent snapshot.
-    fn commit(&mut self) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic.
-        // This throws away the list that was popped; those values were
-        // successfully matched and are no longer needed.
-        self.snaps.pop_back().unwrap();
+    // The index (in the original array) of the front of the working array.
+    fn index(&self) -> usize {
+        self.next
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next == self.array.len()
+    }
+
+    // If there are leftover elements, return the error that explains
+    // why the first one didn't match (if known).
+    fn take_leftover_error(&mut self) -> Option<ValidateError> {
+        let e = self.element_error.take()?;
+        match e.path().first() {
+            Some(PathElement::Index(i)) if *i == self.index() && !self.is_empty() => Some(e),
+            _ => None,
+        }
     }
 
     // Peek at the front of the working array.
-    fn peek_front(&self) -> Option<&Value> {
-        self.array.front()
+    fn peek_front(&self) -> Option<&'v Value> {
+        self.array.get(self.next)
     }
 
     // Remove an element from the working array.
-    // If there is an active snapshot, stash the element there until we're
-    // certain we've matched the entire group.
     fn pop_front(&mut self) {
-        // If validate code is implemented correctly, then unwrap() should
-        // never panic (we've already peeked at this value in order to match
-        // it.)
-        let element = self.array.pop_front().unwrap();
-        // If there is a current snapshot, preserve this element
-        // for later rewind.
-        if let Some(snap) = self.snaps.back_mut() {
-            snap.push_back(element);
-        }
+        // If validate code is implemented correctly, we've already peeked
+        // at this value in order to match it.
+        debug_assert!(!self.is_empty());
+        self.next += 1;
     }
 }
 
 // Prevent warnings if both serde_cbor and serde_json are disabled.
 #[allow(dead_code)]
-
 // This is the main validation dispatch function.
 // It tries to match a Node and a Value, recursing as needed.
 fn validate(value: &Value, node: &Node, ctx: &Context) -> ValidateResult {
-    match node {
-        Node::Literal(l) => validate_literal(l, value),
+    let mark = ctx.report_mark();
+    let result = match node {
+        Node::Literal(l) => validate_literal(l, value, ctx),
+        Node::PreludeType(PreludeType::Any) => validate_any(value, ctx),
         Node::PreludeType(p) => validate_prelude_type(*p, value),
         Node::Choice(c) => validate_choice(c, value, ctx),
         Node::Map(m) => validate_map(m, value, ctx),
@@ -329,27 +1040,81 @@ fn validate(value: &Value, node: &Node, ctx: &Context) -> ValidateResult {
         Node::Control(ctl) => validate_control(ctl, value, ctx),
         Node::Choiceify(r) => validate_choiceify(r, value, ctx),
         Node::ChoiceifyInline(a) => validate_choiceify_inline(a, value, ctx),
+        Node::Tagged(t) => validate_tagged(t, value, ctx),
+    };
+    // Anything found by a failed match doesn't count, since the caller may
+    // try something else.
+    if result.is_err() {
+        ctx.forget_report(mark);
     }
+    result
 }
 
-// Perform map key search.
-// Some keys (literal values) can be found with a fast search, while
-// others may require a linear search.
-fn validate_map_key<'a>(
-    value_map: &'a mut WorkingMap,
-    node: &Node,
-    ctx: &Context,
-) -> TempResult<(Value, &'a Value)> {
-    match node {
-        Node::Literal(l) => map_search_literal(l, value_map),
-        _ => map_search(node, value_map, ctx),
+// Match tagged data, e.g. `#6.32(tstr)`.
+//
+// Tags removed by the TagPolicy are already gone, so in that case the
+// content is matched by itself.
+fn validate_tagged(tagged: &Tagged, value: &Value, ctx: &Context) -> ValidateResult {
+    let policy = &ctx.lookup.options().tags;
+    match (tagged.tag, value) {
+        (None, Value::Tag(_, inner)) => validate(inner, &tagged.node, ctx),
+        (Some(tag), Value::Tag(t, inner)) if tag == *t => validate(inner, &tagged.node, ctx),
+        (Some(tag), _) if policy.ignores(tag) => validate(value, &tagged.node, ctx),
+        (None, _) if *policy != TagPolicy::Strict => validate(value, &tagged.node, ctx),
+        (Some(tag), _) => Err(ctx.error(|| mismatch(format!("tag {}", tag)))),
+        (None, _) => Err(ctx.error(|| mismatch("tagged value"))),
+    }
+}
+
+// Everything matches `any`, but if we were asked about tags we need to look
+// through the whole value for them.
+fn validate_any(value: &Value, ctx: &Context) -> ValidateResult {
+    ctx.log_permissive(|| Permissive::Any);
+    if !ctx.inspects_any() {
+        return Ok(());
+    }
+    validate_any_tags(value, ctx)
+}
+
+fn validate_any_tags(value: &Value, ctx: &Context) -> ValidateResult {
+    match value {
+        Value::Tag(tag, inner) => {
+            if let Some(allowed) = &ctx.lookup.options().any_tags {
+                if !allowed.contains(tag) {
+                    return Err(ctx.error(|| mismatch(format!("any without tag {}", tag))));
+                }
+            }
+            ctx.log_any_tag(*tag);
+            validate_any_tags(inner, ctx)
+        }
+        Value::Array(a) => {
+            for (index, element) in a.iter().enumerate() {
+                validate_any_tags(element, ctx)
+                    .map_err(|e| ctx.detail(e, |e| e.at(PathElement::Index(index))))?;
+            }
+            Ok(())
+        }
+        Value::Map(m) => {
+            let max_len = ctx.lookup.options().max_value_len;
+            for (index, (key, val)) in m.iter().enumerate() {
+                validate_any_tags(key, ctx)
+                    .and_then(|()| validate_any_tags(val, ctx))
+                    .map_err(|e| {
+                        ctx.detail(e, |e| e.at_entry(index, render_value(key, max_len)))
+                    })?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
     }
 }
 
 /// Validate a `Choice` containing an arbitrary number of "option" nodes.
 ///
 /// If any of the options matches, this validation is successful.
+/// See [`ChoiceErrors`] for which error is returned if none match.
 fn validate_choice(choice: &Choice, value: &Value, ctx: &Context) -> ValidateResult {
+    let mut errors = ChoiceErrors::new(ctx);
     for node in &choice.options {
         match validate(value, node, ctx) {
             Ok(()) => {
@@ -361,11 +1126,122 @@ fn validate_choice(choice: &Choice, value: &Value, ctx: &Context) -> ValidateRes
                 if e.is_fatal() {
                     return Err(e);
                 }
+                errors.push(e);
             }
         }
     }
-    let expected = format!("choice of {}", choice.options.len());
-    Err(mismatch(expected))
+    Err(errors.finish(ctx, || format!("choice of {}", choice.options.len())))
+}
+
+/// Selects the error to report when every option of a choice fails.
+///
+/// Options are always tried in schema order, and the first one that matches
+/// wins.  If none match, the reported error is the one from the option that
+/// got deepest into the data before failing, as measured by the length of
+/// the error's path (so the option that matched a map key is preferred over
+/// the options that didn't).  If several options got equally far, the
+/// earliest one is reported.
+///
+/// If no option got past the top level, none of the errors is more useful
+/// than the others; a "choice of N" error listing what each option
+/// expected is reported instead, with the first hint any of them had.
+///
+/// Inside an array, every option starts at the same array element, so
+/// failing at that element doesn't count as progress.
+#[derive(Default)]
+struct ChoiceErrors {
+    // The depth of the deepest error, the option it came from, and the
+    // error.
+    deepest: Option<(usize, usize, ValidateError)>,
+    // The number of options that have failed.
+    count: usize,
+    // The array index where the options start, if in an array context.
+    start_index: Option<usize>,
+    hint: Option<&'static str>,
+    // What each option expected, in schema order.  Left empty in lean
+    // mode, where the errors are blank anyway.
+    tried: Vec<String>,
+    lean: bool,
+}
+
+impl ChoiceErrors {
+    fn new(ctx: &Context) -> ChoiceErrors {
+        ChoiceErrors {
+            lean: ctx.lean,
+            ..ChoiceErrors::default()
+        }
+    }
+
+    fn in_array(ctx: &Context, start_index: usize) -> ChoiceErrors {
+        ChoiceErrors {
+            start_index: Some(start_index),
+            ..ChoiceErrors::new(ctx)
+        }
+    }
+
+    /// Record the error from one option.
+    fn push(&mut self, err: ValidateError) {
+        let path = err.path();
+        let mut depth = path.len();
+        if let (Some(PathElement::Index(i)), Some(start)) = (path.first(), self.start_index) {
+            if *i == start {
+                depth -= 1;
+            }
+        }
+        let deeper = match &self.deepest {
+            None => depth > 0,
+            Some((deepest, _, _)) => depth > *deepest,
+        };
+        if self.hint.is_none() {
+            self.hint = err.hint();
+        }
+        if !self.lean {
+            self.tried.push(option_expected(&err));
+        }
+        if deeper {
+            self.deepest = Some((depth, self.count, err));
+        }
+        self.count += 1;
+    }
+
+    /// Return the error to report.
+    fn finish<F>(self, ctx: &Context, expected: F) -> ValidateError
+    where
+        F: FnOnce() -> String,
+    {
+        let hint = self.hint;
+        let tried = self.tried;
+        let generic = || {
+            let expected = if tried.is_empty() {
+                expected()
+            } else {
+                format!("{} ({})", expected(), tried.join(" / "))
+            };
+            let err = mismatch(expected);
+            match hint {
+                Some(hint) => err.with_hint(hint),
+                None => err,
+            }
+        };
+        match (self.deepest, self.start_index) {
+            (Some((_, option, err)), _) => {
+                ctx.detail(err.erase_mapcut(), |e| e.via(SchemaElement::Choice(option)))
+            }
+            (None, Some(index)) => ctx.error(|| generic().at(PathElement::Index(index))),
+            (None, None) => ctx.error(generic),
+        }
+    }
+}
+
+// Describe what a failed choice option expected, leaving out where: every
+// option that didn't get past the top level failed at the same place.
+fn option_expected(err: &ValidateError) -> String {
+    match err {
+        ValidateError::Mismatch(m) => m.expected().to_string(),
+        ValidateError::MissingMapKey(m) => format!("missing {}: {}", m.key(), m.expected()),
+        ValidateError::MapCut(e) => option_expected(e),
+        other => other.to_string(),
+    }
 }
 
 /// Validate a `Rule` reference
@@ -373,7 +1249,79 @@ fn validate_choice(choice: &Choice, value: &Value, ctx: &Context) -> ValidateRes
 /// Seek out the right `Node` and `Context`, and recurse.
 fn validate_rule(rule: &Rule, value: &Value, ctx: &Context) -> ValidateResult {
     let answer = ctx.lookup_rule(rule)?;
-    validate(value, answer.node, &answer.ctx)
+    answer.in_rule(validate(value, answer.node, &answer.ctx))
+}
+
+/// Find everything that's wrong with a value that doesn't match a node.
+///
+/// Where a map value or array element doesn't match, the errors inside it
+/// are collected, and then it's set aside so that matching can carry on
+/// with the rest of the map or array.  If no option of a choice matches,
+/// the errors from every option are kept.  Anything else that doesn't
+/// match (e.g. a map where an array was expected) is a single error.
+///
+/// The errors come in the order of the data they point at, except that a
+/// choice lists them option by option.  At most one more than
+/// `max_errors` are kept.
+fn collect_errors(value: &Value, node: &Node, ctx: &Context) -> TempResult<Vec<ValidateError>> {
+    let mut errors = match (node, value) {
+        (Node::Rule(r), _) => {
+            let answer = ctx.lookup_rule(r)?;
+            let errors = collect_errors(value, answer.node, &answer.ctx)?;
+            errors.into_iter().map(|e| answer.rule_error(e)).collect()
+        }
+        (Node::Choice(c), _) => {
+            let mut errors = Vec::new();
+            for (index, option) in c.options.iter().enumerate() {
+                let option_errors = collect_errors(value, option, ctx)?;
+                if option_errors.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let via = |e: ValidateError| e.via(SchemaElement::Choice(index));
+                errors.extend(option_errors.into_iter().map(via));
+            }
+            errors
+        }
+        (Node::Array(a), Value::Array(values)) => {
+            let errors = remember_errors(a, values, ctx, || collect_array_errors(a, values, ctx))?;
+            let name = || a.name.clone();
+            errors.into_iter().map(|e| e.in_schema(name)).collect()
+        }
+        (Node::Map(m), Value::Map(value_map)) => {
+            let values = std::slice::from_ref(value);
+            let errors = remember_errors(m, values, ctx, || collect_map_errors(m, value_map, ctx))?;
+            let name = || m.name.clone();
+            errors.into_iter().map(|e| e.in_schema(name)).collect()
+        }
+        _ => validate(value, node, ctx).err().into_iter().collect(),
+    };
+    errors.truncate(ctx.lookup.options().max_errors + 1);
+    Ok(errors)
+}
+
+// Collect the errors in an array or map, unless they were already collected
+// for the same data matched in the same way.  Otherwise an array nested in
+// arrays that could match it in several ways would be looked at once for
+// each combination of them.
+fn remember_errors<T, F>(
+    schema: &T,
+    values: &[Value],
+    ctx: &Context,
+    collect: F,
+) -> TempResult<Vec<ValidateError>>
+where
+    F: FnOnce() -> TempResult<Vec<ValidateError>>,
+{
+    let key = MatchKey::new(schema, values, ctx);
+    if let Some(errors) = ctx.scratch.collected.borrow().get(&key) {
+        return Ok(errors.clone());
+    }
+    let errors = collect()?;
+    ctx.scratch
+        .collected
+        .borrow_mut()
+        .insert(key, errors.clone());
+    Ok(errors)
 }
 
 /// Create a `Value` from a `Literal`.
@@ -389,46 +1337,97 @@ impl From<&Literal> for Value {
     }
 }
 
-fn validate_literal(literal: &Literal, value: &Value) -> ValidateResult {
-    if *value == Value::from(literal) {
+fn validate_literal(literal: &Literal, value: &Value, ctx: &Context) -> ValidateResult {
+    let matched = match (literal, value) {
+        (Literal::Float(f), Value::Float(v)) => float_equals(*f, v.0),
+        _ => literal_equals(literal, value),
+    };
+    if matched {
         return Ok(());
     }
-    Err(mismatch(format!("{}", literal)))
+    let max_len = ctx.lookup.options().max_value_len;
+    Err(ctx.error(|| {
+        let err = mismatch(render_schema(literal, max_len));
+        match literal {
+            Literal::Bool(_) => bool_hint(err, value),
+            _ => err,
+        }
+    }))
 }
 
-// Find a specific key in the map and return that key plus a reference to its value.
-fn map_search_literal<'a>(
-    literal: &Literal,
-    working_map: &'a mut WorkingMap,
-) -> TempResult<(Value, &'a Value)> {
-    let search_key = Value::from(literal);
-    match working_map.peek_at(&search_key) {
-        Some(val) => Ok((search_key, val)),
-        None => {
-            // We didn't find the key; return an error
-            Err(mismatch(format!("map{{{}}}", literal)))
-        }
+// Floats are compared as f64 values, whatever width they were encoded with.
+// This is numeric equality, not bitwise (as Value::Float's ordering is), so
+// -0.0 matches 0.0, and an infinity only matches the infinity of the same
+// sign.  NaN isn't numerically equal to anything, but a schema that names
+// NaN (which has to be built as IVT, since CDDL text can't write it) means
+// any NaN, whatever its sign or payload.
+fn float_equals(literal: f64, value: f64) -> bool {
+    literal == value || (literal.is_nan() && value.is_nan())
+}
+
+// Some CBOR producers send the integers 0 and 1 instead of booleans.  They
+// never match `bool`, `true` or `false`, but the schema may have meant to
+// allow them.
+fn bool_hint(err: ValidateError, value: &Value) -> ValidateError {
+    match value {
+        Value::Integer(0..=1) => err
+            .with_hint("found an integer; to allow 0 and 1 too, the schema needs \"0 / 1 / bool\""),
+        _ => err,
     }
 }
 
-// Iterate over each key in the working map, looking for a match.
-// If we find a match, return a copy of the key, and a reference to the value.
-// This is less efficient than map_search_literal.
-fn map_search<'a>(
-    node: &Node,
-    working_map: &'a mut WorkingMap,
-    ctx: &Context,
-) -> TempResult<(Value, &'a Value)> {
-    for (key, value) in &working_map.map {
-        let attempt = validate(key, node, ctx);
-        if attempt.is_ok() {
-            return Ok((key.clone(), value));
+// Returns true if `Value::from(literal) == *value`, without making a copy
+// of the literal.
+fn literal_equals(literal: &Literal, value: &Value) -> bool {
+    match (literal, value) {
+        (Literal::Bool(l), Value::Bool(v)) => l == v,
+        (Literal::Int(l), Value::Integer(v)) => l == v,
+        (Literal::Float(l), Value::Float(_)) => Value::from_float(*l) == *value,
+        (Literal::Text(l), Value::Text(v)) => l == v,
+        (Literal::Bytes(l), Value::Bytes(v)) => l == v,
+        _ => false,
+    }
+}
+
+// Compares a value with a literal, in the order of `Value`, so that a
+// literal can be found among sorted values without making a copy of it.
+fn compare_literal(value: &Value, literal: &Literal) -> std::cmp::Ordering {
+    // The position of each variant in the declaration of `Value`.
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Integer(_) => 2,
+        Value::Float(_) => 3,
+        Value::Bytes(_) => 4,
+        Value::Text(_) => 5,
+        Value::Array(_) => 6,
+        Value::Map(_) => 7,
+        Value::Tag(..) => 8,
+    };
+    match (value, literal) {
+        (Value::Bool(v), Literal::Bool(l)) => v.cmp(l),
+        (Value::Integer(v), Literal::Int(l)) => v.cmp(l),
+        (Value::Float(v), Literal::Float(l)) => v.cmp(&FloatOrd(*l)),
+        (Value::Bytes(v), Literal::Bytes(l)) => v.cmp(l),
+        (Value::Text(v), Literal::Text(l)) => v.cmp(l),
+        _ => {
+            let literal_rank = match literal {
+                Literal::Bool(_) => 1,
+                Literal::Int(_) => 2,
+                Literal::Float(_) => 3,
+                Literal::Bytes(_) => 4,
+                Literal::Text(_) => 5,
+            };
+            rank(value).cmp(&literal_rank)
         }
     }
-    // We searched all the keys without finding a match.  Validation fails.
-    Err(mismatch(format!("map{{{}}}", node)))
 }
 
+// The integer range of CBOR: major type 0 holds 0..=2^64-1, and major type
+// 1 holds -2^64..=-1.  Value::Integer is wide enough to hold all of them.
+const UINT_MAX: i128 = u64::MAX as i128;
+const NINT_MIN: i128 = -(u64::MAX as i128) - 1;
+
 // Note `ty` is passed by value because clippy says it's only 1 byte.
 fn validate_prelude_type(ty: PreludeType, value: &Value) -> ValidateResult {
     match (ty, value) {
@@ -436,15 +1435,18 @@ fn validate_prelude_type(ty: PreludeType, value: &Value) -> ValidateResult {
         (PreludeType::Nil, Value::Null) => Ok(()),
         (PreludeType::Nil, _) => Err(mismatch("nil")),
         (PreludeType::Bool, Value::Bool(_)) => Ok(()),
-        (PreludeType::Bool, _) => Err(mismatch("bool")),
-        (PreludeType::Int, Value::Integer(_)) => Ok(()),
+        (PreludeType::Bool, _) => Err(bool_hint(mismatch("bool"), value)),
+        (PreludeType::Int, Value::Integer(x)) if (NINT_MIN..=UINT_MAX).contains(x) => Ok(()),
         (PreludeType::Int, _) => Err(mismatch("int")),
-        (PreludeType::Uint, Value::Integer(x)) if *x >= 0 => Ok(()),
+        (PreludeType::Uint, Value::Integer(x)) if (0..=UINT_MAX).contains(x) => Ok(()),
         (PreludeType::Uint, _) => Err(mismatch("uint")),
-        (PreludeType::Nint, Value::Integer(x)) if *x < 0 => Ok(()),
+        (PreludeType::Nint, Value::Integer(x)) if (NINT_MIN..0).contains(x) => Ok(()),
         (PreludeType::Nint, _) => Err(mismatch("nint")),
         (PreludeType::Float, Value::Float(_)) => Ok(()),
         (PreludeType::Float, _) => Err(mismatch("float")),
+        (PreludeType::Number, Value::Integer(x)) if (NINT_MIN..=UINT_MAX).contains(x) => Ok(()),
+        (PreludeType::Number, Value::Float(_)) => Ok(()),
+        (PreludeType::Number, _) => Err(mismatch("number")),
         (PreludeType::Tstr, Value::Text(_)) => Ok(()),
         (PreludeType::Tstr, _) => Err(mismatch("tstr")),
         (PreludeType::Bstr, Value::Bytes(_)) => Ok(()),
@@ -454,13 +1456,49 @@ fn validate_prelude_type(ty: PreludeType, value: &Value) -> ValidateResult {
 
 // FIXME: should this be combined with Map handling?
 fn validate_array(ar: &Array, value: &Value, ctx: &Context) -> ValidateResult {
-    match value {
+    let mark = ctx.report_mark();
+    let result = match value {
         Value::Array(a) => validate_array_part2(ar, a, ctx),
         _ => Err(mismatch("array")),
-    }
+    };
+    ctx.in_schema(mark, result, || ar.name.clone())
 }
 
 fn validate_array_part2(ar: &Array, value_array: &[Value], ctx: &Context) -> ValidateResult {
+    // Looking past the greedy match validates the elements again, so an
+    // array nested in arrays that fail would be validated once for each
+    // way of getting to it.  Instead the result is kept, and reused when
+    // the same array is matched in the same way.  Not while making a
+    // report, though, since that would leave out what it found.
+    let memo = ctx.report.is_none();
+    if memo && !ctx.scratch.arrays.borrow().is_empty() {
+        let key = MatchKey::new(ar, value_array, ctx);
+        if let Some(result) = ctx.scratch.arrays.borrow().get(&key) {
+            return result.clone();
+        }
+    }
+    let mark = ctx.report_mark();
+    match validate_array_greedy(ar, value_array, ctx) {
+        Err(e) if !e.is_fatal() => {
+            let result = if array_fits(&ar.members, value_array, ctx)? {
+                // The greedy match's report only covered part of the array,
+                // so it's dropped; the report has nothing about this array.
+                ctx.forget_report(mark);
+                Ok(())
+            } else {
+                Err(e)
+            };
+            if memo {
+                let key = MatchKey::new(ar, value_array, ctx);
+                ctx.scratch.arrays.borrow_mut().insert(key, result.clone());
+            }
+            result
+        }
+        result => result,
+    }
+}
+
+fn validate_array_greedy(ar: &Array, value_array: &[Value], ctx: &Context) -> ValidateResult {
     // Strategy for validating an array:
     // 1. We assume that the code that constructed the IVT Array placed the
     //    members in matching order (literals first, more general types at the
@@ -480,16 +1518,31 @@ fn validate_array_part2(ar: &Array, value_array: &[Value], ctx: &Context) -> Val
 
     let mut working_array = WorkingArray::new(value_array);
 
-    for member in &ar.members {
-        validate_array_member(member, &mut working_array, ctx)?;
+    for (position, member) in ar.members.iter().enumerate() {
+        let name = || match member_name(member) {
+            Some(name) => Some(name.to_string()),
+            None => ar.name.as_ref().map(|name| format!("{}[]", name)),
+        };
+        let mark = ctx.report_mark();
+        let result = validate_array_member(member, &mut working_array, ctx);
+        let result = ctx.via(result, || SchemaElement::Index(position));
+        // If an element stopped this member from matching more, and it's
+        // left over, its error is returned later.
+        if let Some(e) = working_array.element_error.take() {
+            let e = ctx.detail(e, |e| e.in_schema(name).via(SchemaElement::Index(position)));
+            working_array.element_error = Some(e);
+        }
+        ctx.in_schema(mark, result, name)?;
     }
-    if working_array.array.is_empty() {
+    if working_array.is_empty() {
         Ok(())
+    } else if let Some(e) = working_array.take_leftover_error() {
+        Err(e)
     } else {
         // If the working map isn't empty, that means we had some extra values
         // that didn't match anything.
         // FIXME: Should this be a unique error type?
-        Err(mismatch("shorter array"))
+        Err(mismatch("shorter array").with_kind(ErrorKind::ArrayLengthMismatch))
     }
 }
 
@@ -506,7 +1559,12 @@ fn validate_array_member(
             // The key is ignored.  Validate the value only.
             // FIXME: should we try to use the key to provide a more
             // useful error message?
-            validate_array_value(&kv.value, working_array, ctx)
+            let mark = ctx.report_mark();
+            let result = validate_array_value(&kv.value, working_array, ctx);
+            let result = ctx.via(result, || {
+                SchemaElement::Member(ctx.scratch.name(&kv.key_name()))
+            });
+            ctx.in_schema(mark, result, || kv.name.clone())
         }
         Node::Rule(r) => {
             // FIXME: This seems like a gross hack.  We need to dereference
@@ -517,19 +1575,27 @@ fn validate_array_member(
             // BUG: Choice nodes will have the same problem.
 
             let answer = ctx.lookup_rule(r)?;
-            validate_array_member(answer.node, working_array, &answer.ctx)
+            answer.in_rule(validate_array_member(
+                answer.node,
+                working_array,
+                &answer.ctx,
+            ))
         }
         Node::Unwrap(r) => {
             // Like Rule, we are dereferencing the Rule by hand here so that
             // we can "see through" to the underlying data without forgetting
             // we were in an array context.
             let answer = ctx.lookup_rule(r)?;
-            validate_array_unwrap(answer.node, working_array, &answer.ctx)
+            let result = resolve_alias(answer.node, &answer.ctx, |node, ctx| {
+                validate_array_unwrap(node, working_array, ctx)
+            });
+            answer.in_rule(result)
         }
         Node::Choice(c) => {
             // We need to explore each of the possible choices.
             // We can't use validate_array_value() because we'll lose our
             // array context.
+            let mut errors = ChoiceErrors::in_array(ctx, working_array.index());
             for option in &c.options {
                 match validate_array_member(option, working_array, ctx) {
                     Ok(()) => {
@@ -541,19 +1607,19 @@ fn validate_array_member(
                         if e.is_fatal() {
                             return Err(e);
                         }
+                        errors.push(e);
                     }
                 }
             }
             // None of the choices worked.
-            let expected = format!("choice of {}", c.options.len());
-            Err(mismatch(expected))
+            Err(errors.finish(ctx, || format!("choice of {}", c.options.len())))
         }
         Node::Group(g) => {
             // As we call validate_array_member, we don't know how many items
             // it might speculatively pop from the list.  So we'll take a snapshot
-            // now and commit our changes if we match successfully (and roll them
-            // back if it fails).
-            working_array.snapshot();
+            // now and roll back to it if the match fails.
+            let snap = working_array.snapshot();
+            let mark = ctx.report_mark();
 
             // Recurse into each member of the group.
             for group_member in &g.members {
@@ -562,17 +1628,17 @@ fn validate_array_member(
                         // So far so good...
                     }
                     Err(e) => {
-                        // Since we failed to validate the entire group, rewind to our most
-                        // recent snapshot.  This may put values back into the array,
+                        // Since we failed to validate the entire group, rewind to our
+                        // snapshot.  This may put values back into the array,
                         // so they can be matched by whatever we try next (or trigger
                         // an error if they aren't consumed by anything).
-                        working_array.rewind();
+                        working_array.rewind(snap);
+                        ctx.forget_report(mark);
                         return Err(e);
                     }
                 }
             }
             // All group members validated Ok.
-            working_array.commit();
             Ok(())
         }
         m => validate_array_value(m, working_array, ctx),
@@ -585,18 +1651,25 @@ fn validate_array_unwrap(
     ctx: &Context,
 ) -> ValidateResult {
     // After traversing an unwrap from inside an array, the next node must be an
-    // Array node too.
-    match node {
-        Node::Array(a) => {
-            // Recurse into each member of the unwrapped array.
-            for member in &a.members {
-                validate_array_member(member, working_array, ctx)?;
-            }
-            // All array members validated Ok.
-            Ok(())
+    // Array node.  A Map node may be unwrapped into an array too; its keys
+    // are ignored, as they would be if the members were written in place.
+    let members = match node {
+        Node::Array(a) => &a.members,
+        Node::Map(m) => &m.members,
+        // This doesn't depend on the data, so it isn't a mismatch.
+        _ => {
+            return Err(ValidateError::Structural(
+                "unwrapped rule in an array isn't an array or map".into(),
+            ))
         }
-        _ => Err(mismatch("unwrap array")),
+    };
+    // Recurse into each member of the unwrapped array.
+    for (position, member) in members.iter().enumerate() {
+        let result = validate_array_member(member, working_array, ctx);
+        ctx.via(result, || SchemaElement::Index(position))?;
     }
+    // All array members validated Ok.
+    Ok(())
 }
 
 /// Validate an occurrence against a mutable working array.
@@ -607,14 +1680,24 @@ fn validate_array_occur(
     ctx: &Context,
 ) -> ValidateResult {
     let (lower_limit, upper_limit) = occur.limits();
-    let mut count: usize = 0;
+    let mut count: u64 = 0;
+    let mut stop_error = None;
 
     loop {
+        let before = working_array.index();
         match validate_array_member(&occur.node, working_array, ctx) {
+            Ok(_) if working_array.index() == before => {
+                // This matched no elements (e.g. `* (? int)`), so every
+                // further repetition would too.  Count as many as are
+                // needed, rather than going round forever.
+                count = (count + 1).max(lower_limit.min(upper_limit));
+                break;
+            }
             Ok(_) => (),
             Err(e) => {
                 if e.is_mismatch() {
                     // Stop trying to match this occurrence.
+                    stop_error = Some(e);
                     break;
                 }
                 // The error is something serious (e.g. MissingRule or
@@ -624,15 +1707,262 @@ fn validate_array_occur(
             }
         }
         count += 1;
-        if count >= upper_limit {
-            // Stop matching; we've consumed the maximum number of this key.
+        if count >= upper_limit {
+            // Stop matching; we've consumed the maximum number of this key.
+            break;
+        }
+    }
+    if count < lower_limit {
+        // If there was an element that didn't match, that's more useful
+        // than complaining that there weren't enough of them.
+        return match stop_error {
+            Some(e) if working_array.peek_front().is_some() => Err(e),
+            _ => Err(ctx.error(|| {
+                mismatch(format!(
+                    "more array element [{}] (found {}, need {})",
+                    occur,
+                    count,
+                    occur.effective_limit().describe()
+                ))
+                .with_kind(ErrorKind::ArrayLengthMismatch)
+            })),
+        };
+    }
+    if let Some(e) = stop_error {
+        working_array.element_error = Some(e);
+    }
+    ctx.log_occurrence(occur, count);
+    Ok(())
+}
+
+// The positions in an array where matching might have got to, and the
+// elements that were set aside to get there (see SetAside).
+type Positions = BTreeMap<usize, Option<Rc<SetAside>>>;
+
+// When collecting errors, an array element that doesn't match is set aside
+// (with the errors found inside it), so that matching can go on past it.
+// Each position remembers the elements set aside on the way there, most
+// recent first; a position that can be got to by setting fewer aside is
+// preferred.
+struct SetAside {
+    // The number of elements set aside up to and including this one.
+    count: usize,
+    errors: Vec<ValidateError>,
+    previous: Option<Rc<SetAside>>,
+}
+
+fn set_aside_count(trail: &Option<Rc<SetAside>>) -> usize {
+    trail.as_ref().map_or(0, |s| s.count)
+}
+
+// Add a position, unless it can already be got to as cheaply.
+fn add_position(positions: &mut Positions, position: usize, trail: Option<Rc<SetAside>>) {
+    let count = set_aside_count(&trail);
+    match positions.get(&position) {
+        Some(known) if set_aside_count(known) <= count => {}
+        _ => {
+            positions.insert(position, trail);
+        }
+    }
+}
+
+// Find out whether the members can match all of the array in any way at
+// all, trying every number of elements for each occurrence and every
+// option of each choice.
+//
+// Greedy matching is tried first, since it's fast and it's what explains
+// a mismatch.  But it can't give back an element that a later member
+// needed, e.g. for `[* int, int]` or `[? int, int]`, so if it fails this
+// decides.  Rather than backtracking, this follows every position each
+// member could end at, so it takes polynomial time however ambiguous the
+// members are.
+fn array_fits(members: &[Node], values: &[Value], ctx: &Context) -> TempResult<bool> {
+    // Nothing here is reported, and mismatches are only counted.
+    let ctx = Context {
+        report: None,
+        lean: true,
+        ..ctx.clone()
+    };
+    let starts: Positions = std::iter::once((0, None)).collect();
+    let ends = sequence_ends(members, starts, values, &ctx, false)?;
+    Ok(ends.contains_key(&values.len()))
+}
+
+// The errors in an array that doesn't match, found by matching it in the
+// same way as array_fits, but setting aside the elements that don't match.
+//
+// If even that can't match the whole array (e.g. it doesn't have enough
+// elements), the array by itself is the error.
+fn collect_array_errors(
+    ar: &Array,
+    values: &[Value],
+    ctx: &Context,
+) -> TempResult<Vec<ValidateError>> {
+    let starts: Positions = std::iter::once((0, None)).collect();
+    let ends = sequence_ends(&ar.members, starts, values, ctx, true)?;
+    let mut trail = match ends.get(&values.len()) {
+        Some(trail) => trail.as_deref(),
+        None => {
+            return Ok(validate_array_part2(ar, values, ctx)
+                .err()
+                .into_iter()
+                .collect())
+        }
+    };
+    let mut set_aside = Vec::new();
+    while let Some(s) = trail {
+        set_aside.push(&s.errors);
+        trail = s.previous.as_deref();
+    }
+    Ok(set_aside.into_iter().rev().flatten().cloned().collect())
+}
+
+// The positions where a sequence of members could end, starting from any
+// of `starts`.
+fn sequence_ends(
+    members: &[Node],
+    starts: Positions,
+    values: &[Value],
+    ctx: &Context,
+    collect: bool,
+) -> TempResult<Positions> {
+    members.iter().try_fold(starts, |positions, member| {
+        member_ends(member, &positions, values, ctx, collect)
+    })
+}
+
+// The positions where an array member could end, starting from any of
+// `starts`.  This sees through the same nodes as validate_array_member.
+fn member_ends(
+    member: &Node,
+    starts: &Positions,
+    values: &[Value],
+    ctx: &Context,
+    collect: bool,
+) -> TempResult<Positions> {
+    if starts.is_empty() {
+        return Ok(Positions::new());
+    }
+    match member {
+        Node::Occur(o) => occur_ends(o, starts, values, ctx, collect),
+        Node::KeyValue(kv) => value_ends(&kv.value, starts, values, ctx, collect),
+        Node::Rule(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            member_ends(answer.node, starts, values, &answer.ctx, collect)
+        }
+        Node::Unwrap(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            resolve_alias(answer.node, &answer.ctx, |node, ctx| {
+                let members = match node {
+                    Node::Array(a) => &a.members,
+                    Node::Map(m) => &m.members,
+                    _ => {
+                        return Err(ValidateError::Structural(
+                            "unwrapped rule in an array isn't an array or map".into(),
+                        ))
+                    }
+                };
+                sequence_ends(members, starts.clone(), values, ctx, collect)
+            })
+        }
+        Node::Choice(c) => {
+            let mut ends = Positions::new();
+            for option in &c.options {
+                for (end, trail) in member_ends(option, starts, values, ctx, collect)? {
+                    add_position(&mut ends, end, trail);
+                }
+            }
+            Ok(ends)
+        }
+        Node::Group(g) => sequence_ends(&g.members, starts.clone(), values, ctx, collect),
+        m => value_ends(m, starts, values, ctx, collect),
+    }
+}
+
+fn occur_ends(
+    occur: &Occur,
+    starts: &Positions,
+    values: &[Value],
+    ctx: &Context,
+    collect: bool,
+) -> TempResult<Positions> {
+    let (lower_limit, upper_limit) = occur.limits();
+    let mut current = starts.clone();
+    let mut count: u64 = 0;
+    // First, the repetitions that are needed.  Once another one can't get
+    // anywhere new, no number of them will, so the lower limit ends up
+    // there too.  This happens within one step per element: a member that
+    // can match nothing only adds positions, and one that can't always
+    // moves on.
+    while count < lower_limit && !current.is_empty() {
+        let next = member_ends(&occur.node, &current, values, ctx, collect)?;
+        count += 1;
+        if next.keys().eq(current.keys()) {
             break;
         }
+        current = next;
     }
-    if count < lower_limit {
-        return Err(mismatch(format!("more array element [{}]", occur)));
+    // Then the ones that may or may not be there.  Only positions that
+    // haven't been reached yet need to be followed, so each position is
+    // only followed once.
+    let mut ends = current.clone();
+    let mut frontier = current;
+    while count < upper_limit && !frontier.is_empty() {
+        let next = member_ends(&occur.node, &frontier, values, ctx, collect)?;
+        frontier = next
+            .into_iter()
+            .filter(|(end, _)| !ends.contains_key(end))
+            .collect();
+        ends.extend(frontier.iter().map(|(end, trail)| (*end, trail.clone())));
+        count += 1;
     }
-    Ok(())
+    Ok(ends)
+}
+
+// The positions after a single element that matches `node`.  When
+// collecting errors, an element that doesn't match is set aside instead.
+fn value_ends(
+    node: &Node,
+    starts: &Positions,
+    values: &[Value],
+    ctx: &Context,
+    collect: bool,
+) -> TempResult<Positions> {
+    let mut ends = Positions::new();
+    for (&start, trail) in starts {
+        if let Some(value) = values.get(start) {
+            match validate(value, node, ctx) {
+                Ok(()) => add_position(&mut ends, start + 1, trail.clone()),
+                Err(e) if e.is_fatal() => return Err(e),
+                Err(_) if collect => {
+                    let count = set_aside_count(trail) + 1;
+                    if ends
+                        .get(&(start + 1))
+                        .map_or(false, |t| set_aside_count(t) <= count)
+                    {
+                        continue;
+                    }
+                    // Past the number of errors that are kept, it only
+                    // matters that the array can be matched.
+                    let errors = if count > ctx.lookup.options().max_errors {
+                        Vec::new()
+                    } else {
+                        let errors = collect_errors(value, node, ctx)?;
+                        let at = |e: ValidateError| e.at(PathElement::Index(start));
+                        errors.into_iter().map(at).collect()
+                    };
+                    let set_aside = SetAside {
+                        count,
+                        errors,
+                        previous: trail.clone(),
+                    };
+                    add_position(&mut ends, start + 1, Some(Rc::new(set_aside)));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    Ok(ends)
 }
 
 /// Validate some node against a mutable working array.
@@ -641,25 +1971,68 @@ fn validate_array_value(
     working_array: &mut WorkingArray,
     ctx: &Context,
 ) -> ValidateResult {
+    let index = working_array.index();
     match working_array.peek_front() {
         Some(val) => {
-            validate(val, node, ctx)?;
+            let mark = ctx.report_mark();
+            validate(val, node, ctx)
+                .map_err(|e| ctx.detail(e, |e| e.at(PathElement::Index(index))))?;
+            ctx.report_at(mark, || PathElement::Index(index));
             // We had a successful match; remove the matched value.
             working_array.pop_front();
             Ok(())
         }
-        None => Err(mismatch(format!("array element {}", node))),
+        None => {
+            let max_len = ctx.lookup.options().max_value_len;
+            Err(ctx.error(|| {
+                let node = render_schema(node, max_len);
+                mismatch(format!("array element {}", node))
+                    .with_kind(ErrorKind::ArrayLengthMismatch)
+            }))
+        }
     }
 }
 
 fn validate_map(m: &Map, value: &Value, ctx: &Context) -> ValidateResult {
-    match value {
-        Value::Map(vm) => validate_map_part2(m, vm, ctx),
+    let mark = ctx.report_mark();
+    let result = match value {
+        Value::Map(vm) => validate_map_part2(m, vm, ctx, None),
+        _ => Err(mismatch("map")),
+    };
+    ctx.in_schema(mark, result, || m.name.clone())
+}
+
+/// Validate a map, telling a visitor how its members were matched.
+pub(crate) fn do_visit_map(
+    m: &Map,
+    value: &Value,
+    ctx: &dyn LookupContext,
+    visitor: &mut dyn MapVisitor,
+) -> ValidateResult {
+    let ctx = Context {
+        lookup: ctx,
+        generic_map: GenericMap::default(),
+        depth: 0,
+        report: None,
+        lean: false,
+        scratch: &Scratch::default(),
+    };
+    let value = remove_tags(value, &ctx.lookup.options().tags)?;
+    match value.as_ref() {
+        Value::Map(vm) => validate_map_part2(m, vm, &ctx, Some(visitor)),
         _ => Err(mismatch("map")),
     }
+    .map_err(|e| e.finish(ctx.lookup))
 }
 
-fn validate_map_part2(m: &Map, value_map: &ValueMap, ctx: &Context) -> ValidateResult {
+// Validation without a visitor is the same as visiting with a visitor that
+// ignores everything; we just skip keeping the log.
+fn validate_map_part2(
+    m: &Map,
+    value_map: &ValueMap,
+    ctx: &Context,
+    visitor: Option<&mut dyn MapVisitor>,
+) -> ValidateResult {
     // Strategy for validating a map:
     // 1. We assume that the code that constructed the IVT Map placed the keys
     //    in matching order (literals first, more general types at the end) so
@@ -675,21 +2048,73 @@ fn validate_map_part2(m: &Map, value_map: &ValueMap, ctx: &Context) -> ValidateR
     // 8. If the key is not found and we haven't consumed the expected number of
     //    keys, return an error.
 
-    let mut working_map = WorkingMap::new(value_map);
+    let mut working_map = WorkingMap::new(value_map, ctx.scratch);
+    if visitor.is_some() {
+        working_map.events = Some(Vec::new());
+    }
+
+    let result = validate_map_members(m, &mut working_map, ctx);
+    if let Some(visitor) = visitor {
+        replay_map_events(&working_map, value_map, visitor);
+    }
+    result
+}
+
+// Tell a visitor what happened while matching a map.
+//
+// Members are reported in the order they were tried, leaving out attempts
+// that were later rewound.  A member that matched some keys and then failed
+// to match another (e.g. `* tstr => int`) only counts as matched.
+fn replay_map_events(working_map: &WorkingMap, value_map: &ValueMap, visitor: &mut dyn MapVisitor) {
+    let events = working_map.events.as_deref().unwrap_or_default();
+    let mut unmatched_seen: Vec<&KeyValue> = Vec::new();
+    for event in events {
+        match event {
+            MapEvent::Matched(kv, key) => visitor.matched(kv, key, &value_map[key]),
+            MapEvent::Unmatched(kv) => {
+                let matched_elsewhere = events
+                    .iter()
+                    .any(|e| matches!(e, MapEvent::Matched(other, _) if other == kv));
+                if !matched_elsewhere && !unmatched_seen.contains(&kv) {
+                    unmatched_seen.push(kv);
+                    visitor.unmatched_member(kv);
+                }
+            }
+        }
+    }
+    for (_, key, value) in working_map.entries() {
+        visitor.unmatched_entry(key, value);
+    }
+}
 
+fn validate_map_members(m: &Map, working_map: &mut WorkingMap, ctx: &Context) -> ValidateResult {
     for member in &m.members {
-        validate_map_member(member, &mut working_map, ctx).map_err(|e| {
+        validate_map_member(member, working_map, ctx).map_err(|e| {
             // If a MapCut error pops out here, change it to a Mismatch, so that
             // it can't cause trouble in nested maps.
             e.erase_mapcut()
         })?;
     }
-    if working_map.map.is_empty() {
+    if working_map.is_empty() {
         Ok(())
     } else {
         // If the working map isn't empty, that means we had some extra values
-        // that didn't match anything.
-        Err(mismatch("shorter map"))
+        // that didn't match anything.  If one of them has a key that matched
+        // but a value that didn't, report that instead.  Errors are kept in
+        // schema member order, so the earliest member wins, regardless of
+        // how the keys sort.  Otherwise, the error leads to the first extra
+        // entry.
+        let max_len = ctx.lookup.options().max_value_len;
+        let leftover = |(entry, _): &(usize, ValidateError)| working_map.unmatched.contains(*entry);
+        match working_map.value_errors.iter().position(leftover) {
+            Some(index) => Err(working_map.value_errors.swap_remove(index).1.erase_mapcut()),
+            None => Err(ctx.error(|| {
+                let (index, key, _) = working_map.entries().next().unwrap();
+                mismatch("shorter map")
+                    .with_kind(ErrorKind::UnexpectedMapKey)
+                    .at_entry(index, render_value(key, max_len))
+            })),
+        }
     }
 }
 
@@ -701,42 +2126,62 @@ fn validate_map_member(
     match member {
         // FIXME: does it make sense for this to destructure & dispatch
         // each Node type here?  Is there any way to make this generic?
-        Node::Occur(o) => validate_map_occur(o, working_map, ctx),
-        Node::KeyValue(kv) => validate_map_keyvalue(kv, working_map, ctx),
+        Node::Occur(o) => {
+            let mark = ctx.report_mark();
+            let result = validate_map_occur(o, working_map, ctx);
+            ctx.in_schema(mark, result, || member_name(&o.node).map(String::from))
+        }
+        Node::KeyValue(kv) => {
+            let mark = ctx.report_mark();
+            let result = validate_map_keyvalue(kv, working_map, ctx);
+            let result = ctx.via(result, || {
+                SchemaElement::Member(ctx.scratch.name(&kv.key_name()))
+            });
+            ctx.in_schema(mark, result, || kv.name.clone())
+        }
         Node::Rule(r) => {
             // We can't use the generic validate() here; we would forget that
             // we were in a map context.  We need to punch down a level into
             // the rule and match again.
             let answer = ctx.lookup_rule(r)?;
-            validate_map_member(answer.node, working_map, &answer.ctx)
+            answer.in_rule(validate_map_member(answer.node, working_map, &answer.ctx))
         }
         Node::Unwrap(r) => {
             // Like Rule, we are dereferencing the Rule by hand here so that
             // we can "see through" to the underlying data without forgetting
             // we were in a map context.
             let answer = ctx.lookup_rule(r)?;
-            validate_map_unwrap(answer.node, working_map, &answer.ctx)
+            let result = resolve_alias(answer.node, &answer.ctx, |node, ctx| {
+                validate_map_unwrap(node, working_map, ctx)
+            });
+            answer.in_rule(result)
         }
         Node::Group(g) => {
-            // As we call validate_array_member, we don't know how many items
-            // it might speculatively pop from the list.  So we'll take a
-            // snapshot now and commit our changes if we match successfully
-            // (and roll them back if it fails).
-            working_map.snapshot();
+            // As we call validate_map_member, we don't know how many items
+            // it might speculatively remove from the map.  So we'll take a
+            // snapshot now and roll back to it if the match fails.
+            let snap = working_map.snapshot();
+            let mark = ctx.report_mark();
 
             // Recurse into each member of the group.
             for group_member in &g.members {
+                working_map.failed_group_matches = 0;
                 match validate_map_member(group_member, working_map, ctx) {
                     Ok(_) => {
                         // So far so good...
                     }
                     Err(e) => {
                         // Since we failed to validate the entire group,
-                        // rewind to our most recent snapshot.  This may put
+                        // rewind to our snapshot.  This may put
                         // values back into the map, so they can be matched by
                         // whatever we try next (or trigger an error if they
                         // aren't consumed by anything).
-                        working_map.rewind();
+                        //
+                        // Remember how far we got, counting any progress
+                        // made by a nested group that failed.
+                        let nested = working_map.failed_group_matches;
+                        working_map.failed_group_matches = working_map.rewind(snap) + nested;
+                        ctx.forget_report(mark);
 
                         // Also forget any MapCut errors, so that a sibling
                         // group may succeed where we failed.
@@ -745,7 +2190,7 @@ fn validate_map_member(
                 }
             }
             // All group members validated Ok.
-            working_map.commit();
+            working_map.forget(snap);
             Ok(())
         }
         Node::Choice(c) => validate_map_choice(&c.options, working_map, ctx),
@@ -759,6 +2204,9 @@ fn validate_map_member(
         Node::Array(_) => Err(ValidateError::Structural("array as map member".into())),
         Node::Range(_) => Err(ValidateError::Structural("range as map member".into())),
         Node::Control(_) => Err(ValidateError::Structural("control op as map member".into())),
+        Node::Tagged(_) => Err(ValidateError::Structural(
+            "tagged data as map member".into(),
+        )),
     }
 }
 
@@ -768,7 +2216,11 @@ fn validate_map_choice(
     ctx: &Context,
 ) -> ValidateResult {
     // We need to explore each of the possible choices.
-    for option in options {
+    let mut errors = ChoiceErrors::new(ctx);
+    // The option that matched the most map entries before failing.
+    let mut closest: Option<(usize, usize)> = None;
+    for (index, option) in options.iter().enumerate() {
+        working_map.failed_group_matches = 0;
         match validate_map_member(option, working_map, ctx) {
             Ok(()) => {
                 return Ok(());
@@ -780,12 +2232,264 @@ fn validate_map_choice(
                 if !e.is_mismatch() {
                     return Err(e);
                 }
+                let matched = working_map.failed_group_matches;
+                if matched > closest.map_or(0, |(_, m)| m) {
+                    closest = Some((index, matched));
+                }
+                errors.push(e);
             }
         }
     }
     // None of the choices worked.
-    let expected = format!("choice of {}", options.len());
-    Err(mismatch(expected))
+    let expected = || format!("choice of {}", options.len());
+    match closest {
+        Some((index, matched)) => {
+            let option = &options[index];
+            closest_option_error(expected, index, matched, option, working_map, ctx)
+        }
+        None => Err(errors.finish(ctx, expected)),
+    }
+}
+
+/// Explain why the closest option of a map choice didn't match.
+///
+/// The option is tried again against a copy of the map, this time going on
+/// past each failure, so that every missing or mismatched member is listed
+/// in schema order, along with any other keys the option didn't match.
+fn closest_option_error<F>(
+    expected: F,
+    index: usize,
+    matched: usize,
+    option: &Node,
+    working_map: &WorkingMap,
+    ctx: &Context,
+) -> ValidateResult
+where
+    F: FnOnce() -> String,
+{
+    let mut trial = working_map.trial();
+    // In lean mode the problems aren't kept, but the option is still tried
+    // again, in case that finds a fatal error.
+    let mut problems = Vec::new();
+    let keep = if ctx.lean { None } else { Some(&mut problems) };
+    collect_map_problems(option, &mut trial, ctx, keep)?;
+    if ctx.lean {
+        return Err(lean_mismatch());
+    }
+
+    let mut msg = format!(
+        "{}; closest is option {}, which matched {} {}",
+        expected(),
+        index + 1,
+        matched,
+        if matched == 1 { "entry" } else { "entries" }
+    );
+    // The entries whose values were explained above.
+    let explained: Vec<usize> = problems.iter().filter_map(ValidateError::entry).collect();
+    for problem in problems {
+        let problem = match problem.erase_mapcut() {
+            ValidateError::MissingMapKey(m) => format!("missing {}: {}", m.key(), m.expected()),
+            ValidateError::Mismatch(m) => m.to_string(),
+            other => other.to_string(),
+        };
+        msg += &format!("; {}", problem);
+    }
+    let max_len = ctx.lookup.options().max_value_len;
+    let keys: Vec<String> = trial
+        .entries()
+        .filter(|(index, _, _)| !explained.contains(index))
+        .map(|(_, k, _)| render_value(k, max_len))
+        .collect();
+    if !keys.is_empty() {
+        msg += &format!("; not matched: {}", keys.join(", "));
+    }
+    Err(mismatch(msg).via(SchemaElement::Choice(index)))
+}
+
+// Validate each member of a group separately, collecting the mismatches
+// (if `problems` is given).
+//
+// Groups (including groups referenced by name) are expanded, so that a
+// failure inside a nested group doesn't hide the members after it.
+fn collect_map_problems(
+    node: &Node,
+    working_map: &mut WorkingMap,
+    ctx: &Context,
+    mut problems: Option<&mut Vec<ValidateError>>,
+) -> TempResult<()> {
+    match node {
+        Node::Group(g) => {
+            for member in &g.members {
+                collect_map_problems(member, working_map, ctx, problems.as_deref_mut())?;
+            }
+            Ok(())
+        }
+        Node::Rule(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            collect_map_problems(answer.node, working_map, &answer.ctx, problems)
+        }
+        _ => match validate_map_member(node, working_map, ctx) {
+            Ok(()) => Ok(()),
+            // A cut only matters when choosing between options; here we
+            // want to know about every member that failed.
+            Err(e) if !e.is_fatal() => {
+                if let Some(problems) = problems {
+                    problems.push(e);
+                }
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
+    }
+}
+
+// The errors in a map that doesn't match.
+//
+// Each member is matched in turn, and one that fails doesn't stop the
+// others from being tried.  If it failed because of an entry (e.g. its key
+// matched but its value didn't), that entry is set aside, with the errors
+// inside it.  Each entry that's left over at the end is explained by the
+// first member whose key matches it, if there is one.
+fn collect_map_errors(
+    m: &Map,
+    value_map: &ValueMap,
+    ctx: &Context,
+) -> TempResult<Vec<ValidateError>> {
+    let mut working_map = WorkingMap::new(value_map, ctx.scratch);
+    // The errors, and the entry each one is in (if any).
+    let mut found = Vec::new();
+    for member in &m.members {
+        collect_map_member_errors(member, &mut working_map, ctx, &mut found)?;
+    }
+    let max_len = ctx.lookup.options().max_value_len;
+    let leftover: Vec<_> = working_map.entries().collect();
+    for (entry, key, value) in leftover {
+        match entry_errors(&m.members, entry, key, value, ctx)? {
+            Some(errors) => found.extend(errors.into_iter().map(|e| (Some(entry), e))),
+            None => {
+                let e = mismatch("shorter map")
+                    .with_kind(ErrorKind::UnexpectedMapKey)
+                    .at_entry(entry, render_value(key, max_len));
+                found.push((Some(entry), e));
+            }
+        }
+    }
+    // Errors that aren't in any entry (e.g. a missing key) come first.
+    found.sort_by_key(|(entry, _)| *entry);
+    Ok(found.into_iter().map(|(_, e)| e.erase_mapcut()).collect())
+}
+
+// Match a member of a map, and note why if it doesn't match.
+//
+// Groups (including groups referenced by name) are expanded, as in
+// collect_map_problems.
+fn collect_map_member_errors(
+    node: &Node,
+    working_map: &mut WorkingMap,
+    ctx: &Context,
+    found: &mut Vec<(Option<usize>, ValidateError)>,
+) -> TempResult<()> {
+    match node {
+        Node::Group(g) => {
+            for member in &g.members {
+                collect_map_member_errors(member, working_map, ctx, found)?;
+            }
+            Ok(())
+        }
+        Node::Rule(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            let start = found.len();
+            collect_map_member_errors(answer.node, working_map, &answer.ctx, found)?;
+            let errors = found.split_off(start);
+            found.extend(
+                errors
+                    .into_iter()
+                    .map(|(entry, e)| (entry, answer.rule_error(e))),
+            );
+            Ok(())
+        }
+        _ => {
+            working_map.failed_entry = None;
+            let e = match validate_map_member(node, working_map, ctx) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.is_fatal() => return Err(e),
+                Err(e) => e,
+            };
+            let failed_entry = match node {
+                Node::KeyValue(_) | Node::Occur(_) => working_map.failed_entry.take(),
+                _ => None,
+            };
+            match failed_entry {
+                Some(entry) => {
+                    let (key, value) = working_map.map.iter().nth(entry).unwrap();
+                    let errors = member_entry_errors(node, entry, key, value, ctx)?;
+                    let errors = errors.unwrap_or_else(|| vec![e]);
+                    found.extend(errors.into_iter().map(|e| (Some(entry), e)));
+                    working_map.remove(entry);
+                }
+                None => found.push((None, e)),
+            }
+            Ok(())
+        }
+    }
+}
+
+// The errors in a map entry, from the first of the members whose key
+// matches it, or None if no key matches (or the value does too).
+fn entry_errors(
+    members: &[Node],
+    index: usize,
+    key: &Value,
+    value: &Value,
+    ctx: &Context,
+) -> TempResult<Option<Vec<ValidateError>>> {
+    for member in members {
+        if let Some(errors) = member_entry_errors(member, index, key, value, ctx)? {
+            return Ok(Some(errors));
+        }
+    }
+    Ok(None)
+}
+
+fn member_entry_errors(
+    member: &Node,
+    index: usize,
+    key: &Value,
+    value: &Value,
+    ctx: &Context,
+) -> TempResult<Option<Vec<ValidateError>>> {
+    match member {
+        Node::KeyValue(kv) => match validate(key, &kv.key, ctx) {
+            Ok(()) => {
+                let errors = collect_errors(value, &kv.value, ctx)?;
+                if errors.is_empty() {
+                    return Ok(None);
+                }
+                let max_len = ctx.lookup.options().max_value_len;
+                let at_key = |e: ValidateError| e.at_entry(index, render_value(key, max_len));
+                Ok(Some(errors.into_iter().map(at_key).collect()))
+            }
+            Err(e) if e.is_fatal() => Err(e),
+            Err(_) => Ok(None),
+        },
+        Node::Occur(o) => member_entry_errors(&o.node, index, key, value, ctx),
+        Node::Group(g) => entry_errors(&g.members, index, key, value, ctx),
+        Node::Choice(c) => entry_errors(&c.options, index, key, value, ctx),
+        Node::ChoiceifyInline(a) => entry_errors(&a.members, index, key, value, ctx),
+        Node::Rule(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            let errors = member_entry_errors(answer.node, index, key, value, &answer.ctx)?;
+            Ok(errors.map(|errors| errors.into_iter().map(|e| answer.rule_error(e)).collect()))
+        }
+        Node::Unwrap(r) => {
+            let answer = ctx.lookup_rule(r)?;
+            resolve_alias(answer.node, &answer.ctx, |node, ctx| match node {
+                Node::Map(m) => entry_errors(&m.members, index, key, value, ctx),
+                _ => Ok(None),
+            })
+        }
+        _ => Ok(None),
+    }
 }
 
 // TODO: this duplicates a lot of code from validate_choiceify_members. Merge them?
@@ -799,6 +2503,7 @@ fn validate_map_choiceify_members(
     // Because we are in a group context, referring to other groups by name is
     // also allowed; we will transparently unwrap those (recursively).
 
+    let mut errors = ChoiceErrors::new(ctx);
     for item in choices {
         let validate_result = match item {
             Node::KeyValue(kv) => {
@@ -833,11 +2538,11 @@ fn validate_map_choiceify_members(
                 if !e.is_mismatch() {
                     return Err(e);
                 }
+                errors.push(e);
             }
         }
     }
-    let expected = format!("choiceified group of {}", choices.len());
-    Err(mismatch(expected))
+    Err(errors.finish(ctx, || format!("choiceified group of {}", choices.len())))
 }
 
 /// Validate a "choice-ified group" (the CDDL "&" operator)
@@ -848,7 +2553,7 @@ fn validate_map_choiceify(
     working_map: &mut WorkingMap,
     ctx: &Context,
 ) -> ValidateResult {
-    let NodeContext { node, ctx } = ctx.lookup_rule(rule)?;
+    let NodeContext { node, ctx, .. } = ctx.lookup_rule(rule)?;
     match node {
         Node::Group(g) => validate_map_choiceify_members(&g.members, working_map, &ctx),
         Node::Rule(r) => validate_map_choiceify(r, working_map, &ctx),
@@ -870,7 +2575,9 @@ fn validate_map_unwrap(node: &Node, working_map: &mut WorkingMap, ctx: &Context)
             // All array members validated Ok.
             Ok(())
         }
-        _ => Err(mismatch("unwrap map")),
+        _ => Err(ValidateError::Structural(
+            "unwrapped rule in a map isn't a map".into(),
+        )),
     }
 }
 
@@ -881,7 +2588,8 @@ fn validate_map_occur(
     ctx: &Context,
 ) -> ValidateResult {
     let (lower_limit, upper_limit) = occur.limits();
-    let mut count: usize = 0;
+    let mut count: u64 = 0;
+    let mut last_error = None;
 
     loop {
         match validate_map_member(&occur.node, working_map, ctx) {
@@ -889,6 +2597,7 @@ fn validate_map_occur(
             Err(e) => {
                 if e.is_mismatch() {
                     // Stop trying to match this occurrence.
+                    last_error = Some(e);
                     break;
                 }
                 // Either we got a MapCut error, or it's something even more
@@ -903,15 +2612,50 @@ fn validate_map_occur(
             break;
         }
     }
+    if let Some(e) = last_error {
+        let failed_entry = working_map.failed_entry.take();
+        if let Some(entry) = failed_entry.filter(|_| !e.path().is_empty()) {
+            // A key matched, but its value didn't.
+            if count < lower_limit {
+                // This is still the entry that made the member fail.
+                working_map.failed_entry = Some(entry);
+                return Err(e);
+            }
+            working_map.value_errors.push((entry, e));
+        } else if let (0, ValidateError::MissingMapKey(_)) = (count, &e) {
+            // If nothing matched because a required key is absent, say so.
+            if count < lower_limit {
+                return Err(e);
+            }
+        }
+    }
     if count < lower_limit {
         // Read this format string as "{{" then "{}" then "}}"
         // The first and last print a single brace; the value is in the
         // middle, e.g "{foo}".
-        return Err(mismatch(format!("map{{{}}}]", occur)));
+        return Err(ctx.error(|| {
+            mismatch(format!(
+                "map{{{}}} (found {}, need {})",
+                occur,
+                count,
+                occur.effective_limit().describe()
+            ))
+        }));
     }
+    ctx.log_occurrence(occur, count);
     Ok(())
 }
 
+// The name of a map or array member's place in the schema, if the member
+// is a key-value pair (with or without an occurrence).
+fn member_name(member: &Node) -> Option<&str> {
+    match member {
+        Node::KeyValue(kv) => kv.name.as_deref(),
+        Node::Occur(o) => member_name(&o.node),
+        _ => None,
+    }
+}
+
 /// Validate a key-value pair against a mutable working map.
 fn validate_map_keyvalue(
     kv: &KeyValue,
@@ -931,30 +2675,156 @@ fn validate_map_keyvalue(
 
     let key_node = &kv.key;
     let val_node = &kv.value;
-    let cut = kv.cut;
-
-    // If we fail to validate a key, exit now with an error.
-    let (working_key, working_val) = validate_map_key(working_map, key_node, ctx)?;
-
-    // Match the value that was returned.
-    match validate(working_val, val_node, ctx) {
-        Ok(()) => {
-            working_map.remove(&working_key);
-            Ok(())
+    let cut = kv.cut && !ctx.lookup.options().ignore_cuts;
+
+    // Search the map for a key that matches, and validate its value.
+    // Literal keys can be compared directly, while others have to be
+    // validated.  If a key matches but its value doesn't, we may keep
+    // searching other keys (unless cut semantics are in force).
+    let max_len = ctx.lookup.options().max_value_len;
+    let at_key = |e: ValidateError, index: usize, key: &Value| {
+        ctx.detail(e, |e| e.at_entry(index, render_value(key, max_len)))
+    };
+    let mut value_error = None;
+    let mut key_error = None;
+    let literal_entry = resolve_alias(key_node, ctx, |node, _| match node {
+        Node::Literal(l) => Ok(Some(working_map.find_literal(l))),
+        _ => Ok(None),
+    })?;
+    let found = match literal_entry {
+        Some(entry) => {
+            let mark = ctx.report_mark();
+            match entry {
+                Some((index, key, working_val)) => match validate(working_val, val_node, ctx) {
+                    Ok(()) => {
+                        ctx.report_at(mark, || PathElement::Key(render_value(key, max_len)));
+                        Some((index, key))
+                    }
+                    Err(e) => {
+                        let e = null_hint(e, working_val);
+                        value_error = Some((index, at_key(e, index, key)));
+                        None
+                    }
+                },
+                None => None,
+            }
+        }
+        None => {
+            let mut found = None;
+            for (index, key, working_val) in working_map.entries() {
+                let mark = ctx.report_mark();
+                if let Err(e) = validate(key, key_node, ctx) {
+                    // Keys are validated like any other data, so a key type
+                    // can be e.g. an array.  If a key has the right shape
+                    // but something inside it is wrong, remember why, in
+                    // case nothing else matches.
+                    if near_miss_key(&e) && key_error.is_none() {
+                        key_error = Some((index, at_key(key_mismatch(e), index, key)));
+                    }
+                    continue;
+                }
+                // The entry itself is listed as permissive, so there's no
+                // need to list what was permissive about its key.
+                ctx.forget_permissive(mark);
+                ctx.log_permissive(|| Permissive::Wildcard(kv.to_string()));
+                match validate(working_val, val_node, ctx) {
+                    Ok(()) => {
+                        ctx.report_at(mark, || PathElement::Key(render_value(key, max_len)));
+                        found = Some((index, key));
+                        break;
+                    }
+                    Err(e) => {
+                        // The key matched, but the entry didn't.
+                        ctx.forget_report(mark);
+                        let e = at_key(null_hint(e, working_val), index, key);
+                        if cut || !e.is_mismatch() {
+                            value_error = Some((index, e));
+                            break;
+                        }
+                        // Keep the first error, in case nothing else matches.
+                        value_error.get_or_insert((index, e));
+                    }
+                }
+            }
+            found
         }
-        Err(e) => {
-            match (cut, e) {
-                (true, ValidateError::Mismatch(m)) => {
-                    // If "cut" semantics are in force, then rewrite Mismatch errors.
-                    // This allows special handling when nested inside Occur nodes.
-                    Err(ValidateError::MapCut(m))
+    };
+
+    if let Some((index, key)) = found {
+        working_map.remove(index);
+        working_map.log_matched(kv, key);
+        return Ok(());
+    }
+    working_map.log_unmatched(kv);
+    working_map.failed_entry = value_error.as_ref().or(key_error.as_ref()).map(|(i, _)| *i);
+
+    match value_error {
+        // No key matched at all.
+        None => match key_error {
+            Some((_, e)) => Err(e),
+            None => {
+                let max_len = ctx.lookup.options().max_value_len;
+                Err(ctx.error(|| {
+                    let key = render_schema(key_node, max_len);
+                    missing_map_key(key, render_schema(val_node, max_len))
+                }))
+            }
+        },
+        Some((_, e)) => {
+            if cut && e.is_mismatch() {
+                // If "cut" semantics are in force, then rewrite Mismatch errors.
+                // This allows special handling when nested inside Occur nodes.
+                if ctx.lean {
+                    Err(lean_cut())
+                } else {
+                    Err(ValidateError::MapCut(Box::new(e)))
                 }
-                (_, x) => Err(x),
+            } else {
+                Err(e)
             }
         }
     }
 }
 
+// If a map value is null and the schema doesn't allow that, it's likely
+// that the schema (or the data) confused a missing key with a null value.
+fn null_hint(e: ValidateError, value: &Value) -> ValidateError {
+    match (e, value) {
+        (ValidateError::Mismatch(m), Value::Null) if m.path().is_empty() => {
+            ValidateError::Mismatch(
+                m.with_hint("found null; to allow a null value, the schema needs \"/ nil\""),
+            )
+        }
+        (e, _) => e,
+    }
+}
+
+// Returns true if a map key failed to match a key type only because of
+// something inside it, e.g. an array key with a wrong element.
+fn near_miss_key(e: &ValidateError) -> bool {
+    match e {
+        ValidateError::Mismatch(m) => !m.path().is_empty(),
+        ValidateError::MissingMapKey(_) => true,
+        _ => false,
+    }
+}
+
+// Rewrite an error from inside a map key, so its path (which leads into the
+// key) isn't confused with the path to the map entry.
+fn key_mismatch(e: ValidateError) -> ValidateError {
+    let (what, path) = match &e {
+        ValidateError::Mismatch(m) => (m.expected().to_string(), m.path()),
+        ValidateError::MissingMapKey(m) => (format!("{}: {}", m.key(), m.expected()), m.path()),
+        _ => return e,
+    };
+    let path: String = path.iter().map(PathElement::to_string).collect();
+    if path.is_empty() {
+        mismatch(format!("{} in map key", what))
+    } else {
+        mismatch(format!("{} at {} in map key", what, path))
+    }
+}
+
 fn validate_standalone_group(g: &Group, value: &Value, ctx: &Context) -> ValidateResult {
     // Since we're not in an array or map context, it's not clear how we should
     // validate a group containing multiple elements.  If we see one, return an
@@ -969,16 +2839,16 @@ fn validate_standalone_group(g: &Group, value: &Value, ctx: &Context) -> Validat
 }
 
 fn deref_range_rule(node: &Node, ctx: &Context) -> TempResult<Literal> {
-    match node {
-        Node::Literal(l) => Ok(l.clone()),
-        Node::Rule(r) => {
-            let answer = ctx.lookup_rule(r)?;
-            deref_range_rule(answer.node, &answer.ctx)
-        }
+    resolve_alias(node, ctx, |node, _| match node {
+        Node::Literal(l @ Literal::Int(_)) | Node::Literal(l @ Literal::Float(_)) => Ok(l.clone()),
+        Node::Literal(l) => Err(ValidateError::Structural(format!(
+            "non-numeric range operator ({})",
+            l
+        ))),
         _ => Err(ValidateError::Structural(
             "confusing type on range operator".into(),
         )),
-    }
+    })
 }
 
 // Returns true if value is within range
@@ -1003,20 +2873,22 @@ fn validate_range(range: &Range, value: &Value, ctx: &Context) -> ValidateResult
             if check_range(i1, i2, v, range.inclusive) {
                 Ok(())
             } else {
-                Err(mismatch(format!("{}", range)))
+                Err(ctx.error(|| mismatch(format!("{}", range))))
             }
         }
+        // NaN is never within a range, since it isn't ordered against the
+        // endpoints.
         (Literal::Float(f1), Literal::Float(f2), Value::Float(v)) => {
             if check_range(f1, f2, &v.0, range.inclusive) {
                 Ok(())
             } else {
-                Err(mismatch(format!("{}", range)))
+                Err(ctx.error(|| mismatch(format!("{}", range))))
             }
         }
         _ => {
             if discriminant(&start) == discriminant(&end) {
                 // The range types were the same, so this is just a mismatch.
-                Err(mismatch(format!("{}", range)))
+                Err(ctx.error(|| mismatch(format!("{}", range))))
             } else {
                 // The range types didn't agree; return an error that points the
                 // finger at the CDDL instead.
@@ -1028,25 +2900,34 @@ fn validate_range(range: &Range, value: &Value, ctx: &Context) -> ValidateResult
     }
 }
 
-// Follow a chain of Rule references until we reach a non-Rule node.
-fn chase_rules<'a, F, R>(node: &'a Node, ctx: &'a Context<'a>, f: F) -> TempResult<R>
+// Follow a chain of rule aliases (e.g. `person-name = name  name = tstr`)
+// until we reach a node that isn't a rule reference, and call `f` with
+// that node and its context.
+//
+// Anything that needs to see through aliases (range endpoints, control
+// operands, unwrap targets, map keys) should use this, so an alias behaves
+// the same wherever it's used.  A cycle of aliases (`a = b  b = a`) stops
+// at the recursion limit.
+fn resolve_alias<F, R>(node: &Node, ctx: &Context, f: F) -> TempResult<R>
 where
-    F: Fn(&Node) -> TempResult<R>,
-    R: 'static,
+    F: FnOnce(&Node, &Context) -> TempResult<R>,
 {
     if let Node::Rule(rule) = node {
         let answer = ctx.lookup_rule(rule)?;
-        chase_rules(answer.node, &answer.ctx, f)
+        resolve_alias(answer.node, &answer.ctx, f)
     } else {
-        f(node)
+        f(node, ctx)
     }
 }
 
 fn validate_control(ctl: &Control, value: &Value, ctx: &Context) -> ValidateResult {
     match ctl {
         Control::Size(ctl_size) => validate_control_size(ctl_size, value, ctx),
+        Control::Bits(ctl_bits) => validate_control_bits(ctl_bits, value, ctx),
+        #[cfg(feature = "regex")]
         Control::Regexp(re) => validate_control_regexp(re, value),
         Control::Cbor(ctl_cbor) => validate_control_cbor(ctl_cbor, value, ctx),
+        Control::Default(ctl_default) => validate(value, &ctl_default.target, ctx),
     }
 }
 
@@ -1060,7 +2941,6 @@ fn validate_control_cbor(_ctl_cbor: &CtlOpCbor, _value: &Value, _ctx: &Context)
 #[cfg(feature = "serde_cbor")]
 fn validate_control_cbor(ctl_cbor: &CtlOpCbor, value: &Value, ctx: &Context) -> ValidateResult {
     use serde_cbor::Value as CBOR_Value;
-    use std::convert::TryFrom;
 
     match value {
         Value::Bytes(bytes) => {
@@ -1068,41 +2948,55 @@ fn validate_control_cbor(ctl_cbor: &CtlOpCbor, value: &Value, ctx: &Context) ->
                 .map_err(|e| ValidateError::ValueError(format!("{}", e)))?;
 
             let nested_value = Value::try_from(cbor_value)?;
-
-            validate(&nested_value, ctl_cbor.node.as_ref(), ctx)
+            let nested_value = remove_tags(&nested_value, &ctx.lookup.options().tags)?;
+
+            // The nested data only lives until we're done with it, so any
+            // array results that point into it are dropped with it.
+            let arrays = ctx.scratch.arrays.replace(BTreeMap::new());
+            let result = validate(&nested_value, ctl_cbor.node.as_ref(), ctx);
+            ctx.scratch.arrays.replace(arrays);
+            result
         }
         _ => Err::<(), ValidateError>(mismatch("Bytes")),
     }
 }
 
 fn validate_control_size(ctl: &CtlOpSize, value: &Value, ctx: &Context) -> ValidateResult {
-    // Follow the chain of rules references until we wind up with a non-Rule node.
-    let size: u64 = chase_rules(&ctl.size, ctx, |size_node| {
-        // Compute the size in bytes
+    // A single size is an upper limit for an integer (the number of bytes
+    // it fits in), but the exact size of a string, as in RFC 8610 3.8.1.
+    let (limits, exact) = resolve_alias(&ctl.size, ctx, |size_node, ctx| {
+        // Compute the permitted sizes in bytes
         match size_node {
-            Node::Literal(Literal::Int(i)) => (*i).try_into().map_err(|_| {
-                // Note the parser doesn't handle >64 bit positive integers.
-                // Under normal circumstances, the only way this can occur is
-                // when the limit is negative.
-                let msg = format!("bad .size limit {}", i);
-                ValidateError::Structural(msg)
-            }),
+            Node::Literal(Literal::Int(i)) => Ok((
+                SizeLimits {
+                    lower: 0,
+                    upper: size_limit(*i)?,
+                },
+                true,
+            )),
+            Node::Range(range) => Ok((size_range(range, ctx)?, false)),
             _ => {
                 // Under normal circumstances this error is unreachable
-                // because the flatten code will only allow literal integer sizes.
+                // because the flatten code will only allow literal integer
+                // sizes or ranges.
                 let msg = format!("bad .size argument type ({})", size_node);
                 Err(ValidateError::Structural(msg))
             }
         }
     })?;
 
-    chase_rules(&ctl.target, ctx, |target_node| {
+    let string_limits = SizeLimits {
+        lower: if exact { limits.upper } else { limits.lower },
+        upper: limits.upper,
+    };
+
+    resolve_alias(&ctl.target, ctx, |target_node, ctx| {
         // Ensure that the target node evaluates to some type that is
         // compatible with the .size operator, and then validate the size limit.
         match target_node {
-            Node::PreludeType(PreludeType::Uint) => validate_size_uint(size, value),
-            Node::PreludeType(PreludeType::Tstr) => validate_size_tstr(size, value),
-            Node::PreludeType(PreludeType::Bstr) => validate_size_bstr(size, value),
+            Node::PreludeType(PreludeType::Uint) => validate_size_uint(&limits, value, ctx),
+            Node::PreludeType(PreludeType::Tstr) => validate_size_tstr(&string_limits, value, ctx),
+            Node::PreludeType(PreludeType::Bstr) => validate_size_bstr(&string_limits, value, ctx),
             _ => {
                 let msg = format!("bad .size target type ({})", target_node);
 
@@ -1112,10 +3006,140 @@ fn validate_control_size(ctl: &CtlOpSize, value: &Value, ctx: &Context) -> Valid
     })
 }
 
+fn validate_control_bits(ctl: &CtlOpBits, value: &Value, ctx: &Context) -> ValidateResult {
+    let set_bits = resolve_alias(&ctl.target, ctx, |target_node, _| {
+        // Find the bits that are set, if the value has the target type.
+        match (target_node, value) {
+            (Node::PreludeType(PreludeType::Uint), Value::Integer(i)) if *i >= 0 => {
+                Ok(SetBits::Uint(*i))
+            }
+            (Node::PreludeType(PreludeType::Uint), _) => Err(mismatch("uint")),
+            (Node::PreludeType(PreludeType::Bstr), Value::Bytes(b)) => Ok(SetBits::Bstr(b)),
+            (Node::PreludeType(PreludeType::Bstr), _) => Err(mismatch("bstr")),
+            _ => {
+                let msg = format!("bad .bits target type ({})", target_node);
+                Err(ValidateError::Structural(msg))
+            }
+        }
+    })?;
+
+    // Each bit number must match the control type, the same way any other
+    // integer would.  This is what lets one `&(...)` rule describe both a
+    // single value and a set of bits.
+    for bit in (0..set_bits.len()).filter(|n| set_bits.contains(*n)) {
+        match validate(&Value::Integer(bit.into()), &ctl.bits, ctx) {
+            Ok(()) => {}
+            Err(e) if e.is_fatal() => return Err(e),
+            Err(_) => {
+                return Err(ctx.error(|| {
+                    mismatch(format!(
+                        "{} with allowed .bits (found bit {})",
+                        set_bits.type_name(),
+                        bit
+                    ))
+                }))
+            }
+        }
+    }
+    Ok(())
+}
+
+// The data checked by a .bits control.
+enum SetBits<'v> {
+    Uint(i128),
+    Bstr(&'v [u8]),
+}
+
+impl SetBits<'_> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            SetBits::Uint(_) => "uint",
+            SetBits::Bstr(_) => "bstr",
+        }
+    }
+
+    // The number of bits, including the ones that aren't set.
+    fn len(&self) -> u64 {
+        match self {
+            SetBits::Uint(_) => 128,
+            SetBits::Bstr(b) => b.len() as u64 * 8,
+        }
+    }
+
+    // Whether a bit is set.  Bit 0 of a byte string is the least
+    // significant bit of its first byte, and bit 8 is the least significant
+    // bit of the second byte.  Bits past the end are never set.
+    fn contains(&self, n: u64) -> bool {
+        match self {
+            SetBits::Uint(i) => n < 128 && i & 1 << n != 0,
+            SetBits::Bstr(b) => usize::try_from(n / 8)
+                .ok()
+                .and_then(|byte| b.get(byte))
+                .map_or(false, |byte| byte & 1 << (n % 8) != 0),
+        }
+    }
+}
+
+// The permitted sizes for a .size control, in bytes (inclusive).
+struct SizeLimits {
+    lower: u64,
+    upper: u64,
+}
+
+impl SizeLimits {
+    // Check a size, returning a mismatch that names the type if it's
+    // outside the limits.
+    fn check(&self, size: u64, type_name: &str, ctx: &Context) -> ValidateResult {
+        if size > self.upper {
+            Err(ctx.error(|| mismatch(format!("{} over .size limit", type_name))))
+        } else if size < self.lower {
+            Err(ctx.error(|| mismatch(format!("{} under .size limit", type_name))))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn size_limit(i: i128) -> TempResult<u64> {
+    i.try_into().map_err(|_| {
+        // Note the parser doesn't handle >64 bit positive integers.
+        // Under normal circumstances, the only way this can occur is
+        // when the limit is negative.
+        let msg = format!("bad .size limit {}", i);
+        ValidateError::Structural(msg)
+    })
+}
+
+// Convert a range like `(1..10)` into size limits.
+fn size_range(range: &Range, ctx: &Context) -> TempResult<SizeLimits> {
+    let start = deref_range_rule(&range.start, ctx)?;
+    let end = deref_range_rule(&range.end, ctx)?;
+    match (start, end) {
+        (Literal::Int(start), Literal::Int(end)) => {
+            let lower = size_limit(start)?;
+            let end = size_limit(end)?;
+            let upper = if range.inclusive {
+                end
+            } else if end == 0 {
+                // An empty range; nothing can match.
+                return Ok(SizeLimits { lower: 1, upper: 0 });
+            } else {
+                end - 1
+            };
+            Ok(SizeLimits { lower, upper })
+        }
+        _ => {
+            let msg = format!("bad .size range {}", range);
+            Err(ValidateError::Structural(msg))
+        }
+    }
+}
+
 /// Validate the control operator "regexp"
 ///
 /// `regexp` applies a regular expression to a text string.
 ///
+#[cfg(feature = "regex")]
 fn validate_control_regexp(re: &CtlOpRegexp, value: &Value) -> ValidateResult {
     match value {
         Value::Text(text) => {
@@ -1129,21 +3153,19 @@ fn validate_control_regexp(re: &CtlOpRegexp, value: &Value) -> ValidateResult {
     }
 }
 
-fn validate_size_uint(size: u64, value: &Value) -> ValidateResult {
+// Check the size of an unsigned integer.
+//
+// The size of an unsigned integer is the number of bytes needed to hold
+// its value; zero needs no bytes at all.
+fn validate_size_uint(limits: &SizeLimits, value: &Value, ctx: &Context) -> ValidateResult {
     match value {
         Value::Integer(x) => {
             if *x < 0 {
                 Err(mismatch(".size on negative integer"))
-            } else if size >= 16 {
-                // If the size is 16 or larger, the i128 value will always fit.
-                Ok(())
             } else {
-                let mask = (1i128 << (size * 8)) - 1;
-                if *x & !mask == 0 {
-                    Ok(())
-                } else {
-                    Err(mismatch("uint over .size limit"))
-                }
+                let bits = 128 - x.leading_zeros();
+                let size = (bits + 7) / 8;
+                limits.check(size.into(), "uint", ctx)
             }
         }
         _ => Err(mismatch("uint")),
@@ -1151,31 +3173,17 @@ fn validate_size_uint(size: u64, value: &Value) -> ValidateResult {
 }
 
 // Check the size of a text string.
-fn validate_size_tstr(size: u64, value: &Value) -> ValidateResult {
+fn validate_size_tstr(limits: &SizeLimits, value: &Value, ctx: &Context) -> ValidateResult {
     match value {
-        Value::Text(s) => {
-            let size: usize = size.try_into().unwrap_or(usize::MAX);
-            if s.len() > size {
-                Err(mismatch("tstr over .size limit"))
-            } else {
-                Ok(())
-            }
-        }
+        Value::Text(s) => limits.check(s.len() as u64, "tstr", ctx),
         _ => Err(mismatch("tstr")),
     }
 }
 
 // Check the size of a byte string.
-fn validate_size_bstr(size: u64, value: &Value) -> ValidateResult {
+fn validate_size_bstr(limits: &SizeLimits, value: &Value, ctx: &Context) -> ValidateResult {
     match value {
-        Value::Bytes(b) => {
-            let size: usize = size.try_into().unwrap_or(usize::MAX);
-            if b.len() > size {
-                Err(mismatch("bstr over .size limit"))
-            } else {
-                Ok(())
-            }
-        }
+        Value::Bytes(b) => limits.check(b.len() as u64, "bstr", ctx),
         _ => Err(mismatch("bstr")),
     }
 }
@@ -1185,6 +3193,7 @@ fn validate_choiceify_members(choices: &[Node], value: &Value, ctx: &Context) ->
     // For each KeyValue, extract its .value member and try to validate that.
     // Because we are in a group context, referring to other groups by name is
     // also allowed; we will transparently unwrap those (recursively).
+    let mut errors = ChoiceErrors::new(ctx);
     for item in choices {
         let validate_result = match item {
             Node::KeyValue(kv) => validate(value, &kv.value, ctx),
@@ -1217,18 +3226,18 @@ fn validate_choiceify_members(choices: &[Node], value: &Value, ctx: &Context) ->
                 if e.is_fatal() {
                     return Err(e);
                 }
+                errors.push(e);
             }
         }
     }
-    let expected = format!("choiceified group of {}", choices.len());
-    Err(mismatch(expected))
+    Err(errors.finish(ctx, || format!("choiceified group of {}", choices.len())))
 }
 
 /// Validate a "choice-ified group" (the CDDL "&" operator)
 ///
 /// Each value in the named group will be used as a possible choice.
 fn validate_choiceify(rule: &Rule, value: &Value, ctx: &Context) -> ValidateResult {
-    let NodeContext { node, ctx } = ctx.lookup_rule(rule)?;
+    let NodeContext { node, ctx, .. } = ctx.lookup_rule(rule)?;
     match node {
         Node::Group(g) => validate_choiceify_members(&g.members, value, &ctx),
         Node::Rule(r) => validate_choiceify(r, value, &ctx),
@@ -22,6 +22,8 @@ pub enum Value {
     Text(String),
     Array(Vec<Value>),
     Map(BTreeMap<Value, Value>),
+    /// A tagged value (CBOR major type 6), e.g. `0("2013-03-21T20:04:00Z")`.
+    Tag(u64, Box<Value>),
 }
 
 // FloatOrd doesn't implement Debug, so we have to do all the work by hand.
@@ -32,10 +34,19 @@ impl fmt::Debug for Value {
             Value::Bool(x) => x.fmt(f),
             Value::Integer(x) => x.fmt(f),
             Value::Float(x) => x.0.fmt(f),
-            Value::Bytes(x) => x.fmt(f),
+            Value::Bytes(x) => {
+                // Use CDDL's hex syntax, so empty bytes don't look like an
+                // empty array.
+                write!(f, "h'")?;
+                for byte in x {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
             Value::Text(x) => x.fmt(f),
             Value::Array(x) => x.fmt(f),
             Value::Map(x) => x.fmt(f),
+            Value::Tag(tag, x) => write!(f, "{}({:?})", tag, x),
         }
     }
 }
@@ -0,0 +1,92 @@
+//! This module lets callers watch how a map is matched against a schema.
+//!
+//! Validation of a map only says whether the whole map matched.  A
+//! [`MapVisitor`] hears which schema member matched each entry, which
+//! members didn't match anything, and which entries were left over.  This
+//! is useful for things like reporting unknown fields, or finding the
+//! schema for each value in a document.
+//!
+//! # Examples
+//!
+//! ```
+//! use cddl_cat::context::BasicContext;
+//! use cddl_cat::flatten::flatten_from_str;
+//! use cddl_cat::ivt::{KeyValue, Node};
+//! use cddl_cat::value::Value;
+//! use cddl_cat::visit::{visit_map, MapVisitor};
+//!
+//! #[derive(Default)]
+//! struct Fields {
+//!     present: Vec<Value>,
+//!     absent: usize,
+//! }
+//!
+//! impl MapVisitor for Fields {
+//!     fn matched(&mut self, _member: &KeyValue, key: &Value, _value: &Value) {
+//!         self.present.push(key.clone());
+//!     }
+//!
+//!     fn unmatched_member(&mut self, _member: &KeyValue) {
+//!         self.absent += 1;
+//!     }
+//! }
+//!
+//! let rules = flatten_from_str("person = { name: tstr, ? age: int }").unwrap();
+//! let map = match &rules["person"].node {
+//!     Node::Map(m) => m.clone(),
+//!     _ => unreachable!(),
+//! };
+//! let ctx = BasicContext::new(rules);
+//! let entries = vec![(Value::Text("name".into()), Value::Text("Bob".into()))];
+//! let value = Value::Map(entries.into_iter().collect());
+//!
+//! let mut fields = Fields::default();
+//! visit_map(&map, &value, &ctx, &mut fields).unwrap();
+//! assert_eq!(fields.present, vec![Value::Text("name".into())]);
+//! assert_eq!(fields.absent, 1);
+//! ```
+
+use crate::context::LookupContext;
+use crate::ivt::{KeyValue, Map};
+use crate::util::ValidateResult;
+use crate::validate::do_visit_map;
+use crate::value::Value;
+
+/// Receives the results of matching a map against a schema.
+///
+/// Every method does nothing by default, so a visitor only needs to
+/// implement the ones it cares about.
+///
+/// Matching sometimes tries a member speculatively (e.g. inside a group
+/// choice) and then backs out.  The visitor only hears about the matches
+/// that were kept, in the order they were made, after matching finishes.
+pub trait MapVisitor {
+    /// A schema member matched an entry of the map.
+    fn matched(&mut self, _member: &KeyValue, _key: &Value, _value: &Value) {}
+
+    /// A schema member didn't match any entry.
+    ///
+    /// This happens when an optional member is absent, and also for the
+    /// required member that caused validation to fail.
+    fn unmatched_member(&mut self, _member: &KeyValue) {}
+
+    /// An entry of the map wasn't matched by any member.
+    ///
+    /// These are reported last.
+    fn unmatched_entry(&mut self, _key: &Value, _value: &Value) {}
+}
+
+/// Validate a map, telling `visitor` how its members were matched.
+///
+/// The result is the same as validating the value against a rule
+/// containing `map`.  The visitor is called even if validation fails, so
+/// it can see how far matching got.  Only the top-level map is visited;
+/// maps nested inside its values are validated as usual.
+pub fn visit_map(
+    map: &Map,
+    value: &Value,
+    ctx: &dyn LookupContext,
+    visitor: &mut dyn MapVisitor,
+) -> ValidateResult {
+    do_visit_map(map, value, ctx, visitor)
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "serde_cbor")]
+
+// Count the heap allocations made while validating.
+//
+// This is a separate test binary because it needs its own global
+// allocator.  Only allocations made by the thread that's counting are
+// counted, so the test harness can't interfere.
+
+use cddl_cat::cbor::validate_cbor_slice;
+use cddl_cat::context::{LookupContext, ValidationOptions};
+use cddl_cat::schema::Schema;
+use cddl_cat::value::Value;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+struct Counter;
+
+thread_local! {
+    static COUNT: Cell<Option<usize>> = Cell::new(None);
+}
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        COUNT.with(|c| c.set(c.get().map(|n| n + 1)));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        COUNT.with(|c| c.set(c.get().map(|n| n + 1)));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counter = Counter;
+
+// The number of allocations made by `f`.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    COUNT.with(|c| c.set(Some(0)));
+    f();
+    COUNT.with(|c| c.replace(None)).unwrap()
+}
+
+const SCHEMA: &str = r#"
+    log = [* message]
+    message = { type: "ping", ? seq: uint }
+            / { type: "reading", sensor: tstr, body: reading }
+    reading = {
+        kind: "temp" / "humidity",
+        values: [* int / float],
+        ? unit: tstr,
+        status: &(ok: 0, warn: 1, fail: 2),
+        flags: uint .bits flag,
+        at: pair<int>,
+        * tstr => any,
+    }
+    flag = &(low: 0, high: 1)
+    pair<T> = [T, T]
+"#;
+
+// [
+//   {"type": "ping"},
+//   {"type": "ping", "seq": 7},
+//   {"type": "reading", "sensor": "t1", "body": {
+//     "kind": "humidity", "values": [1, 2.5, -3], "status": 1, "flags": 3,
+//     "at": [10, 20], "note": "x"}}
+// ]
+const LOG: &[u8] = b"\x83\
+    \xa1\x64type\x64ping\
+    \xa2\x64type\x64ping\x63seq\x07\
+    \xa3\x64type\x67reading\x66sensor\x62t1\x64body\
+    \xa6\x64kind\x68humidity\x66values\x83\x01\xf9\x41\x00\x22\
+    \x66status\x01\x65flags\x03\x62at\x82\x0a\x14\x64note\x61x";
+
+// The same log, with "flags": 4
+const BAD_LOG: &[u8] = b"\x83\
+    \xa1\x64type\x64ping\
+    \xa2\x64type\x64ping\x63seq\x07\
+    \xa3\x64type\x67reading\x66sensor\x62t1\x64body\
+    \xa6\x64kind\x68humidity\x66values\x83\x01\xf9\x41\x00\x22\
+    \x66status\x01\x65flags\x04\x62at\x82\x0a\x14\x64note\x61x";
+
+#[test]
+fn lean_allocations() {
+    // The allocations needed to decode the data, which validation can't
+    // avoid.
+    let decode = allocations(|| {
+        let cbor_value: serde_cbor::Value = serde_cbor::from_slice(LOG).unwrap();
+        Value::try_from(cbor_value).unwrap();
+    });
+
+    let schema = Schema::from_str(SCHEMA).unwrap();
+    let rule_def = schema.lookup_rule("log").unwrap();
+    let full = allocations(|| validate_cbor_slice(rule_def, LOG, &schema).unwrap());
+
+    let mut options = ValidationOptions::default();
+    options.lean = true;
+    let schema = schema.with_options(options);
+    let rule_def = schema.lookup_rule("log").unwrap();
+    let lean = allocations(|| validate_cbor_slice(rule_def, LOG, &schema).unwrap());
+
+    assert_eq!(lean, decode);
+    assert!(full > lean);
+
+    // Data that doesn't match gets the same error either way.
+    let full_schema = Schema::from_str(SCHEMA).unwrap();
+    let full_rule = full_schema.lookup_rule("log").unwrap();
+    let full_err = validate_cbor_slice(full_rule, BAD_LOG, &full_schema).unwrap_err();
+    let lean_err = validate_cbor_slice(rule_def, BAD_LOG, &schema).unwrap_err();
+    assert_eq!(
+        full_err.to_string(),
+        "Mismatch(expected uint with allowed .bits (found bit 2) at [2][\"body\"][\"flags\"])"
+    );
+    assert_eq!(lean_err.to_string(), full_err.to_string());
+    assert_eq!(lean_err.data_range(), full_err.data_range());
+}
+
+#[test]
+fn session_allocations() {
+    let decode = allocations(|| {
+        let cbor_value: serde_cbor::Value = serde_cbor::from_slice(LOG).unwrap();
+        Value::try_from(cbor_value).unwrap();
+    });
+
+    // A session validates in lean mode without being asked to.
+    let schema = Schema::from_str(SCHEMA).unwrap();
+    let rule_def = schema.lookup_rule("log").unwrap();
+    let session = schema.session();
+    for _ in 0..3 {
+        let n = allocations(|| session.validate_cbor_slice(rule_def, LOG).unwrap());
+        assert_eq!(n, decode);
+    }
+
+    // A map with more than 64 entries needs a buffer, which the session
+    // keeps for the next document.
+    let schema = Schema::from_str("big = { * tstr => uint }").unwrap();
+    let rule_def = schema.lookup_rule("big").unwrap();
+    let big_map: BTreeMap<String, u32> = (0..100).map(|n| (format!("k{}", n), n)).collect();
+    let big = serde_cbor::to_vec(&big_map).unwrap();
+    let decode = allocations(|| {
+        let cbor_value: serde_cbor::Value = serde_cbor::from_slice(&big).unwrap();
+        Value::try_from(cbor_value).unwrap();
+    });
+    let session = schema.session();
+    let first = allocations(|| session.validate_cbor_slice(rule_def, &big).unwrap());
+    let second = allocations(|| session.validate_cbor_slice(rule_def, &big).unwrap());
+    assert!(first > decode);
+    assert_eq!(second, decode);
+
+    // Data that doesn't match gets the same error as without a session.
+    let schema = Schema::from_str(SCHEMA).unwrap();
+    let rule_def = schema.lookup_rule("log").unwrap();
+    let err = validate_cbor_slice(rule_def, BAD_LOG, &schema).unwrap_err();
+    let session_err = schema
+        .session()
+        .validate_cbor_slice(rule_def, BAD_LOG)
+        .unwrap_err();
+    assert_eq!(session_err.to_string(), err.to_string());
+    assert_eq!(session_err.data_range(), err.data_range());
+}
@@ -1,9 +1,12 @@
 #![cfg(feature = "serde_cbor")]
 
-use cddl_cat::cbor::validate_cbor_bytes;
+use cddl_cat::cbor::{validate_cbor, validate_cbor_bytes, validate_cbor_report};
+use cddl_cat::context::{TagPolicy, ValidationOptions, ValidationReport};
+use cddl_cat::schema::Schema;
 use cddl_cat::util::ErrorMatch;
-use cddl_cat::ValidateResult;
+use cddl_cat::{ValidateError, ValidateResult};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[rustfmt::skip] // allow arbitrary indents for readability
 pub mod cbor {
@@ -25,6 +28,20 @@ pub mod cbor {
     pub const FLOAT_1_0:    &[u8] = b"\xf9\x3c\x00";            // #7.25 (f16)
     pub const FLOAT_1E5:    &[u8] = b"\xfa\x47\xc3\x50\x00";    // #7.26 (f32)
     pub const FLOAT_1E300:  &[u8] = b"\xfb\x7e\x37\xe4\x3c\x88\x00\x75\x9c"; // #7.27 (f64)
+    pub const FLOAT_NEG_0:  &[u8] = b"\xf9\x80\x00";            // -0.0 (f16)
+    pub const F16_1_5:      &[u8] = b"\xf9\x3e\x00";            // 1.5 (f16)
+    pub const F32_1_5:      &[u8] = b"\xfa\x3f\xc0\x00\x00";    // 1.5 (f32)
+    pub const F64_1_5:      &[u8] = b"\xfb\x3f\xf8\x00\x00\x00\x00\x00\x00"; // 1.5 (f64)
+    pub const F16_0_25:     &[u8] = b"\xf9\x34\x00";            // 0.25 (f16)
+    pub const F32_0_25:     &[u8] = b"\xfa\x3e\x80\x00\x00";    // 0.25 (f32)
+    pub const F16_3_14:     &[u8] = b"\xf9\x42\x48";            // 3.140625 (f16)
+    pub const F32_3_14:     &[u8] = b"\xfa\x40\x48\xf5\xc3";    // 3.1400001 (f32)
+    pub const F64_3_14:     &[u8] = b"\xfb\x40\x09\x1e\xb8\x51\xeb\x85\x1f"; // 3.14 (f64)
+    pub const F16_NAN:      &[u8] = b"\xf9\x7e\x00";            // NaN (f16)
+    pub const F64_NAN:      &[u8] = b"\xfb\x7f\xf8\x00\x00\x00\x00\x00\x00"; // NaN (f64)
+    pub const F16_INF:      &[u8] = b"\xf9\x7c\x00";            // Infinity (f16)
+    pub const F16_NEG_INF:  &[u8] = b"\xf9\xfc\x00";            // -Infinity (f16)
+    pub const F64_INF:      &[u8] = b"\xfb\x7f\xf0\x00\x00\x00\x00\x00\x00"; // Infinity (f64)
 
     pub const ARRAY_EMPTY:  &[u8] = b"\x80";              // []
     pub const ARRAY_123:    &[u8] = b"\x83\x01\x02\x03";  // [1,2,3]
@@ -50,6 +67,71 @@ fn validate_cbor_null() {
     assert_eq!(err.to_string(), "Mismatch(expected nil)");
 }
 
+#[derive(Debug, Serialize)]
+struct UnitStruct;
+
+#[derive(Debug, Serialize)]
+struct OptionalName {
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SkipOptionalName {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[test]
+fn validate_cbor_unit_none() {
+    let cddl_input = r#"thing = nil"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::UNDEFINED).unwrap();
+    let inputs = [
+        serde_cbor::to_vec(&()).unwrap(),
+        serde_cbor::to_vec(&UnitStruct).unwrap(),
+        serde_cbor::to_vec(&Option::<u32>::None).unwrap(),
+    ];
+    for cbor_bytes in &inputs {
+        assert_eq!(cbor_bytes, cbor::NULL);
+        validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+    }
+
+    let none = OptionalName { name: None };
+    let some = OptionalName {
+        name: Some("Bob".to_string()),
+    };
+    let skip_none = SkipOptionalName { name: None };
+    let skip_some = SkipOptionalName {
+        name: Some("Bob".to_string()),
+    };
+
+    // A None field is serialized as a map member with a null value.
+    let cddl_input = r#"thing = { ? name: tstr }"#;
+    let cbor_bytes = serde_cbor::to_vec(&none).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected tstr at ["name"] (found null; to allow a null value, the schema needs "/ nil"))"#
+    );
+    for cbor_bytes in &[
+        serde_cbor::to_vec(&some).unwrap(),
+        serde_cbor::to_vec(&skip_none).unwrap(),
+        serde_cbor::to_vec(&skip_some).unwrap(),
+    ] {
+        validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+    }
+
+    let cddl_input = r#"thing = { name: nil / tstr }"#;
+    for cbor_bytes in &[
+        serde_cbor::to_vec(&none).unwrap(),
+        serde_cbor::to_vec(&some).unwrap(),
+        serde_cbor::to_vec(&skip_some).unwrap(),
+    ] {
+        validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+    }
+    let cbor_bytes = serde_cbor::to_vec(&skip_none).unwrap();
+    validate_cbor_bytes("thing", cddl_input, &cbor_bytes).err_missing_map_key();
+}
+
 #[test]
 fn validate_cbor_bool() {
     let cddl_input = r#"thing = true"#;
@@ -57,6 +139,66 @@ fn validate_cbor_bool() {
     validate_cbor_bytes("thing", cddl_input, cbor::BOOL_FALSE).unwrap_err();
     let err = validate_cbor_bytes("thing", cddl_input, cbor::NULL).unwrap_err();
     assert_eq!(err.to_string(), "Mismatch(expected true)");
+
+    // Only CBOR booleans match the literals and `bool`; the integers 0 and
+    // 1 don't, and get a hint because sending them is a common mistake.
+    let hint = r#"found an integer; to allow 0 and 1 too, the schema needs "0 / 1 / bool""#;
+    let cases: &[(&str, &[u8], &[u8])] = &[
+        ("thing = true", cbor::BOOL_TRUE, cbor::INT_1),
+        ("thing = false", cbor::BOOL_FALSE, cbor::INT_0),
+        ("thing = bool", cbor::BOOL_TRUE, cbor::INT_1),
+        ("thing = bool", cbor::BOOL_FALSE, cbor::INT_0),
+        ("thing = true / false", cbor::BOOL_FALSE, cbor::INT_0),
+        (
+            "thing = &(yes: true, no: false)",
+            cbor::BOOL_TRUE,
+            cbor::INT_1,
+        ),
+    ];
+    for (cddl_input, good, near_miss) in cases {
+        validate_cbor_bytes("thing", cddl_input, good).unwrap();
+        let err = validate_cbor_bytes("thing", cddl_input, near_miss).unwrap_err();
+        match &err {
+            ValidateError::Mismatch(m) => assert_eq!(m.hint(), Some(hint), "{}", cddl_input),
+            _ => panic!("{}: expected mismatch, got {:?}", cddl_input, err),
+        }
+    }
+    let err = validate_cbor_bytes("thing", "thing = bool", cbor::INT_1).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("Mismatch(expected bool ({}))", hint)
+    );
+
+    // Other integers aren't mistaken for booleans.
+    let err = validate_cbor_bytes("thing", "thing = bool", cbor::INT_9).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected bool)");
+    // The hint follows the data into arrays and maps.
+    let err = validate_cbor_bytes("thing", "thing = [* bool]", b"\x82\xf5\x00").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("Mismatch(expected bool at [1] ({}))", hint)
+    );
+    let err = validate_cbor_bytes("thing", "thing = { a: bool }", b"\xa1\x61a\x01").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(r#"Mismatch(expected bool at ["a"] ({}))"#, hint)
+    );
+    // The schema the hint suggests accepts both.
+    let cddl_input = "thing = 0 / 1 / bool";
+    for cbor_bytes in &[cbor::INT_0, cbor::INT_1, cbor::BOOL_TRUE, cbor::BOOL_FALSE] {
+        validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+    }
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_9).err_mismatch();
+
+    // Booleans don't match integers either.
+    validate_cbor_bytes("thing", "thing = 1", cbor::BOOL_TRUE).err_mismatch();
+    validate_cbor_bytes("thing", "thing = 0", cbor::BOOL_FALSE).err_mismatch();
+    validate_cbor_bytes("thing", "thing = int", cbor::BOOL_TRUE).err_mismatch();
+    validate_cbor_bytes("thing", "thing = 0..1", cbor::BOOL_TRUE).err_mismatch();
+
+    // Comparison controls like `.ne` aren't supported yet.
+    let err = validate_cbor_bytes("thing", "thing = bool .ne true", cbor::BOOL_FALSE).unwrap_err();
+    assert!(matches!(err, ValidateError::Unsupported(_)));
 }
 
 #[test]
@@ -91,6 +233,81 @@ fn validate_cbor_float() {
     // E.g. CBOR #7.27 (64-bit) shouldn't validate against "float16" or "float32".
 }
 
+#[test]
+fn validate_cbor_float_special() {
+    let specials = [
+        cbor::F16_NAN,
+        cbor::F64_NAN,
+        cbor::F16_INF,
+        cbor::F16_NEG_INF,
+        cbor::F64_INF,
+    ];
+    // NaN and the infinities are floats of any size.
+    for cddl_input in &[
+        "thing = float",
+        "thing = float16",
+        "thing = float64",
+        "thing = number",
+    ] {
+        for &cbor_bytes in &specials {
+            validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+        }
+    }
+    for &cbor_bytes in &specials {
+        validate_cbor_bytes("thing", "thing = int", cbor_bytes).err_mismatch();
+        validate_cbor_bytes("thing", "thing = 1.0", cbor_bytes).err_mismatch();
+        // NaN isn't in any range, and the infinities are outside any range
+        // CDDL can write.
+        validate_cbor_bytes("thing", "thing = -1e308..1e308", cbor_bytes).err_mismatch();
+    }
+    // .size doesn't apply to floats.
+    let err = validate_cbor_bytes("thing", "thing = float .size 8", cbor::F16_INF).unwrap_err();
+    assert_eq!(err.to_string(), "Structural(bad .size target type (Float))");
+
+    // CDDL can't write NaN or infinity, and float literals don't overflow.
+    let err = validate_cbor_bytes("thing", "thing = 1e999", cbor::F64_INF).unwrap_err();
+    assert!(matches!(err, ValidateError::ParseError(_)), "{:?}", err);
+    let err = validate_cbor_bytes("thing", "thing = 0x1p1024", cbor::F64_INF).unwrap_err();
+    assert!(matches!(err, ValidateError::ParseError(_)), "{:?}", err);
+}
+
+#[test]
+fn validate_cbor_float_literal() {
+    // Values that every float width represents exactly are equal after
+    // widening to f64.
+    for &cbor_bytes in &[cbor::F16_1_5, cbor::F32_1_5, cbor::F64_1_5] {
+        validate_cbor_bytes("thing", "thing = 1.5", cbor_bytes).unwrap();
+        validate_cbor_bytes("thing", "thing = 1.25", cbor_bytes).err_mismatch();
+    }
+    for &cbor_bytes in &[cbor::F16_0_25, cbor::F32_0_25] {
+        validate_cbor_bytes("thing", "thing = 0.25", cbor_bytes).unwrap();
+        validate_cbor_bytes("thing", "thing = 0.5", cbor_bytes).unwrap_err();
+    }
+    validate_cbor_bytes("thing", "thing = 0.25", cbor::INT_1).err_mismatch();
+    validate_cbor_bytes("thing", "thing = 1.0", cbor::INT_1).err_mismatch();
+
+    // 3.14 isn't exactly representable, and the narrower encodings round it
+    // to a different value.
+    validate_cbor_bytes("thing", "thing = 3.14", cbor::F64_3_14).unwrap();
+    let err = validate_cbor_bytes("thing", "thing = 3.14", cbor::F16_3_14).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected 3.14)");
+    validate_cbor_bytes("thing", "thing = 3.14", cbor::F32_3_14).err_mismatch();
+    validate_cbor_bytes("thing", "thing = 3.140625", cbor::F16_3_14).unwrap();
+    // Hexfloat literals can name the exact value.
+    validate_cbor_bytes("thing", "thing = 0x1.92p1", cbor::F16_3_14).unwrap();
+    validate_cbor_bytes("thing", "thing = 0x1.92p1", cbor::F32_3_14).err_mismatch();
+    validate_cbor_bytes("thing", "thing = 0x1.8p0", cbor::F16_1_5).unwrap();
+    validate_cbor_bytes("thing", "thing = -0x1p0..0x1p1", cbor::F64_1_5).unwrap();
+    // A range accepts all of them.
+    for &cbor_bytes in &[cbor::F16_3_14, cbor::F32_3_14, cbor::F64_3_14] {
+        validate_cbor_bytes("thing", "thing = 3.13..3.15", cbor_bytes).unwrap();
+    }
+
+    // Zero matches zero of either sign.
+    validate_cbor_bytes("thing", "thing = 0.0", cbor::FLOAT_NEG_0).unwrap();
+    validate_cbor_bytes("thing", "thing = -0.0", cbor::FLOAT_0_0).unwrap();
+}
+
 #[test]
 fn validate_cbor_choice() {
     let cddl_input = r#"thing = 23 / 24"#;
@@ -102,21 +319,84 @@ fn validate_cbor_choice() {
     validate_cbor_bytes("thing", cddl_input, cbor::FLOAT_1_0).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap();
     let err = validate_cbor_bytes("thing", cddl_input, cbor::BOOL_TRUE).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected choice of 2)");
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (choice of 2 (int / float) / tstr))"
+    );
 
     let cddl_input = r#"thing = (foo / bar) foo = (int / float) bar = tstr"#;
     validate_cbor_bytes("thing", cddl_input, cbor::INT_23).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::FLOAT_1_0).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap();
     let err = validate_cbor_bytes("thing", cddl_input, cbor::BOOL_TRUE).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected choice of 2)");
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (choice of 2 (int / float) / tstr))"
+    );
 
     let cddl_input = r#"thing = (int / float // tstr / bstr)"#;
     validate_cbor_bytes("thing", cddl_input, cbor::INT_23).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::FLOAT_1_0).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap();
     let err = validate_cbor_bytes("thing", cddl_input, cbor::BOOL_TRUE).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected choice of 2)");
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (choice of 2 (int / float) / choice of 2 (tstr / bstr)))"
+    );
+
+    // Literals and prelude types can be mixed.
+    let cddl_input = r#"answer = "yes" / "no" / int"#;
+    validate_cbor_bytes("answer", cddl_input, b"\x63yes").unwrap();
+    validate_cbor_bytes("answer", cddl_input, b"\x62no").unwrap();
+    validate_cbor_bytes("answer", cddl_input, cbor::NINT_1000).unwrap();
+    let err = validate_cbor_bytes("answer", cddl_input, cbor::TEXT_IETF).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3 ("yes" / "no" / int))"#
+    );
+}
+
+#[test]
+fn validate_cbor_type_socket() {
+    // The plugs can come before or after the socket is used.
+    let cddl_input = r#"
+        $reading /= tstr
+        thing = [* $reading]
+        $reading /= int / float
+    "#;
+    validate_cbor_bytes("thing", cddl_input, b"\x83\x00\x61a\xf9\x3c\x00").unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, b"\x82\x00\xf5").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 3 (tstr / int / float) at [1])"
+    );
+    validate_cbor_bytes("$reading", cddl_input, cbor::NINT_1000).unwrap();
+    let err = validate_cbor_bytes("$reading", cddl_input, cbor::BOOL_TRUE).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 3 (tstr / int / float))"
+    );
+
+    // A socket with no plugs matches nothing.
+    let cddl_input = r#"thing = int / $extra"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_1).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (int / $extra (an empty socket)))"
+    );
+    let cddl_input = r#"thing = [int, * $extra]"#;
+    validate_cbor_bytes("thing", cddl_input, b"\x81\x01").unwrap();
+    let cddl_input = r#"thing = $extra"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_1).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected $extra (an empty socket))"
+    );
+
+    // Rules that aren't sockets still need to be defined.
+    let cddl_input = r#"thing = int / extra"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).err_missing_rule();
 }
 
 #[test]
@@ -141,6 +421,31 @@ fn validate_cbor_integer() {
     validate_cbor_bytes("thing", cddl_input, cbor::INT_24).unwrap_err();
 }
 
+#[test]
+fn validate_cbor_number() {
+    let cddl_input = r#"thing = number"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_0).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_1T).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::NINT_1000).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::FLOAT_1_0).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::FLOAT_1E300).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected number)");
+    validate_cbor_bytes("thing", cddl_input, cbor::BOOL_TRUE).err_mismatch();
+    validate_cbor_bytes("thing", cddl_input, cbor::NULL).err_mismatch();
+
+    // Floats are checked by value, so every width of float matches each of
+    // the sized float types.
+    for name in &["float16", "float32", "float64", "float16-32", "float32-64"] {
+        let cddl_input = format!("thing = {}", name);
+        validate_cbor_bytes("thing", &cddl_input, cbor::FLOAT_1_0).unwrap();
+        validate_cbor_bytes("thing", &cddl_input, cbor::FLOAT_1E5).unwrap();
+        validate_cbor_bytes("thing", &cddl_input, cbor::FLOAT_1E300).unwrap();
+        let err = validate_cbor_bytes("thing", &cddl_input, cbor::INT_1).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected float)");
+    }
+}
+
 #[test]
 fn validate_cbor_ranges() {
     let cddl_input = r#"thing = 1..5"#;
@@ -182,6 +487,112 @@ fn validate_cbor_ranges() {
     let cddl_input = r#"thing = 1..[5]"#;
     let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_1).unwrap_err();
     assert_eq!(err.to_string(), "Structural(bad type on range operator)");
+
+    // Ranges are only defined between numbers.
+    let cddl_input = r#"thing = "a".."z""#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Structural(non-numeric range operator ("a"))"#
+    );
+
+    let cddl_input = r#"thing = h'00'...h'ff'"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::BYTES_1234).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Structural(non-numeric range operator (h'00'))"
+    );
+
+    let cddl_input = r#"first = "a"  thing = 0..first"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_1).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Structural(non-numeric range operator ("a"))"#
+    );
+}
+
+#[test]
+fn validate_cbor_int_boundaries() {
+    use cddl_cat::cbor::validate_cbor_hex;
+
+    const UINT_MAX: &str = "1bffffffffffffffff"; // 2^64-1
+    const I64_MIN: &str = "3b7fffffffffffffff"; // -2^63
+    const I64_MIN_MINUS_1: &str = "3b8000000000000000"; // -2^63-1
+    const NINT_MIN: &str = "3bffffffffffffffff"; // -2^64
+
+    // Plain literals on both sides of i64::MIN, and at the bottom of the
+    // CBOR nint range.
+    let cddl_input = r#"thing = -9223372036854775808"#;
+    validate_cbor_hex("thing", cddl_input, I64_MIN).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN_MINUS_1).unwrap_err();
+    let cddl_input = r#"thing = -9223372036854775809"#;
+    validate_cbor_hex("thing", cddl_input, I64_MIN_MINUS_1).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN).unwrap_err();
+    let cddl_input = r#"thing = -18446744073709551616"#;
+    validate_cbor_hex("thing", cddl_input, NINT_MIN).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN).unwrap_err();
+    let cddl_input = r#"thing = 18446744073709551615"#;
+    validate_cbor_hex("thing", cddl_input, UINT_MAX).unwrap();
+
+    // Below -2^64 isn't representable in CBOR, so it's rejected.
+    validate_cbor_hex("thing", "thing = -18446744073709551617", NINT_MIN).err_parse();
+
+    // Range endpoints
+    let cddl_input = r#"thing = -18446744073709551616..-9223372036854775809"#;
+    validate_cbor_hex("thing", cddl_input, NINT_MIN).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN_MINUS_1).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN).unwrap_err();
+    let cddl_input = r#"thing = -18446744073709551616...-9223372036854775809"#;
+    validate_cbor_hex("thing", cddl_input, I64_MIN_MINUS_1).unwrap_err();
+    let cddl_input = r#"thing = -9223372036854775808..18446744073709551615"#;
+    validate_cbor_hex("thing", cddl_input, I64_MIN).unwrap();
+    validate_cbor_hex("thing", cddl_input, UINT_MAX).unwrap();
+    validate_cbor_hex("thing", cddl_input, I64_MIN_MINUS_1).unwrap_err();
+    let cddl_input = r#"thing = -18446744073709551617..0"#;
+    validate_cbor_hex("thing", cddl_input, I64_MIN).err_parse();
+    let cddl_input = r#"min=-18446744073709551616 thing = min .. 0"#;
+    validate_cbor_hex("thing", cddl_input, NINT_MIN).unwrap();
+
+    // Control operands
+    let cddl_input = r#"thing = bstr .size -18446744073709551616"#;
+    let err = validate_cbor_hex("thing", cddl_input, "40").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Structural(bad .size limit -18446744073709551616)"
+    );
+    let cddl_input = r#"thing = bstr .size (0..18446744073709551615)"#;
+    validate_cbor_hex("thing", cddl_input, "40").unwrap();
+    let cddl_input = r#"thing = uint .size 18446744073709551615"#;
+    validate_cbor_hex("thing", cddl_input, "00").unwrap();
+}
+
+#[test]
+fn validate_cbor_prelude_int_boundaries() {
+    use cddl_cat::cbor::validate_cbor_hex;
+
+    let cases = [
+        // (cbor, uint, nint)
+        ("1b7fffffffffffffff", true, false), // 2^63-1
+        ("1b8000000000000000", true, false), // 2^63
+        ("1bffffffffffffffff", true, false), // 2^64-1
+        ("00", true, false),                 // 0
+        ("20", false, true),                 // -1
+        ("3b7fffffffffffffff", false, true), // -2^63
+        ("3b8000000000000000", false, true), // -2^63-1
+        ("3bffffffffffffffff", false, true), // -2^64
+    ];
+    for &(cbor_hex, uint, nint) in &cases {
+        let check = |cddl_input: &str, expected: bool| {
+            let result = validate_cbor_hex("thing", cddl_input, cbor_hex);
+            assert_eq!(result.is_ok(), expected, "{} {}", cddl_input, cbor_hex);
+        };
+        check("thing = int", true);
+        check("thing = uint", uint);
+        check("thing = nint", nint);
+    }
+
+    // A bignum (tag 2) isn't an int, even if the value would fit.
+    validate_cbor_hex("thing", "thing = int", "c24101").err_mismatch();
 }
 
 #[test]
@@ -211,6 +622,96 @@ fn validate_cbor_bytestring() {
     validate_cbor_bytes("thing", cddl_input, cbor::BYTES_1234).unwrap();
 }
 
+// Schemas that use text and byte strings in as many ways as possible, with
+// the canonical names; `string_aliases` also tries them with the aliases.
+const STRING_SCHEMAS: &[&str] = &[
+    "thing = tstr",
+    "thing = bstr",
+    r#"thing = tstr / "x" / 'x'"#,
+    "thing = tstr .size 3",
+    "thing = bstr .size (1..4)",
+    #[cfg(feature = "regex")]
+    r#"thing = tstr .regexp "^I""#,
+    r#"thing = bstr .regexp "^I""#,
+    "thing = tstr .. tstr",
+    "thing = bstr ... 1",
+    "thing = { * tstr => uint }",
+    "thing = { bstr => tstr }",
+    "thing = { ? tstr ^ => uint, * bstr => any }",
+    "thing = [* tstr / bstr]",
+    "thing = [bstr, tstr]",
+    "thing = bstr .cbor uint",
+    "thing = bstr .bits 1",
+    "thing = tstr .bits 1",
+    "thing = pair<tstr>  pair<T> = [T, T]",
+    "thing = pair<bstr .size 1>  pair<T> = [T, T]",
+    "thing = { key }  key = (tstr => bstr)",
+];
+
+#[test]
+fn string_aliases() {
+    let samples: &[&[u8]] = &[
+        cbor::TEXT_EMPTY,
+        cbor::TEXT_IETF,
+        cbor::TEXT_CJK,
+        cbor::BYTES_EMPTY,
+        cbor::BYTES_1234,
+        cbor::CBOR_INT_23,
+        cbor::INT_0,
+        cbor::ARRAY_EMPTY,
+        b"\x82\x61I\x62IE",      // ["I", "IE"]
+        b"\x82\x41\x01\x61x",    // [h'01', "x"]
+        b"\x82\x41\x01\x41\x02", // [h'01', h'02']
+        b"\xa1\x61a\x01",        // {"a": 1}
+        b"\xa1\x41\x01\x61x",    // {h'01': "x"}
+        b"\xa1\x61a\x41\x01",    // {"a": h'01'}
+    ];
+    let outcome = |cddl: &str, cbor: &[u8]| match validate_cbor_bytes("thing", cddl, cbor) {
+        Ok(()) => "ok".to_string(),
+        Err(e) => e.to_string(),
+    };
+    let lints = |cddl: &str| {
+        cddl_cat::lint::lint_cddl(cddl)
+            .unwrap()
+            .iter()
+            .map(|w| format!("{}: {}", w.kind().name(), w.message()))
+            .collect::<Vec<_>>()
+    };
+
+    for canonical in STRING_SCHEMAS {
+        let alias = canonical.replace("tstr", "text").replace("bstr", "bytes");
+        let mut matched = 0;
+        for cbor in samples {
+            let expected = outcome(canonical, cbor);
+            assert_eq!(
+                outcome(&alias, cbor),
+                expected,
+                "{} with {:02x?}",
+                alias,
+                cbor
+            );
+            if expected == "ok" {
+                matched += 1;
+            }
+        }
+        // Make sure the schema tests something other than its errors, unless
+        // it's meant to be rejected.
+        let rejected = ["..", "...", "bstr .regexp", "tstr .bits"];
+        if !rejected.iter().any(|r| canonical.contains(r)) {
+            assert!(matched > 0, "nothing matched {}", canonical);
+        }
+        assert_eq!(lints(&alias), lints(canonical), "{}", alias);
+    }
+
+    // The lints that look at key types treat the aliases the same too.
+    let shadowed = lints(r#"thing = { * tstr => any, "name": text }"#);
+    assert_eq!(
+        shadowed,
+        lints(r#"thing = { * text => any, "name": tstr }"#)
+    );
+    assert_eq!(shadowed.len(), 1);
+}
+
 #[test]
 fn validate_cbor_array() {
     let cddl_input = r#"thing = []"#;
@@ -259,7 +760,7 @@ fn validate_cbor_homogenous_array() {
     let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_EMPTY).unwrap_err();
     assert_eq!(
         err.to_string(),
-        "Mismatch(expected more array element [+ Int])"
+        "Mismatch(expected more array element [+ Int] (found 0, need at least 1))"
     );
 
     let cddl_input = r#"thing = [? int]"#; // zero or one
@@ -271,14 +772,25 @@ fn validate_cbor_homogenous_array() {
 
     let cddl_input = r#"thing = [* tstr]"#;
     let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
-    // FIXME: this error message is confusing.
-    // Having consumed 0 tstr, we find that the array still has values.
-    assert_eq!(err.to_string(), "Mismatch(expected shorter array)");
+    // Having consumed 0 tstr, we find that the array still has values;
+    // the first one explains why.
+    assert_eq!(err.to_string(), "Mismatch(expected tstr at [0])");
 
     // Alias type.
     let cddl_input = r#"thing = [* zipcode]  zipcode = int"#;
     validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_EMPTY).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap();
+
+    // Occurrence limits larger than 2^32 should work on all targets.
+    let cddl_input = r#"thing = [0*5000000000 int]"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_EMPTY).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap();
+    let cddl_input = r#"thing = [5000000000* int]"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected more array element [5000000000* Int] (found 3, need at least 5000000000))"
+    );
 }
 
 #[test]
@@ -370,10 +882,22 @@ fn validate_cbor_array_unwrap() {
     let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
     assert_eq!(err.to_string(), "MissingRule(footer)");
 
-    // Unwrapping a map into an array isn't allowed.
+    // A map can be unwrapped into an array; its keys are ignored.
     let cddl_input = r#"header = {a: int, b: int} thing = [~header c: int]"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap();
+    let cddl_input = r#"point = {x: int, y: int} full = [~point, z: int]"#;
+    validate_cbor_bytes("full", cddl_input, cbor::ARRAY_123).unwrap();
+    validate_cbor_bytes("full", cddl_input, cbor::ARRAY_12).err_mismatch();
+    let cddl_input = r#"point = {x: int, * tstr => tstr} full = [~point, z: int]"#;
+    validate_cbor_bytes("full", cddl_input, b"\x83\x01\x61a\x03").unwrap();
+
+    // Anything else can't be unwrapped.
+    let cddl_input = r#"header = int thing = [~header c: int]"#;
     let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected unwrap array)");
+    assert_eq!(
+        err.to_string(),
+        "Structural(unwrapped rule in an array isn't an array or map)"
+    );
 }
 
 #[test]
@@ -432,10 +956,20 @@ fn validate_cbor_map_unwrap() {
     let cddl_input = r#"thing = {name: tstr, ~agroup} agroup = {age: int}"#;
     validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap();
 
+    // {"x": 1, "y": 2, "z": 3}
+    let point_bytes = b"\xa3\x61x\x01\x61y\x02\x61z\x03";
+    let cddl_input = r#"point = {x: int, y: int} full = {~point, z: int}"#;
+    validate_cbor_bytes("full", cddl_input, point_bytes).unwrap();
+    let cddl_input = r#"point = {x: int, y: int} full = {~point}"#;
+    validate_cbor_bytes("full", cddl_input, point_bytes).err_mismatch();
+
     // Unwrapping an array into a map isn't allowed.
     let cddl_input = r#"thing = {name: tstr, ~agroup} agroup = [age: int]"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected unwrap map)");
+    assert_eq!(
+        err.to_string(),
+        "Structural(unwrapped rule in a map isn't a map)"
+    );
 }
 
 #[test]
@@ -461,13 +995,23 @@ fn validate_cbor_map_group() {
         r#"thing = {agroup maybe} agroup = (age: int, name: tstr) maybe = (? minor: bool)"#;
     validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap();
 
+    // The group's occurrence applies to all of its members.
+    let cddl_input = r#"thing = {name: tstr, age: int, ? agroup} agroup = (minor: bool)"#;
+    validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap();
+    let cddl_input = r#"thing = {name: tstr, ? agroup} agroup = (age: int, minor: bool)"#;
+    validate_cbor_bytes("thing", cddl_input, &cbor_bytes).err_mismatch();
+
+    let cddl_input = r#"thing = {name: tstr, agroup}"#;
+    let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
+    assert_eq!(err.to_string(), "MissingRule(agroup)");
+
     let cddl_input = r#"thing = {name: tstr, agroup} agroup = (wrong: int)"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), r#"Mismatch(expected map{"wrong"})"#);
+    assert_eq!(err.to_string(), r#"MissingMapKey("wrong": Int)"#);
 
     let cddl_input = r#"thing = {name: tstr, agroup} agroup = (age: bool)"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected bool)");
+    assert_eq!(err.to_string(), r#"Mismatch(expected bool at ["age"])"#);
 
     // This is constructed to require backtracking by the validator:
     // `foo` will consume `age` before failing; we need to rewind to
@@ -508,18 +1052,18 @@ fn validate_cbor_map() {
     validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap();
     let cddl_input = r#"thing = {name: tstr, age: int, + minor: bool}"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(
-        err.to_string(),
-        r#"Mismatch(expected map{+ "minor": Bool}])"#
-    );
+    assert_eq!(err.to_string(), r#"MissingMapKey("minor": Bool)"#);
 
     let cddl_input = r#"thing = {name: tstr, age: tstr}"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected tstr)");
+    assert_eq!(err.to_string(), r#"Mismatch(expected tstr at ["age"])"#);
 
     let cddl_input = r#"thing = {name: tstr}"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected shorter map)");
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected shorter map at ["age"])"#
+    );
 
     // "* keytype => valuetype" is the expected syntax for collecting
     // any remaining key/value pairs of the expected type.
@@ -536,11 +1080,14 @@ fn validate_cbor_map() {
     // collected because the key type doesn't match.
     let cddl_input = r#"thing = {* int => any}"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected shorter map)");
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected shorter map at ["age"])"#
+    );
 
     let cddl_input = r#"thing = {name: tstr, age: int, minor: bool}"#;
     let err = validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap_err();
-    assert_eq!(err.to_string(), r#"Mismatch(expected map{"minor"})"#);
+    assert_eq!(err.to_string(), r#"MissingMapKey("minor": Bool)"#);
 
     let cddl_input = r#"thing = {x: int, y: int, z: int}"#;
     validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
@@ -566,6 +1113,190 @@ struct Pickup {
     per_pickup: bool,
 }
 
+#[test]
+fn validate_cbor_structured_keys() {
+    use serde_cbor::Value as CBOR_Value;
+
+    fn coord(x: i128, y: i128) -> CBOR_Value {
+        CBOR_Value::Array(vec![CBOR_Value::Integer(x), CBOR_Value::Integer(y)])
+    }
+    fn text(s: &str) -> CBOR_Value {
+        CBOR_Value::Text(s.into())
+    }
+    fn check(cddl_input: &str, entries: Vec<(CBOR_Value, CBOR_Value)>) -> ValidateResult {
+        let map = CBOR_Value::Map(entries.into_iter().collect());
+        let cbor_bytes = serde_cbor::to_vec(&map).unwrap();
+        validate_cbor_bytes("lookup", cddl_input, &cbor_bytes)
+    }
+
+    let cddl_input = "lookup = { coord => tstr }  coord = [int, int]";
+    check(cddl_input, vec![(coord(1, 2), text("a"))]).unwrap();
+    check(cddl_input, vec![(text("a"), text("a"))]).err_missing_map_key();
+    // A key of the right shape, with a bad element.
+    let bad_key = CBOR_Value::Array(vec![CBOR_Value::Integer(1), text("x")]);
+    let err = check(cddl_input, vec![(bad_key.clone(), text("a"))]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected int at [1] in map key at [[1, "x"]])"#
+    );
+    // The key matched, so the value's mismatch is reported.
+    let err = check(cddl_input, vec![(coord(1, 2), CBOR_Value::Integer(3))]).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected tstr at [[1, 2]])");
+
+    // Several keys may match, up to the occurrence limits.
+    let cddl_input = "lookup = { 1*2 coord => tstr }  coord = [int, int]";
+    check(cddl_input, vec![(coord(1, 2), text("a"))]).unwrap();
+    check(
+        cddl_input,
+        vec![(coord(1, 2), text("a")), (coord(2, 1), text("b"))],
+    )
+    .unwrap();
+    let three = vec![
+        (coord(1, 2), text("a")),
+        (coord(2, 1), text("b")),
+        (coord(2, 2), text("c")),
+    ];
+    check(cddl_input, three.clone()).err_mismatch();
+    check("lookup = { * coord => tstr }  coord = [int, int]", three).unwrap();
+
+    // A leftover key that almost matched explains itself.
+    let cddl_input = "lookup = { * coord => tstr }  coord = [int, int]";
+    let err = check(
+        cddl_input,
+        vec![(coord(1, 2), text("a")), (bad_key, text("b"))],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected int at [1] in map key at [[1, "x"]])"#
+    );
+    // Other keys don't mix up with structured ones.
+    let cddl_input = "lookup = { * coord => tstr, * tstr => int }  coord = [int, int]";
+    check(
+        cddl_input,
+        vec![
+            (coord(1, 2), text("a")),
+            (text("b"), CBOR_Value::Integer(2)),
+        ],
+    )
+    .unwrap();
+
+    // Map-valued keys work the same way.
+    let cddl_input = "lookup = { point => tstr }  point = { x: int, y: int }";
+    let point = |entries: Vec<(&str, CBOR_Value)>| {
+        CBOR_Value::Map(entries.into_iter().map(|(k, v)| (text(k), v)).collect())
+    };
+    let good = point(vec![
+        ("x", CBOR_Value::Integer(1)),
+        ("y", CBOR_Value::Integer(2)),
+    ]);
+    check(cddl_input, vec![(good, text("a"))]).unwrap();
+    let missing_y = point(vec![("x", CBOR_Value::Integer(1))]);
+    let err = check(cddl_input, vec![(missing_y, text("a"))]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected "y": Int in map key at [{"x": 1}])"#
+    );
+}
+
+#[test]
+fn validate_cbor_array_keys_and_values() {
+    use serde_cbor::Value as CBOR_Value;
+
+    fn text(s: &str) -> CBOR_Value {
+        CBOR_Value::Text(s.into())
+    }
+    fn route(from: &str, to: &str) -> CBOR_Value {
+        CBOR_Value::Array(vec![text(from), text(to)])
+    }
+    fn hop(addr: &str, cost: CBOR_Value) -> CBOR_Value {
+        let entries = vec![(text("addr"), text(addr)), (text("cost"), cost)];
+        CBOR_Value::Map(entries.into_iter().collect())
+    }
+    fn hops(hops: Vec<CBOR_Value>) -> CBOR_Value {
+        CBOR_Value::Array(hops)
+    }
+    fn check(cddl_input: &str, entries: Vec<(CBOR_Value, CBOR_Value)>) -> ValidateResult {
+        let map = CBOR_Value::Map(entries.into_iter().collect());
+        let cbor_bytes = serde_cbor::to_vec(&map).unwrap();
+        validate_cbor_bytes("routes", cddl_input, &cbor_bytes)
+    }
+    let good_hop = || hop("x", CBOR_Value::Integer(1));
+
+    let cddl_input = "routes = { [tstr, tstr] => [* hop] }  hop = { addr: tstr, cost: uint }";
+    check(cddl_input, vec![(route("a", "b"), hops(vec![]))]).unwrap();
+    check(
+        cddl_input,
+        vec![(route("a", "b"), hops(vec![good_hop(), good_hop()]))],
+    )
+    .unwrap();
+
+    // A bad hop is found through both the key and the index.
+    let bad_hop = hop("y", text("high"));
+    let err = check(
+        cddl_input,
+        vec![(route("a", "b"), hops(vec![good_hop(), bad_hop.clone()]))],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected uint at [["a", "b"]][1]["cost"])"#
+    );
+    assert_eq!(err.schema_name(), Some("hop.cost"));
+
+    // A key with the wrong number of elements is left over, and the error
+    // leads to it.
+    let short = CBOR_Value::Array(vec![text("a")]);
+    let long = CBOR_Value::Array(vec![text("a"), text("b"), text("c")]);
+    for (key, key_text) in vec![(short, r#"["a"]"#), (long, r#"["a", "b", "c"]"#)] {
+        let err = check(
+            cddl_input,
+            vec![(route("a", "b"), hops(vec![])), (key, hops(vec![]))],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Mismatch(expected shorter map at [{}])", key_text)
+        );
+    }
+    // Keys are compared as whole arrays, so a second route is left over
+    // too.
+    let err = check(
+        cddl_input,
+        vec![
+            (route("a", "b"), hops(vec![])),
+            (route("b", "a"), hops(vec![])),
+        ],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected shorter map at [["b", "a"]])"#
+    );
+
+    // As a table, every route is checked.
+    let cddl_input = "routes = { * [tstr, tstr] => [* hop] }  hop = { addr: tstr, cost: uint }";
+    let table = vec![
+        (route("a", "b"), hops(vec![good_hop()])),
+        (route("b", "a"), hops(vec![])),
+    ];
+    check(cddl_input, table.clone()).unwrap();
+    let mut bad_table = table.clone();
+    bad_table.push((route("b", "c"), hops(vec![bad_hop])));
+    let err = check(cddl_input, bad_table).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected uint at [["b", "c"]][0]["cost"])"#
+    );
+    let mut bad_table = table;
+    bad_table.push((CBOR_Value::Array(vec![text("c")]), hops(vec![])));
+    let err = check(cddl_input, bad_table).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected shorter map at [["c"]])"#
+    );
+}
+
 #[test]
 fn validate_choice_example() {
     // This is an example from RFC8610 2.2.2
@@ -631,6 +1362,67 @@ fn validate_choiceify_example() {
     validate_cbor_bytes("extended-color", cddl_input, cbor::INT_23).err_mismatch();
 }
 
+#[derive(Debug, Serialize)]
+enum Shape {
+    Circle(u32),
+    Rect { width: u32, height: u32 },
+    Empty,
+}
+
+#[derive(Debug, Serialize)]
+enum WrongShape {
+    Circle(String),
+    Rect { width: u32 },
+    Triangle(u32),
+}
+
+#[test]
+fn validate_cbor_enum() {
+    let cddl_input = r#"
+        shape = { "Circle": uint } / { "Rect": rect } / "Empty"
+        rect = { width: uint, height: uint }
+    "#;
+    let cut_input = r#"
+        shape = { "Circle" ^ => uint } / { "Rect" ^ => rect } / "Empty"
+        rect = { width: uint, height: uint }
+    "#;
+
+    for input in &[cddl_input, cut_input] {
+        let shapes = [
+            Shape::Circle(5),
+            Shape::Rect {
+                width: 3,
+                height: 4,
+            },
+            Shape::Empty,
+        ];
+        for shape in &shapes {
+            let cbor_bytes = serde_cbor::to_vec(shape).unwrap();
+            validate_cbor_bytes("shape", input, &cbor_bytes).unwrap();
+        }
+
+        // When the data only matches one variant's key, the error should come
+        // from the value of that variant.
+        let cbor_bytes = serde_cbor::to_vec(&WrongShape::Circle("x".into())).unwrap();
+        let err = validate_cbor_bytes("shape", input, &cbor_bytes).unwrap_err();
+        assert_eq!(err.to_string(), r#"Mismatch(expected uint at ["Circle"])"#);
+
+        let cbor_bytes = serde_cbor::to_vec(&WrongShape::Rect { width: 3 }).unwrap();
+        let err = validate_cbor_bytes("shape", input, &cbor_bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"MissingMapKey("height": Uint) at ["Rect"]"#
+        );
+
+        let cbor_bytes = serde_cbor::to_vec(&WrongShape::Triangle(3)).unwrap();
+        let err = validate_cbor_bytes("shape", input, &cbor_bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Mismatch(expected choice of 3 (missing "Circle": Uint / missing "Rect": rect / "Empty"))"#
+        );
+    }
+}
+
 #[test]
 fn test_fatal_propagation() {
     // Ensure that standalone choices can't conceal fatal errors.
@@ -658,17 +1450,27 @@ fn test_fatal_propagation() {
 
 #[test]
 fn cbor_control_size() {
-    let cddl_input = r#"thing = bstr .size 3"#;
-    validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).unwrap();
-    validate_cbor_bytes("thing", cddl_input, cbor::BYTES_1234).err_mismatch();
+    // A single size is the exact size of a string.
+    let cddl_input = r#"thing = bstr .size 4"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::BYTES_1234).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected bstr under .size limit)");
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_EMPTY).err_mismatch();
-    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_CJK).err_mismatch();
+    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).err_mismatch();
+    let cddl_input = r#"thing = bstr .size 3"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::BYTES_1234).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected bstr over .size limit)");
 
     let cddl_input = r#"thing = tstr .size 3"#;
-    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_EMPTY).unwrap();
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_CJK).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_EMPTY).err_mismatch();
     validate_cbor_bytes("thing", cddl_input, cbor::TEXT_IETF).err_mismatch();
     validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).err_mismatch();
+    let cddl_input = r#"thing = tstr .size (0..3)"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_EMPTY).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::TEXT_CJK).unwrap();
+
+    // For an integer, it's the most bytes it may need.
 
     let cddl_input = r#"thing = uint .size 3"#;
     validate_cbor_bytes("thing", cddl_input, cbor::INT_0).unwrap();
@@ -693,6 +1495,68 @@ fn cbor_control_size() {
     validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).unwrap_err();
 }
 
+#[test]
+fn cbor_control_bits() {
+    let cddl_input = r#"thing = uint .bits rwx  rwx = &(r: 2, w: 1, x: 0)"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_0).unwrap();
+    validate_cbor_bytes("thing", cddl_input, b"\x07").unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_9).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected uint with allowed .bits (found bit 3))"
+    );
+    validate_cbor_bytes("thing", cddl_input, cbor::NINT_1000).err_mismatch();
+    validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).err_mismatch();
+
+    // Bits in a byte string are numbered from the first byte.
+    let cddl_input = r#"thing = bstr .bits (0 / 8..10)"#;
+    validate_cbor_bytes("thing", cddl_input, cbor::BYTES_EMPTY).unwrap();
+    validate_cbor_bytes("thing", cddl_input, b"\x42\x01\x07").unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, b"\x42\x01\x08").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected bstr with allowed .bits (found bit 11))"
+    );
+    validate_cbor_bytes("thing", cddl_input, b"\x42\x02\x00").err_mismatch();
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_0).err_mismatch();
+
+    // The same rule can describe one flag, or a set of them (RFC 8610 3.8.2).
+    let cddl_input = r#"
+        packet = { kind: tcpflags, set: uint .bits tcpflags, bytes: bstr .bits tcpflags }
+        tcpflags = &(fin: 8, syn: 9, rst: 10, ns: 0) / (4..7)
+    "#;
+    #[derive(Serialize)]
+    struct Packet {
+        kind: u32,
+        set: u32,
+        #[serde(with = "bytes")]
+        bytes: Vec<u8>,
+    }
+    mod bytes {
+        pub fn serialize<S: serde::Serializer>(b: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(b)
+        }
+    }
+    let check = |kind, set, bytes: &[u8]| {
+        let packet = Packet {
+            kind,
+            set,
+            bytes: bytes.to_vec(),
+        };
+        validate_cbor_bytes("packet", cddl_input, &serde_cbor::to_vec(&packet).unwrap())
+    };
+    check(9, 0x301, &[0x51, 0x03]).unwrap();
+    check(5, 0xf0, &[0xf0]).unwrap();
+    check(3, 0, &[]).err_mismatch();
+    check(9, 0x302, &[]).err_mismatch();
+    check(9, 0, &[0x00, 0x08]).err_mismatch();
+
+    // The target must be uint or bstr.
+    let cddl_input = r#"thing = tstr .bits 1"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::TEXT_EMPTY).unwrap_err();
+    assert_eq!(err.to_string(), "Structural(bad .bits target type (Tstr))");
+}
+
 #[test]
 fn cbor_control_cbor() {
     let cddl_input = r#"thing = bytes .cbor uint"#;
@@ -711,12 +1575,36 @@ fn cbor_control_cbor() {
     validate_cbor_bytes("thing", cddl_input, cbor::CBOR_INT_23).unwrap();
 }
 
+#[test]
+fn cbor_control_default() {
+    // The default value doesn't change what matches.
+    let cddl_input = "thing = uint .default 3";
+    validate_cbor_bytes("thing", cddl_input, cbor::INT_0).unwrap();
+    validate_cbor_bytes("thing", cddl_input, cbor::NINT_1000).err_mismatch();
+
+    let cddl_input = r#"thing = { ? retries: uint .default 3 }"#;
+    // {}
+    validate_cbor_bytes("thing", cddl_input, b"\xa0").unwrap();
+    // {"retries": 1}
+    validate_cbor_bytes("thing", cddl_input, b"\xa1\x67retries\x01").unwrap();
+    // {"retries": "x"}
+    validate_cbor_bytes("thing", cddl_input, b"\xa1\x67retries\x61x").err_mismatch();
+
+    // Only literal defaults are supported.
+    let err = validate_cbor_bytes("thing", "thing = uint .default other", cbor::INT_0);
+    assert_eq!(
+        err.unwrap_err().to_string(),
+        "Unsupported .default value type"
+    );
+}
+
 #[track_caller]
 fn validate_cbor_tstr(name: &str, cddl: &str, input: &str) -> ValidateResult {
     let cbor_bytes = serde_cbor::to_vec(&input).unwrap();
     validate_cbor_bytes(name, cddl, &cbor_bytes)
 }
 
+#[cfg(feature = "regex")]
 #[test]
 fn cbor_control_regexp() {
     // Should match strings that look like integers with no leading zeroes.
@@ -755,4 +1643,1177 @@ fn cbor_control_regexp() {
     // This is an example from RFC8610 2.2.2
     let cddl_input = r#" nai = tstr .regexp "[A-Za-z0-9]+@[A-Za-z0-9]+(\\.[A-Za-z0-9]+)+" "#;
     validate_cbor_tstr("nai", cddl_input, "N1@CH57HF.4Znqe0.dYJRN.igjf").unwrap();
+
+    // Escapes in the pattern.
+    let cddl_input = r#" ip = tstr .regexp "\\d+\\.\\d+\\.\\d+\\.\\d+" "#;
+    validate_cbor_tstr("ip", cddl_input, "192.168.0.1").unwrap();
+    validate_cbor_tstr("ip", cddl_input, "192.168.0").err_mismatch();
+    validate_cbor_tstr("ip", cddl_input, "192x168x0x1").err_mismatch();
+
+    // A pattern that doesn't compile is an error in the schema, found
+    // when it's flattened.
+    let cddl_input = r#" pat = tstr .regexp "(ab" "#;
+    let err = validate_cbor_tstr("pat", cddl_input, "ab").unwrap_err();
+    assert_eq!(err.to_string(), "Structural(malformed regexp)");
+    let schema = cddl_cat::schema::Schema::from_str(cddl_input).unwrap();
+    let errors = schema.flatten_errors();
+    assert_eq!(errors["pat"].to_string(), "Structural(malformed regexp)");
+}
+
+#[test]
+fn cbor_owned_value() {
+    use cddl_cat::cbor::validate_cbor_owned;
+    use serde_cbor::Value as CBOR_Value;
+    use std::convert::TryFrom;
+
+    let cddl_input = r#"thing = { "a": [* bstr], "b": any, * tstr => tstr }"#;
+    // {"a": [h'0102'], "b": 1(2), "c": "d"}
+    let cbor_bytes = b"\xa3\x61a\x81\x42\x01\x02\x61b\xc1\x02\x61c\x61d";
+    let value: CBOR_Value = serde_cbor::from_slice(cbor_bytes).unwrap();
+    validate_cbor_owned("thing", cddl_input, value).unwrap();
+
+    // The owned and borrowed conversions agree.
+    let value: CBOR_Value = serde_cbor::from_slice(cbor_bytes).unwrap();
+    let borrowed = cddl_cat::value::Value::try_from(&value).unwrap();
+    let owned = cddl_cat::value::Value::try_from(value).unwrap();
+    assert_eq!(owned, borrowed);
+
+    let value = CBOR_Value::Bytes(vec![1, 2]);
+    let err = validate_cbor_owned("thing", cddl_input, value).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected map)");
+    let value = CBOR_Value::Null;
+    validate_cbor_owned("other", cddl_input, value).err_missing_rule();
+}
+
+#[test]
+fn cbor_tagged_value() {
+    use cddl_cat::value::Value;
+    use serde_cbor::tags::Tagged;
+    use serde_cbor::Value as CBOR_Value;
+    use std::convert::TryFrom;
+
+    // 32("a"), nested in 1(...)
+    let tagged = Tagged::new(Some(1), Tagged::new(Some(32), "a"));
+    let cbor_bytes = serde_cbor::to_vec(&tagged).unwrap();
+    assert_eq!(cbor_bytes, b"\xc1\xd8\x20\x61a");
+    let cbor_value: CBOR_Value = serde_cbor::from_slice(&cbor_bytes).unwrap();
+    let expected = Value::Tag(
+        1,
+        Box::new(Value::Tag(32, Box::new(Value::Text("a".into())))),
+    );
+    assert_eq!(Value::try_from(&cbor_value).unwrap(), expected);
+    assert_eq!(Value::try_from(cbor_value).unwrap(), expected);
+
+    // A tag with no number is the same as no tag at all.
+    let untagged = Tagged::new(None, "a");
+    let cbor_bytes = serde_cbor::to_vec(&untagged).unwrap();
+    let cbor_value: CBOR_Value = serde_cbor::from_slice(&cbor_bytes).unwrap();
+    assert_eq!(
+        Value::try_from(cbor_value).unwrap(),
+        Value::Text("a".into())
+    );
+
+    let cddl_input = "thing = #6.1(#6.32(tstr))";
+    validate_cbor_bytes("thing", cddl_input, b"\xc1\xd8\x20\x61a").unwrap();
+    validate_cbor_bytes("thing", cddl_input, b"\xd8\x20\x61a").err_mismatch();
+}
+
+#[test]
+fn cbor_type_expr() {
+    use cddl_cat::cbor::{validate_cbor, validate_cbor_bytes_expr, validate_cbor_bytes_expr_with};
+    use cddl_cat::context::BasicContext;
+    use cddl_cat::flatten::{flatten_from_str, flatten_type_expr};
+
+    validate_cbor_bytes_expr("uint", cbor::INT_24).unwrap();
+    validate_cbor_bytes_expr("uint .size 1", cbor::INT_24).unwrap();
+    validate_cbor_bytes_expr("tstr", cbor::INT_24).err_mismatch();
+    validate_cbor_bytes_expr("[* uint]", cbor::ARRAY_123).unwrap();
+
+    let person = PersonStruct {
+        name: "Bob".to_string(),
+        age: 43,
+    };
+    let cbor_bytes = serde_cbor::to_vec(&person).unwrap();
+    validate_cbor_bytes_expr("{ name: tstr, * tstr => any }", &cbor_bytes).unwrap();
+    validate_cbor_bytes_expr("{ name: tstr }", &cbor_bytes).err_mismatch();
+
+    // Rule references can't be resolved without a rule map.
+    validate_cbor_bytes_expr("{ name: tstr, age: age }", &cbor_bytes).err_missing_rule();
+
+    // Supply a rule map to resolve the references.
+    let ctx = BasicContext::new(flatten_from_str("age = 0..150").unwrap());
+    let rule_def = flatten_type_expr("{ name: tstr, age: age }").unwrap();
+    let value = serde_cbor::from_slice(&cbor_bytes).unwrap();
+    validate_cbor(&rule_def, &value, &ctx).unwrap();
+    validate_cbor_bytes_expr_with("{ name: tstr, age: age }", &cbor_bytes, &ctx).unwrap();
+    validate_cbor_bytes_expr_with("{ name: tstr, age: name }", &cbor_bytes, &ctx)
+        .err_missing_rule();
+    let ctx = BasicContext::new(flatten_from_str("age = 0..10").unwrap());
+    validate_cbor_bytes_expr_with("{ name: tstr, age: age }", &cbor_bytes, &ctx).err_mismatch();
+
+    // Malformed expressions are parse errors.
+    validate_cbor_bytes_expr("x = uint", cbor::INT_24).err_parse();
+}
+
+#[test]
+fn cbor_missing_rule_suggestion() {
+    let cddl_input = r#"person = {name: tstr, age: int}"#;
+    let err = validate_cbor_bytes("Person", cddl_input, cbor::INT_0).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "MissingRule(Person); did you mean 'person'?"
+    );
+
+    // References discovered during validation get the same treatment.
+    let cddl_input = r#"thing = [* colour] color = tstr"#;
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::ARRAY_123).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "MissingRule(colour); did you mean 'color'?"
+    );
+}
+
+#[test]
+fn cbor_sequence() {
+    use cddl_cat::cbor::validate_cbor_seq_bytes;
+
+    // A sequence of 3 items: 1, "IETF", [1, 2, 3]
+    let seq = [cbor::INT_1, cbor::TEXT_IETF, cbor::ARRAY_123].concat();
+
+    // A type rule must match exactly one item.
+    validate_cbor_seq_bytes("thing", "thing = int", cbor::INT_1).unwrap();
+    validate_cbor_seq_bytes("thing", "thing = int", &seq).err_mismatch();
+
+    // A group rule can describe the whole sequence.
+    let cddl_input = r#"thing = (int, tstr, [* int])"#;
+    validate_cbor_seq_bytes("thing", cddl_input, &seq).unwrap();
+
+    // An occurrence on the root rule limits the number of items.
+    let cddl_input = r#"log = * entry  entry = int / tstr / [* int]"#;
+    validate_cbor_seq_bytes("log", cddl_input, &seq).unwrap();
+    validate_cbor_seq_bytes("log", cddl_input, b"").unwrap();
+
+    let cddl_input = r#"log = (1*2 entry)  entry = int / tstr / [* int]"#;
+    validate_cbor_seq_bytes("log", cddl_input, cbor::INT_1).unwrap();
+    let err = validate_cbor_seq_bytes("log", cddl_input, &seq).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected shorter sequence (sequence has 3 items))"
+    );
+    let err = validate_cbor_seq_bytes("log", cddl_input, b"").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected more array element [1*2 entry] (found 0, need 1 to 2) (sequence has 0 items))"
+    );
+
+    // Truncated CBOR says how much is missing.
+    let err = validate_cbor_seq_bytes("log", cddl_input, &seq[..seq.len() - 1]).unwrap_err();
+    assert!(matches!(err, ValidateError::Truncated(_)));
+}
+
+fn validate_cbor_tags(cddl_input: &str, cbor_bytes: &[u8], tags: TagPolicy) -> ValidateResult {
+    let mut options = ValidationOptions::default();
+    options.tags = tags;
+    let schema = Schema::from_str(cddl_input).unwrap().with_options(options);
+    let rule_def = schema.schema().rules().get("thing").unwrap();
+    let cbor_value = serde_cbor::from_slice(cbor_bytes).unwrap();
+    validate_cbor(rule_def, &cbor_value, &schema)
+}
+
+#[test]
+fn validate_cbor_tagged() {
+    use TagPolicy::*;
+
+    // 1(1000000000000)
+    let tag1_int = [b"\xc1", cbor::INT_1T].concat();
+    // 2(1000000000000)
+    let tag2_int = [b"\xc2", cbor::INT_1T].concat();
+    // 1("IETF")
+    let tag1_text = [b"\xc1", cbor::TEXT_IETF].concat();
+
+    let cddl_input = "thing = #6.1(uint)";
+    validate_cbor_bytes("thing", cddl_input, &tag1_int).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, &tag2_int).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected tag 1)");
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_1T).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected tag 1)");
+    let err = validate_cbor_bytes("thing", cddl_input, &tag1_text).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected uint)");
+
+    // Any tag number.
+    let cddl_input = "thing = #6(uint)";
+    validate_cbor_bytes("thing", cddl_input, &tag1_int).unwrap();
+    validate_cbor_bytes("thing", cddl_input, &tag2_int).unwrap();
+    let err = validate_cbor_bytes("thing", cddl_input, cbor::INT_1T).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected tagged value)");
+
+    // Any content.
+    validate_cbor_bytes("thing", "thing = #6.1", &tag1_text).unwrap();
+    validate_cbor_bytes("thing", "thing = #6.2", &tag1_text).err_mismatch();
+    validate_cbor_bytes("thing", "thing = #6", &tag1_text).unwrap();
+
+    // Tags removed by the tag policy are matched whether they were there
+    // or not.
+    let cddl_input = "thing = #6.1(uint)";
+    validate_cbor_tags(cddl_input, &tag1_int, Unwrap).unwrap();
+    validate_cbor_tags(cddl_input, cbor::INT_1T, Unwrap).unwrap();
+    validate_cbor_tags(cddl_input, cbor::INT_1T, Allow(vec![1])).unwrap();
+    validate_cbor_tags(cddl_input, cbor::INT_1T, Allow(vec![2])).err_mismatch();
+    validate_cbor_tags(cddl_input, &tag2_int, Unwrap).unwrap();
+    validate_cbor_tags(cddl_input, &tag2_int, Allow(vec![2])).err_mismatch();
+    validate_cbor_tags("thing = #6(uint)", cbor::INT_1T, Unwrap).unwrap();
+
+    // Tagged data inside other types.
+    let cddl_input = "thing = [* #6.1(uint)]";
+    validate_cbor_bytes("thing", cddl_input, &[b"\x81", &tag1_int[..]].concat()).unwrap();
+    validate_cbor_bytes("thing", cddl_input, &[b"\x81", &tag2_int[..]].concat()).err_mismatch();
+    let cddl_input = "thing = #6.1(uint) / #6.2(bytes)";
+    validate_cbor_bytes("thing", cddl_input, &tag1_int).unwrap();
+    validate_cbor_bytes("thing", cddl_input, &tag2_int).err_mismatch();
+    validate_cbor_bytes("thing", cddl_input, &[b"\xc2", cbor::BYTES_1234].concat()).unwrap();
+}
+
+#[test]
+fn validate_cbor_major_types() {
+    let cases: &[(&str, &[u8], bool)] = &[
+        ("#0", cbor::INT_1T, true),
+        ("#0", cbor::NINT_1000, false),
+        ("#1", cbor::NINT_1000, true),
+        ("#1", cbor::INT_0, false),
+        ("#2", cbor::BYTES_1234, true),
+        ("#2", cbor::TEXT_IETF, false),
+        ("#3", cbor::TEXT_IETF, true),
+        ("#3", cbor::BYTES_1234, false),
+        ("#4", b"\x82\x01\x61a", true),
+        ("#4", b"\xa0", false),
+        ("#5", b"\xa1\x01\x61a", true),
+        ("#5", b"\x80", false),
+        ("#6", b"\xc1\x01", true),
+        ("#6", cbor::INT_1, false),
+        ("#6.1", b"\xc1\x01", true),
+        ("#6.2", b"\xc1\x01", false),
+        ("#7", cbor::BOOL_TRUE, true),
+        ("#7", cbor::NULL, true),
+        ("#7", cbor::FLOAT_1_0, true),
+        ("#7", cbor::INT_0, false),
+        ("#7.21", cbor::BOOL_TRUE, true),
+        ("#7.20", cbor::BOOL_TRUE, false),
+        ("#7.22", cbor::NULL, true),
+        ("#7.25", cbor::FLOAT_1_0, true),
+        ("#", cbor::TEXT_IETF, true),
+        ("#", b"\xc1\x01", true),
+    ];
+    for (cddl_type, cbor_bytes, ok) in cases {
+        let cddl_input = format!("thing = {}", cddl_type);
+        let result = validate_cbor_bytes("thing", &cddl_input, cbor_bytes);
+        assert_eq!(result.is_ok(), *ok, "{} {:?}", cddl_type, result);
+    }
+
+    let schema = Schema::from_str("a = #0.1  b = #8").unwrap();
+    let errors = schema.flatten_errors();
+    assert_eq!(errors["a"].to_string(), "Unsupported major type #0.1");
+    assert_eq!(errors["b"].to_string(), "Structural(major type #8)");
+}
+
+#[test]
+fn cbor_tag_policy() {
+    use TagPolicy::*;
+
+    // 0("IETF")
+    let tag0_text = [b"\xc0", cbor::TEXT_IETF].concat();
+    // 1(0("IETF"))
+    let tag1_tag0_text = [b"\xc1", &tag0_text[..]].concat();
+
+    let cases: &[(&str, Vec<u8>)] = &[
+        // scalar
+        ("thing = tstr", tag0_text.clone()),
+        // array element
+        ("thing = [* tstr]", [b"\x81", &tag0_text[..]].concat()),
+        // map key, both literal and non-literal
+        (r#"thing = { "a": int }"#, b"\xa1\xc0\x61a\x01".to_vec()),
+        ("thing = { * tstr => int }", b"\xa1\xc0\x61a\x01".to_vec()),
+        // map value
+        (
+            r#"thing = { "a": tstr }"#,
+            [b"\xa1\x61a", &tag0_text[..]].concat(),
+        ),
+    ];
+    for (cddl_input, cbor_bytes) in cases {
+        // A tagged map key won't match, so it shows up as a missing key.
+        let check_err = |result: ValidateResult| match result {
+            Err(ValidateError::Mismatch(_)) | Err(ValidateError::MissingMapKey(_)) => {}
+            _ => panic!("{}: expected mismatch, got {:?}", cddl_input, result),
+        };
+        check_err(validate_cbor_tags(cddl_input, cbor_bytes, Strict));
+        validate_cbor_tags(cddl_input, cbor_bytes, Unwrap).unwrap();
+        validate_cbor_tags(cddl_input, cbor_bytes, Allow(vec![0])).unwrap();
+        check_err(validate_cbor_tags(
+            cddl_input,
+            cbor_bytes,
+            Allow(vec![1, 2]),
+        ));
+    }
+
+    // Strict is the default.
+    validate_cbor_bytes("thing", "thing = tstr", &tag0_text).err_mismatch();
+    validate_cbor_bytes("thing", "thing = any", &tag0_text).unwrap();
+
+    // Nested tags must all be ignored.
+    validate_cbor_tags("thing = tstr", &tag1_tag0_text, Unwrap).unwrap();
+    validate_cbor_tags("thing = tstr", &tag1_tag0_text, Allow(vec![0, 1])).unwrap();
+    validate_cbor_tags("thing = tstr", &tag1_tag0_text, Allow(vec![0])).err_mismatch();
+    validate_cbor_tags("thing = tstr", &tag1_tag0_text, Allow(vec![1])).err_mismatch();
+
+    // Removing tags can't be allowed to merge two map keys.
+    // {0("a"): 1, "a": 2}
+    let cbor_bytes = b"\xa2\x61a\x02\xc0\x61a\x01";
+    let cddl_input = "thing = { * tstr => int }";
+    validate_cbor_tags(cddl_input, cbor_bytes, Strict).err_mismatch();
+    let err = validate_cbor_tags(cddl_input, cbor_bytes, Unwrap).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"ValueError(duplicate map key "a" after removing tags)"#
+    );
+}
+
+#[test]
+fn cbor_shared_schema() {
+    use std::thread;
+
+    let schema = Schema::from_str("thing = [* tstr]").unwrap();
+    // [0("IETF")]
+    let cbor_bytes = [b"\x81\xc0", cbor::TEXT_IETF].concat();
+
+    // Two views of the same rules, validating at the same time.
+    let views = [TagPolicy::Strict, TagPolicy::Unwrap].iter().map(|tags| {
+        let mut options = ValidationOptions::default();
+        options.tags = tags.clone();
+        schema.with_options(options)
+    });
+    let threads: Vec<_> = views
+        .map(|view| {
+            let cbor_bytes = cbor_bytes.clone();
+            thread::spawn(move || {
+                let rule_def = view.schema().rules().get("thing").unwrap();
+                let cbor_value = serde_cbor::from_slice(&cbor_bytes).unwrap();
+                (0..100)
+                    .filter(|_| validate_cbor(rule_def, &cbor_value, &view).is_ok())
+                    .count()
+            })
+        })
+        .collect();
+    let valid: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(valid, vec![0, 100]);
+
+    // Changing the schema doesn't change a view of it.
+    let view = schema.with_options(ValidationOptions::default());
+    let mut schema = schema;
+    schema.remove_rule("thing").unwrap();
+    assert!(view.schema().rules().contains_key("thing"));
+    assert!(schema.rules().is_empty());
+}
+
+#[test]
+fn cbor_compiled_schema() {
+    use cddl_cat::compile_cddl;
+
+    let schema = compile_cddl("thing = { name: tstr, ? age: uint }").unwrap();
+    // {"name": "Bob"}
+    schema
+        .validate_cbor("thing", b"\xa1\x64name\x63Bob")
+        .unwrap();
+    // {"name": "Bob", "age": 43}
+    schema
+        .validate_cbor("thing", b"\xa2\x64name\x63Bob\x63age\x18\x2b")
+        .unwrap();
+    // {"name": 1}
+    let err = schema
+        .validate_cbor("thing", b"\xa1\x64name\x01")
+        .unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected tstr at ["name"])"#);
+    schema.validate_cbor("thing", b"").unwrap_err();
+    let err = schema.validate_cbor("other", cbor::INT_0).unwrap_err();
+    assert_eq!(err.to_string(), "MissingRule(other)");
+
+    // Schema errors are found before any data is seen.
+    compile_cddl("thing = [* uint").unwrap_err();
+}
+
+#[test]
+fn cbor_rule_override() {
+    let cddl_input = r#"
+        thing = { id: uint, data: payload, ? list: wrapped<payload> }
+        wrapped<T> = [* T]
+        payload = bstr
+    "#;
+    let schema = Schema::from_str(cddl_input).unwrap();
+    // {"id": 1, "data": h'0102', "list": [h'01']}
+    let cbor_bytes = b"\xa3\x62id\x01\x64data\x42\x01\x02\x64list\x81\x41\x01";
+    validate_cbor_bytes("thing", cddl_input, cbor_bytes).unwrap();
+
+    // The override is used everywhere the rule is referred to, including
+    // through a generic parameter.
+    let err = schema
+        .validate_with_override("thing", cbor_bytes, "payload", "payload = bstr .size 1")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected bstr over .size limit at ["data"])"#
+    );
+    assert_eq!(err.rule_source(), Some("payload = bstr .size 1"));
+    let err = schema
+        .validate_with_override(
+            "thing",
+            cbor_bytes,
+            "payload",
+            "payload = bstr .size (2..2)",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected bstr under .size limit at ["list"][0])"#
+    );
+    schema
+        .validate_with_override(
+            "thing",
+            cbor_bytes,
+            "payload",
+            "payload = bstr .size (1..2)",
+        )
+        .unwrap();
+    // The overridden rule can be validated against directly.
+    schema
+        .validate_with_override("payload", b"\x40", "payload", "payload = bstr .size 0")
+        .unwrap();
+
+    // The schema isn't changed.
+    let rule_def = schema.rules().get("thing").unwrap();
+    let cbor_value = serde_cbor::from_slice(cbor_bytes).unwrap();
+    validate_cbor(rule_def, &cbor_value, &schema).unwrap();
+}
+
+fn validate_cbor_with_report(
+    cddl_input: &str,
+    cbor_bytes: &[u8],
+    options: ValidationOptions,
+) -> Result<ValidationReport, ValidateError> {
+    let schema = Schema::from_str(cddl_input).unwrap().with_options(options);
+    let rule_def = schema.schema().rules().get("thing").unwrap();
+    let cbor_value = serde_cbor::from_slice(cbor_bytes).unwrap();
+    validate_cbor_report(rule_def, &cbor_value, &schema)
+}
+
+#[test]
+fn cbor_any_tags() {
+    let default = ValidationOptions::default;
+    let only_tag1 = || {
+        let mut options = ValidationOptions::default();
+        options.any_tags = Some(vec![1]);
+        options
+    };
+    let tags = |pairs: &[(u64, usize)]| pairs.iter().cloned().collect::<BTreeMap<_, _>>();
+
+    // [1(0), 1(2), {"a": 32(0("IETF"))}]
+    let cbor_bytes = [
+        &b"\x83\xc1\x00\xc1\x02\xa1\x61a\xd8\x20\xc0"[..],
+        cbor::TEXT_IETF,
+    ]
+    .concat();
+    let report = validate_cbor_with_report("thing = any", &cbor_bytes, default()).unwrap();
+    assert_eq!(report.any_tags, tags(&[(0, 1), (1, 2), (32, 1)]));
+    let report = validate_cbor_with_report("thing = [* any]", &cbor_bytes, default()).unwrap();
+    assert_eq!(report.any_tags, tags(&[(0, 1), (1, 2), (32, 1)]));
+
+    // Data that isn't matched by `any` isn't reported.
+    let report = validate_cbor_with_report("thing = [uint]", b"\x81\x01", default()).unwrap();
+    assert_eq!(report, ValidationReport::default());
+    // Tags removed by the tag policy don't reach `any`.
+    let mut options = ValidationOptions::default();
+    options.tags = TagPolicy::Unwrap;
+    let report = validate_cbor_with_report("thing = any", &cbor_bytes, options).unwrap();
+    assert_eq!(report.any_tags, tags(&[]));
+
+    // Tags found by an option that failed are forgotten.
+    // [1(0), 2]
+    let cbor_bytes = b"\x82\xc1\x00\x02";
+    let cddl_input = "thing = [any, tstr] / [any, uint]";
+    let report = validate_cbor_with_report(cddl_input, cbor_bytes, default()).unwrap();
+    assert_eq!(report.any_tags, tags(&[(1, 1)]));
+    let cddl_input = "thing = [(any, tstr) // (any, uint)]";
+    let report = validate_cbor_with_report(cddl_input, cbor_bytes, default()).unwrap();
+    assert_eq!(report.any_tags, tags(&[(1, 1)]));
+    // {"a": 1(0), "b": 2}
+    let cbor_bytes = b"\xa2\x61a\xc1\x00\x61b\x02";
+    let cddl_input = "thing = { (a: any, b: tstr) // (a: any, b: uint) }";
+    let report = validate_cbor_with_report(cddl_input, cbor_bytes, default()).unwrap();
+    assert_eq!(report.any_tags, tags(&[(1, 1)]));
+
+    // Unknown tags under `any` can be rejected.
+    validate_cbor_with_report("thing = any", b"\xc1\x00", only_tag1()).unwrap();
+    let err = validate_cbor_with_report("thing = any", b"\xc2\x41\x00", only_tag1()).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected any without tag 2)");
+    // [0, {"a": 1(2(h'00'))}]
+    let cbor_bytes = b"\x82\x00\xa1\x61a\xc1\xc2\x41\x00";
+    let err = validate_cbor_with_report("thing = any", cbor_bytes, only_tag1()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected any without tag 2 at [1]["a"])"#
+    );
+    let err =
+        validate_cbor_with_report("thing = [uint, any]", cbor_bytes, only_tag1()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected any without tag 2 at [1]["a"])"#
+    );
+    validate_cbor_with_report("thing = [uint, any]", b"\x82\x00\xc1\x00", only_tag1()).unwrap();
+}
+
+#[test]
+fn cbor_permissive() {
+    // Render each permissive match as its path and construct.
+    let explain = |cddl_input: &str, cbor_bytes: &[u8]| -> Vec<String> {
+        let report =
+            validate_cbor_with_report(cddl_input, cbor_bytes, ValidationOptions::default())
+                .unwrap();
+        report
+            .permissive
+            .iter()
+            .map(|m| {
+                let path: String = m.path.iter().map(|p| p.to_string()).collect();
+                format!("{} {:?}", path, m.construct)
+            })
+            .collect()
+    };
+    let none: Vec<String> = vec![];
+
+    // {"name": "Bob", "x": 1}
+    let cbor_bytes = b"\xa2\x64name\x63Bob\x61x\x01";
+    assert_eq!(explain("thing = { name: tstr, x: int }", cbor_bytes), none);
+    assert_eq!(
+        explain("thing = { name: tstr, * tstr => any }", cbor_bytes),
+        vec![r#"["x"] Wildcard("Tstr: Any")"#, r#"["x"] Any"#]
+    );
+    assert_eq!(
+        explain("thing = { * tstr => tstr, x: int }", cbor_bytes),
+        vec![r#"["name"] Wildcard("Tstr: Tstr")"#]
+    );
+    // A key matched by `any` is covered by the wildcard.
+    assert_eq!(
+        explain("thing = { * any => tstr / int }", cbor_bytes),
+        vec![
+            r#"["name"] Wildcard("Any: Choice")"#,
+            r#"["x"] Wildcard("Any: Choice")"#,
+        ]
+    );
+
+    // [0, {"data": [1, 2]}]
+    let cbor_bytes = b"\x82\x00\xa1\x64data\x82\x01\x02";
+    assert_eq!(explain("thing = any", cbor_bytes), vec![" Any"]);
+    assert_eq!(
+        explain("thing = [uint, { data: [* any] }]", cbor_bytes),
+        vec![r#"[1]["data"][0] Any"#, r#"[1]["data"][1] Any"#]
+    );
+
+    // Matches made by an option that failed are forgotten.
+    // [1(0), 2]
+    let cbor_bytes = b"\x82\xc1\x00\x02";
+    let cddl_input = "thing = [any, tstr] / [any, uint]";
+    assert_eq!(explain(cddl_input, cbor_bytes), vec!["[0] Any"]);
+
+    // Each match is named by its place in the schema.
+    // [0, {"data": [1], "x": 2}]
+    let cbor_bytes = b"\x82\x00\xa2\x64data\x81\x01\x61x\x02";
+    let cddl_input = "thing = [uint, { data: [* any], * tstr => int }]";
+    let report =
+        validate_cbor_with_report(cddl_input, cbor_bytes, ValidationOptions::default()).unwrap();
+    let names: Vec<_> = report
+        .permissive
+        .iter()
+        .map(|m| m.name.as_deref())
+        .collect();
+    assert_eq!(names, vec![Some("thing[].data[]"), Some("thing[].*")]);
+    // {"a": 1(0), "b": 2}
+    let cbor_bytes = b"\xa2\x61a\xc1\x00\x61b\x02";
+    let cddl_input = "thing = { (a: any, b: tstr) // (a: any, b: uint) }";
+    assert_eq!(explain(cddl_input, cbor_bytes), vec![r#"["a"] Any"#]);
+    // {"name": "Bob", "x": 1}
+    let cbor_bytes = b"\xa2\x64name\x63Bob\x61x\x01";
+    assert_eq!(
+        explain("thing = { * tstr => tstr, * tstr => int }", cbor_bytes),
+        vec![
+            r#"["name"] Wildcard("Tstr: Tstr")"#,
+            r#"["x"] Wildcard("Tstr: Int")"#,
+        ]
+    );
+}
+
+#[test]
+fn cbor_occurrences() {
+    use cddl_cat::ast::Occur;
+    use cddl_cat::flatten::flatten_from_str;
+    use cddl_cat::ivt::Node;
+
+    // Render each occurrence as its path, member, and count.
+    let occurrences = |cddl_input: &str, cbor_bytes: &[u8]| -> Vec<String> {
+        let report =
+            validate_cbor_with_report(cddl_input, cbor_bytes, ValidationOptions::default())
+                .unwrap();
+        report
+            .occurrences
+            .iter()
+            .map(|m| {
+                let path: String = m.path.iter().map(|p| p.to_string()).collect();
+                format!("{}{} {}: {}", path, m.occur, m.member, m.count)
+            })
+            .collect()
+    };
+
+    // {"name": "Bob", "tags": ["a", "b"], "x": 1}
+    let cbor_bytes = b"\xa3\x64name\x63Bob\x64tags\x82\x61a\x61b\x61x\x01";
+    let cddl_input = "thing = { name: tstr, ? age: int, tags: [* tstr], * tstr => int }";
+    assert_eq!(
+        occurrences(cddl_input, cbor_bytes),
+        vec![
+            r#"? "age": Int: 0"#,
+            r#"["tags"]* Tstr: 2"#,
+            "* Tstr: Int: 1",
+        ]
+    );
+
+    let report =
+        validate_cbor_with_report(cddl_input, cbor_bytes, ValidationOptions::default()).unwrap();
+    let names: Vec<_> = report
+        .occurrences
+        .iter()
+        .map(|m| m.name.as_deref())
+        .collect();
+    assert_eq!(
+        names,
+        vec![Some("thing.age"), Some("thing.tags[]"), Some("thing.*")]
+    );
+
+    // Occurrences matched by an option that failed are forgotten.
+    // [1, 2]
+    let cddl_input = "thing = [* tstr, * uint] / [+ uint]";
+    assert_eq!(
+        occurrences(cddl_input, b"\x82\x01\x02"),
+        vec!["* Tstr: 0", "* Uint: 2"]
+    );
+    let cddl_input = "thing = [2* tstr] / [+ uint]";
+    assert_eq!(occurrences(cddl_input, b"\x82\x01\x02"), vec!["+ Uint: 2"]);
+
+    // Occurrences are shown as written, even where the schema allows more
+    // than can match.
+    // {"a": 1}
+    let cddl_input = r#"thing = { * "a" => int, 0*3 "b" => int, *9 tstr => int }"#;
+    let report =
+        validate_cbor_with_report(cddl_input, b"\xa1\x61a\x01", ValidationOptions::default())
+            .unwrap();
+    let found: Vec<(&str, &Occur, u64)> = report
+        .occurrences
+        .iter()
+        .map(|m| (m.written.as_str(), &m.occur, m.count))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("*", &Occur::Optional, 1),
+            ("0*3", &Occur::Optional, 0),
+            ("0*9", &Occur::Numbered(0, 9), 0),
+        ]
+    );
+    let flat = flatten_from_str(cddl_input).unwrap();
+    let members: Vec<String> = match &flat["thing"].node {
+        Node::Map(m) => m.members.iter().map(Node::to_string).collect(),
+        other => panic!("expected a map, got {:?}", other),
+    };
+    assert_eq!(
+        members,
+        vec![r#"* "a": Int"#, r#"0*3 "b": Int"#, "0*9 Tstr: Int"]
+    );
+
+    // Errors say how many matches were found.
+    let err = validate_cbor_bytes("thing", "thing = [2*3 uint]", b"\x81\x01").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected more array element [2*3 Uint] (found 1, need 2 to 3))"
+    );
+    let cddl_input = "thing = { 2* tstr => int }";
+    let err = validate_cbor_bytes("thing", cddl_input, b"\xa1\x61x\x01").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected map{2* Tstr: Int} (found 1, need at least 2))"
+    );
+}
+
+#[test]
+fn cbor_hex_base64() {
+    use cddl_cat::cbor::{validate_cbor_base64, validate_cbor_hex};
+
+    let cddl_input = "thing = [* int]";
+    validate_cbor_hex("thing", cddl_input, "83010203").unwrap();
+    validate_cbor_hex("thing", cddl_input, "8301020A").unwrap();
+    validate_cbor_hex("thing", cddl_input, "").unwrap_err();
+    validate_cbor_hex("thing", cddl_input, "8163424f42").err_mismatch();
+
+    let err = validate_cbor_hex("thing", cddl_input, "830102030").unwrap_err();
+    assert_eq!(err.to_string(), "DecodeError(odd number of hex digits)");
+    let err = validate_cbor_hex("thing", cddl_input, "830x0203").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "DecodeError(invalid hex character 'x' at offset 3)"
+    );
+    match err {
+        ValidateError::DecodeError(e) => assert_eq!(e.offset(), Some(3)),
+        _ => panic!("expected DecodeError, got {:?}", err),
+    }
+
+    validate_cbor_base64("thing", cddl_input, "gwECAw==").unwrap();
+    validate_cbor_base64("thing", cddl_input, "gWNCT0I=").err_mismatch();
+    let err = validate_cbor_base64("thing", cddl_input, "gwE*Aw==").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "DecodeError(invalid base64 character '*' at offset 3)"
+    );
+    let err = validate_cbor_base64("thing", cddl_input, "gwECA").unwrap_err();
+    assert_eq!(err.to_string(), "DecodeError(invalid base64 length)");
+}
+
+#[test]
+fn cbor_data_range() {
+    use cddl_cat::cbor::{validate_cbor_seq_bytes, validate_cbor_slice};
+    use cddl_cat::context::LookupContext;
+    use serde_cbor::Value as CBOR_Value;
+
+    let range = |cddl_input: &str, cbor_hex: &str| {
+        let cbor_bytes = hex::decode(cbor_hex).unwrap();
+        validate_cbor_bytes("thing", cddl_input, &cbor_bytes)
+            .unwrap_err()
+            .data_range()
+    };
+
+    // [1, 1000, "x"]
+    assert_eq!(range("thing = [* uint]", "83011903e86178"), Some(5..7));
+    // [_ 1, "x"]
+    assert_eq!(range("thing = [* uint]", "9f016178ff"), Some(2..4));
+    // [(_ "a", "b"), "x"]
+    assert_eq!(
+        range("thing = [tstr, int]", "827f61616162ff6178"),
+        Some(7..9)
+    );
+    // [[1, [2, "x"]]], where the error is nested deeper.
+    assert_eq!(
+        range("thing = [* [* uint / [* uint]]]", "81820182026178"),
+        Some(1..7)
+    );
+    // {"a": 1, "b": "x"}
+    let err = validate_cbor_bytes(
+        "thing",
+        "thing = { a: int, b: int }",
+        &hex::decode("a261610161626178").unwrap(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["b"])"#);
+    assert_eq!(err.data_range(), Some(4..8));
+    assert_eq!(
+        format!("{:#}", err),
+        "Mismatch(expected int at [\"b\"])\nin thing.b\nvia .b\nin data at bytes 4..8"
+    );
+    // {_ "a": {}}, where a key inside the entry is missing.
+    assert_eq!(range("thing = { a: { c: int } }", "bf6161a0ff"), Some(1..4));
+    // {1: "x"}
+    assert_eq!(range("thing = { 1 => int }", "a1016178"), Some(1..4));
+
+    // Keys that look the same once shortened are still told apart.
+    let prefix = "k".repeat(100);
+    let map: BTreeMap<String, CBOR_Value> = vec![
+        (format!("{}1", prefix), CBOR_Value::Integer(1)),
+        (format!("{}2", prefix), CBOR_Value::Text("x".into())),
+    ]
+    .into_iter()
+    .collect();
+    let cbor_hex = hex::encode(serde_cbor::to_vec(&map).unwrap());
+    // Each key takes 103 bytes.
+    assert_eq!(
+        range("thing = { * tstr => uint }", &cbor_hex),
+        Some(105..210)
+    );
+
+    // Errors at the top level have no range.
+    assert_eq!(range("thing = [* uint]", "6178"), None);
+    assert_eq!(range("thing = { a: int }", "a0"), None);
+
+    // Tags removed by the tag policy are skipped over.
+    let mut options = ValidationOptions::default();
+    options.tags = TagPolicy::Unwrap;
+    let schema = Schema::from_str("thing = [* uint]")
+        .unwrap()
+        .with_options(options);
+    let rule_def = schema.lookup_rule("thing").unwrap();
+    // 1([1, "x"])
+    let cbor_bytes = hex::decode("c182016178").unwrap();
+    let err = validate_cbor_slice(rule_def, &cbor_bytes, &schema).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected uint at [1])");
+    assert_eq!(err.data_range(), Some(3..5));
+
+    // Items of a sequence have ranges too.
+    let err = validate_cbor_seq_bytes("thing", "thing = (* uint)", b"\x01\x61x").unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected uint at [1])");
+    assert_eq!(err.data_range(), Some(1..3));
+
+    // Decoding data without validating it against encoded bytes has no
+    // ranges to report.
+    let cbor_value = serde_cbor::from_slice(b"\x82\x01\x61x").unwrap();
+    let err = validate_cbor(rule_def, &cbor_value, &schema).unwrap_err();
+    assert_eq!(err.data_range(), None);
+}
+
+#[test]
+fn cbor_collect_errors() {
+    use cddl_cat::validate_cbor_bytes_collect;
+
+    let collect = |cddl_input: &str, cbor_hex: &str| -> Vec<String> {
+        let cbor_bytes = hex::decode(cbor_hex).unwrap();
+        match validate_cbor_bytes_collect("thing", cddl_input, &cbor_bytes) {
+            Ok(()) => Vec::new(),
+            Err(errs) => errs.errors().iter().map(|e| e.to_string()).collect(),
+        }
+    };
+
+    // {"b": "x", "a": "y"}: both wrong fields are reported, in the order
+    // they appear in the data.
+    let cddl_input = "thing = { a: int, b: int }";
+    assert_eq!(
+        collect(cddl_input, "a26162617861616179"),
+        vec![
+            r#"Mismatch(expected int at ["b"])"#,
+            r#"Mismatch(expected int at ["a"])"#,
+        ]
+    );
+    // {"a": 1, "b": 2}
+    assert!(collect(cddl_input, "a2616101616202").is_empty());
+
+    // [1, "x", 3, "y"]
+    assert_eq!(
+        collect("thing = [* uint]", "84016178036179"),
+        vec![
+            "Mismatch(expected uint at [1])",
+            "Mismatch(expected uint at [3])"
+        ]
+    );
+    // {"a": [1, "x"], "b": "y"}
+    assert_eq!(
+        collect("thing = { a: [* int], b: int }", "a261618201617861626179"),
+        vec![
+            r#"Mismatch(expected int at ["a"][1])"#,
+            r#"Mismatch(expected int at ["b"])"#,
+        ]
+    );
+    // {"p": {"x": "a", "y": "b"}, "q": "c"}
+    assert_eq!(
+        collect(
+            "thing = { p: { x: int, y: int }, q: int }",
+            "a26170a2617861616179616261716163"
+        ),
+        vec![
+            r#"Mismatch(expected int at ["p"]["x"])"#,
+            r#"Mismatch(expected int at ["p"]["y"])"#,
+            r#"Mismatch(expected int at ["q"])"#,
+        ]
+    );
+    // [1, "x"]: if no option of a choice matches, each one says why.
+    assert_eq!(
+        collect("thing = int / [* uint]", "82016178"),
+        vec!["Mismatch(expected int)", "Mismatch(expected uint at [1])"]
+    );
+    // Keys that only differ past the length that's shown are still told
+    // apart.
+    let prefix = "k".repeat(100);
+    let map: BTreeMap<String, &str> =
+        vec![(format!("{}a", prefix), "x"), (format!("{}b", prefix), "y")]
+            .into_iter()
+            .collect();
+    let cbor_hex = hex::encode(serde_cbor::to_vec(&map).unwrap());
+    let errors = collect("thing = { * tstr => uint }", &cbor_hex);
+    assert_eq!(errors.len(), 2);
+    assert!(errors
+        .iter()
+        .all(|e| e.starts_with("Mismatch(expected uint at")));
+    // A value of the wrong type is a single error.
+    assert_eq!(
+        collect("thing = [* uint]", "6178"),
+        vec!["Mismatch(expected array)"]
+    );
+    // Errors that stop validation are reported alone.
+    assert_eq!(
+        collect("thing = [* nope]", "8101"),
+        vec!["MissingRule(nope)"]
+    );
+}
+
+#[test]
+#[ntest::timeout(5000)] // 5 seconds
+fn cbor_collect_errors_is_fast() {
+    use cddl_cat::validate_cbor_bytes_collect;
+    use serde_cbor::Value as CBOR_Value;
+
+    // Carrying on past each wrong element shouldn't mean matching the
+    // array again for each one.
+    let n = 100_000;
+    let values: Vec<CBOR_Value> = (0..n)
+        .map(|i| match i % 2000 {
+            0 => CBOR_Value::Text("x".into()),
+            _ => CBOR_Value::Integer(i),
+        })
+        .collect();
+    let cbor_bytes = serde_cbor::to_vec(&CBOR_Value::Array(values)).unwrap();
+    let errs = validate_cbor_bytes_collect("thing", "thing = [* uint]", &cbor_bytes).unwrap_err();
+    assert_eq!(errs.errors().len(), 50);
+}
+
+#[test]
+#[ntest::timeout(5000)] // 5 seconds
+fn cbor_ambiguous_array_is_fast() {
+    use cddl_cat::cbor::validate_cbor_seq_bytes;
+
+    // Each of these has to look past the greedy match, and could take
+    // exponential time if every way of matching were tried in turn.
+    let mut cbor_bytes = b"\x9f".to_vec();
+    cbor_bytes.extend(std::iter::repeat(0u8).take(5000));
+    cbor_bytes.push(0xff);
+    for cddl_input in &[
+        "thing = [* (? int), int]",
+        "thing = [* (int // uint), int]",
+        "thing = [* int, * (int, int), int]",
+        "thing = [* (int, ? int), int, int]",
+    ] {
+        validate_cbor_bytes("thing", cddl_input, &cbor_bytes).unwrap();
+    }
+    validate_cbor_bytes("thing", "thing = [* (? int), tstr]", &cbor_bytes).err_mismatch();
+
+    // Sequences are matched the same way.
+    let seq = [b"\x01\x02", cbor::TEXT_IETF].concat();
+    validate_cbor_seq_bytes("thing", "thing = (* uint, 2, tstr)", &seq).unwrap();
+    validate_cbor_seq_bytes("thing", "thing = (* uint, 3, tstr)", &seq).err_mismatch();
+}
+
+#[test]
+#[ntest::timeout(3000)] // 3 seconds
+fn cbor_nested_array_mismatch_is_fast() {
+    // Each array that fails has to look past the greedy match, which
+    // validates the array inside it again.  That needs doing once per
+    // array, not once for every way of getting to it.
+    let depth = 40;
+    let mut cbor_bytes = vec![0x81; depth];
+    cbor_bytes.push(0x60);
+    let cddl_input = "t = [t] / int";
+    validate_cbor_bytes("t", cddl_input, &cbor_bytes).err_mismatch();
+    *cbor_bytes.last_mut().unwrap() = 0x00;
+    validate_cbor_bytes("t", cddl_input, &cbor_bytes).unwrap();
+}
+
+#[test]
+#[ntest::timeout(3000)] // 3 seconds
+fn cbor_large_map_literal_keys_is_fast() {
+    // Finding each literal key shouldn't mean looking at every entry.
+    let n = 20000;
+    let members: Vec<String> = (0..n).map(|i| format!("k{}: uint", i)).collect();
+    let cddl_input = format!("thing = {{ {} }}", members.join(", "));
+    let schema = Schema::from_str(&cddl_input).unwrap();
+    let map: BTreeMap<String, u32> = (0..n).map(|i| (format!("k{}", i), i)).collect();
+    let cbor_bytes = serde_cbor::to_vec(&map).unwrap();
+    schema.validate_cbor("thing", &cbor_bytes).unwrap();
+
+    let map: BTreeMap<String, u32> = (1..=n).map(|i| (format!("k{}", i), i)).collect();
+    let cbor_bytes = serde_cbor::to_vec(&map).unwrap();
+    let err = schema.validate_cbor("thing", &cbor_bytes).unwrap_err();
+    assert_eq!(err.to_string(), r#"MissingMapKey("k0": Uint)"#);
+}
+
+#[test]
+#[ntest::timeout(2000)] // 2 seconds
+fn cbor_missing_rule_first() {
+    use cddl_cat::cbor::{validate_cbor_base64, validate_cbor_hex, validate_cbor_seq_bytes};
+
+    let cddl_input = "person = { name: tstr }  people = [* person]";
+    let expected = "MissingRule(persno); did you mean 'person'?";
+
+    // An array of 64 million zeros would take far longer than the timeout
+    // to decode.
+    let mut huge = vec![0u8; 64 << 20];
+    huge[..5].copy_from_slice(&[0x9a, 0x04, 0x00, 0x00, 0x00]);
+    let err = validate_cbor_bytes("persno", cddl_input, &huge).unwrap_err();
+    assert_eq!(err.to_string(), expected);
+    let err = validate_cbor_seq_bytes("persno", cddl_input, &huge).unwrap_err();
+    assert_eq!(err.to_string(), expected);
+
+    // The rule is looked up before the data is decoded at all, so data that
+    // can't be decoded gets the same error.
+    let err = validate_cbor_bytes("persno", cddl_input, b"\xff\xff").unwrap_err();
+    assert_eq!(err.to_string(), expected);
+    let err = validate_cbor_hex("persno", cddl_input, "not hex").unwrap_err();
+    assert_eq!(err.to_string(), expected);
+    let err = validate_cbor_base64("persno", cddl_input, "not base64").unwrap_err();
+    assert_eq!(err.to_string(), expected);
+}
+
+#[test]
+fn cbor_packed_struct() {
+    #[derive(Serialize)]
+    struct Person {
+        name: String,
+        age: u32,
+        nickname: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Rect { width: u32, height: u32 },
+        Empty,
+    }
+
+    let bob = Person {
+        name: "Bob".to_string(),
+        age: 43,
+        nickname: None,
+    };
+    // {0: "Bob", 1: 43, 2: null}
+    let cbor_bytes = serde_cbor::ser::to_vec_packed(&bob).unwrap();
+    assert_eq!(hex::encode(&cbor_bytes), "a30063426f6201182b02f6");
+
+    let cddl_input = "person = { 0: tstr, 1: uint, 2: tstr / nil }";
+    validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap();
+
+    // The same schema, with a name for each field number.
+    let cddl_input = r#"
+        person = { name => tstr, age => uint, ? nickname => tstr / nil }
+        name = 0  age = 1  nickname = 2
+    "#;
+    validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap();
+
+    // The field numbers show up in error paths.
+    let cddl_input = "person = { 0: tstr, 1: tstr, 2: tstr / nil }";
+    let err = validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected tstr at [1])");
+    let cddl_input = "person = { 0: tstr, 1: uint, 2: tstr / nil, 3: uint }";
+    let err = validate_cbor_bytes("person", cddl_input, &cbor_bytes).unwrap_err();
+    assert_eq!(err.to_string(), "MissingMapKey(3: Uint)");
+    let cddl_input = "person = { 0: tstr, 1: uint }";
+    validate_cbor_bytes("person", cddl_input, &cbor_bytes).err_mismatch();
+
+    // Enum variants are numbered too: a struct variant is a map keyed by
+    // its variant number, and a unit variant is just the number.
+    let cddl_input = "shape = { 0: { 0: uint, 1: uint } } / 1";
+    for shape in &[
+        Shape::Rect {
+            width: 3,
+            height: 4,
+        },
+        Shape::Empty,
+    ] {
+        let cbor_bytes = serde_cbor::ser::to_vec_packed(shape).unwrap();
+        validate_cbor_bytes("shape", cddl_input, &cbor_bytes).unwrap();
+    }
+}
+
+#[test]
+fn cbor_empty_and_truncated() {
+    use cddl_cat::cbor::{validate_cbor_bytes_expr, validate_cbor_seq_bytes};
+    use cddl_cat::{validate_cbor_base64, validate_cbor_hex};
+
+    let cddl_input = "thing = [* uint]";
+    let err = validate_cbor_bytes("thing", cddl_input, b"").unwrap_err();
+    assert!(matches!(err, ValidateError::EmptyInput));
+    assert_eq!(err.to_string(), "EmptyInput");
+    let err = validate_cbor_hex("thing", cddl_input, "").unwrap_err();
+    assert!(matches!(err, ValidateError::EmptyInput));
+    let err = validate_cbor_base64("thing", cddl_input, "").unwrap_err();
+    assert!(matches!(err, ValidateError::EmptyInput));
+    let err = validate_cbor_bytes_expr("uint", b"").unwrap_err();
+    assert!(matches!(err, ValidateError::EmptyInput));
+    // A missing rule is still reported first.
+    let err = validate_cbor_bytes("nothing", cddl_input, b"").unwrap_err();
+    assert!(matches!(err, ValidateError::MissingRule(_)));
+
+    // Each of these is cut short, and needs at least this many bytes.
+    let truncated: &[(&str, usize)] = &[
+        // [1, 2, 3] with its last element missing
+        ("830102", 4),
+        // 1000 with half its argument missing
+        ("1903", 3),
+        // An array of 10 elements, with none of them
+        ("8a", 2),
+        // [1000] with the element's argument missing
+        ("8119", 4),
+        // A 5-byte string, with 3 bytes
+        ("45010203", 6),
+        // An indefinite-length array, missing its break
+        ("9f0102", 4),
+        // [[1, 2]] missing the inner 2
+        ("818201", 4),
+        // A tagged array missing its element
+        ("c181", 3),
+    ];
+    for (cbor_hex, needed) in truncated {
+        let err = validate_cbor_hex("thing", cddl_input, cbor_hex).unwrap_err();
+        match &err {
+            ValidateError::Truncated(t) => {
+                assert_eq!(t.needed(), *needed, "{}", cbor_hex);
+                assert_eq!(t.available(), cbor_hex.len() / 2, "{}", cbor_hex);
+            }
+            _ => panic!("{}: expected Truncated, got {:?}", cbor_hex, err),
+        }
+    }
+    let err = validate_cbor_hex("thing", cddl_input, "830102").unwrap_err();
+    assert_eq!(err.to_string(), "Truncated(need at least 4 bytes, found 3)");
+
+    // Malformed data that isn't short is still a ValueError.
+    let err = validate_cbor_hex("thing", cddl_input, "1c").unwrap_err();
+    assert!(matches!(err, ValidateError::ValueError(_)), "{:?}", err);
+
+    // An empty sequence has no items, which may be fine.
+    let cddl_input = "log = (* uint)";
+    validate_cbor_seq_bytes("log", cddl_input, b"").unwrap();
+    // The last item is cut short: 1, 2, then 1000 missing a byte.
+    let err = validate_cbor_seq_bytes("log", cddl_input, b"\x01\x02\x19\x03").unwrap_err();
+    assert_eq!(err.to_string(), "Truncated(need at least 5 bytes, found 4)");
+}
+
+#[test]
+fn cbor_partly_unsupported() {
+    // `limit` uses a control operator that isn't supported; that only
+    // matters to data that reaches it.
+    let cddl_input = r#"
+        thing = { id: uint, ? limit: limit }
+        limit = uint .lt 10
+        either = tstr / limit
+    "#;
+    // {"id": 1}
+    validate_cbor_bytes("thing", cddl_input, b"\xa1\x62id\x01").unwrap();
+    // {"id": 1, "limit": 2}
+    let err = validate_cbor_bytes("thing", cddl_input, b"\xa2\x62id\x01\x65limit\x02").unwrap_err();
+    assert_eq!(err.to_string(), "Unsupported control operator");
+
+    // A choice that matches before reaching the broken rule is fine, but
+    // one that gets there fails.
+    validate_cbor_bytes("either", cddl_input, b"\x61a").unwrap();
+    let err = validate_cbor_bytes("either", cddl_input, cbor::INT_1).unwrap_err();
+    assert_eq!(err.to_string(), "Unsupported control operator");
+
+    // The broken rule is still known by name.
+    let err = validate_cbor_bytes("limit", cddl_input, cbor::INT_1).unwrap_err();
+    assert_eq!(err.to_string(), "Unsupported control operator");
+    let err = validate_cbor_bytes("limits", cddl_input, cbor::INT_1).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "MissingRule(limits); did you mean 'limit'?"
+    );
+
+    let schema = Schema::from_str(cddl_input).unwrap();
+    assert_eq!(
+        schema.flatten_errors().keys().collect::<Vec<_>>(),
+        vec!["limit"]
+    );
+    assert!(schema.rules().contains_key("thing"));
+    assert!(!schema.rules().contains_key("limit"));
 }
@@ -65,7 +65,7 @@ fn validate_literal_text() {
 
 #[test]
 fn validate_choice() {
-    let options = vec![1, 2, 3];
+    let options = [1, 2, 3];
     let options = options.iter().map(|n| literal_int(*n)).collect();
     let node = &Node::Choice(Choice { options });
 
@@ -92,7 +92,10 @@ fn validate_map() {
             Node::KeyValue(KeyValue::new(key, value, cut))
         })
         .collect();
-    let node = &Node::Map(Map { members: kv_vec });
+    let node = &Node::Map(Map {
+        members: kv_vec,
+        name: None,
+    });
 
     let value_template = [("Alice", 42), ("Bob", 43), ("Carol", 44)];
     let value: HashMap<&str, u32> = value_template.iter().cloned().collect();
@@ -145,3 +148,37 @@ fn validate_rule_ref() {
     validate_cbor(&rule_def, &gen_value(7), &ctx).unwrap();
     validate_cbor(&rule_def, &gen_value(8), &ctx).unwrap_err();
 }
+
+#[test]
+fn validate_literal_float_special() {
+    // A NaN literal matches any NaN.
+    let node = &Node::Literal(Literal::Float(f64::NAN));
+    gen_value(f64::NAN).test_validate(node).unwrap();
+    gen_value(-f64::NAN).test_validate(node).unwrap();
+    gen_value(f32::NAN).test_validate(node).unwrap();
+    gen_value(1.0).test_validate(node).unwrap_err();
+    gen_value(f64::INFINITY).test_validate(node).unwrap_err();
+
+    // Infinities only match the infinity of the same sign.
+    let node = &Node::Literal(Literal::Float(f64::INFINITY));
+    gen_value(f64::INFINITY).test_validate(node).unwrap();
+    gen_value(f32::INFINITY).test_validate(node).unwrap();
+    gen_value(f64::NEG_INFINITY)
+        .test_validate(node)
+        .unwrap_err();
+    gen_value(f64::MAX).test_validate(node).unwrap_err();
+    gen_value(f64::NAN).test_validate(node).unwrap_err();
+    let node = &Node::Literal(Literal::Float(f64::NEG_INFINITY));
+    gen_value(f64::NEG_INFINITY).test_validate(node).unwrap();
+    gen_value(f64::INFINITY).test_validate(node).unwrap_err();
+
+    // NaN is outside any range, even an infinite one.
+    let node = &Node::Range(Range {
+        start: Box::new(Node::Literal(Literal::Float(f64::NEG_INFINITY))),
+        end: Box::new(Node::Literal(Literal::Float(f64::INFINITY))),
+        inclusive: true,
+    });
+    gen_value(f64::INFINITY).test_validate(node).unwrap();
+    gen_value(0.5).test_validate(node).unwrap();
+    gen_value(f64::NAN).test_validate(node).unwrap_err();
+}
@@ -0,0 +1,156 @@
+// Run the test vectors in `tests/vectors`.
+//
+// See `tests/vectors/README.md` for the file format, and for how to add
+// new vectors.
+
+#![cfg(all(feature = "serde_cbor", feature = "serde_json"))]
+
+use cddl_cat::cbor::validate_cbor_bytes;
+use cddl_cat::ValidateError;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+// One file of test vectors, all checking the same rule.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VectorFile {
+    // The CDDL file, relative to the vector file.
+    cddl: String,
+    // The rule the data is validated against.
+    rule: String,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Vector {
+    name: String,
+    // The data, as CBOR in hex.
+    cbor: String,
+    // The data in CBOR diagnostic notation; only for people to read.
+    #[allow(dead_code)]
+    #[serde(default)]
+    diag: Option<String>,
+    // "ok", or the kind of error that validation should return.
+    expect: String,
+    // If set, the error message should be exactly this.
+    #[serde(default)]
+    message: Option<String>,
+    // Any explanation of the expected result; only for people to read.
+    #[allow(dead_code)]
+    #[serde(default)]
+    note: Option<String>,
+}
+
+// The name of the kind of an error, as used in the `expect` field.
+fn error_kind(err: &ValidateError) -> String {
+    format!("{:?}", err.kind())
+}
+
+// Run one vector, returning a description of what went wrong.
+fn check_vector(cddl: &str, rule: &str, vector: &Vector) -> Result<(), String> {
+    let cbor = hex::decode(&vector.cbor).map_err(|e| format!("bad cbor hex: {}", e))?;
+    let result = validate_cbor_bytes(rule, cddl, &cbor);
+    let (kind, message) = match &result {
+        Ok(()) => ("ok".to_string(), None),
+        Err(e) => (error_kind(e), Some(e.to_string())),
+    };
+    let got = || match &message {
+        Some(m) => format!("{} ({})", kind, m),
+        None => kind.to_string(),
+    };
+    if kind != vector.expect {
+        return Err(format!("expected {}, got {}", vector.expect, got()));
+    }
+    if vector.message.is_some() && vector.message != message {
+        let expected = vector.message.as_deref().unwrap_or_default();
+        return Err(format!("expected message {:?}, got {}", expected, got()));
+    }
+    Ok(())
+}
+
+// Run all the vectors in one file, returning the number run and a line
+// describing each failure.
+fn run_file(path: &Path) -> (usize, Vec<String>) {
+    let display = path.display();
+    let text = fs::read_to_string(path).unwrap();
+    let file: VectorFile = serde_json::from_str(&text)
+        .unwrap_or_else(|e| panic!("{}: can't read vectors: {}", display, e));
+    let cddl_path = path.parent().unwrap().join(&file.cddl);
+    let cddl = fs::read_to_string(&cddl_path)
+        .unwrap_or_else(|e| panic!("{}: can't read {}: {}", display, file.cddl, e));
+
+    let failures = file
+        .vectors
+        .iter()
+        .filter_map(|vector| {
+            check_vector(&cddl, &file.rule, vector)
+                .err()
+                .map(|e| format!("{}: {}: {}", display, vector.name, e))
+        })
+        .collect();
+    (file.vectors.len(), failures)
+}
+
+#[test]
+fn conformance_vectors() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no test vectors in {}", dir.display());
+
+    let mut count = 0;
+    let mut failures = Vec::new();
+    for path in &paths {
+        let (n, f) = run_file(path);
+        count += n;
+        failures.extend(f);
+    }
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} test vectors failed:\n{}",
+            failures.len(),
+            count,
+            failures.join("\n")
+        );
+    }
+}
+
+#[test]
+fn conformance_failures() {
+    let vector = |cbor: &str, expect: &str, message: Option<&str>| Vector {
+        name: "test".into(),
+        cbor: cbor.into(),
+        diag: None,
+        expect: expect.into(),
+        message: message.map(Into::into),
+        note: None,
+    };
+    let cddl = "thing = [* uint]";
+    check_vector(cddl, "thing", &vector("820102", "ok", None)).unwrap();
+    check_vector(cddl, "thing", &vector("8120", "TypeMismatch", None)).unwrap();
+    let message = Some("Mismatch(expected uint at [0])");
+    check_vector(cddl, "thing", &vector("8120", "TypeMismatch", message)).unwrap();
+
+    let err = check_vector(cddl, "thing", &vector("8120", "ok", None)).unwrap_err();
+    assert_eq!(
+        err,
+        "expected ok, got TypeMismatch (Mismatch(expected uint at [0]))"
+    );
+    let err = check_vector(cddl, "thing", &vector("820102", "TypeMismatch", None)).unwrap_err();
+    assert_eq!(err, "expected TypeMismatch, got ok");
+    let err = check_vector(cddl, "thing", &vector("8120", "TypeMismatch", Some("x"))).unwrap_err();
+    assert_eq!(
+        err,
+        r#"expected message "x", got TypeMismatch (Mismatch(expected uint at [0]))"#
+    );
+    let err = check_vector(cddl, "thing", &vector("8", "ok", None)).unwrap_err();
+    assert_eq!(err, "bad cbor hex: Odd number of digits");
+    let err = check_vector("thing = [", "thing", &vector("80", "ok", None)).unwrap_err();
+    assert!(err.starts_with("expected ok, got InvalidSchema"), "{}", err);
+}
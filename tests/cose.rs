@@ -0,0 +1,191 @@
+#![cfg(feature = "serde_cbor")]
+
+// COSE (RFC 9052) and CWT (RFC 8392) structures, validated against an
+// abridged version of their CDDL.
+//
+// These use many features at once: integer map keys, byte strings holding
+// encoded CBOR (`.cbor`), choices, and tags.  They're kept as a regression
+// suite for the combination.
+
+use cddl_cat::cbor::validate_cbor_slice;
+use cddl_cat::context::{LookupContext, TagPolicy, ValidationOptions};
+use cddl_cat::schema::Schema;
+use cddl_cat::ValidateResult;
+
+// From RFC 9052 (sections 2, 3, 4.2 and 6.2) and RFC 8392 (sections 3 and
+// 6), with the message types that aren't tested here left out.
+const COSE_CDDL: &str = r#"
+    COSE_Messages = COSE_Untagged_Message / COSE_Tagged_Message
+    COSE_Untagged_Message = COSE_Sign1 / COSE_Mac0
+    COSE_Tagged_Message = COSE_Sign1_Tagged / COSE_Mac0_Tagged
+
+    COSE_Sign1_Tagged = #6.18(COSE_Sign1)
+    COSE_Mac0_Tagged = #6.17(COSE_Mac0)
+
+    COSE_Sign1 = [
+        Headers,
+        payload: bstr / nil,
+        signature: bstr,
+    ]
+
+    COSE_Mac0 = [
+        Headers,
+        payload: bstr / nil,
+        tag: bstr,
+    ]
+
+    Headers = (
+        protected: empty_or_serialized_map,
+        unprotected: header_map,
+    )
+
+    header_map = {
+        Generic_Headers,
+        $$header-parameters,
+        * label => values,
+    }
+
+    ; A socket for header parameters registered by other documents.
+    $$header-parameters //= (? 33 => bstr / [2* bstr])  ; x5chain, RFC 9360
+
+    empty_or_serialized_map = bstr .cbor header_map / bstr .size 0
+
+    Generic_Headers = (
+        ? 1 => int / tstr,  ; algorithm identifier
+        ? 2 => [+ label],   ; criticality
+        ? 3 => tstr / int,  ; content type
+        ? 4 => bstr,        ; key identifier
+        ? (5 => bstr //     ; IV
+           6 => bstr),      ; Partial IV
+    )
+
+    label = int / tstr
+    values = any
+
+    ; A CWT is a COSE message whose payload is a claims set.
+    CWT = CWT_Sign1 / CWT_Mac0
+    CWT_Sign1 = [Headers, payload: bstr .cbor Claims, signature: bstr]
+    CWT_Mac0 = [Headers, payload: bstr .cbor Claims, tag: bstr]
+    CWT_Message = #6.18(CWT_Sign1) / #6.17(CWT_Mac0)
+    CWT_Tagged = #6.61(CWT_Message)
+
+    Claims = {
+        ? 1 ^ => tstr,         ; iss
+        ? 2 ^ => tstr,         ; sub
+        ? 3 ^ => tstr,         ; aud
+        ? 4 ^ => NumericDate,  ; exp
+        ? 5 ^ => NumericDate,  ; nbf
+        ? 6 ^ => NumericDate,  ; iat
+        ? 7 ^ => bstr,         ; cti
+        * label => any,
+    }
+    NumericDate = int / float
+"#;
+
+// RFC 9052 appendix C.2.1: a COSE_Sign1 message, tagged 18.
+const SIGN1_TAGGED: &str = "d28443a10126a10442313154546869732069732074686520636f6e74656e742e58408eb33e4ca31d1c465ab05aac34cc6b23d58fef5c083106c4d25a91aef0b0117e2af9a291aa32e14ab834dc56ed2a223444547e01f11d3b0916e5a4c345cacb36";
+
+// RFC 8392 appendix A.1: a claims set.
+const CLAIMS: &str = "a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b71";
+
+// RFC 8392 appendix A.3: a signed CWT, a COSE_Sign1 tagged 18.
+const CWT_SIGNED: &str = "d28443a10126a104524173796d6d657472696345434453413235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7158405427c1ff28d23fbad1f29c4c7c6a555e601d6fa29f9179bc3d7438bacaca5acd08c8d4d4f96131680c429a01f85951ecee743a52b9b63632c57209120e1c9e30";
+
+// RFC 8392 appendix A.4: a MACed CWT, a COSE_Mac0 tagged 17 and then 61
+// (the CWT tag).
+const CWT_MACED: &str = "d83dd18443a10104a1044c53796d6d65747269633235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7148093101ef6d789200";
+
+fn validate(rule: &str, cbor_hex: &str, tags: TagPolicy) -> ValidateResult {
+    let mut options = ValidationOptions::default();
+    options.tags = tags;
+    let schema = Schema::from_str(COSE_CDDL).unwrap().with_options(options);
+    let rule_def = schema.lookup_rule(rule).unwrap();
+    validate_cbor_slice(rule_def, &hex::decode(cbor_hex).unwrap(), &schema)
+}
+
+// Remove the COSE and CWT tags.
+fn cose_tags() -> TagPolicy {
+    TagPolicy::Allow(vec![17, 18, 61])
+}
+
+#[test]
+fn cose_examples() {
+    validate("COSE_Messages", SIGN1_TAGGED, TagPolicy::Strict).unwrap();
+    validate("COSE_Sign1_Tagged", SIGN1_TAGGED, TagPolicy::Strict).unwrap();
+    validate("CWT_Message", CWT_SIGNED, TagPolicy::Strict).unwrap();
+    validate("CWT_Tagged", CWT_MACED, TagPolicy::Strict).unwrap();
+
+    // The untagged types, with the tags removed from the data.
+    validate("COSE_Untagged_Message", SIGN1_TAGGED, cose_tags()).unwrap();
+    validate("COSE_Sign1", SIGN1_TAGGED, cose_tags()).unwrap();
+    validate("Claims", CLAIMS, cose_tags()).unwrap();
+    validate("CWT", CWT_SIGNED, cose_tags()).unwrap();
+    validate("CWT", CWT_MACED, cose_tags()).unwrap();
+    validate("COSE_Untagged_Message", CWT_MACED, cose_tags()).unwrap();
+}
+
+#[test]
+fn cose_mismatches() {
+    let err = |rule: &str, cbor_hex: &str, tags: TagPolicy| {
+        let err = validate(rule, cbor_hex, tags).unwrap_err();
+        (err.to_string(), err.schema_name().unwrap().to_string())
+    };
+
+    // The claims set with "exp" as a text string.
+    let claims = CLAIMS.replace("041a5612aeb0", "046461626364");
+    assert_eq!(
+        err("Claims", &claims, cose_tags()),
+        (
+            "Mismatch(expected choice of 2 (int / float) at [4])".into(),
+            "Claims.4".into()
+        )
+    );
+    // The same claims set, inside a signed CWT.
+    let cwt = CWT_SIGNED.replace(CLAIMS, &claims);
+    assert_eq!(
+        err("CWT", &cwt, cose_tags()),
+        (
+            "Mismatch(expected choice of 2 (int / float) at [2][4])".into(),
+            "Claims.4".into()
+        )
+    );
+
+    // The protected header is an array, not a map.
+    let sign1 = SIGN1_TAGGED.replace("d28443a10126", "d28443820126");
+    assert_eq!(
+        err("COSE_Sign1", &sign1, cose_tags()),
+        (
+            "Mismatch(expected choice of 2 (map / bstr over .size limit) at [0])".into(),
+            "Headers.protected".into()
+        )
+    );
+    // A COSE_Sign1 without a signature.
+    let sign1 = &SIGN1_TAGGED[..SIGN1_TAGGED.find("5840").unwrap()].replace("d284", "d283");
+    assert_eq!(
+        err("COSE_Sign1", sign1, cose_tags()),
+        (
+            "Mismatch(expected array element Bstr)".into(),
+            "COSE_Sign1.signature".into()
+        )
+    );
+
+    // A tagged message only matches the tagged types, unless its tag is
+    // removed.
+    assert_eq!(
+        err("COSE_Sign1", SIGN1_TAGGED, TagPolicy::Strict),
+        ("Mismatch(expected array)".into(), "COSE_Sign1".into())
+    );
+    let sign1 = SIGN1_TAGGED.replacen("d2", "", 1);
+    let e = validate("COSE_Sign1_Tagged", &sign1, TagPolicy::Strict).unwrap_err();
+    assert_eq!(e.to_string(), "Mismatch(expected tag 18)");
+    let e = validate("COSE_Sign1_Tagged", CWT_MACED, TagPolicy::Strict).unwrap_err();
+    assert_eq!(e.to_string(), "Mismatch(expected tag 18)");
+    // The tag may be removed without changing whether it matches.
+    validate("COSE_Sign1_Tagged", SIGN1_TAGGED, cose_tags()).unwrap();
+    // Only the listed tags are removed; this leaves tag 17.
+    let err = validate("CWT", CWT_MACED, TagPolicy::Allow(vec![61])).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (array / array))"
+    );
+}
@@ -0,0 +1,261 @@
+// Compare cddl-cat with another CDDL validator.
+//
+// Each case (the test vectors in `tests/vectors`, and the divergences
+// below) is validated by cddl-cat.  If `CDDL_DIFF_CMD` is set, it's also
+// validated by running that command, and each case where the two disagree
+// about whether the data is valid, other than the divergences listed below,
+// is reported as a line of JSON.
+//
+// The command is split on whitespace, and these words are replaced:
+// - `{cddl}`: the path of a file holding the schema
+// - `{rule}`: the name of the rule to validate against
+// - `{cbor}`: the path of a file holding the CBOR data
+// - `{cbor_hex}`: the CBOR data, in hex
+//
+// An exit status of 0 means the data is valid; anything else means it
+// isn't.  For example:
+//
+//     CDDL_DIFF_CMD="cddl validate --cddl {cddl} --cbor {cbor}" \
+//         cargo test --test differential
+//
+// The test fails if there's a disagreement that isn't listed in
+// DIVERGENCES, or if a listed divergence no longer happens, so that the
+// list stays accurate.
+
+#![cfg(all(feature = "serde_cbor", feature = "serde_json"))]
+
+use cddl_cat::cbor::validate_cbor_bytes;
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// A known, intentional difference between cddl-cat and other validators.
+//
+// These are the corner cases where validators commonly differ: some read
+// the RFCs more strictly than cddl-cat does, and some less.  If the other
+// validator being compared behaves differently, update `other_ok` for it.
+struct Divergence {
+    name: &'static str,
+    // The schema; its first rule is the one validated, so that validators
+    // that only use the first rule agree on which one it is.
+    cddl: &'static str,
+    cbor: &'static str,
+    // The result from cddl-cat: "ok", or the kind of error.
+    cddl_cat: &'static str,
+    // Whether the other validator accepts the data.  This is always the
+    // opposite of cddl-cat.
+    other_ok: bool,
+    why: &'static str,
+}
+
+const DIVERGENCES: &[Divergence] = &[
+    Divergence {
+        name: "float16 encoded as float32",
+        cddl: "f = float16",
+        // 1.5, as a 32-bit float
+        cbor: "fa3fc00000",
+        cddl_cat: "ok",
+        other_ok: false,
+        why: "floats are checked by value, because the decoder doesn't keep their width",
+    },
+    Divergence {
+        name: "float64 encoded as float16",
+        cddl: "f = float64",
+        // 1.5, as a 16-bit float
+        cbor: "f93e00",
+        cddl_cat: "ok",
+        other_ok: false,
+        why: "floats are checked by value, because the decoder doesn't keep their width",
+    },
+    Divergence {
+        name: "duplicate map keys",
+        cddl: "m = { a: int }",
+        // {"a": 1, "a": 2}
+        cbor: "a2616101616102",
+        cddl_cat: "ok",
+        other_ok: false,
+        why: "the decoder keeps the last value for a duplicate key",
+    },
+    Divergence {
+        name: "undefined matches nil",
+        cddl: "u = nil",
+        cbor: "f7",
+        cddl_cat: "ok",
+        other_ok: false,
+        why: "the decoder reads undefined as null",
+    },
+    Divergence {
+        name: "a non-cut key may match a later member",
+        cddl: r#"m = { ? "a" => int, * tstr => any }"#,
+        // {"a": "x"}
+        cbor: "a161616178",
+        cddl_cat: "ok",
+        other_ok: false,
+        why: "RFC 8610 section 3.5.4; validators that always cut map keys reject this",
+    },
+    Divergence {
+        name: "a tagged value only matches any",
+        cddl: "u = uint",
+        // 1(10)
+        cbor: "c10a",
+        cddl_cat: "TypeMismatch",
+        other_ok: true,
+        why: "tags are kept by default (see TagPolicy); validators that ignore tags accept this",
+    },
+];
+
+// The parts of a test vector file that are needed here; see
+// `tests/vectors/README.md`.
+#[derive(Deserialize)]
+struct VectorFile {
+    cddl: String,
+    rule: String,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    cbor: String,
+    expect: String,
+}
+
+// One (schema, data, expected) case.
+struct Case {
+    name: String,
+    cddl: String,
+    rule: String,
+    cbor: String,
+    // The result expected from cddl-cat.
+    expect: String,
+    // For a listed divergence, whether the other validator should accept
+    // the data, and why it differs.
+    other_ok: Option<bool>,
+    why: Option<&'static str>,
+}
+
+fn cases() -> Vec<Case> {
+    let mut cases: Vec<Case> = DIVERGENCES
+        .iter()
+        .map(|d| Case {
+            name: d.name.into(),
+            cddl: d.cddl.into(),
+            rule: d.cddl.split_whitespace().next().unwrap().into(),
+            cbor: d.cbor.into(),
+            expect: d.cddl_cat.into(),
+            other_ok: Some(d.other_ok),
+            why: Some(d.why),
+        })
+        .collect();
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    paths.sort();
+    for path in &paths {
+        let file: VectorFile = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let cddl = fs::read_to_string(dir.join(&file.cddl)).unwrap();
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        for vector in file.vectors {
+            cases.push(Case {
+                name: format!("{}: {}", stem, vector.name),
+                cddl: cddl.clone(),
+                rule: file.rule.clone(),
+                cbor: vector.cbor,
+                expect: vector.expect,
+                other_ok: None,
+                why: None,
+            });
+        }
+    }
+    cases
+}
+
+// The result from cddl-cat: "ok", or the kind of error, named the same
+// way as in the test vectors.
+fn cddl_cat_result(case: &Case) -> String {
+    let cbor = hex::decode(&case.cbor).unwrap();
+    match validate_cbor_bytes(&case.rule, &case.cddl, &cbor) {
+        Ok(()) => "ok".into(),
+        Err(e) => format!("{:?}", e.kind()),
+    }
+}
+
+// Run the other validator, returning whether it accepted the data.
+fn other_ok(cmd: &str, case: &Case, dir: &Path) -> bool {
+    let cddl_path = dir.join("schema.cddl");
+    let cbor_path = dir.join("data.cbor");
+    fs::write(&cddl_path, &case.cddl).unwrap();
+    fs::write(&cbor_path, hex::decode(&case.cbor).unwrap()).unwrap();
+
+    let path = |p: &PathBuf| p.to_string_lossy().into_owned();
+    let words: Vec<String> = cmd
+        .split_whitespace()
+        .map(|word| {
+            word.replace("{cddl}", &path(&cddl_path))
+                .replace("{rule}", &case.rule)
+                .replace("{cbor_hex}", &case.cbor)
+                .replace("{cbor}", &path(&cbor_path))
+        })
+        .collect();
+    let output = Command::new(&words[0])
+        .args(&words[1..])
+        .output()
+        .unwrap_or_else(|e| panic!("can't run {:?}: {}", words[0], e));
+    output.status.success()
+}
+
+#[test]
+fn differential() {
+    let cases = cases();
+
+    // cddl-cat's side of each divergence must stay true.  (The vectors are
+    // checked by tests/conformance.rs.)
+    for case in cases.iter().filter(|c| c.other_ok.is_some()) {
+        assert_eq!(cddl_cat_result(case), case.expect, "{}", case.name);
+        assert_ne!(case.other_ok, Some(case.expect == "ok"), "{}", case.name);
+    }
+
+    let cmd = match std::env::var("CDDL_DIFF_CMD") {
+        Ok(cmd) if !cmd.trim().is_empty() => cmd,
+        _ => return,
+    };
+    let dir = std::env::temp_dir().join(format!("cddl-cat-diff-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut unexpected = Vec::new();
+    for case in &cases {
+        let ours = cddl_cat_result(case);
+        let theirs = other_ok(&cmd, case, &dir);
+        let agree = (ours == "ok") == theirs;
+        let status = match (agree, case.other_ok) {
+            (true, None) => continue,
+            // A listed divergence.
+            (false, Some(other_ok)) if other_ok == theirs => continue,
+            (false, _) => "new",
+            // A listed divergence that didn't happen.
+            (true, Some(_)) => "resolved",
+        };
+        let line = json!({
+            "case": case.name,
+            "rule": case.rule,
+            "cbor": case.cbor,
+            "cddl_cat": ours,
+            "other": if theirs { "ok" } else { "error" },
+            "status": status,
+            "why": case.why,
+        });
+        unexpected.push(line.to_string());
+    }
+    fs::remove_dir_all(&dir).ok();
+    assert!(
+        unexpected.is_empty(),
+        "unexpected results:\n{}",
+        unexpected.join("\n")
+    );
+}
@@ -0,0 +1,191 @@
+// Empty text strings and byte strings, in every position they can appear.
+
+#[cfg(feature = "serde_cbor")]
+mod uses_cbor {
+    use cddl_cat::cbor::validate_cbor_bytes;
+    use cddl_cat::util::ErrorMatch;
+
+    #[rustfmt::skip]
+    mod cbor {
+        pub const TEXT_EMPTY:       &[u8] = b"\x60";             // ""
+        pub const TEXT_A:           &[u8] = b"\x61\x61";         // "a"
+        pub const BYTES_EMPTY:      &[u8] = b"\x40";             // h''
+        pub const BYTES_01:         &[u8] = b"\x41\x01";         // h'01'
+        pub const INT_0:            &[u8] = b"\x00";
+        pub const INT_1:            &[u8] = b"\x01";
+
+        pub const MAP_TEXT_EMPTY:   &[u8] = b"\xa1\x61\x61\x60"; // {"a": ""}
+        pub const MAP_BYTES_EMPTY:  &[u8] = b"\xa1\x61\x61\x40"; // {"a": h''}
+        pub const MAP_EMPTY_TEXT:   &[u8] = b"\xa1\x60\x01";     // {"": 1}
+        pub const MAP_EMPTY_BYTES:  &[u8] = b"\xa1\x40\x01";     // {h'': 1}
+
+        pub const ARRAY_TEXT_EMPTY: &[u8] = b"\x81\x60";         // [""]
+        pub const ARRAY_TWO_EMPTY:  &[u8] = b"\x82\x60\x40";     // ["", h'']
+        pub const ARRAY_EMPTY:      &[u8] = b"\x80";             // []
+    }
+
+    fn check(cddl_type: &str, cbor: &[u8]) -> cddl_cat::ValidateResult {
+        let cddl = format!("thing = {}", cddl_type);
+        validate_cbor_bytes("thing", &cddl, cbor)
+    }
+
+    #[test]
+    fn empty_scalar() {
+        check("tstr", cbor::TEXT_EMPTY).unwrap();
+        check("bstr", cbor::BYTES_EMPTY).unwrap();
+        check("tstr", cbor::BYTES_EMPTY).err_mismatch();
+        check("bstr", cbor::TEXT_EMPTY).err_mismatch();
+
+        // Empty literals match only empty values of the same type.
+        check(r#""""#, cbor::TEXT_EMPTY).unwrap();
+        check(r#""""#, cbor::TEXT_A).err_mismatch();
+        check(r#""""#, cbor::BYTES_EMPTY).err_mismatch();
+        for empty_bytes in &["h''", "''", "b64''"] {
+            check(empty_bytes, cbor::BYTES_EMPTY).unwrap();
+            check(empty_bytes, cbor::BYTES_01).err_mismatch();
+            check(empty_bytes, cbor::TEXT_EMPTY).err_mismatch();
+        }
+        let err = check("h''", cbor::INT_0).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected h'')");
+
+        // An empty string isn't the same as zero or false.
+        check(r#""""#, cbor::INT_0).err_mismatch();
+        check("h''", b"\xf4").err_mismatch();
+    }
+
+    #[test]
+    fn empty_size() {
+        check("tstr .size 0", cbor::TEXT_EMPTY).unwrap();
+        check("tstr .size 0", cbor::TEXT_A).err_mismatch();
+        check("bstr .size 0", cbor::BYTES_EMPTY).unwrap();
+        check("bstr .size 0", cbor::BYTES_01).err_mismatch();
+        check("uint .size 0", cbor::INT_0).unwrap();
+        check("uint .size 0", cbor::INT_1).err_mismatch();
+
+        // A size range with a lower limit rejects empty values.
+        let err = check("tstr .size (1..10)", cbor::TEXT_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected tstr under .size limit)");
+        check("tstr .size (1..10)", cbor::TEXT_A).unwrap();
+        let err = check("bstr .size (1..10)", cbor::BYTES_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected bstr under .size limit)");
+        check("bstr .size (1..10)", cbor::BYTES_01).unwrap();
+        check("uint .size (1..8)", cbor::INT_0).err_mismatch();
+        check("uint .size (1..8)", cbor::INT_1).unwrap();
+
+        // A range starting at zero permits them.
+        check("tstr .size (0..10)", cbor::TEXT_EMPTY).unwrap();
+        check("bstr .size (0...1)", cbor::BYTES_EMPTY).unwrap();
+        check("bstr .size (0...1)", cbor::BYTES_01).err_mismatch();
+        check("bstr .size (0...0)", cbor::BYTES_EMPTY).err_mismatch();
+
+        // Range endpoints can be rule references.
+        let cddl = "min = 1  max = 4  thing = bstr .size (min .. max)";
+        validate_cbor_bytes("thing", cddl, cbor::BYTES_01).unwrap();
+        validate_cbor_bytes("thing", cddl, cbor::BYTES_EMPTY).err_mismatch();
+
+        let err = check("bstr .size (-1..4)", cbor::BYTES_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), "Structural(bad .size limit -1)");
+        let err = check("bstr .size (0.5..4.5)", cbor::BYTES_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), "Structural(bad .size range 0.5..4.5)");
+    }
+
+    #[test]
+    fn empty_map_value() {
+        check("{ a: tstr }", cbor::MAP_TEXT_EMPTY).unwrap();
+        check(r#"{ a: "" }"#, cbor::MAP_TEXT_EMPTY).unwrap();
+        check("{ a: bstr }", cbor::MAP_BYTES_EMPTY).unwrap();
+        check("{ a: h'' }", cbor::MAP_BYTES_EMPTY).unwrap();
+        check("{ ? a: tstr }", cbor::MAP_TEXT_EMPTY).unwrap();
+
+        let err = check("{ a: tstr .size (1..10) }", cbor::MAP_TEXT_EMPTY).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Mismatch(expected tstr under .size limit at ["a"])"#
+        );
+        let err = check("{ ? a: h'01' }", cbor::MAP_BYTES_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), r#"Mismatch(expected h'01' at ["a"])"#);
+    }
+
+    #[test]
+    fn empty_map_key() {
+        check(r#"{ "": uint }"#, cbor::MAP_EMPTY_TEXT).unwrap();
+        check(r#"{ * tstr => uint }"#, cbor::MAP_EMPTY_TEXT).unwrap();
+        check(r#"{ "a": uint }"#, cbor::MAP_EMPTY_TEXT).err_missing_map_key();
+        check(r#"{ "": uint }"#, cbor::MAP_EMPTY_BYTES).err_missing_map_key();
+
+        check("{ h'': uint }", cbor::MAP_EMPTY_BYTES).unwrap();
+        check("{ * bstr => uint }", cbor::MAP_EMPTY_BYTES).unwrap();
+        check("{ h'': uint }", cbor::MAP_EMPTY_TEXT).err_missing_map_key();
+
+        let err = check(r#"{ "": tstr }"#, cbor::MAP_EMPTY_TEXT).unwrap_err();
+        assert_eq!(err.to_string(), r#"Mismatch(expected tstr at [""])"#);
+        let err = check("{ * bstr => tstr }", cbor::MAP_EMPTY_BYTES).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected tstr at [h''])");
+    }
+
+    #[test]
+    fn empty_array_element() {
+        check("[tstr]", cbor::ARRAY_TEXT_EMPTY).unwrap();
+        check(r#"[""]"#, cbor::ARRAY_TEXT_EMPTY).unwrap();
+        check(r#"["", h'']"#, cbor::ARRAY_TWO_EMPTY).unwrap();
+        check("[tstr, bstr .size 0]", cbor::ARRAY_TWO_EMPTY).unwrap();
+
+        // An empty value is still an element; it counts towards occurrences.
+        check(r#"[+ ""]"#, cbor::ARRAY_TEXT_EMPTY).unwrap();
+        check(r#"[+ ""]"#, cbor::ARRAY_EMPTY).err_mismatch();
+        check(r#"[? "", h'']"#, cbor::ARRAY_TWO_EMPTY).unwrap();
+        check(r#"[* tstr, h'']"#, cbor::ARRAY_TWO_EMPTY).unwrap();
+
+        let err = check("[+ tstr .size (1..3)]", cbor::ARRAY_TEXT_EMPTY).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Mismatch(expected tstr under .size limit at [0])"
+        );
+        let err = check(r#"["", h'01']"#, cbor::ARRAY_TWO_EMPTY).unwrap_err();
+        assert_eq!(err.to_string(), "Mismatch(expected h'01' at [1])");
+    }
+}
+
+#[cfg(feature = "serde_json")]
+mod uses_json {
+    use cddl_cat::json::validate_json_str;
+    use cddl_cat::util::ErrorMatch;
+
+    fn check(cddl_type: &str, json: &str) -> cddl_cat::ValidateResult {
+        let cddl = format!("thing = {}", cddl_type);
+        validate_json_str("thing", &cddl, json)
+    }
+
+    #[test]
+    fn json_empty_strings() {
+        // scalar
+        check("tstr", r#""""#).unwrap();
+        check(r#""""#, r#""""#).unwrap();
+        check(r#""""#, r#""a""#).err_mismatch();
+        check(r#""""#, "0").err_mismatch();
+        check(r#""""#, "null").err_mismatch();
+        check("tstr .size (1..10)", r#""""#).err_mismatch();
+        check("tstr .size (0..10)", r#""""#).unwrap();
+
+        // map value
+        check("{ a: tstr }", r#"{ "a": "" }"#).unwrap();
+        check(r#"{ a: "" }"#, r#"{ "a": "" }"#).unwrap();
+        // A size range needs parentheses.
+        check("{ a: tstr .size 1..3 }", r#"{ "a": "" }"#).err_parse();
+
+        // map key
+        check(r#"{ "": int }"#, r#"{ "": 1 }"#).unwrap();
+        check("{ * tstr => int }", r#"{ "": 1 }"#).unwrap();
+        let err = check(r#"{ "": int }"#, r#"{ "": "" }"#).unwrap_err();
+        assert_eq!(err.to_string(), r#"Mismatch(expected int at [""])"#);
+
+        // array element
+        check(r#"[+ ""]"#, r#"["", ""]"#).unwrap();
+        check(r#"[+ ""]"#, "[]").err_mismatch();
+        let err = check("[* tstr .size (1..3)]", r#"["a", ""]"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Mismatch(expected tstr under .size limit at [1])"
+        );
+    }
+}
@@ -33,4 +33,342 @@ mod uses_json {
         let err = validate_json_str("x", "x = nil", "0").unwrap_err();
         assert_eq!(format!("{}", err), "Mismatch(expected nil)");
     }
+
+    #[test]
+    fn error_rule_source() {
+        use cddl_cat::context::LookupContext;
+        use cddl_cat::json::validate_json;
+        use cddl_cat::schema::Schema;
+
+        let mut schema = Schema::from_str(
+            r#"
+            person = { name: tstr, address: address, pets: [* pet] }
+            address = { street: tstr, ? city: tstr }
+            pet = animal<"cat"> / animal<"dog">
+            "#,
+        )
+        .unwrap();
+        schema
+            .add_rules("animal<K> = { kind: K, name\n: tstr }")
+            .unwrap();
+        let validate = |json: &str| {
+            let json = serde_json::from_str(json).unwrap();
+            let rule_def = schema.lookup_rule("person").unwrap();
+            validate_json(rule_def, &json, &schema).unwrap_err()
+        };
+
+        // The innermost rule is the one that's shown.
+        let err = validate(r#"{ "name": "Bob", "address": { "street": 1 }, "pets": [] }"#);
+        assert_eq!(
+            err.rule_source(),
+            Some("address = { street: tstr, ? city: tstr }")
+        );
+        assert_eq!(
+            format!("{}", err),
+            r#"Mismatch(expected tstr at ["address"]["street"])"#
+        );
+        assert_eq!(
+            format!("{:#}", err),
+            "Mismatch(expected tstr at [\"address\"][\"street\"])\n\
+             in address.street\n\
+             via .address > address.street\n\
+             in rule: address = { street: tstr, ? city: tstr }"
+        );
+
+        // Rules added later, and generic rules, keep their text too.
+        let err = validate(
+            r#"{ "name": "Bob", "address": { "street": "Main" }, "pets": [{ "kind": "cat" }] }"#,
+        );
+        assert_eq!(
+            err.rule_source(),
+            Some("animal<K> = { kind: K, name\n: tstr }")
+        );
+
+        // Errors in the top-level rule don't have a rule source, since the
+        // rule wasn't referenced by name.
+        let err = validate(r#"{ "name": 1 }"#);
+        assert_eq!(err.rule_source(), None);
+        assert_eq!(
+            format!("{:#}", err),
+            format!("{}\nin person.name\nvia .name", err)
+        );
+
+        // Without the CDDL text, there's nothing to show.
+        let schema = Schema::from_rules(schema.rules().clone());
+        let json = serde_json::from_str(r#"{ "name": "Bob", "address": {}, "pets": [] }"#).unwrap();
+        let rule_def = schema.lookup_rule("person").unwrap();
+        let err = validate_json(rule_def, &json, &schema).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"MissingMapKey("street": Tstr) at ["address"]"#
+        );
+        assert_eq!(err.rule_source(), None);
+        assert_eq!(err.schema_name(), Some("address.street"));
+    }
+
+    #[test]
+    fn error_schema_path() {
+        let path = |cddl: &str, json: &str| {
+            let rule = &cddl[..cddl.find(' ').unwrap()];
+            let err = validate_json_str(rule, cddl, json).unwrap_err();
+            let path: Vec<String> = err.schema_path().iter().map(|e| e.to_string()).collect();
+            path.concat()
+        };
+
+        // Members that come from a group, or an unwrapped map, follow the
+        // rule they were written in.
+        let cddl = "thing = { common, b: int }  common = (a: int)";
+        assert_eq!(path(cddl, r#"{"a": "x", "b": 1}"#), "common.a");
+        let cddl = "thing = { ~base, b: int }  base = { a: int }";
+        assert_eq!(path(cddl, r#"{"a": "x", "b": 1}"#), "base.a");
+        let cddl = "thing = [~base, int]  base = [int, tstr]";
+        assert_eq!(path(cddl, r#"[1, 2, 3]"#), "[0]base[1]");
+
+        // Choice options count from 0; a choice that fails at the top level
+        // doesn't say which option was closest.
+        let cddl = "thing = { a: int } / { b: uint }";
+        assert_eq!(path(cddl, r#"{"b": -1}"#), "/1.b");
+        let cddl = "thing = [* int / [* uint]]";
+        assert_eq!(path(cddl, r#"[1, [-1]]"#), "[0]/1[0]");
+        assert_eq!(path(cddl, r#"[1, "x"]"#), "[0]");
+
+        // Generic rules are named, but their arguments are where they're
+        // written.
+        let cddl = "thing = { p: pair<tstr> }  pair<T> = [T, T]";
+        assert_eq!(path(cddl, r#"{"p": ["a", 1]}"#), ".ppair[1]");
+
+        // The alternate form shows it after the place's name, with each
+        // rule after the first marked.
+        let cddl = r#"
+            log = [* message]
+            message = { type: "ping" } / { type: "reading", body: reading }
+            reading = { flags: uint }
+        "#;
+        let json = r#"[{"type": "reading", "body": {"flags": "x"}}]"#;
+        let err = validate_json_str("log", cddl, json).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Mismatch(expected uint at [0][\"body\"][\"flags\"])\n\
+             in reading.flags\n\
+             via [0] > message/1.body > reading.flags"
+        );
+    }
+
+    #[test]
+    fn error_schema_name() {
+        let name = |cddl: &str, json: &str| {
+            let rule = &cddl[..cddl.find(' ').unwrap()];
+            let err = validate_json_str(rule, cddl, json).unwrap_err();
+            err.schema_name().map(String::from)
+        };
+        let name = |cddl, json| name(cddl, json).unwrap();
+
+        let cddl = "thing = { items: [* { id: uint, tags: [* tstr] }] }";
+        assert_eq!(
+            name(cddl, r#"{"items": [{"id": -1, "tags": []}]}"#),
+            "thing.items[].id"
+        );
+        assert_eq!(
+            name(cddl, r#"{"items": [{"id": 1, "tags": [2]}]}"#),
+            "thing.items[].tags[]"
+        );
+        assert_eq!(
+            name(cddl, r#"{"items": [{"tags": []}]}"#),
+            "thing.items[].id"
+        );
+        assert_eq!(
+            name(cddl, r#"{"items": [{"id": 1, "tags": 2}]}"#),
+            "thing.items[].tags"
+        );
+        assert_eq!(name(cddl, r#"{"items": [1]}"#), "thing.items[]");
+        assert_eq!(name(cddl, r#"[]"#), "thing");
+
+        let err =
+            validate_json_str("thing", cddl, r#"{"items": [{"id": -1, "tags": []}]}"#).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Mismatch(expected uint at [\"items\"][0][\"id\"])\n\
+             in thing.items[].id\n\
+             via .items[0].id"
+        );
+
+        // The innermost place that has a name is used, even if it's in
+        // another rule.
+        let cddl = "thing = { items: [* item] }  item = { id: uint }  id = uint";
+        assert_eq!(name(cddl, r#"{"items": [{"id": -1}]}"#), "item.id");
+        let cddl = "thing = { items: [* id] }  id = uint";
+        assert_eq!(name(cddl, r#"{"items": [-1]}"#), "thing.items[]");
+
+        // Keys that aren't literals, array members with keys, groups, and
+        // choices.
+        let cddl = "thing = { * tstr => [int] }";
+        assert_eq!(name(cddl, r#"{"a": ["x"]}"#), "thing.*[]");
+        let cddl = "point = [x: int, y: int]";
+        assert_eq!(name(cddl, r#"[1, "a"]"#), "point.y");
+        let cddl = "thing = { common, b: int }  common = (a: int)";
+        assert_eq!(name(cddl, r#"{"a": "x", "b": 1}"#), "common.a");
+        let cddl = "thing = { a: { b: int } / [int] }";
+        assert_eq!(name(cddl, r#"{"a": {"b": "x"}}"#), "thing.a.b");
+    }
+
+    #[test]
+    fn error_category() {
+        use cddl_cat::ErrorCategory::{Data, Schema};
+
+        let deep = "[".repeat(60) + &"]".repeat(60);
+        let cases = [
+            // Problems with the schema, found while flattening...
+            ("x = [", "1", Schema, "Unparseable"),
+            ("x = uint .lt 3", "1", Schema, "Unsupported"),
+            ("y = int", "1", Schema, "MissingRule"),
+            // ...or while validating.
+            ("x = [y]", "[1]", Schema, "MissingRule"),
+            ("x = tstr .size -1", r#""a""#, Schema, "Structural"),
+            ("x = 1..y  y = 2.5", "1", Schema, "Structural"),
+            ("x = { ~y }  y = [int]", "{}", Schema, "Structural"),
+            ("x = y<int>  y = int", "1", Schema, "GenericError"),
+            // Problems with the data.
+            ("x = tstr", "1", Data, "Mismatch"),
+            ("x = { a: int }", "{}", Data, "MissingMapKey"),
+            (
+                "x = { a: int, * tstr => any }",
+                r#"{ "a": "b" }"#,
+                Data,
+                "Mismatch",
+            ),
+            ("x = int", "1e999", Data, "ValueError"),
+            ("x = int", " ", Data, "EmptyInput"),
+            ("x = [* x] / int", &deep, Data, "RecursionLimit"),
+        ];
+        for (cddl, json, category, kind) in &cases {
+            let err = validate_json_str("x", cddl, json).unwrap_err();
+            assert_eq!(err.category(), *category, "{}: {}", cddl, err);
+            assert!(err.to_string().starts_with(kind), "{}: {}", cddl, err);
+        }
+    }
+
+    #[test]
+    fn error_kind() {
+        use cddl_cat::ErrorKind::*;
+
+        let deep = "[".repeat(60) + &"]".repeat(60);
+        let cases = [
+            ("x = tstr", "1", TypeMismatch),
+            ("x = 1", "2", TypeMismatch),
+            ("x = [* tstr]", "[1]", TypeMismatch),
+            ("x = { a: int }", "{}", MissingMapKey),
+            ("x = { a: int }", r#"{"a": 1, "b": 2}"#, UnexpectedMapKey),
+            // A cut error is the kind of what's inside it.
+            (
+                "x = { a: int, * tstr => any }",
+                r#"{"a": "b"}"#,
+                TypeMismatch,
+            ),
+            ("x = [int, int]", "[1]", ArrayLengthMismatch),
+            ("x = [int]", "[1, 2]", ArrayLengthMismatch),
+            ("x = [2*3 int]", "[1]", ArrayLengthMismatch),
+            ("x = [y]", "[1]", MissingRule),
+            ("x = uint .lt 3", "1", Unsupported),
+            ("x = [", "1", InvalidSchema),
+            ("x = tstr .size -1", r#""a""#, InvalidSchema),
+            ("x = int", "1e999", InvalidData),
+            ("x = int", " ", InvalidData),
+            ("x = [* x] / int", &deep, RecursionLimit),
+        ];
+        for (cddl, json, kind) in &cases {
+            let err = validate_json_str("x", cddl, json).unwrap_err();
+            assert_eq!(err.kind(), *kind, "{}: {}", cddl, err);
+        }
+
+        // The kind doesn't change what's displayed.
+        let err = validate_json_str("x", "x = { a: int }", r#"{"a": 1, "b": 2}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Mismatch(expected shorter map at ["b"])"#
+        );
+    }
+}
+
+#[cfg(feature = "serde_cbor")]
+mod uses_cbor {
+    use cddl_cat::cbor::{validate_cbor_bytes, validate_cbor_hex};
+    use cddl_cat::ErrorCategory::{Data, Schema};
+
+    #[test]
+    fn error_category() {
+        let cases = [
+            ("x = [", "01", Schema),
+            ("x = tstr .size tstr", "", Schema),
+            ("x = bstr .cbor y", "4101", Schema),
+            ("x = tstr", "01", Data),
+            ("x = tstr", "", Data),
+            ("x = tstr", "1a00", Data),
+            ("x = tstr", "0x", Data),
+            ("x = bstr .cbor int", "4161", Data),
+        ];
+        for (cddl, cbor_hex, category) in &cases {
+            let err = validate_cbor_hex("x", cddl, cbor_hex).unwrap_err();
+            assert_eq!(err.category(), *category, "{}: {}", cddl, err);
+        }
+        // A cut error is categorized by what's inside it.
+        let err = validate_cbor_bytes("x", "x = { a: int }", b"\xa1\x61a\x61b").unwrap_err();
+        assert_eq!(err.category(), Data);
+    }
+
+    #[test]
+    fn error_kind() {
+        use cddl_cat::cbor::validate_cbor_seq_bytes;
+        use cddl_cat::ErrorKind::{ArrayLengthMismatch, InvalidData, TypeMismatch};
+
+        let err = validate_cbor_bytes("x", "x = { a: int }", b"\xa1\x61a\x61b").unwrap_err();
+        assert_eq!(err.kind(), TypeMismatch);
+        let err = validate_cbor_hex("x", "x = tstr", "1a00").unwrap_err();
+        assert_eq!(err.kind(), InvalidData);
+        // Sequences count as arrays, even after the item count is added.
+        let err = validate_cbor_seq_bytes("x", "x = (int, int)", b"\x01").unwrap_err();
+        assert_eq!(err.kind(), ArrayLengthMismatch, "{}", err);
+        let err = validate_cbor_seq_bytes("x", "x = (int)", b"\x01\x02").unwrap_err();
+        assert_eq!(err.kind(), ArrayLengthMismatch, "{}", err);
+    }
+
+    // The CBOR head of a 4-byte length string.
+    fn long_head(major: u8, len: usize) -> Vec<u8> {
+        let mut head = vec![major << 5 | 26];
+        head.extend(&(len as u32).to_be_bytes());
+        head
+    }
+
+    #[test]
+    fn error_long_strings() {
+        use std::time::{Duration, Instant};
+
+        const MB: usize = 1 << 20;
+        // A certificate-sized literal.
+        let pem = format!("-----BEGIN {}-----END", "A".repeat(4000));
+        let bytes_cddl = format!("x = h'{}'", "0f".repeat(2000));
+        let text_cddl = format!("x = \"{}\"", pem);
+        let key_cddl = format!("x = {{ \"{}\": int }}", pem);
+
+        let mut big_bstr = long_head(2, MB);
+        big_bstr.resize(big_bstr.len() + MB, 0x0f);
+        let mut big_tstr = long_head(3, MB);
+        big_tstr.resize(big_tstr.len() + MB, b'A');
+        // A map with one entry: a 1 MB byte string key, and the value 1.
+        let mut big_key = vec![0xa1];
+        big_key.extend(&big_bstr);
+        big_key.push(0x01);
+
+        let cases = [
+            (bytes_cddl.as_str(), &big_bstr, "Mismatch(expected h'0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f...(+3939 bytes)...f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f')"),
+            (text_cddl.as_str(), &big_tstr, "Mismatch(expected \"-----BEGIN AAAAAAAAAAAAAAAAAAAA...(+3957 bytes)...AAAAAAAAAAAAAAAAAAAAAAA-----END\")"),
+            (key_cddl.as_str(), &vec![0xa0], "MissingMapKey(\"-----BEGIN AAAAAAAAAAAAAAAAAAAA...(+3957 bytes)...AAAAAAAAAAAAAAAAAAAAAAA-----END\": Int)"),
+            ("x = { * bstr => tstr }", &big_key, "Mismatch(expected tstr at [h'0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f...(+2097091 bytes)])"),
+        ];
+        for (cddl, cbor, message) in &cases {
+            let start = Instant::now();
+            let err = validate_cbor_bytes("x", cddl, cbor).unwrap_err();
+            assert!(start.elapsed() < Duration::from_secs(1), "{}", err);
+            assert_eq!(err.to_string(), *message);
+        }
+    }
 }
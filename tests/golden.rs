@@ -0,0 +1,38 @@
+#![cfg(all(feature = "regex", feature = "serde_cbor"))]
+
+// The flattened form of a schema must be the same on every run and every
+// platform, so that it can be hashed to notice when a schema changes.
+//
+// This compares the `Debug` rendering of `tests/golden/schema.cddl` with
+// the one saved in `tests/golden/schema.txt`.  If a change to this crate
+// is meant to change the rendering, run the test with `UPDATE_GOLDEN=1` to
+// save the new one, and check the difference.
+
+use cddl_cat::flatten::flatten_from_str;
+use std::fs;
+use std::path::Path;
+
+fn render() -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let cddl = fs::read_to_string(dir.join("schema.cddl")).unwrap();
+    format!("{:#?}\n", flatten_from_str(&cddl).unwrap())
+}
+
+#[test]
+fn golden_flatten() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/schema.txt");
+    let rendered = render();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &rendered).unwrap();
+    }
+    // Line endings may have been changed by a git checkout.
+    let golden = fs::read_to_string(&path).unwrap().replace("\r\n", "\n");
+    assert!(
+        rendered == golden,
+        "the flattened schema doesn't match {}; run with UPDATE_GOLDEN=1 to update it",
+        path.display()
+    );
+
+    // Flattening again gives the same result.
+    assert_eq!(render(), rendered);
+}
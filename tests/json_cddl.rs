@@ -1,7 +1,8 @@
 #![cfg(feature = "serde_json")]
 
 use cddl_cat::json::validate_json_str;
-use cddl_cat::util::ErrorMatch;
+use cddl_cat::util::{ErrorMatch, PathElement};
+use cddl_cat::ValidateError;
 use serde::{Deserialize, Serialize};
 
 #[test]
@@ -80,6 +81,33 @@ fn validate_json_integer() {
     validate_json_str("thing", cddl_input, "-1000").unwrap();
     validate_json_str("thing", cddl_input, "0").unwrap_err();
     validate_json_str("thing", cddl_input, "24").unwrap_err();
+    let cddl_input = r#"thing = number"#;
+    validate_json_str("thing", cddl_input, "-1000").unwrap();
+    validate_json_str("thing", cddl_input, "1.5").unwrap();
+    validate_json_str("thing", cddl_input, r#""1.5""#).err_mismatch();
+}
+
+#[test]
+fn validate_json_integer_boundaries() {
+    // 2^63-1, 2^63 and 2^64-1 fit in an i64 or u64.
+    for json in &[
+        "9223372036854775807",
+        "9223372036854775808",
+        "18446744073709551615",
+    ] {
+        validate_json_str("thing", "thing = int", json).unwrap();
+        validate_json_str("thing", "thing = uint", json).unwrap();
+        validate_json_str("thing", "thing = nint", json).err_mismatch();
+    }
+    validate_json_str("thing", "thing = int", "-9223372036854775808").unwrap();
+    validate_json_str("thing", "thing = nint", "-9223372036854775808").unwrap();
+    validate_json_str("thing", "thing = uint", "-9223372036854775808").err_mismatch();
+
+    // serde_json reads anything outside i64 and u64 as a float.
+    for json in &["18446744073709551616", "-9223372036854775809"] {
+        validate_json_str("thing", "thing = int", json).err_mismatch();
+        validate_json_str("thing", "thing = float", json).unwrap();
+    }
 }
 
 #[test]
@@ -217,8 +245,10 @@ fn validate_json_array_unwrap() {
     let cddl_input = r#"thing = [c: int ~footer]"#;
     validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap_err();
 
-    // Unwrapping a map into an array isn't allowed.
+    // A map can be unwrapped into an array; its keys are ignored.
     let cddl_input = r#"header = {a: int, b: int} thing = [~header c: int]"#;
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    let cddl_input = r#"header = tstr thing = [~header c: int]"#;
     validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap_err();
 }
 
@@ -379,7 +409,7 @@ fn validate_json_map_cut() {
             tstr => tstr,
         }"#;
     let err = validate_json_str("thing", cddl, json_str).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected int)");
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["foo"])"#);
 
     // Only "=>" can ever be non-cut.  Members using ":" always get
     // cut semantics.
@@ -432,7 +462,364 @@ fn validate_json_map_cut() {
     let json_str = r#"{ "aaa": 17, "zzz": "baz" }"#;
     let cddl = r#"thing = {* tstr ^ => int }"#;
     let err = validate_json_str("thing", cddl, json_str).unwrap_err();
-    assert_eq!(err.to_string(), "Mismatch(expected int)");
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["zzz"])"#);
+}
+
+#[test]
+fn json_map_group_choice() {
+    // Each group choice is a complete set of members; shapes can't be mixed.
+    let cddl = r#"msg = { a: int // b: tstr }"#;
+    validate_json_str("msg", cddl, r#"{ "a": 1 }"#).unwrap();
+    validate_json_str("msg", cddl, r#"{ "b": "x" }"#).unwrap();
+    let err = validate_json_str("msg", cddl, r#"{ "a": 1, "b": "x" }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected shorter map at ["b"])"#
+    );
+
+    // If neither option gets anywhere, the error lists what each one expected.
+    let err = validate_json_str("msg", cddl, r#"{ "c": 1 }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 2 (missing "a": Int / missing "b": Tstr))"#
+    );
+    // An option that matched a key explains itself.
+    let err = validate_json_str("msg", cddl, r#"{ "a": "x" }"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["a"])"#);
+
+    // A cut only applies within its own option.
+    let cddl = r#"msg = { (a: int, b: int) // a: tstr }"#;
+    validate_json_str("msg", cddl, r#"{ "a": "x" }"#).unwrap();
+}
+
+#[test]
+fn json_group_socket() {
+    // Each plug is a group choice, wherever the plugs are.
+    let cddl = r#"
+        $$event //= (kind: "click", x: int, y: int)
+        event = { id: uint, $$event }
+        $$event //= (kind: "key", key: tstr)
+    "#;
+    validate_json_str(
+        "event",
+        cddl,
+        r#"{ "id": 1, "kind": "click", "x": 1, "y": 2 }"#,
+    )
+    .unwrap();
+    validate_json_str("event", cddl, r#"{ "id": 1, "kind": "key", "key": "a" }"#).unwrap();
+    // Plugs can't be mixed.
+    let json = r#"{ "id": 1, "kind": "key", "x": 1, "y": 2 }"#;
+    validate_json_str("event", cddl, json).err_mismatch();
+    let err = validate_json_str("event", cddl, r#"{ "id": 1 }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 2 (missing "kind": "click" / missing "kind": "key"))"#
+    );
+
+    // A socket with no plugs is an empty choice, so it can only be used
+    // with an occurrence that allows none.
+    let cddl = r#"event = { id: uint, * $$extension }"#;
+    validate_json_str("event", cddl, r#"{ "id": 1 }"#).unwrap();
+    validate_json_str("event", cddl, r#"{ "id": 1, "x": 1 }"#).err_mismatch();
+    let cddl = r#"event = { id: uint, $$extension }"#;
+    let err = validate_json_str("event", cddl, r#"{ "id": 1 }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected $$extension (an empty socket))"
+    );
+
+    // Group plugs work in arrays too.
+    let cddl = r#"point = [tstr, $$coords]  $$coords //= (int, int)  $$coords //= (float)"#;
+    validate_json_str("point", cddl, r#"["a", 1, 2]"#).unwrap();
+    validate_json_str("point", cddl, r#"["a", 1.5]"#).unwrap();
+    validate_json_str("point", cddl, r#"["a", 1]"#).err_mismatch();
+}
+
+#[test]
+fn json_map_inline_group() {
+    let cddl = r#"point = { (x: int, y: int) }"#;
+    validate_json_str("point", cddl, r#"{ "x": 1, "y": 2 }"#).unwrap();
+    validate_json_str("point", cddl, r#"{ "x": 1 }"#).err_missing_map_key();
+
+    // An optional inline group is all or nothing.
+    let cddl = r#"point = { x: int, ? (y: int, z: int) }"#;
+    validate_json_str("point", cddl, r#"{ "x": 1 }"#).unwrap();
+    validate_json_str("point", cddl, r#"{ "x": 1, "y": 2, "z": 3 }"#).unwrap();
+    validate_json_str("point", cddl, r#"{ "x": 1, "y": 2 }"#).err_mismatch();
+
+    // Members inside keep their own occurrences.
+    let cddl = r#"point = { (x: int, ? y: int) }"#;
+    validate_json_str("point", cddl, r#"{ "x": 1 }"#).unwrap();
+    let cddl = r#"tags = { name: tstr, * (tstr => int) }"#;
+    validate_json_str("tags", cddl, r#"{ "name": "a", "b": 1, "c": 2 }"#).unwrap();
+    validate_json_str("tags", cddl, r#"{ "name": "a", "b": "x" }"#).err_mismatch();
+
+    // A group choice inside an inline group.
+    let cddl = r#"shape = { kind: tstr, (radius: int // width: int, height: int) }"#;
+    validate_json_str("shape", cddl, r#"{ "kind": "c", "radius": 1 }"#).unwrap();
+    let json = r#"{ "kind": "r", "width": 1, "height": 2 }"#;
+    validate_json_str("shape", cddl, json).unwrap();
+    let json = r#"{ "kind": "r", "radius": 1, "height": 2 }"#;
+    validate_json_str("shape", cddl, json).err_mismatch();
+}
+
+#[test]
+fn json_map_ignore_cuts() {
+    use cddl_cat::context::{LookupContext, ValidationOptions};
+    use cddl_cat::json::validate_json;
+    use cddl_cat::schema::Schema;
+
+    let validate = |cddl: &str, json_str: &str, ignore_cuts: bool| {
+        let mut options = ValidationOptions::default();
+        options.ignore_cuts = ignore_cuts;
+        let schema = Schema::from_str(cddl).unwrap().with_options(options);
+        let rule_def = schema.lookup_rule("thing").unwrap();
+        let json = serde_json::from_str(json_str).unwrap();
+        validate_json(rule_def, &json, &schema)
+    };
+
+    // Each of these is rejected because of a cut, so ignoring cuts lets
+    // the entry be matched by the later member instead.
+    let json_str = r#"{ "foo": "not-an-int" }"#;
+    for cddl in &[
+        r#"thing = { ? "foo": int, tstr => tstr }"#,
+        r#"thing = { ? "foo" ^ => int, tstr => tstr }"#,
+        r#"thing = { ? foo: int, * tstr => tstr }"#,
+    ] {
+        let err = validate(cddl, json_str, false).unwrap_err();
+        assert_eq!(err.to_string(), r#"Mismatch(expected int at ["foo"])"#);
+        validate(cddl, json_str, true).unwrap();
+    }
+    let cddl = r#"thing = { * tstr ^ => int, * tstr => tstr }"#;
+    let json_str = r#"{ "aaa": 17, "zzz": "baz" }"#;
+    validate(cddl, json_str, false).err_mismatch();
+    validate(cddl, json_str, true).unwrap();
+
+    // Non-cut members behave the same either way.
+    let cddl = r#"thing = { ? "foo" => int, tstr => tstr }"#;
+    validate(cddl, r#"{ "foo": "not-an-int" }"#, false).unwrap();
+    validate(cddl, r#"{ "foo": "not-an-int" }"#, true).unwrap();
+
+    // Ignoring cuts doesn't make anything else more lenient.
+    let cddl = r#"thing = { ? "foo": int }"#;
+    let err = validate(cddl, r#"{ "foo": "not-an-int" }"#, true).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["foo"])"#);
+    let cddl = r#"thing = { foo: int, bar: tstr }"#;
+    validate(cddl, r#"{ "foo": 1 }"#, true).err_missing_map_key();
+}
+
+#[test]
+fn json_alias_chains() {
+    // Three-deep alias chains, used in every position where an alias needs
+    // to be seen through.
+    let cddl = r#"
+        thing = {
+            key3 => text3,                  ; map key and value type
+            age: low3 .. high3,             ; range endpoints
+            nick: text3 .size limit3,       ; control target and argument
+            ~base3,                         ; unwrap target in a map
+            pair: [~pair3],                 ; unwrap target in an array
+        }
+        text0 = tstr    text1 = text0   text2 = text1   text3 = text2
+        key0 = "name"   key1 = key0     key2 = key1     key3 = key2
+        low0 = 0        low1 = low0     low2 = low1     low3 = low2
+        high0 = 120     high1 = high0   high2 = high1   high3 = high2
+        limit0 = 1..8   limit1 = limit0 limit2 = limit1 limit3 = limit2
+        base0 = { id: uint }
+        base1 = base0   base2 = base1   base3 = base2
+        pair0 = [int, int]
+        pair1 = pair0   pair2 = pair1   pair3 = pair2
+    "#;
+    let good = r#"{ "name": "Bob", "age": 43, "nick": "bobby", "id": 7, "pair": [1, 2] }"#;
+    validate_json_str("thing", cddl, good).unwrap();
+
+    let check = |json_str: &str, expected: &str| {
+        let err = validate_json_str("thing", cddl, json_str).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+    };
+    check(
+        r#"{ "name": 1, "age": 43, "nick": "bobby", "id": 7, "pair": [1, 2] }"#,
+        r#"Mismatch(expected tstr at ["name"])"#,
+    );
+    check(
+        r#"{ "Name": "Bob", "age": 43, "nick": "bobby", "id": 7, "pair": [1, 2] }"#,
+        r#"MissingMapKey(key3: text3)"#,
+    );
+    check(
+        r#"{ "name": "Bob", "age": 200, "nick": "bobby", "id": 7, "pair": [1, 2] }"#,
+        r#"Mismatch(expected low3..high3 at ["age"])"#,
+    );
+    check(
+        r#"{ "name": "Bob", "age": 43, "nick": "bobby tables", "id": 7, "pair": [1, 2] }"#,
+        r#"Mismatch(expected tstr over .size limit at ["nick"])"#,
+    );
+    check(
+        r#"{ "name": "Bob", "age": 43, "nick": "bobby", "pair": [1, 2] }"#,
+        r#"MissingMapKey("id": Uint)"#,
+    );
+    check(
+        r#"{ "name": "Bob", "age": 43, "nick": "bobby", "id": 7, "pair": [1, "2"] }"#,
+        r#"Mismatch(expected int at ["pair"][1])"#,
+    );
+
+    // A cycle of aliases stops at the recursion limit, wherever it's used.
+    for cddl in &[
+        "thing = a  a = b  b = a",
+        "thing = { a => int }  a = b  b = a",
+        "thing = a .. 10  a = b  b = a",
+        "thing = tstr .size a  a = b  b = a",
+        "thing = a .size 3  a = b  b = a",
+        "thing = { ~a }  a = b  b = a",
+        "thing = { x: [~a] }  a = b  b = a",
+    ] {
+        let err = validate_json_str("thing", cddl, r#"{ "x": [1] }"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RecursionLimit(hit recursion limit of 50 rules)",
+            "{}",
+            cddl
+        );
+    }
+}
+
+#[test]
+fn json_map_error_order() {
+    // When several members fail, the first one in the schema is reported,
+    // however the keys sort.
+    let json_str = r#"{ "a": "x", "b": "y" }"#;
+    let cddl = r#"m = { ? "a" => int, ? "b" => int }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["a"])"#);
+    let cddl = r#"m = { ? "b" => int, ? "a" => int }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["b"])"#);
+    let cddl = r#"m = { ? "b" => int, ? "a" => int, * tstr => bool }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["b"])"#);
+    let cddl = r#"m = { b: int, a: int, c: int }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["b"])"#);
+    let cddl = r#"m = { c: int, b: int, a: int }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"MissingMapKey("c": Int)"#);
+
+    // Within one member, entries are tried in key order.
+    let cddl = r#"m = { * tstr => int }"#;
+    let err = validate_json_str("m", cddl, r#"{ "z": "x", "a": "y" }"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["a"])"#);
+
+    // An option of a choice that was abandoned doesn't get to explain
+    // leftover entries.
+    let cddl = r#"m = { ? "b" => bool, "q" => int // "z" => int, ? "b" => int }"#;
+    let json_str = r#"{ "z": 1, "b": "y" }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["b"])"#);
+
+    // When listing every problem, they are in schema order.
+    let cddl = r#"m = { x: int // n: int, c: int, b: int, a: int }"#;
+    let json_str = r#"{ "a": "x", "b": "y", "n": 1, "o": 2 }"#;
+    let err = validate_json_str("m", cddl, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 2; closest is option 2, which matched 1 entry; missing "c": Int; expected int at ["b"]; expected int at ["a"]; not matched: "o")"#
+    );
+}
+
+#[test]
+fn json_map_missing_vs_mismatch() {
+    let cddl = r#"
+        person = { name: tstr, age: int, ? address: address }
+        address = { street: tstr, "number" => uint }
+    "#;
+
+    // A required key is absent.
+    let json_str = r#"{ "name": "Bob" }"#;
+    let err = validate_json_str("person", cddl, json_str).unwrap_err();
+    match &err {
+        ValidateError::MissingMapKey(m) => {
+            assert_eq!(m.key(), r#""age""#);
+            assert_eq!(m.expected(), "Int");
+            assert!(m.path().is_empty());
+        }
+        _ => panic!("expected MissingMapKey, got {:?}", err),
+    }
+    assert_eq!(err.to_string(), r#"MissingMapKey("age": Int)"#);
+
+    // The key is present, but the value has the wrong type.
+    let json_str = r#"{ "name": "Bob", "age": "old" }"#;
+    let err = validate_json_str("person", cddl, json_str).unwrap_err();
+    match &err {
+        ValidateError::Mismatch(m) => {
+            assert_eq!(m.expected(), "int");
+            assert_eq!(m.path(), &[PathElement::Key(r#""age""#.into())]);
+        }
+        _ => panic!("expected Mismatch, got {:?}", err),
+    }
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["age"])"#);
+
+    // The same, in a nested map.
+    let json_str = r#"{ "name": "Bob", "age": 43, "address": { "street": "Main" } }"#;
+    let err = validate_json_str("person", cddl, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"MissingMapKey("number": Uint) at ["address"]"#
+    );
+    let json_str = r#"{ "name": "Bob", "age": 43, "address": { "street": "Main", "number": -1 } }"#;
+    let err = validate_json_str("person", cddl, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected uint at ["address"]["number"])"#
+    );
+
+    // With non-cut semantics, a key whose value doesn't match may still be
+    // consumed by a later member; if it isn't, we report the value mismatch.
+    let cddl = r#"thing = { "foo" => int, * tstr => tstr }"#;
+    let json_str = r#"{ "foo": "bar" }"#;
+    let err = validate_json_str("thing", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["foo"])"#);
+    let json_str = r#"{ "bar": "baz" }"#;
+    validate_json_str("thing", cddl, json_str).err_missing_map_key();
+
+    // A non-literal key keeps searching for an entry whose value matches.
+    let cddl = r#"thing = { tstr => int, tstr => tstr }"#;
+    let json_str = r#"{ "a": "x", "b": 1 }"#;
+    validate_json_str("thing", cddl, json_str).unwrap();
+    let cddl = r#"thing = { tstr => int }"#;
+    let json_str = r#"{ "a": "x" }"#;
+    let err = validate_json_str("thing", cddl, json_str).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["a"])"#);
+}
+
+#[test]
+fn json_error_value_len() {
+    use cddl_cat::context::{LookupContext, ValidationOptions};
+    use cddl_cat::json::validate_json;
+    use cddl_cat::schema::Schema;
+
+    let json_str = format!(r#"{{ "{}": "x" }}"#, "k".repeat(100));
+    let json: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+
+    // Long map keys are truncated in error messages.
+    let schema = Schema::from_str("thing = { tstr => int }").unwrap();
+    let rule_def = schema.lookup_rule("thing").unwrap();
+    let err = validate_json(rule_def, &json, &schema).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            r#"Mismatch(expected int at ["{}...(+38 bytes)])"#,
+            "k".repeat(63)
+        )
+    );
+
+    let mut options = ValidationOptions::default();
+    options.max_value_len = 4;
+    let schema = schema.with_options(options);
+    let rule_def = schema.lookup_rule("thing").unwrap();
+    let err = validate_json(rule_def, &json, &schema).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected int at ["kkk...(+98 bytes)])"#
+    );
 }
 
 #[derive(Debug, Serialize)]
@@ -508,6 +895,78 @@ fn validate_choice_example() {
     validate_json_str("address", cddl_input, &json_str).unwrap();
 }
 
+#[test]
+fn json_group_choice_closest() {
+    // The RFC8610 2.2.2 example again, with data that half-matches the
+    // po_box option.
+    let cddl_input = r#"
+        address = { delivery }
+
+        delivery = (
+        street: tstr, ? number: uint, city //
+        po_box: uint, city //
+        per_pickup: true )
+
+        city = (
+        name: tstr, zip_code: uint
+        )"#;
+
+    let json_str = r#"{ "po_box": 101, "name": "San Francisco" }"#;
+    let err = validate_json_str("address", cddl_input, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3; closest is option 2, which matched 2 entries; missing "zip_code": Uint)"#
+    );
+
+    // Every problem with the closest option is listed, along with the keys
+    // it didn't match.
+    let json_str = r#"{ "po_box": 101, "zip_code": "94103", "suite": 4 }"#;
+    let err = validate_json_str("address", cddl_input, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3; closest is option 2, which matched 1 entry; missing "name": Tstr; expected uint at ["zip_code"]; not matched: "suite")"#
+    );
+
+    // The street option gets further than the po_box option.
+    let json_str = r#"{ "street": "Eleventh St.", "number": 375, "zip_code": 94103 }"#;
+    let err = validate_json_str("address", cddl_input, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3; closest is option 1, which matched 2 entries; missing "name": Tstr)"#
+    );
+
+    // If no option matches anything, there is no closest option.
+    let err = validate_json_str("address", cddl_input, r#"{ "suite": 4 }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3 (missing "street": Tstr / missing "po_box": Uint / missing "per_pickup": true))"#
+    );
+    let err = validate_json_str("address", cddl_input, r#"{ "po_box": "x" }"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected uint at ["po_box"])"#);
+
+    // The closest option is reported at its place in the data.
+    let cddl_input = cddl_input.replace("address = { delivery }", "address = [* { delivery }]");
+    let json_str = r#"[{ "per_pickup": true }, { "po_box": 101, "name": "SF" }]"#;
+    let err = validate_json_str("address", &cddl_input, json_str).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 3; closest is option 2, which matched 2 entries; missing "zip_code": Uint at [1])"#
+    );
+}
+
+#[test]
+fn json_nested_group_rewind() {
+    // When an outer group fails, values matched by a nested group must be
+    // put back too, so that the next option can match them.
+    let cddl_input = "thing = [ ((int, int), tstr) // (int, int, int) ]";
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, 2, "a"]"#).unwrap();
+
+    let cddl_input = "thing = { ((a: int, b: int), c: tstr) // (a: int, b: int, c: int) }";
+    validate_json_str("thing", cddl_input, r#"{ "a": 1, "b": 2, "c": 3 }"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"{ "a": 1, "b": 2, "c": "x" }"#).unwrap();
+}
+
 #[test]
 fn json_generic_basic() {
     let cddl_input = r#"identity<T> = T  thing = identity<int>"#;
@@ -692,13 +1151,20 @@ fn json_control_size() {
     validate_json_str("thing", cddl_input, "16777216").err_mismatch();
     validate_json_str("thing", cddl_input, "-256").err_mismatch();
 
+    // The size of a string is its length in UTF-8, and a single size must
+    // match exactly.
     let cddl_input = r#"thing = tstr .size 10"#;
+    validate_json_str("thing", cddl_input, r#""abcdefghij""#).unwrap();
+    validate_json_str("thing", cddl_input, r#""水水水a""#).unwrap();
+    validate_json_str("thing", cddl_input, r#""""#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#""JSON""#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#""水水水""#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#""水水水水""#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#""abcdefghijk""#).err_mismatch();
+    let cddl_input = r#"thing = tstr .size (0..10)"#;
     validate_json_str("thing", cddl_input, r#""""#).unwrap();
-    validate_json_str("thing", cddl_input, r#""JSON""#).unwrap();
     validate_json_str("thing", cddl_input, r#""水""#).unwrap();
     validate_json_str("thing", cddl_input, r#""水水水水""#).err_mismatch();
-    validate_json_str("thing", cddl_input, r#""abcdefghij""#).unwrap();
-    validate_json_str("thing", cddl_input, r#""abcdefghijk""#).err_mismatch();
 
     // .size is not allowed on signed integers.
     let cddl_input = r#"thing = int .size 3"#;
@@ -713,6 +1179,7 @@ fn json_control_size() {
     validate_json_str("thing", cddl_input, "0").unwrap_err();
 }
 
+#[cfg(feature = "regex")]
 #[test]
 fn json_control_regexp() {
     // Should match strings that look like integers with no leading zeroes.
@@ -785,7 +1252,7 @@ fn json_choiceify_map() {
     let json = r#"{"name": "Alice", "age": 33, "four": "X" }"#;
     validate_json_str("person", cddl_input, json).unwrap();
     let json = r#"{"name": "Alice", "four": "X" }"#;
-    validate_json_str("person", cddl_input, json).err_mismatch();
+    validate_json_str("person", cddl_input, json).err_missing_map_key();
 
     // With an extra level of name indirection
     let cddl_input = r#"
@@ -915,3 +1382,438 @@ fn json_choiceify_array() {
     let json = r#"[ 2 ]"#;
     validate_json_str("array", cddl_input, json).err_structural();
 }
+
+#[test]
+fn json_type_expr() {
+    use cddl_cat::json::{validate_json_str_expr, validate_json_str_expr_with};
+    use cddl_cat::schema::Schema;
+
+    validate_json_str_expr("uint", "24").unwrap();
+    validate_json_str_expr("int / tstr", r#""abc""#).unwrap();
+    validate_json_str_expr("int / tstr", "1.5").err_mismatch();
+
+    let json = r#"{ "name": "Bob", "age": 43 }"#;
+    validate_json_str_expr("{ name: tstr, age: uint }", json).unwrap();
+    validate_json_str_expr("{ name: tstr, * tstr => any }", json).unwrap();
+    validate_json_str_expr("{ name: tstr, age: tstr }", json).err_mismatch();
+    validate_json_str_expr("{ name: tstr, age: age }", json).err_missing_rule();
+
+    let schema = Schema::from_str("age = 0..150").unwrap();
+    validate_json_str_expr_with("{ name: tstr, age: age }", json, &schema).unwrap();
+    validate_json_str_expr_with("[* age]", "[1, 2, 151]", &schema).err_mismatch();
+}
+
+#[test]
+fn json_group_map_and_array() {
+    // The same group contributes key-value members when used in a map, and
+    // positional elements (ignoring the keys) when used in an array.
+    let cddl_input = r#"
+        pair = (label: tstr, value: int)
+        pair-map = { pair }
+        pair-array = [ pair ]
+        pairs = [ * pair ]
+        both = { pair, list: [ pair ], ? extra: [ ~tuple, pair ] }
+        tuple = [ tstr, int ]
+    "#;
+
+    let json = r#"{ "label": "a", "value": 1 }"#;
+    validate_json_str("pair-map", cddl_input, json).unwrap();
+    validate_json_str("pair-array", cddl_input, json).unwrap_err();
+
+    let json = r#"[ "a", 1 ]"#;
+    validate_json_str("pair-array", cddl_input, json).unwrap();
+    validate_json_str("pair-map", cddl_input, json).unwrap_err();
+    let json = r#"[ 1, "a" ]"#;
+    validate_json_str("pair-array", cddl_input, json).unwrap_err();
+
+    let json = r#"[ "a", 1, "b", 2 ]"#;
+    validate_json_str("pairs", cddl_input, json).unwrap();
+    let json = r#"[ "a", 1, "b" ]"#;
+    validate_json_str("pairs", cddl_input, json).unwrap_err();
+
+    let json = r#"{ "label": "a", "value": 1, "list": [ "b", 2 ] }"#;
+    validate_json_str("both", cddl_input, json).unwrap();
+    let json = r#"{ "label": "a", "value": 1, "list": [ "b", 2 ], "extra": [ "c", 3, "d", 4 ] }"#;
+    validate_json_str("both", cddl_input, json).unwrap();
+    let json = r#"{ "label": "a", "value": 1, "list": { "label": "b", "value": 2 } }"#;
+    validate_json_str("both", cddl_input, json).unwrap_err();
+}
+
+#[derive(Debug, Serialize)]
+struct UnitStruct;
+
+#[derive(Debug, Serialize)]
+struct OptionalName {
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SkipOptionalName {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[test]
+fn json_unit_none() {
+    let cddl_input = r#"thing = nil"#;
+    let inputs = [
+        serde_json::to_string(&()).unwrap(),
+        serde_json::to_string(&UnitStruct).unwrap(),
+        serde_json::to_string(&Option::<u32>::None).unwrap(),
+    ];
+    for json_str in &inputs {
+        assert_eq!(json_str, "null");
+        validate_json_str("thing", cddl_input, json_str).unwrap();
+    }
+
+    let none = serde_json::to_string(&OptionalName { name: None }).unwrap();
+    let some = serde_json::to_string(&OptionalName {
+        name: Some("Bob".to_string()),
+    })
+    .unwrap();
+    let skip_none = serde_json::to_string(&SkipOptionalName { name: None }).unwrap();
+
+    // A None field is serialized as a map member with a null value.
+    let cddl_input = r#"thing = { ? name: tstr }"#;
+    let err = validate_json_str("thing", cddl_input, &none).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected tstr at ["name"] (found null; to allow a null value, the schema needs "/ nil"))"#
+    );
+    validate_json_str("thing", cddl_input, &some).unwrap();
+    validate_json_str("thing", cddl_input, &skip_none).unwrap();
+
+    let cddl_input = r#"thing = { name: nil / tstr }"#;
+    validate_json_str("thing", cddl_input, &none).unwrap();
+    validate_json_str("thing", cddl_input, &some).unwrap();
+    validate_json_str("thing", cddl_input, &skip_none).err_missing_map_key();
+}
+
+#[test]
+fn json_optional_nil() {
+    // Whether a key may be absent (`?`) and whether its value may be null
+    // (`/ nil`) are independent; check every combination.
+    let present_null = r#"{ "name": null }"#;
+    let present_typed = r#"{ "name": "Bob" }"#;
+    let absent = r#"{}"#;
+    let wrong = r#"{ "name": 7 }"#;
+
+    let cddl_input = r#"thing = { ? name: tstr / nil }"#;
+    validate_json_str("thing", cddl_input, present_null).unwrap();
+    validate_json_str("thing", cddl_input, present_typed).unwrap();
+    validate_json_str("thing", cddl_input, absent).unwrap();
+    validate_json_str("thing", cddl_input, wrong).err_mismatch();
+
+    let cddl_input = r#"thing = { name: tstr / nil }"#;
+    validate_json_str("thing", cddl_input, present_null).unwrap();
+    validate_json_str("thing", cddl_input, present_typed).unwrap();
+    validate_json_str("thing", cddl_input, absent).err_missing_map_key();
+    validate_json_str("thing", cddl_input, wrong).err_mismatch();
+
+    let cddl_input = r#"thing = { ? name: tstr }"#;
+    validate_json_str("thing", cddl_input, present_null).err_mismatch();
+    validate_json_str("thing", cddl_input, present_typed).unwrap();
+    validate_json_str("thing", cddl_input, absent).unwrap();
+
+    let cddl_input = r#"thing = { name: tstr }"#;
+    validate_json_str("thing", cddl_input, present_typed).unwrap();
+    validate_json_str("thing", cddl_input, absent).err_missing_map_key();
+
+    // A null value where the schema doesn't allow one comes with a hint.
+    let err = validate_json_str("thing", cddl_input, present_null).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected tstr at ["name"] (found null; to allow a null value, the schema needs "/ nil"))"#
+    );
+    match err {
+        ValidateError::Mismatch(m) => {
+            assert_eq!(m.expected(), "tstr");
+            assert_eq!(m.path(), &[PathElement::Key(r#""name""#.into())]);
+            assert!(m.hint().unwrap().contains("/ nil"));
+        }
+        _ => panic!("expected a mismatch, got {:?}", err),
+    }
+
+    // Other mismatches, and nulls nested inside a member's value, don't get
+    // the hint.
+    let err = validate_json_str("thing", cddl_input, wrong).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected tstr at ["name"])"#);
+    let cddl_input = r#"thing = { name: [tstr] }"#;
+    let err = validate_json_str("thing", cddl_input, r#"{ "name": [null] }"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected tstr at ["name"][0])"#);
+
+    // The same goes for members matched by a key type.
+    let cddl_input = r#"thing = { * tstr => int }"#;
+    let err = validate_json_str("thing", cddl_input, present_null).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected int at ["name"] (found null; to allow a null value, the schema needs "/ nil"))"#
+    );
+}
+
+#[test]
+fn json_choice_deepest_error() {
+    fn err_string(cddl_input: &str, json_str: &str) -> String {
+        validate_json_str("thing", cddl_input, json_str)
+            .unwrap_err()
+            .to_string()
+    }
+
+    // No option got past the top level.
+    assert_eq!(
+        err_string("thing = int / tstr", "true"),
+        "Mismatch(expected choice of 2 (int / tstr))"
+    );
+
+    // The option that got deepest is reported, regardless of order.
+    let cddl_input = "thing = { a: int } / { a: { b: int } } / { c: int }";
+    assert_eq!(
+        err_string(cddl_input, r#"{ "a": { "b": "x" } }"#),
+        r#"Mismatch(expected int at ["a"]["b"])"#
+    );
+
+    // If several options got equally far, the first one is reported.
+    assert_eq!(
+        err_string("thing = { a: int } / { a: tstr }", r#"{ "a": true }"#),
+        r#"Mismatch(expected int at ["a"])"#
+    );
+    assert_eq!(
+        err_string("thing = { a: tstr } / { a: int }", r#"{ "a": true }"#),
+        r#"Mismatch(expected tstr at ["a"])"#
+    );
+
+    // Group choices in arrays and maps work the same way.
+    let cddl_input = "thing = [ (1, { a: int }) // (2, { a: int, b: tstr }) ]";
+    assert_eq!(
+        err_string(cddl_input, r#"[2, { "a": 1, "b": 3 }]"#),
+        r#"Mismatch(expected tstr at [1]["b"])"#
+    );
+    let cddl_input = "thing = { (x: int // y: tstr) }";
+    assert_eq!(
+        err_string(cddl_input, r#"{ "y": 1 }"#),
+        r#"Mismatch(expected tstr at ["y"])"#
+    );
+}
+
+#[test]
+fn json_array_choice_occur() {
+    // Each element may take a different alternative.
+    let cddl_input = "thing = [* (int / tstr)]";
+    validate_json_str("thing", cddl_input, "[]").unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, "a", "b", 2]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, true]"#).err_mismatch();
+
+    let cddl_input = r#"
+        thing = [1*3 (a-rec / b-rec)]
+        a-rec = [int]
+        b-rec = { x: int }
+    "#;
+    validate_json_str("thing", cddl_input, "[[1]]").unwrap();
+    validate_json_str("thing", cddl_input, r#"[[1], {"x": 2}, [3]]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[{"x": 1}, {"x": 2}]"#).unwrap();
+    validate_json_str("thing", cddl_input, "[]").err_mismatch();
+    validate_json_str("thing", cddl_input, r#"[[1], {"x": 2}, [3], [4]]"#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#"[[1], "x"]"#).err_mismatch();
+
+    // Group choices with different lengths are counted as one match each.
+    let cddl_input = "thing = [1*2 (int // (tstr, tstr))]";
+    validate_json_str("thing", cddl_input, r#"["a", "b", 1]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, "a", "b"]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, "a", "b", 2]"#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#"[1, "a"]"#).err_mismatch();
+}
+
+#[test]
+fn json_array_ambiguous_occur() {
+    // Optional trailing elements.
+    let cddl_input = "pair = [x: int, y: int, ? z: int]";
+    validate_json_str("pair", cddl_input, "[1, 2]").unwrap();
+    validate_json_str("pair", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("pair", cddl_input, "[1]").err_mismatch();
+    validate_json_str("pair", cddl_input, "[1, 2, 3, 4]").err_mismatch();
+
+    // Repeated elements in the middle of a record.
+    let cddl_input = "thing = [hdr: int, * tstr, trailer: int]";
+    validate_json_str("thing", cddl_input, "[1, 2]").unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, "a", "b", 2]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, "a"]"#).err_mismatch();
+    validate_json_str("thing", cddl_input, r#"[1, "a", 2, "b"]"#).err_mismatch();
+
+    // An occurrence that would take an element a later member needs.
+    let cddl_input = "thing = [* int, int]";
+    validate_json_str("thing", cddl_input, "[1]").unwrap();
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("thing", cddl_input, "[]").err_mismatch();
+    let cddl_input = "thing = [? int, int]";
+    validate_json_str("thing", cddl_input, "[1]").unwrap();
+    validate_json_str("thing", cddl_input, "[1, 2]").unwrap();
+    let cddl_input = "thing = [+ int, 3]";
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("thing", cddl_input, "[3]").err_mismatch();
+    validate_json_str("thing", cddl_input, "[1, 3, 2]").err_mismatch();
+    let cddl_input = "thing = [2*3 int, int]";
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("thing", cddl_input, "[1, 2, 3, 4]").unwrap();
+    validate_json_str("thing", cddl_input, "[1, 2]").err_mismatch();
+    validate_json_str("thing", cddl_input, "[1, 2, 3, 4, 5]").err_mismatch();
+
+    // Choices and groups can be ambiguous too.
+    let cddl_input = "thing = [* (int // tstr), int]";
+    validate_json_str("thing", cddl_input, r#"[1, "a", 2]"#).unwrap();
+    let cddl_input = "thing = [* (int, ? tstr), tstr]";
+    validate_json_str("thing", cddl_input, r#"[1, "a", 2, "b"]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[1, 2]"#).err_mismatch();
+    let cddl_input = "thing = [* int, ~tail]  tail = [int, tstr]";
+    validate_json_str("thing", cddl_input, r#"[1, 2, "a"]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"[2, "a"]"#).unwrap();
+    validate_json_str("thing", cddl_input, r#"["a"]"#).err_mismatch();
+    let cddl_input = "thing = [* int, pair]  pair = (int, int)";
+    validate_json_str("thing", cddl_input, "[1, 2, 3]").unwrap();
+    validate_json_str("thing", cddl_input, "[1]").err_mismatch();
+
+    // A repeated group that can match nothing doesn't go round forever.
+    let cddl_input = "thing = [* (? int), int]";
+    validate_json_str("thing", cddl_input, "[1, 2]").unwrap();
+    validate_json_str("thing", "thing = [1000* (? int)]", "[1]").unwrap();
+
+    // A mismatch is still explained by the greedy match.
+    let err = validate_json_str("thing", "thing = [* int, tstr]", "[1, 2]").unwrap_err();
+    assert_eq!(err.to_string(), "Mismatch(expected array element Tstr)");
+}
+
+#[test]
+fn json_error_path_data_keys() {
+    let cddl_input = r#"
+        index = { * tstr => [1* doc-id] }
+        doc-id = uint
+    "#;
+    validate_json_str("index", cddl_input, r#"{ "users": [1, 2], "groups": [3] }"#).unwrap();
+
+    // The path uses the data key that was being processed, not the schema's
+    // key pattern.
+    let err = validate_json_str("index", cddl_input, r#"{ "users": ["x"] }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected uint at ["users"][0])"#
+    );
+    let err = validate_json_str("index", cddl_input, r#"{ "users": [1, 2, -3] }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected uint at ["users"][2])"#
+    );
+    match err {
+        ValidateError::Mismatch(m) => assert_eq!(
+            m.path(),
+            &[
+                PathElement::Key(r#""users""#.to_string()),
+                PathElement::Index(2)
+            ]
+        ),
+        _ => panic!("expected Mismatch, got {:?}", err),
+    }
+
+    // An empty array doesn't have a bad element.
+    let err = validate_json_str("index", cddl_input, r#"{ "users": [] }"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected more array element [1* doc-id] (found 0, need at least 1) at ["users"])"#
+    );
+
+    // Optional members explain leftover data, too.
+    let err =
+        validate_json_str("thing", "thing = { ? age: int }", r#"{ "age": "x" }"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected int at ["age"])"#);
+    let err = validate_json_str("thing", "thing = [* (int / tstr)]", "[1, true]").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Mismatch(expected choice of 2 (int / tstr) at [1])"
+    );
+
+    let cddl_input = "thing = [* { name: tstr }]";
+    let err =
+        validate_json_str("thing", cddl_input, r#"[{ "name": "a" }, { "name": 1 }]"#).unwrap_err();
+    assert_eq!(err.to_string(), r#"Mismatch(expected tstr at [1]["name"])"#);
+
+    // Keys that look the same once shortened don't get each other's errors.
+    // The first key's value isn't a uint, but it is a tstr, so it's the
+    // second key that is left over.
+    let prefix = "k".repeat(100);
+    let json = format!(r#"{{ "{0}1": "a", "{0}2": true }}"#, prefix);
+    let cddl_input = "thing = { * tstr => uint, * tstr => tstr }";
+    let err = validate_json_str("thing", cddl_input, &json).unwrap_err();
+    assert!(
+        err.to_string().starts_with("Mismatch(expected tstr at"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn json_built_rules() {
+    use cddl_cat::ivt::RulesBuilder;
+    use cddl_cat::ivt::{choice, literal, rule_ref, ArrayBuilder, MapBuilder, OccurLimit};
+    use cddl_cat::json::validate_json;
+    use cddl_cat::schema::Schema;
+
+    let person = MapBuilder::new()
+        .member(literal("name"), rule_ref("tstr"), None)
+        .member(literal("age"), rule_ref("uint"), Some(OccurLimit::Optional))
+        .member(
+            literal("role"),
+            choice(vec![literal("admin"), literal("user")]),
+            None,
+        )
+        .build();
+    let people = ArrayBuilder::new()
+        .element(rule_ref("person"), Some(OccurLimit::ZeroOrMore))
+        .build();
+    let rules = RulesBuilder::new()
+        .rule("person", person)
+        .rule("people", people)
+        .build()
+        .unwrap();
+    let schema = Schema::from_rules(rules);
+    let people = &schema.rules()["people"];
+
+    let json: serde_json::Value = serde_json::from_str(
+        r#"[{ "name": "a", "role": "user" }, { "name": "b", "age": 3, "role": "admin" }]"#,
+    )
+    .unwrap();
+    validate_json(people, &json, &schema).unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_str(r#"[{ "name": "a", "role": "guest" }]"#).unwrap();
+    let err = validate_json(people, &json, &schema).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Mismatch(expected choice of 2 ("admin" / "user") at [0]["role"])"#
+    );
+}
+
+#[test]
+fn json_empty_input() {
+    let cddl_input = "thing = [* uint]";
+    for json_str in &["", " ", "\n\t \r\n"] {
+        let err = validate_json_str("thing", cddl_input, json_str).unwrap_err();
+        assert!(matches!(err, ValidateError::EmptyInput), "{:?}", err);
+        assert_eq!(err.to_string(), "EmptyInput");
+    }
+    let err = validate_json_str("thing", cddl_input, "[1, 2").unwrap_err();
+    assert!(matches!(err, ValidateError::ValueError(_)), "{:?}", err);
+}
+
+#[test]
+fn json_compiled_schema() {
+    let schema = cddl_cat::compile_cddl("thing = { name: tstr, ? age: uint }").unwrap();
+    for json_str in &[r#"{"name": "Bob"}"#, r#"{"name": "Alice", "age": 31}"#] {
+        schema.validate_json("thing", json_str).unwrap();
+    }
+    schema
+        .validate_json("thing", r#"{"name": "Bob", "age": -1}"#)
+        .err_mismatch();
+    schema
+        .validate_json("thing", r#"{"age": 1}"#)
+        .err_missing_map_key();
+    let err = schema.validate_json("thing", "").unwrap_err();
+    assert_eq!(err.to_string(), "EmptyInput");
+}
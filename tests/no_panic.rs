@@ -0,0 +1,335 @@
+#![cfg(feature = "serde_cbor")]
+
+// Validation must never panic because of the data: any bytes, validated
+// against any rule of a schema that flattens, give `Ok` or `Err`.
+//
+// This generates CBOR from a fixed seed, so a failure can be reproduced.
+// Most inputs are well-formed CBOR built from the schema's own strings and
+// numbers, so they get past decoding; the rest are mutated or cut short, or
+// are just random bytes.  Set `NO_PANIC_CASES` to try more inputs per rule
+// (the default is 300), and `NO_PANIC_SEED` to try different ones.
+
+use cddl_cat::cbor::{validate_cbor_report, validate_cbor_slice};
+use cddl_cat::context::{TagPolicy, ValidationOptions};
+use cddl_cat::schema::Schema;
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+// Schemas that use as many features as possible, especially the ones that
+// look inside the data (controls, ranges, tags and floats).
+const SCHEMAS: &[&str] = &[
+    r#"
+        thing = { id: uint, ? name: tstr .size (1..8), * tstr => any }
+        list = [* thing]
+        pair<T> = [T, T]
+        pairs = pair<float>
+        either = thing / [+ uint] / nil
+    "#,
+    r#"
+        ranges = [0..10, -5...5, 1.5..2.5, 0..0xffffffffffffffff]
+        floats = [float16, float32, float64, float16-32]
+        ints = [int, nint, uint, biguint, bignint]
+        simple = [bool, true, false, nil, null, any]
+        literals = [1, -1, 1.5, "x", h'01ff', 'bytes']
+    "#,
+    r#"
+        sized = [bstr .size 4, tstr .size 2, uint .size 3, bstr .size (2..3)]
+        bits = [uint .bits flags, bstr .bits flags]
+        flags = &(a: 0, b: 1, c: 7, d: 63, e: 127, f: 128)
+        nested = bstr .cbor thing
+        thing = [uint, ? tstr]
+    "#,
+    #[cfg(feature = "regex")]
+    r#"
+        text = tstr .regexp "[a-z]+[0-9]?"
+    "#,
+    r#"
+        dates = [tdate, uri, mime-message, regexp]
+        encoded = [b64url, b64legacy, eb64url, eb64legacy, eb16, encoded-cbor]
+        anything = cbor-any
+    "#,
+    r#"
+        tree = [* tree] / uint
+        expr = int / [op, expr, expr]
+        op = "+" / "-"
+        deep = { ? child: deep }
+        loop = [loop] / []
+    "#,
+    r#"
+        record = [a: uint, b: tstr, ~rest]
+        rest = [c: float, d: bool]
+        unwrapped = { ~base, extra: int }
+        base = { id: uint }
+        choiceified = &colors / &(x: 1, y: 2)
+        colors = (red: 0, green: 1, blue: 2)
+        cut = { "x" ^ => int, * tstr => tstr }
+        keys = { 1 => int, -1 => tstr, h'00' => bool, * int => any }
+        occur = [1*2 int, 2*3 tstr, ? bool, + nil]
+        group = (a: int, b: int)
+        grouped = { group, ? c: [* group] }
+    "#,
+];
+
+fn main_seed() -> u64 {
+    std::env::var("NO_PANIC_SEED")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0x5eed)
+}
+
+fn cases() -> usize {
+    std::env::var("NO_PANIC_CASES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(300)
+}
+
+// A small xorshift generator, good enough to pick test inputs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        // xorshift64* (the state must never be 0)
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+// Strings and numbers taken from the schema, so that generated maps have
+// keys the schema knows about.
+struct Dictionary {
+    words: Vec<String>,
+    numbers: Vec<u64>,
+}
+
+impl Dictionary {
+    fn new(cddl: &str) -> Dictionary {
+        // Long and non-ASCII text, for errors that render the data.
+        let mut words = vec![String::new(), "\u{e9}".repeat(40), "\u{1f600}x".into()];
+        let mut numbers = vec![0, 1, 23, 24, 255, 256, 65535, 65536, u64::MAX];
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        for word in cddl.split(|c: char| !is_word(c)).filter(|w| !w.is_empty()) {
+            match word.parse() {
+                Ok(n) => numbers.push(n),
+                Err(_) => words.push(word.to_string()),
+            }
+        }
+        Dictionary { words, numbers }
+    }
+}
+
+// Append the head of a CBOR item.
+fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(&arg.to_be_bytes());
+        }
+    }
+}
+
+// Append a random, well-formed CBOR item.
+fn item(rng: &mut Rng, dict: &Dictionary, depth: usize, out: &mut Vec<u8>) {
+    let leaf = depth == 0 || rng.below(3) == 0;
+    let kind = if leaf { rng.below(7) } else { 7 + rng.below(4) };
+    match kind {
+        0 => head(out, 0, *rng.pick(&dict.numbers)),
+        1 => head(out, 1, *rng.pick(&dict.numbers)),
+        2 | 3 if rng.below(8) == 0 => {
+            // Text that isn't UTF-8, or bytes that aren't CBOR.
+            let bytes = [0xc3, 0x28, 0xff, 0x82, 0x01];
+            head(out, kind as u8, bytes.len() as u64);
+            out.extend(&bytes);
+        }
+        2 | 3 => {
+            let word = rng.pick(&dict.words).as_bytes();
+            head(out, kind as u8, word.len() as u64);
+            out.extend(word);
+        }
+        4 => {
+            // Simple values, including the reserved ones.
+            let simple = [20, 21, 22, 23, 0, 19, 32, 255];
+            let simple = *rng.pick(&simple);
+            if simple < 24 {
+                out.push(0xe0 | simple);
+            } else {
+                out.extend(&[0xf8, simple]);
+            }
+        }
+        5 => {
+            let floats: &[&[u8]] = &[
+                b"\xf9\x00\x00",
+                b"\xf9\x80\x00",
+                b"\xf9\x3e\x00",
+                b"\xf9\x7c\x00",
+                b"\xf9\x7e\x00",
+                b"\xf9\x00\x01",
+                b"\xfa\x7f\x7f\xff\xff",
+                b"\xfa\xff\x80\x00\x00",
+                b"\xfb\x7f\xf8\x00\x00\x00\x00\x00\x00",
+                b"\xfb\xc3\xe0\x00\x00\x00\x00\x00\x00",
+                b"\xfb\x43\xf0\x00\x00\x00\x00\x00\x00",
+            ];
+            out.extend(*rng.pick(floats));
+        }
+        6 => {
+            // Random bytes in a byte string, which may be nested CBOR.
+            let len = rng.below(12);
+            head(out, 2, len as u64);
+            out.extend((0..len).map(|_| rng.next() as u8));
+        }
+        7 => {
+            let len = rng.below(5);
+            head(out, 4, len as u64);
+            for _ in 0..len {
+                item(rng, dict, depth - 1, out);
+            }
+        }
+        8 => {
+            let len = rng.below(5);
+            head(out, 5, len as u64);
+            for _ in 0..len * 2 {
+                item(rng, dict, depth - 1, out);
+            }
+        }
+        9 => {
+            // Bignums, which wrap a byte string, are the most interesting
+            // tags.
+            let tags = [0, 1, 2, 3, 4, 5, 24, 32, 55799, u64::MAX];
+            head(out, 6, *rng.pick(&tags));
+            item(rng, dict, depth - 1, out);
+        }
+        _ => {
+            // Indefinite-length containers.
+            let major = rng.below(2) as u8 + 4;
+            out.push(major << 5 | 31);
+            for _ in 0..rng.below(4) * (major as usize - 3) {
+                item(rng, dict, depth - 1, out);
+            }
+            out.push(0xff);
+        }
+    }
+}
+
+// Build one test input.
+fn input(rng: &mut Rng, dict: &Dictionary) -> Vec<u8> {
+    let mut out = Vec::new();
+    item(rng, dict, 4, &mut out);
+    match rng.below(8) {
+        0 => {
+            // Random bytes.
+            out = (0..rng.below(16)).map(|_| rng.next() as u8).collect();
+        }
+        1 if !out.is_empty() => {
+            let at = rng.below(out.len());
+            out.truncate(at);
+        }
+        2 if !out.is_empty() => {
+            let at = rng.below(out.len());
+            out[at] = rng.next() as u8;
+        }
+        3 => {
+            // Trailing data after the item.
+            out.push(rng.next() as u8);
+        }
+        _ => {}
+    }
+    out
+}
+
+// The options to validate with; each one changes which code runs.
+fn option_sets() -> Vec<ValidationOptions> {
+    let mut sets = vec![ValidationOptions::default()];
+    let mut options = ValidationOptions::default();
+    options.tags = TagPolicy::Unwrap;
+    options.lean = true;
+    sets.push(options);
+    let mut options = ValidationOptions::default();
+    options.tags = TagPolicy::Allow(vec![2, 24]);
+    options.any_tags = Some(vec![1, 32]);
+    options.ignore_cuts = true;
+    options.max_value_len = 3;
+    options.max_depth = 5;
+    sets.push(options);
+    sets
+}
+
+fn check_schema(label: &str, cddl: &str, rng: &mut Rng) -> usize {
+    // Validating against a schema and its rules is what `validate_cbor_bytes`
+    // does, without flattening the schema again for each input.
+    let parsed = Schema::from_str(cddl).unwrap_or_else(|e| panic!("{}: {}", label, e));
+    let errors = parsed.flatten_errors();
+    assert!(errors.is_empty(), "{}: {:?}", label, errors);
+    let dict = Dictionary::new(cddl);
+    let mut count = 0;
+    for (n, options) in option_sets().into_iter().enumerate() {
+        let schema = parsed.with_options(options);
+        for (rule, rule_def) in parsed.rules() {
+            for _ in 0..cases() {
+                let cbor = input(rng, &dict);
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    validate_cbor_slice(rule_def, &cbor, &schema).ok();
+                    if let Ok(value) = serde_cbor::from_slice(&cbor) {
+                        validate_cbor_report(rule_def, &value, &schema).ok();
+                    }
+                }));
+                if result.is_err() {
+                    panic!(
+                        "{}: rule '{}' with options {} panicked on {}",
+                        label,
+                        rule,
+                        n,
+                        hex::encode(&cbor)
+                    );
+                }
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+#[test]
+fn no_panic_on_any_data() {
+    let mut rng = Rng(main_seed() | 1);
+    let mut count = 0;
+    for (n, cddl) in SCHEMAS.iter().enumerate() {
+        count += check_schema(&format!("SCHEMAS[{}]", n), cddl, &mut rng);
+    }
+
+    // The test vector schemas too.
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "cddl"))
+        .collect();
+    paths.sort();
+    for path in &paths {
+        let cddl = fs::read_to_string(path).unwrap();
+        count += check_schema(&path.display().to_string(), &cddl, &mut rng);
+    }
+    assert!(count > 0);
+}
@@ -7,3 +7,151 @@ fn test_recursion() {
     parse_cddl("a = [[[[[[[[[[[[[[[[[[[[[[ int ]]]]]]]]]]]]]]]]]]]]]]").unwrap();
     parse_cddl("a = {{{{{{{{{{{{{{{{{{{{{{ int }}}}}}}}}}}}}}}}}}}}}}").unwrap();
 }
+
+// Recursive schemas, validated against deeply nested data.
+#[cfg(feature = "serde_json")]
+mod uses_json {
+    use cddl_cat::context::{LookupContext, ValidationOptions};
+    use cddl_cat::json::validate_json;
+    use cddl_cat::lint::lint_cddl;
+    use cddl_cat::schema::Schema;
+    use cddl_cat::ValidateResult;
+    use ntest::timeout;
+    use serde_json::{json, Value};
+
+    const EXPR: &str = r#"
+        expr = int / [op, expr, expr]
+        op = "+" / "-" / "*"
+    "#;
+
+    const TREE: &str = r#"
+        node = { value: int, ? left: node, ? right: node }
+    "#;
+
+    // ["+", 1, ["+", 1, ... leaf]], nested `depth` times.
+    fn deep_expr(depth: usize, leaf: Value) -> Value {
+        (0..depth).fold(leaf, |inner, _| json!(["+", 1, inner]))
+    }
+
+    // {"value": 0, "left": {"value": 0, "left": ... leaf}}, nested `depth`
+    // times.
+    fn deep_tree(depth: usize, leaf: Value) -> Value {
+        (0..depth).fold(leaf, |inner, _| json!({"value": 0, "left": inner}))
+    }
+
+    fn validate(schema: &impl LookupContext, name: &str, value: &Value) -> ValidateResult {
+        validate_json(schema.lookup_rule(name).unwrap(), value, schema)
+    }
+
+    #[test]
+    fn recursive_schema_is_legal() {
+        for cddl in &[EXPR, TREE] {
+            // Recursion isn't a cycle to be flagged.
+            assert_eq!(lint_cddl(cddl).unwrap(), vec![]);
+            let schema = Schema::from_str(cddl).unwrap();
+            assert_eq!(schema.roots().len(), 1);
+        }
+        let schema = Schema::from_str(TREE).unwrap();
+        assert!(schema.dependencies("node").unwrap().contains("node"));
+    }
+
+    #[test]
+    #[timeout(10000)] // 10 seconds
+    fn recursive_valid_data() {
+        // Flatten once, and validate many times.
+        let schema = Schema::from_str(EXPR).unwrap();
+        for depth in 0..45 {
+            validate(&schema, "expr", &deep_expr(depth, json!(7))).unwrap();
+        }
+        // Both branches can recurse.
+        let wide = json!(["*", ["-", 1, ["+", 2, 3]], ["+", ["-", 4, 5], 6]]);
+        validate(&schema, "expr", &wide).unwrap();
+
+        let schema = Schema::from_str(TREE).unwrap();
+        for depth in 0..45 {
+            validate(&schema, "node", &deep_tree(depth, json!({"value": 1}))).unwrap();
+        }
+        let both =
+            json!({"value": 1, "left": {"value": 2}, "right": {"value": 3, "left": {"value": 4}}});
+        validate(&schema, "node", &both).unwrap();
+    }
+
+    #[test]
+    fn recursive_invalid_data() {
+        // The error path shows how deep the problem is.
+        let schema = Schema::from_str(EXPR).unwrap();
+        let err = validate(&schema, "expr", &deep_expr(3, json!("x"))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Mismatch(expected choice of 2 (int / array) at [2][2][2])"
+        );
+        let err = validate(&schema, "expr", &deep_expr(3, json!(["/", 1, 2]))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Mismatch(expected choice of 3 ("+" / "-" / "*") at [2][2][2][0])"#
+        );
+
+        let schema = Schema::from_str(TREE).unwrap();
+        let err = validate(&schema, "node", &deep_tree(3, json!({"value": "x"}))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Mismatch(expected int at ["left"]["left"]["left"]["value"])"#
+        );
+        let err = validate(
+            &schema,
+            "node",
+            &deep_tree(3, json!({"left": {"value": 1}})),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"MissingMapKey("value": Int) at ["left"]["left"]["left"]"#
+        );
+    }
+
+    #[test]
+    #[timeout(10000)] // 10 seconds
+    fn recursive_depth_limit() {
+        // The default limit is 50 rule references, and each level of
+        // nesting here follows one reference.
+        let schema = Schema::from_str(EXPR).unwrap();
+        validate(&schema, "expr", &deep_expr(50, json!(7))).unwrap();
+        let err = validate(&schema, "expr", &deep_expr(51, json!(7))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RecursionLimit(hit recursion limit of 50 rules)"
+        );
+
+        let schema = Schema::from_str(TREE).unwrap();
+        let err = validate(&schema, "node", &deep_tree(60, json!({"value": 1}))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RecursionLimit(hit recursion limit of 50 rules)"
+        );
+
+        // The limit can be changed.
+        let mut options = ValidationOptions::default();
+        options.max_depth = 10;
+        let schema = Schema::from_str(EXPR).unwrap().with_options(options);
+        validate(&schema, "expr", &deep_expr(10, json!(7))).unwrap();
+        let err = validate(&schema, "expr", &deep_expr(11, json!(7))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RecursionLimit(hit recursion limit of 10 rules)"
+        );
+
+        let mut options = ValidationOptions::default();
+        options.max_depth = 100;
+        let schema = Schema::from_str(TREE).unwrap().with_options(options);
+        validate(&schema, "node", &deep_tree(80, json!({"value": 1}))).unwrap();
+
+        // Rules that refer to each other without consuming data hit the
+        // limit too.
+        let schema = Schema::from_str("a = b  b = a / int").unwrap();
+        let err = validate(&schema, "a", &json!(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RecursionLimit(hit recursion limit of 50 rules)"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use cddl_cat::context::BasicContext;
+use cddl_cat::flatten::flatten_from_str;
+use cddl_cat::ivt::{KeyValue, Node};
+use cddl_cat::value::Value;
+use cddl_cat::visit::{visit_map, MapVisitor};
+
+// Records everything it hears, as strings.
+#[derive(Default)]
+struct Recorder(Vec<String>);
+
+impl MapVisitor for Recorder {
+    fn matched(&mut self, member: &KeyValue, key: &Value, value: &Value) {
+        self.0
+            .push(format!("matched {} {:?} {:?}", member.value, key, value));
+    }
+
+    fn unmatched_member(&mut self, member: &KeyValue) {
+        self.0.push(format!("unmatched member {}", member.key));
+    }
+
+    fn unmatched_entry(&mut self, key: &Value, value: &Value) {
+        self.0
+            .push(format!("unmatched entry {:?} {:?}", key, value));
+    }
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.into())
+}
+
+fn map(entries: &[(&str, Value)]) -> Value {
+    Value::Map(entries.iter().map(|(k, v)| (text(k), v.clone())).collect())
+}
+
+// Visit the map in rule `name`, returning the validation result and events.
+fn visit(cddl: &str, name: &str, value: &Value) -> (cddl_cat::ValidateResult, Vec<String>) {
+    let rules = flatten_from_str(cddl).unwrap();
+    let m = match &rules[name].node {
+        Node::Map(m) => m.clone(),
+        other => panic!("not a map: {}", other),
+    };
+    let ctx = BasicContext::new(rules);
+    let mut recorder = Recorder::default();
+    let result = visit_map(&m, value, &ctx, &mut recorder);
+    (result, recorder.0)
+}
+
+#[test]
+fn visit_simple() {
+    let cddl = "person = { name: tstr, ? age: int, * tstr => any }";
+    let value = map(&[
+        ("name", text("Bob")),
+        ("pet", text("cat")),
+        ("toy", text("ball")),
+    ]);
+    let (result, events) = visit(cddl, "person", &value);
+    result.unwrap();
+    assert_eq!(
+        events,
+        vec![
+            r#"matched Tstr "name" "Bob""#,
+            r#"unmatched member "age""#,
+            r#"matched Any "pet" "cat""#,
+            r#"matched Any "toy" "ball""#,
+        ]
+    );
+}
+
+#[test]
+fn visit_failure() {
+    let cddl = "person = { name: tstr, age: int }";
+    let value = map(&[("name", text("Bob")), ("pet", text("cat"))]);
+    let (result, events) = visit(cddl, "person", &value);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        r#"MissingMapKey("age": Int)"#
+    );
+    assert_eq!(
+        events,
+        vec![
+            r#"matched Tstr "name" "Bob""#,
+            r#"unmatched member "age""#,
+            r#"unmatched entry "pet" "cat""#,
+        ]
+    );
+
+    // Leftover entries are reported even though every member matched.
+    let cddl = "person = { name: tstr }";
+    let (result, events) = visit(cddl, "person", &value);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        r#"Mismatch(expected shorter map at ["pet"])"#
+    );
+    assert_eq!(
+        events,
+        vec![
+            r#"matched Tstr "name" "Bob""#,
+            r#"unmatched entry "pet" "cat""#,
+        ]
+    );
+}
+
+#[test]
+fn visit_rewound() {
+    // The first option matches "street" before failing; that match is
+    // rewound, so the visitor only hears about the second option.
+    let cddl = r#"
+        address = { street // po_box }
+        street = ( street: tstr, number: uint )
+        po_box = ( street: tstr, box: uint )
+    "#;
+    let value = map(&[("street", text("Main")), ("box", Value::Integer(3))]);
+    let (result, events) = visit(cddl, "address", &value);
+    result.unwrap();
+    assert_eq!(
+        events,
+        vec![r#"matched Tstr "street" "Main""#, r#"matched Uint "box" 3"#,]
+    );
+}
+
+#[test]
+fn visit_nested_map() {
+    // Only the outer map is visited.
+    let cddl = "outer = { inner: { a: int } }";
+    let value = map(&[("inner", map(&[("a", Value::Integer(1))]))]);
+    let (result, events) = visit(cddl, "outer", &value);
+    result.unwrap();
+    assert_eq!(events, vec![r#"matched Map "inner" {"a": 1}"#]);
+}
+
+#[test]
+fn visit_not_a_map() {
+    let (result, events) = visit("thing = { a: int }", "thing", &text("a"));
+    assert_eq!(result.unwrap_err().to_string(), "Mismatch(expected map)");
+    assert!(events.is_empty());
+}